@@ -0,0 +1,472 @@
+mod expand;
+
+use std::collections::HashMap;
+
+use crate::encoding::{self, Machine};
+use crate::lexer::tokens::CodeSegment;
+use crate::mips::{self, Endian};
+use crate::object::{self, Object, Section, Binding, Relocation, RelocationKind, Line};
+use crate::parse::ast::*;
+use crate::parse::instructions::Instruction;
+use crate::parse::symbols::Symbol;
+use crate::errors;
+
+use colored::Colorize;
+
+type AsmRes<T> = Result<T, errors::Err>;
+
+// an operand as read from the tree
+enum Value {
+	Register(u32),
+	FloatRegister(u32, CodeSegment),
+	Immediate(i32, CodeSegment),
+	Label(String, CodeSegment),
+	Address(Offset, Option<u32>)	// offset and base register
+}
+
+enum Offset {
+	Number(i32),
+	Label(String, CodeSegment)
+}
+
+// a label whose address is filled into an instruction once it is known
+struct Target {
+	kind: RelocationKind,
+	label: String,
+	segment: CodeSegment
+}
+
+// a basic instruction produced by the expansion of a (pseudo-)instruction
+struct Step {
+	machine: Machine,
+	target: Option<Target>
+}
+
+struct Pending {
+	step: Step,
+	segment: Option<CodeSegment>	// the source instruction, none for the padding placing a section at an address
+}
+
+struct Assembler {
+	object: Object,
+	section: Section,
+	text: Vec<Pending>,					// encoded once every label in the file is defined
+	ktext: Vec<Pending>,
+	symbols: HashMap<String, usize>,	// index of every label into the object's symbol table
+	globals: Vec<(String, CodeSegment)>
+}
+
+// assembles the tree of one file into an object, leaving references to other files for the linker
+pub fn assemble(tree: &BaseASTree<Symbol>, endian: Endian) -> AsmRes<Object> {
+	let mut assembler = Assembler {
+		object: Object::new(endian),
+		section: Section::Text,
+		text: Vec::new(),
+		ktext: Vec::new(),
+		symbols: HashMap::new(),
+		globals: Vec::new()
+	};
+
+	for node in tree.children() {
+		assembler.node(node)?;
+	}
+
+	assembler.finish()
+}
+
+impl Assembler {
+	fn offset(&self) -> u32 {
+		match self.section {
+			Section::Text => self.text.len() as u32 * 4,
+			Section::KernelText => self.ktext.len() as u32 * 4,
+			section => self.object.section(section).len() as u32
+		}
+	}
+
+	// the instructions of the current section, while it is executable
+	fn pending(&mut self) -> &mut Vec<Pending> {
+		match self.section {
+			Section::KernelText => &mut self.ktext,
+			_ => &mut self.text
+		}
+	}
+
+	// continues the current section at an address, as far as it is from the default base of the section
+	fn place(&mut self, address: i32, segment: &CodeSegment) -> AsmRes<()> {
+		let base = match self.section {
+			Section::Text => mips::TEXT_BASE,
+			Section::Data => mips::DATA_BASE,
+			Section::KernelText => mips::KTEXT_BASE,
+			Section::KernelData => mips::KDATA_BASE
+		};
+		let offset = (address as u32).wrapping_sub(base);
+
+		if offset < self.offset() || offset - self.offset() > 0x100000 {
+			return err(segment, errors::Msg::Many(vec![
+				format!("Address {} cannot be placed in the {} section.", format!("{:#010x}", address).red(), self.section.name()),
+				format!("Addresses continue the section from {:#010x}, up to 1MB further.", base.wrapping_add(self.offset()))
+			]));
+		}
+
+		if self.section.executable() {
+			if !offset.is_multiple_of(4) {
+				return err(segment, errors::Msg::One(format!("Address {} is not aligned to a word.", format!("{:#010x}", address).red())));
+			}
+			while self.offset() < offset {
+				self.pending().push(Pending {
+					step: Step { machine: Machine::new(Instruction::ShiftLeftLogical), target: None },
+					segment: None
+				});
+			}
+		} else {
+			self.object.section_mut(self.section).resize(offset as usize, 0);
+		}
+		Ok(())
+	}
+
+	fn node(&mut self, node: &ASTNode<Symbol>) -> AsmRes<()> {
+		match node {
+			ASTNode::Node(Symbol::Instruction(instruction, segment)) => self.instruction(*instruction, &[], segment),
+			ASTNode::Node(symbol) => err(symbol.segment(), errors::Msg::One("Unexpected operand.".to_string())),
+			ASTNode::Tree(tree) => match tree.root() {
+				Symbol::Directive(id, segment) => self.directive(id, segment, tree.children()),
+				Symbol::Instruction(instruction, segment) => self.instruction(*instruction, tree.children(), segment),
+				Symbol::DefLabel(label, segment) => {
+					// data which is aligned automatically takes its label with it
+					if let Some(ASTNode::Tree(child)) = tree.children().first() {
+						if let Symbol::Directive(id, _) = child.root() {
+							self.align(alignment(id));
+						}
+					}
+
+					self.define(label, segment)?;
+					for child in tree.children() {
+						self.node(child)?;
+					}
+					Ok(())
+				},
+				symbol => err(symbol.segment(), errors::Msg::One("Unexpected operand.".to_string()))
+			}
+		}
+	}
+
+	// the index of a label in the symbol table, which is added as undefined when first referenced
+	fn symbol(&mut self, label: &str) -> usize {
+		if let Some(idx) = self.symbols.get(label) {
+			return *idx;
+		}
+
+		self.object.symbols.push(object::Symbol {
+			name: label.to_string(),
+			section: None,
+			offset: 0,
+			binding: Binding::Local,
+			segment: None
+		});
+		self.symbols.insert(label.to_string(), self.object.symbols.len() - 1);
+		self.object.symbols.len() - 1
+	}
+
+	fn define(&mut self, label: &str, segment: &CodeSegment) -> AsmRes<()> {
+		let idx = self.symbol(label);
+		let offset = self.offset();
+		let symbol = &mut self.object.symbols[idx];
+
+		if symbol.section.is_some() {
+			let first = symbol.segment.as_ref().map_or(0, |segment| segment.line);
+			return err(segment, errors::Msg::Many(vec![
+				format!("Label {} is already defined.", label.red()),
+				format!("It was first defined on line {}.", first + 1)
+			]));
+		}
+
+		symbol.section = Some(self.section);
+		symbol.offset = offset;
+		symbol.segment = Some(segment.clone());
+		Ok(())
+	}
+
+	fn align(&mut self, alignment: usize) {
+		if !self.section.executable() {
+			let data = self.object.section_mut(self.section);
+			while !data.len().is_multiple_of(alignment) {
+				data.push(0);
+			}
+		}
+	}
+
+	fn directive(&mut self, id: &str, segment: &CodeSegment, children: &[ASTNode<Symbol>]) -> AsmRes<()> {
+		match id {
+			"text" | "data" | "ktext" | "kdata" => {
+				self.section = match id {
+					"text" => Section::Text,
+					"data" => Section::Data,
+					"ktext" => Section::KernelText,
+					_ => Section::KernelData
+				};
+				for (idx, child) in children.iter().enumerate() {
+					match child {
+						ASTNode::Node(Symbol::NumberLiteral(address, address_segment)) if idx == 0 => self.place(*address, address_segment)?,
+						_ => self.node(child)?
+					}
+				}
+				return Ok(());
+			},
+			"globl" | "extern" => {
+				for child in children {
+					if let ASTNode::Node(Symbol::Label(label, label_segment)) = child {
+						if id == "globl" {
+							self.globals.push((label.to_string(), label_segment.clone()));
+						} else {
+							self.symbol(label);
+						}
+					}
+				}
+				return Ok(());
+			},
+			_ => {}
+		}
+
+		if self.section.executable() {
+			return err(segment, errors::Msg::One(format!("Directive {} must be placed in the .data or .kdata section.", format!(".{}", id).red())));
+		}
+
+		self.align(alignment(id));
+
+		let section = self.section;
+		let start = self.offset();
+		let endian = self.object.endian;
+		for child in children {
+			if let ("word", ASTNode::Node(Symbol::Label(label, label_segment))) = (id, child) {
+				let symbol = self.symbol(label);
+				self.object.relocations.push(Relocation {
+					section,
+					offset: self.offset(),
+					kind: RelocationKind::Word32,
+					symbol,
+					addend: 0,
+					segment: Some(label_segment.clone())
+				});
+				self.object.section_mut(section).extend_from_slice(&[0; 4]);
+				continue;
+			}
+
+			let data = self.object.section_mut(section);
+			match (id, child) {
+				("word", ASTNode::Node(Symbol::NumberLiteral(num, _))) => {
+					let mut bytes = [0; 4];
+					endian.write_u32(&mut bytes, *num as u32);
+					data.extend_from_slice(&bytes);
+				},
+				("half", ASTNode::Node(Symbol::NumberLiteral(num, num_segment))) => {
+					let value = range(*num, -32768, 65535, num_segment)?;
+					let mut bytes = [0; 2];
+					endian.write_u16(&mut bytes, value as u16);
+					data.extend_from_slice(&bytes);
+				},
+				("byte", ASTNode::Node(Symbol::NumberLiteral(num, num_segment))) => {
+					let value = range(*num, -128, 255, num_segment)?;
+					data.push(value as u8);
+				},
+				("float", ASTNode::Node(Symbol::FloatLiteral(num, _))) => {
+					let mut bytes = [0; 4];
+					endian.write_u32(&mut bytes, num.parse::<f32>().unwrap_or_default().to_bits());
+					data.extend_from_slice(&bytes);
+				},
+				("double", ASTNode::Node(Symbol::FloatLiteral(num, _))) => {
+					let mut bytes = [0; 8];
+					endian.write_u64(&mut bytes, num.parse::<f64>().unwrap_or_default().to_bits());
+					data.extend_from_slice(&bytes);
+				},
+				("ascii" | "asciiz", ASTNode::Node(Symbol::StringLiteral(string, _))) => {
+					data.extend_from_slice(&unescape(string));
+					if id == "asciiz" {
+						data.push(0);
+					}
+				},
+				("space", ASTNode::Node(Symbol::NumberLiteral(num, num_segment))) => {
+					let len = range(*num, 0, i32::MAX, num_segment)?;
+					data.resize(data.len() + len as usize, 0);
+				},
+				("align", ASTNode::Node(Symbol::NumberLiteral(num, num_segment))) => {
+					let power = range(*num, 0, 15, num_segment)?;
+					while !data.len().is_multiple_of(1 << power) {
+						data.push(0);
+					}
+				},
+				_ => return err(segment, errors::Msg::One(format!("Unknown directive {}.", id.red())))
+			}
+		}
+
+		self.object.lines.push(Line {
+			section,
+			address: start,
+			len: self.offset() - start,
+			segment: segment.clone()
+		});
+		Ok(())
+	}
+
+	fn instruction(&mut self, instruction: Instruction, children: &[ASTNode<Symbol>], segment: &CodeSegment) -> AsmRes<()> {
+		if !self.section.executable() {
+			return err(segment, errors::Msg::One(format!("Instruction {} must be placed in the .text or .ktext section.", instruction.mnemonic().red())));
+		}
+
+		let operands: Vec<Value> = children.iter().map(value).collect();
+		for step in expand::expand(instruction, &operands)? {
+			self.pending().push(Pending {
+				step,
+				segment: Some(segment.clone())
+			});
+		}
+
+		Ok(())
+	}
+
+	// encodes the instructions of an executable section
+	fn encode(&mut self, section: Section, text: &[Pending]) -> AsmRes<()> {
+		let endian = self.object.endian;
+
+		for (idx, pending) in text.iter().enumerate() {
+			let offset = idx as u32 * 4;
+			let mut machine = pending.step.machine;
+
+			if let Some(target) = &pending.step.target {
+				let symbol = self.symbol(&target.label);
+				let local = &self.object.symbols[symbol];
+
+				match (target.kind, local.section) {
+					// branches within the file need no relocation
+					(RelocationKind::Pc16, Some(local_section)) if local_section == section => {
+						let distance = (local.offset as i64 - (offset as i64 + 4)) / 4;
+						if !(-32768..=32767).contains(&distance) {
+							return err(&target.segment, errors::Msg::One(format!("Branch target {} is too far away.", target.label.red())));
+						}
+						machine.immediate = distance as u32 & 0xffff;
+					},
+					_ => {
+						self.object.relocations.push(Relocation {
+							section,
+							offset,
+							kind: target.kind,
+							symbol,
+							addend: 0,
+							segment: Some(target.segment.clone())
+						});
+					}
+				}
+			}
+
+			let mut bytes = [0; 4];
+			endian.write_u32(&mut bytes, encoding::encode(&machine));
+			self.object.section_mut(section).extend_from_slice(&bytes);
+			if let Some(segment) = &pending.segment {
+				self.object.lines.push(Line {
+					section,
+					address: offset,
+					len: 4,
+					segment: segment.clone()
+				});
+			}
+		}
+
+		Ok(())
+	}
+
+	// encodes the text now that every local label is known, and checks the labels declared global
+	fn finish(mut self) -> AsmRes<Object> {
+		let sections = [(Section::Text, std::mem::take(&mut self.text)), (Section::KernelText, std::mem::take(&mut self.ktext))];
+		for (section, text) in sections {
+			self.encode(section, &text)?;
+		}
+
+		for (label, segment) in &self.globals {
+			match self.symbols.get(label).map(|idx| &mut self.object.symbols[*idx]) {
+				Some(symbol) if symbol.section.is_some() => symbol.binding = Binding::Global,
+				_ => return err(segment, errors::Msg::One(format!("Global label {} is never defined.", label.red())))
+			}
+		}
+
+		// labels used but not defined here must come from another file
+		for symbol in &mut self.object.symbols {
+			if symbol.section.is_none() {
+				symbol.binding = Binding::Global;
+			}
+		}
+
+		Ok(self.object)
+	}
+}
+
+fn value(node: &ASTNode<Symbol>) -> Value {
+	match node {
+		ASTNode::Node(Symbol::Register(register, _)) => Value::Register(register.number()),
+		ASTNode::Node(Symbol::FloatRegister(register, segment)) => Value::FloatRegister(register.number(), segment.clone()),
+		ASTNode::Node(Symbol::NumberLiteral(num, segment)) => Value::Immediate(*num, segment.clone()),
+		ASTNode::Node(Symbol::Label(label, segment)) => Value::Label(label.to_string(), segment.clone()),
+		ASTNode::Tree(tree) => {
+			let mut offset = Offset::Number(0);
+			let mut base = None;
+			for child in tree.children() {
+				match child {
+					ASTNode::Node(Symbol::NumberLiteral(num, _)) => offset = Offset::Number(*num),
+					ASTNode::Node(Symbol::Label(label, segment)) => offset = Offset::Label(label.to_string(), segment.clone()),
+					ASTNode::Node(Symbol::Register(register, _)) => base = Some(register.number()),
+					_ => panic!("Illegal address operand {:?}", child)
+				}
+			}
+			Value::Address(offset, base)
+		},
+		_ => panic!("Illegal operand {:?}", node)
+	}
+}
+
+// data directives align their values to their size
+fn alignment(directive: &str) -> usize {
+	match directive {
+		"double" => 8,
+		"word" | "float" => 4,
+		"half" => 2,
+		_ => 1
+	}
+}
+
+fn range(value: i32, min: i32, max: i32, segment: &CodeSegment) -> AsmRes<i32> {
+	if value < min || value > max {
+		return err(segment, errors::Msg::One(format!("Value {} is out of range ({} to {}).", value.to_string().red(), min, max)));
+	}
+
+	Ok(value)
+}
+
+// interprets the escapes the lexer left in a string literal
+fn unescape(string: &str) -> Vec<u8> {
+	let mut bytes = Vec::new();
+	let mut chars = string.chars();
+	while let Some(character) = chars.next() {
+		let character = match character {
+			'\\' => match chars.next() {
+				Some('n') => '\n',
+				Some('t') => '\t',
+				Some('r') => '\r',
+				Some('0') => '\0',
+				Some(other) => other,
+				None => '\\'
+			},
+			_ => character
+		};
+
+		let mut buffer = [0; 4];
+		bytes.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+	}
+
+	bytes
+}
+
+fn err<T>(segment: &CodeSegment, msg: errors::Msg) -> AsmRes<T> {
+	Err(errors::Err {
+		segment: segment.clone(),
+		errtype: errors::ErrType::Assemble,
+		msg
+	})
+}
@@ -0,0 +1,357 @@
+use super::{Value, Offset, Target, Step, AsmRes, range, err};
+
+use crate::encoding::Machine;
+use crate::lexer::tokens::CodeSegment;
+use crate::object::RelocationKind;
+use crate::parse::instructions::Instruction;
+use crate::errors;
+
+use colored::Colorize;
+
+const ZERO: u32 = 0;
+const AT: u32 = 1;	// pseudo-instructions expand through $at
+const RA: u32 = 31;
+
+// the basic instructions making up an instruction, its operands having been checked by the parser
+pub fn expand(instruction: Instruction, operands: &[Value]) -> AsmRes<Vec<Step>> {
+	use Instruction::*;
+
+	let registers: Vec<u32> = operands.iter().filter_map(|operand| match operand {
+		Value::Register(register) | Value::FloatRegister(register, _) => Some(*register),
+		_ => None
+	}).collect();
+	let r = |idx: usize| registers[idx];
+
+	// the first floating point register is the destination, if the instruction has one
+	let (destination, sources) = instruction.doubles();
+	let floats = operands.iter().filter_map(|operand| match operand {
+		Value::FloatRegister(register, segment) => Some((*register, segment)),
+		_ => None
+	});
+	for (idx, (register, segment)) in floats.enumerate() {
+		let double = if idx == 0 { destination } else { sources };
+		if double && register % 2 != 0 {
+			return err(segment, errors::Msg::Many(vec![
+				format!("Register {} cannot hold a double.", format!("$f{}", register).red()),
+				"Doubles are held in an even register and the one following it.".to_string()
+			]));
+		}
+	}
+
+	// the condition flag of the floating point unit, given before the operands it applies to
+	let condition = |operand: &Value| -> AsmRes<u32> {
+		let (cc, cc_segment) = immediate(operand);
+		Ok(range(cc, 0, 7, cc_segment)? as u32)
+	};
+
+	Ok(match instruction {
+		Add | AddUnsigned | Subtract | SubtractUnsigned | And | Or | Xor | Nor |
+		SetLessThan | SetLessThanUnsigned | MoveConditionalZero | MoveConditionalNotZero | MultiplyToRegister =>
+			vec![rtype(instruction, r(0), r(1), r(2))],
+
+		// the shift amount is taken from rs
+		ShiftLeftLogicalVariable | ShiftRightLogicalVariable | ShiftRightArithmeticVariable =>
+			vec![rtype(instruction, r(0), r(2), r(1))],
+
+		ShiftLeftLogical | ShiftRightLogical | ShiftRightArithmetic => {
+			let (shamt, shamt_segment) = immediate(&operands[2]);
+			let mut machine = Machine::new(instruction);
+			machine.rd = r(0);
+			machine.rt = r(1);
+			machine.shamt = range(shamt, 0, 31, shamt_segment)? as u32;
+			vec![plain(machine)]
+		},
+
+		AddImmediate | AddImmediateUnsigned | SetLessThanImmediate | SetLessThanImmediateUnsigned => {
+			let (value, value_segment) = immediate(&operands[2]);
+			vec![itype(instruction, r(0), r(1), range(value, -32768, 32767, value_segment)? as u32)]
+		},
+
+		// logical immediates are zero extended
+		AndImmediate | OrImmediate | XorImmediate => {
+			let (value, value_segment) = immediate(&operands[2]);
+			vec![itype(instruction, r(0), r(1), range(value, 0, 65535, value_segment)? as u32)]
+		},
+
+		LoadUpperImmediate => {
+			let (value, value_segment) = immediate(&operands[1]);
+			vec![itype(instruction, r(0), ZERO, range(value, -32768, 65535, value_segment)? as u32)]
+		},
+
+		Multiply | MultiplyUnsigned | MultiplyAdd | MultiplyAddUnsigned | MultiplySubtract | MultiplySubtractUnsigned =>
+			vec![rtype(instruction, ZERO, r(0), r(1))],
+
+		// the destination must also be given as rt
+		CountLeadingZeros | CountLeadingOnes => vec![rtype(instruction, r(0), r(1), r(0))],
+
+		Divide | DivideUnsigned if registers.len() == 3 => vec![
+			rtype(instruction, ZERO, r(1), r(2)),
+			rtype(MoveFromLo, r(0), ZERO, ZERO)
+		],
+		Divide | DivideUnsigned => vec![rtype(instruction, ZERO, r(0), r(1))],
+
+		MoveFromHi | MoveFromLo => vec![rtype(instruction, r(0), ZERO, ZERO)],
+		MoveToHi | MoveToLo | JumpRegister => vec![rtype(instruction, ZERO, r(0), ZERO)],
+
+		JumpAndLinkRegister if registers.len() == 1 => vec![rtype(instruction, RA, r(0), ZERO)],
+		JumpAndLinkRegister => vec![rtype(instruction, r(0), r(1), ZERO)],
+
+		LoadByte | LoadByteUnsigned | LoadHalf | LoadHalfUnsigned | LoadWord | LoadWordLeft | LoadWordRight | LoadLinked |
+		StoreByte | StoreHalf | StoreWord | StoreWordLeft | StoreWordRight | StoreConditional =>
+			memory(instruction, r(0), &operands[1])?,
+
+		BranchEqual | BranchNotEqual => vec![branch(instruction, r(0), r(1), &operands[2])],
+		BranchLessEqualZero | BranchGreaterThanZero | BranchLessThanZero | BranchGreaterEqualZero |
+		BranchLessThanZeroAndLink | BranchGreaterEqualZeroAndLink => vec![branch(instruction, r(0), ZERO, &operands[1])],
+
+		Jump | JumpAndLink => {
+			let (label, label_segment) = label(&operands[0]);
+			vec![Step {
+				machine: Machine::new(instruction),
+				target: Some(target(RelocationKind::Mips26, label, label_segment))
+			}]
+		},
+
+		SystemCall => vec![plain(Machine::new(instruction))],
+		Break => {
+			let mut machine = Machine::new(instruction);
+			if let Some(operand) = operands.first() {
+				let (code, code_segment) = immediate(operand);
+				machine.immediate = range(code, 0, 0xfffff, code_segment)? as u32;
+			}
+			vec![plain(machine)]
+		},
+
+		TrapEqual | TrapNotEqual | TrapGreaterEqual | TrapGreaterEqualUnsigned | TrapLessThan | TrapLessThanUnsigned =>
+			vec![rtype(instruction, ZERO, r(0), r(1))],
+		TrapEqualImmediate | TrapNotEqualImmediate | TrapGreaterEqualImmediate | TrapGreaterEqualImmediateUnsigned |
+		TrapLessThanImmediate | TrapLessThanImmediateUnsigned => {
+			let (value, value_segment) = immediate(&operands[1]);
+			vec![itype(instruction, ZERO, r(0), range(value, -32768, 32767, value_segment)? as u32)]
+		},
+
+		// the general register is given first for both directions
+		MoveFromCoprocessor0 | MoveToCoprocessor0 => vec![rtype(instruction, r(1), ZERO, r(0))],
+		ExceptionReturn => vec![plain(Machine::new(instruction))],
+
+		AddSingle | AddDouble | SubtractSingle | SubtractDouble |
+		MultiplySingle | MultiplyDouble | DivideSingle | DivideDouble => vec![float(instruction, r(0), r(1), r(2))],
+
+		AbsoluteSingle | AbsoluteDouble | NegateSingle | NegateDouble |
+		MoveSingle | MoveDouble | SquareRootSingle | SquareRootDouble |
+		ConvertSingleDouble | ConvertSingleWord | ConvertDoubleSingle | ConvertDoubleWord | ConvertWordSingle | ConvertWordDouble |
+		TruncateWordSingle | TruncateWordDouble | RoundWordSingle | RoundWordDouble |
+		FloorWordSingle | FloorWordDouble | CeilWordSingle | CeilWordDouble => vec![float(instruction, r(0), r(1), ZERO)],
+
+		CompareEqualSingle | CompareEqualDouble | CompareLessThanSingle | CompareLessThanDouble |
+		CompareLessEqualSingle | CompareLessEqualDouble => {
+			let cc = if operands.len() == 3 { condition(&operands[0])? } else { 0 };
+			vec![float(instruction, cc << 2, r(0), r(1))]
+		},
+
+		BranchFloatTrue | BranchFloatFalse => {
+			let cc = if operands.len() == 2 { condition(&operands[0])? } else { 0 };
+			vec![branch(instruction, ZERO, cc << 2, &operands[operands.len() - 1])]
+		},
+
+		MoveOnFalse | MoveOnTrue => {
+			let cc = if operands.len() == 3 { condition(&operands[2])? } else { 0 };
+			vec![rtype(instruction, r(0), r(1), cc << 2)]
+		},
+		MoveOnFalseSingle | MoveOnFalseDouble | MoveOnTrueSingle | MoveOnTrueDouble => {
+			let cc = if operands.len() == 3 { condition(&operands[2])? } else { 0 };
+			vec![float(instruction, r(0), r(1), cc << 2)]
+		},
+
+		// the general register is given first for both directions
+		MoveToFloat | MoveFromFloat | MoveControlToFloat | MoveControlFromFloat => vec![rtype(instruction, r(1), ZERO, r(0))],
+
+		LoadWordFloat | StoreWordFloat | LoadDoubleFloat | StoreDoubleFloat => memory(instruction, r(0), &operands[1])?,
+
+		// pseudo-instructions
+		LoadSingle => memory(LoadWordFloat, r(0), &operands[1])?,
+		StoreSingle => memory(StoreWordFloat, r(0), &operands[1])?,
+		LoadDouble => memory(LoadDoubleFloat, r(0), &operands[1])?,
+		StoreDouble => memory(StoreDoubleFloat, r(0), &operands[1])?,
+
+		NoOperation => vec![plain(Machine::new(ShiftLeftLogical))],
+		Move => vec![rtype(AddUnsigned, r(0), r(1), ZERO)],
+		Negate => vec![rtype(Subtract, r(0), ZERO, r(1))],
+		Not => vec![rtype(Nor, r(0), r(1), ZERO)],
+		Remainder => vec![
+			rtype(Divide, ZERO, r(1), r(2)),
+			rtype(MoveFromHi, r(0), ZERO, ZERO)
+		],
+
+		LoadImmediate => {
+			let (value, _) = immediate(&operands[1]);
+			load_immediate(r(0), value)
+		},
+
+		LoadAddress => match &operands[1] {
+			Value::Label(name, label_segment) => absolute(AddImmediateUnsigned, r(0), None, name, label_segment),
+			Value::Immediate(value, _) => load_immediate(r(0), *value),
+			Value::Address(Offset::Label(name, label_segment), base) => {
+				let mut steps = absolute(AddImmediateUnsigned, AT, None, name, label_segment);
+				steps.push(rtype(AddUnsigned, r(0), AT, base.unwrap_or(ZERO)));
+				steps
+			},
+			Value::Address(Offset::Number(value), base) => {
+				let base = base.unwrap_or(ZERO);
+				if fits(*value) {
+					vec![itype(AddImmediateUnsigned, r(0), base, *value as u32)]
+				} else {
+					let mut steps = load_immediate(AT, *value);
+					steps.push(rtype(AddUnsigned, r(0), AT, base));
+					steps
+				}
+			},
+			Value::Register(_) | Value::FloatRegister(_, _) => unreachable!()
+		},
+
+		Branch => vec![branch(BranchEqual, ZERO, ZERO, &operands[0])],
+		BranchEqualZero => vec![branch(BranchEqual, r(0), ZERO, &operands[1])],
+		BranchNotEqualZero => vec![branch(BranchNotEqual, r(0), ZERO, &operands[1])],
+
+		// compared with slt(u) into $at, with the operands swapped for > and <=
+		BranchLessThan | BranchGreaterThan | BranchLessEqual | BranchGreaterEqual |
+		BranchLessThanUnsigned | BranchGreaterThanUnsigned | BranchLessEqualUnsigned | BranchGreaterEqualUnsigned => {
+			let compare = match instruction {
+				BranchLessThan | BranchGreaterThan | BranchLessEqual | BranchGreaterEqual => SetLessThan,
+				_ => SetLessThanUnsigned
+			};
+			let (swap, taken) = match instruction {
+				BranchLessThan | BranchLessThanUnsigned => (false, BranchNotEqual),
+				BranchGreaterThan | BranchGreaterThanUnsigned => (true, BranchNotEqual),
+				BranchLessEqual | BranchLessEqualUnsigned => (true, BranchEqual),
+				_ => (false, BranchEqual)
+			};
+			let (left, right) = if swap { (r(1), r(0)) } else { (r(0), r(1)) };
+
+			vec![
+				rtype(compare, AT, left, right),
+				branch(taken, AT, ZERO, &operands[2])
+			]
+		}
+	})
+}
+
+fn plain(machine: Machine) -> Step {
+	Step { machine, target: None }
+}
+
+fn rtype(instruction: Instruction, rd: u32, rs: u32, rt: u32) -> Step {
+	let mut machine = Machine::new(instruction);
+	machine.rd = rd;
+	machine.rs = rs;
+	machine.rt = rt;
+	plain(machine)
+}
+
+// fd, fs and ft being held in shamt, rd and rt
+fn float(instruction: Instruction, fd: u32, fs: u32, ft: u32) -> Step {
+	let mut machine = Machine::new(instruction);
+	machine.shamt = fd;
+	machine.rd = fs;
+	machine.rt = ft;
+	plain(machine)
+}
+
+fn itype(instruction: Instruction, rt: u32, rs: u32, immediate: u32) -> Step {
+	let mut machine = Machine::new(instruction);
+	machine.rt = rt;
+	machine.rs = rs;
+	machine.immediate = immediate & 0xffff;
+	plain(machine)
+}
+
+fn branch(instruction: Instruction, rs: u32, rt: u32, operand: &Value) -> Step {
+	let (label, label_segment) = label(operand);
+	let mut step = itype(instruction, rt, rs, 0);
+	step.target = Some(target(RelocationKind::Pc16, label, label_segment));
+	step
+}
+
+fn target(kind: RelocationKind, label: &str, segment: &CodeSegment) -> Target {
+	Target {
+		kind,
+		label: label.to_string(),
+		segment: segment.clone()
+	}
+}
+
+fn fits(value: i32) -> bool {
+	(-32768..=32767).contains(&value)
+}
+
+// the upper half of a value, rounded so that adding the sign extended lower half gives the value
+fn high(value: i32) -> u32 {
+	(value as u32).wrapping_add(0x8000) >> 16
+}
+
+fn load_immediate(rt: u32, value: i32) -> Vec<Step> {
+	if fits(value) {
+		vec![itype(Instruction::AddImmediateUnsigned, rt, ZERO, value as u32)]
+	} else if (0..=65535).contains(&value) {
+		vec![itype(Instruction::OrImmediate, rt, ZERO, value as u32)]
+	} else {
+		vec![
+			itype(Instruction::LoadUpperImmediate, AT, ZERO, value as u32 >> 16),
+			itype(Instruction::OrImmediate, rt, AT, value as u32)
+		]
+	}
+}
+
+// lui $at, %hi(label), then the instruction using %lo(label)($at), offset by a base register if given
+fn absolute(instruction: Instruction, rt: u32, base: Option<u32>, label: &str, segment: &CodeSegment) -> Vec<Step> {
+	let mut upper = itype(Instruction::LoadUpperImmediate, AT, ZERO, 0);
+	upper.target = Some(target(RelocationKind::Hi16, label, segment));
+
+	let mut steps = vec![upper];
+	if let Some(base) = base {
+		steps.push(rtype(Instruction::AddUnsigned, AT, AT, base));
+	}
+
+	let mut lower = itype(instruction, rt, AT, 0);
+	lower.target = Some(target(RelocationKind::Lo16, label, segment));
+	steps.push(lower);
+	steps
+}
+
+fn memory(instruction: Instruction, rt: u32, operand: &Value) -> AsmRes<Vec<Step>> {
+	Ok(match operand {
+		Value::Label(name, segment) => absolute(instruction, rt, None, name, segment),
+		Value::Address(Offset::Label(name, segment), base) => absolute(instruction, rt, *base, name, segment),
+		Value::Immediate(value, _) => offset(instruction, rt, ZERO, *value),
+		Value::Address(Offset::Number(value), base) => offset(instruction, rt, base.unwrap_or(ZERO), *value),
+		Value::Register(_) | Value::FloatRegister(_, _) => unreachable!()
+	})
+}
+
+// offsets beyond 16 bits are added to the base in $at
+fn offset(instruction: Instruction, rt: u32, base: u32, value: i32) -> Vec<Step> {
+	if fits(value) {
+		return vec![itype(instruction, rt, base, value as u32)];
+	}
+
+	let mut steps = vec![itype(Instruction::LoadUpperImmediate, AT, ZERO, high(value))];
+	if base != ZERO {
+		steps.push(rtype(Instruction::AddUnsigned, AT, AT, base));
+	}
+	steps.push(itype(instruction, rt, AT, value as u32));
+	steps
+}
+
+fn immediate(operand: &Value) -> (i32, &CodeSegment) {
+	match operand {
+		Value::Immediate(value, segment) => (*value, segment),
+		_ => unreachable!()
+	}
+}
+
+fn label(operand: &Value) -> (&str, &CodeSegment) {
+	match operand {
+		Value::Label(label, segment) => (label, segment),
+		_ => unreachable!()
+	}
+}
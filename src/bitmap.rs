@@ -0,0 +1,290 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::mips;
+use crate::runtime::{self, memory::Memory};
+
+// the largest framebuffer, and the most pixels an image of the display may have
+const MAX_FRAMEBUFFER: u64 = 4 << 20;
+const MAX_PIXELS: u64 = 4096 * 4096;
+
+// the bitmap display of MARS, whose framebuffer holds a word of 0x00RRGGBB for each unit, a row at a time
+#[derive(Clone, Copy, Debug)]
+pub struct Display {
+	pub unit: (u32, u32),	// pixels of each unit
+	pub size: (u32, u32),	// pixels of the display
+	pub base: u32			// of the framebuffer
+}
+
+impl Display {
+	pub fn new(unit: (u32, u32), size: (u32, u32), base: u32) -> Result<Display, String> {
+		if !size.0.is_multiple_of(unit.0) || !size.1.is_multiple_of(unit.1) {
+			return Err(format!("the display of {}x{} pixels cannot be divided into units of {}x{}", size.0, size.1, unit.0, unit.1));
+		}
+		if !base.is_multiple_of(4) {
+			return Err(format!("the framebuffer at {:#010x} is not aligned to a word", base));
+		}
+		if size.0 as u64 * size.1 as u64 > MAX_PIXELS {
+			return Err(format!("the display of {}x{} pixels is larger than the 4096x4096 allowed", size.0, size.1));
+		}
+
+		let display = Display { unit, size, base };
+		let bytes = display.columns() as u64 * display.rows() as u64 * 4;
+		if bytes > MAX_FRAMEBUFFER {
+			return Err(format!("the framebuffer of {}x{} units takes {} bytes, more than the 4MB allowed", display.columns(), display.rows(), bytes));
+		}
+		if base as u64 + bytes > 1 << 32 {
+			return Err(format!("the framebuffer of {} bytes at {:#010x} runs past the end of memory", bytes, base));
+		}
+		Ok(display)
+	}
+
+	// units across and down
+	pub fn columns(&self) -> u32 {
+		self.size.0 / self.unit.0
+	}
+
+	pub fn rows(&self) -> u32 {
+		self.size.1 / self.unit.1
+	}
+
+	// the colour of every unit
+	pub fn units(&self, memory: &Memory) -> Vec<u32> {
+		(0..self.columns() * self.rows())
+			.map(|idx| memory.read_u32(self.base.wrapping_add(idx * 4)) & 0xffffff)
+			.collect()
+	}
+
+	// the display as a row of pixels at a time, each unit drawn at its full size
+	fn pixels(&self, units: &[u32]) -> Vec<u8> {
+		let mut rgb = Vec::with_capacity((self.size.0 * self.size.1 * 3) as usize);
+		for y in 0..self.size.1 {
+			let row = (y / self.unit.1) * self.columns();
+			for x in 0..self.size.0 {
+				let colour = units[(row + x / self.unit.0) as usize];
+				rgb.extend_from_slice(&[(colour >> 16) as u8, (colour >> 8) as u8, colour as u8]);
+			}
+		}
+		rgb
+	}
+
+	// two units to a character, the upper drawn in the foreground of a half block and the lower in its background
+	pub fn half_blocks(&self, units: &[u32]) -> String {
+		let colour = |colour: u32| format!("{};{};{}", (colour >> 16) & 0xff, (colour >> 8) & 0xff, colour & 0xff);
+
+		let mut out = String::new();
+		for y in (0..self.rows()).step_by(2) {
+			for x in 0..self.columns() {
+				let upper = units[(y * self.columns() + x) as usize];
+				let lower = match y + 1 < self.rows() {
+					true => units[((y + 1) * self.columns() + x) as usize],
+					false => 0
+				};
+				let _ = write!(out, "\x1b[38;2;{}m\x1b[48;2;{}m\u{2580}", colour(upper), colour(lower));
+			}
+			out.push_str("\x1b[0m\n");
+		}
+		out
+	}
+
+	// an image of the display, as a PNG or else as a binary PPM, by the extension of the file
+	pub fn image(&self, units: &[u32], path: &Path) -> Vec<u8> {
+		let rgb = self.pixels(units);
+		match path.extension().and_then(|extension| extension.to_str()) {
+			Some(extension) if extension.eq_ignore_ascii_case("ppm") => ppm(self.size, &rgb),
+			_ => png(self.size, &rgb)
+		}
+	}
+}
+
+// the framebuffer may be placed by the name MARS gives to these addresses
+pub fn parse_base(arg: &str) -> Result<u32, String> {
+	let base = match arg.trim_start_matches('$') {
+		"gp" => runtime::GLOBAL_POINTER,
+		"global" => 0x10000000,
+		"data" => mips::DATA_BASE,
+		"heap" => runtime::HEAP_BASE,
+		"mmio" => runtime::mmio::RECEIVER_CONTROL,
+		_ => {
+			let parsed = match arg.strip_prefix("0x") {
+				Some(hex) => u32::from_str_radix(hex, 16),
+				None => arg.parse()
+			};
+			return parsed.map_err(|_| format!("\"{}\" is not an address, or one of gp, global, data, heap or mmio", arg));
+		}
+	};
+	Ok(base)
+}
+
+// a size such as 512x256
+pub fn parse_size(arg: &str) -> Result<(u32, u32), String> {
+	let size = arg.split_once('x').and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+	match size {
+		Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+		_ => Err(format!("\"{}\" is not a size such as 512x256", arg))
+	}
+}
+
+// shows the display and writes its frames as the program runs
+pub struct Recorder {
+	pub display: Display,
+	pub path: Option<PathBuf>,	// the image written at exit, with the frames before it numbered alongside
+	pub show: Option<Box<dyn io::Write>>,	// where the display is drawn as text, such as the terminal
+	frames: u32,
+	last: Vec<u32>,
+	drawn: bool
+}
+
+impl Recorder {
+	pub fn new(display: Display, path: Option<PathBuf>, show: Option<Box<dyn io::Write>>) -> Recorder {
+		Recorder { display, path, show, frames: 0, last: Vec::new(), drawn: false }
+	}
+
+	// a frame, as long as the display changed since the last one
+	pub fn frame(&mut self, memory: &Memory) -> io::Result<()> {
+		let units = self.display.units(memory);
+		if units == self.last {
+			return Ok(());
+		}
+
+		self.frames += 1;
+		if let Some(path) = &self.path {
+			let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+			let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+			let numbered = path.with_file_name(format!("{}-{:04}{}", stem, self.frames, extension));
+			fs::write(&numbered, self.display.image(&units, &numbered))?;
+		}
+		self.draw(&units)?;
+
+		self.last = units;
+		Ok(())
+	}
+
+	// the display as the program left it
+	pub fn finish(&mut self, memory: &Memory) -> io::Result<()> {
+		let units = self.display.units(memory);
+		if let Some(path) = &self.path {
+			fs::write(path, self.display.image(&units, path))?;
+		}
+		if units != self.last {
+			self.draw(&units)?;
+		}
+		Ok(())
+	}
+
+	// over the last frame drawn
+	fn draw(&mut self, units: &[u32]) -> io::Result<()> {
+		if let Some(out) = &mut self.show {
+			if self.drawn {
+				write!(out, "\x1b[{}A", self.display.rows().div_ceil(2))?;
+			}
+			write!(out, "{}", self.display.half_blocks(units))?;
+			out.flush()?;
+			self.drawn = true;
+		}
+		Ok(())
+	}
+}
+
+fn ppm(size: (u32, u32), rgb: &[u8]) -> Vec<u8> {
+	let mut out = format!("P6\n{} {}\n255\n", size.0, size.1).into_bytes();
+	out.extend_from_slice(rgb);
+	out
+}
+
+// 8 bit RGB, compressed with stored deflate blocks only
+fn png(size: (u32, u32), rgb: &[u8]) -> Vec<u8> {
+	let mut raw = Vec::with_capacity(rgb.len() + size.1 as usize);
+	for row in rgb.chunks((size.0 * 3) as usize) {
+		raw.push(0);	// no filter
+		raw.extend_from_slice(row);
+	}
+
+	let mut zlib = vec![0x78, 0x01];
+	let blocks: Vec<&[u8]> = raw.chunks(0xffff).collect();
+	for (idx, block) in blocks.iter().enumerate() {
+		let len = block.len() as u16;
+		zlib.push((idx + 1 == blocks.len()) as u8);
+		zlib.extend_from_slice(&len.to_le_bytes());
+		zlib.extend_from_slice(&(!len).to_le_bytes());
+		zlib.extend_from_slice(block);
+	}
+	zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+	let mut header = Vec::new();
+	header.extend_from_slice(&size.0.to_be_bytes());
+	header.extend_from_slice(&size.1.to_be_bytes());
+	header.extend_from_slice(&[8, 2, 0, 0, 0]);	// bit depth, RGB, compression, filter and interlace methods
+
+	let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+	chunk(&mut out, b"IHDR", &header);
+	chunk(&mut out, b"IDAT", &zlib);
+	chunk(&mut out, b"IEND", &[]);
+	out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+	out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+	let start = out.len();
+	out.extend_from_slice(kind);
+	out.extend_from_slice(data);
+	let crc = crc32(&out[start..]);
+	out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for byte in bytes {
+		crc ^= *byte as u32;
+		for _ in 0..8 {
+			crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+		}
+	}
+	!crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+	for byte in bytes {
+		a = (a + *byte as u32) % 65521;
+		b = (b + a) % 65521;
+	}
+	b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn refuses_displays_which_do_not_fit() {
+		assert!(Display::new((1, 1), (512, 256), 0x10010000).is_ok());
+		assert!(Display::new((8, 8), (4096, 4096), 0x10010000).is_ok());
+		assert!(Display::new((3, 1), (512, 256), 0x10010000).is_err());
+		assert!(Display::new((1, 1), (512, 256), 0x10010002).is_err());
+
+		// more pixels than any image should have, even with few units
+		assert!(Display::new((1000, 1000), (100000, 100000), 0x10010000).is_err());
+		// a framebuffer larger than allowed, and one running off the end of memory
+		assert!(Display::new((1, 1), (2048, 1024), 0x10010000).is_err());
+		assert!(Display::new((1, 1), (512, 256), 0xfffffc00).is_err());
+		assert!(Display::new((1, 1), (16, 16), 0xfffffc00).is_ok());
+	}
+
+	#[test]
+	fn draws_each_unit_at_its_size() {
+		let display = Display::new((2, 1), (4, 2), 0x10010000).unwrap();
+		let mut memory = Memory::new(mips::Endian::Little);
+		for (idx, colour) in [0xff0000, 0x00ff00, 0x0000ff, 0xff123456u32].into_iter().enumerate() {
+			memory.write_u32(0x10010000 + idx as u32 * 4, colour);
+		}
+		let units = display.units(&memory);
+		assert_eq!(units, [0xff0000, 0x00ff00, 0x0000ff, 0x123456]);
+		assert_eq!(display.pixels(&units), [
+			255, 0, 0, 255, 0, 0, 0, 255, 0, 0, 255, 0,
+			0, 0, 255, 0, 0, 255, 0x12, 0x34, 0x56, 0x12, 0x34, 0x56
+		]);
+	}
+}
@@ -0,0 +1,452 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::runtime::Machine;
+
+// cycles taken by main memory, by default
+pub const MEMORY_TIME: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+	Lru,
+	Fifo,
+	Random
+}
+
+// the shape and policies of a cache, written as `size=4k,block=16,ways=2,policy=lru,write=back,allocate=yes,hit=1`
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+	pub size: u32,			// bytes
+	pub block: u32,			// bytes
+	pub ways: u32,			// blocks to a set, as many as the cache holds when fully associative
+	pub policy: Policy,
+	pub write_back: bool,	// otherwise writes go through to the next level as they happen
+	pub allocate: bool,		// a write miss brings its block into the cache
+	pub hit: u32			// cycles taken by a hit
+}
+
+impl Config {
+	pub fn parse(arg: &str) -> Result<Config, String> {
+		let mut config = Config { size: 4096, block: 16, ways: 1, policy: Policy::Lru, write_back: true, allocate: true, hit: 1 };
+		let mut full = false;
+
+		for option in arg.split(',').filter(|option| !option.is_empty()) {
+			let Some((key, value)) = option.split_once('=') else {
+				return Err(format!("\"{}\" is not an option such as size=4k", option));
+			};
+			let number = || -> Result<u32, String> {
+				let (digits, scale) = match value.strip_suffix(['k', 'K']) {
+					Some(digits) => (digits, 1024),
+					None => (value, 1)
+				};
+				digits.parse::<u32>().ok().and_then(|n| n.checked_mul(scale)).ok_or(format!("\"{}\" is not a number for {}", value, key))
+			};
+			let choice = |yes: &str, no: &str| -> Result<bool, String> {
+				match value {
+					_ if value == yes => Ok(true),
+					_ if value == no => Ok(false),
+					_ => Err(format!("{} is either {} or {}, not \"{}\"", key, yes, no, value))
+				}
+			};
+
+			match key {
+				"size" => config.size = number()?,
+				"block" => config.block = number()?,
+				"ways" if value == "full" => full = true,
+				"ways" => config.ways = number()?,
+				"policy" => config.policy = match value {
+					"lru" => Policy::Lru,
+					"fifo" => Policy::Fifo,
+					"random" => Policy::Random,
+					_ => return Err(format!("policy is lru, fifo or random, not \"{}\"", value))
+				},
+				"write" => config.write_back = choice("back", "through")?,
+				"allocate" => config.allocate = choice("yes", "no")?,
+				"hit" => config.hit = number()?,
+				_ => return Err(format!("\"{}\" is not one of size, block, ways, policy, write, allocate or hit", key))
+			}
+		}
+
+		if !config.size.is_power_of_two() || !config.block.is_power_of_two() || config.block < 4 || config.block > config.size {
+			return Err("the size and block size must be powers of two, with blocks of at least 4 bytes".to_string());
+		}
+		if full {
+			config.ways = config.size / config.block;
+		}
+		if config.ways == 0 || !(config.size / config.block).is_multiple_of(config.ways) || !(config.size / config.block / config.ways).is_power_of_two() {
+			return Err(format!("{} blocks cannot be divided into sets of {} ways", config.size / config.block, config.ways));
+		}
+		Ok(config)
+	}
+
+	fn blocks(&self) -> u32 {
+		self.size / self.block
+	}
+
+	fn sets(&self) -> u32 {
+		self.blocks() / self.ways
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Line {
+	valid: bool,
+	dirty: bool,
+	tag: u32,
+	used: u64,		// when last accessed, for lru
+	loaded: u64		// when brought in, for fifo
+}
+
+// what a level passes on to the one below it
+#[derive(Debug, Default)]
+struct Lookup {
+	fill: bool,				// the block is read in
+	through: bool,			// the write goes through
+	evicted: Option<u32>	// a dirty block written back, by its address
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+	reads: u64,
+	writes: u64,
+	hits: u64,
+	compulsory: u64,	// the block was never accessed before
+	capacity: u64,		// a fully associative cache of the same size would also have missed
+	conflict: u64,		// every other miss, caused by too many blocks mapping to the same set
+	write_backs: u64
+}
+
+pub struct Cache {
+	name: &'static str,
+	config: Config,
+	sets: Vec<Vec<Line>>,
+	clock: u64,
+	random: u32,
+	seen: HashSet<u32>,
+	// a fully associative lru cache of the same size, by the last use of each block and its blocks by last use
+	shadow: HashMap<u32, u64>,
+	shadow_order: BTreeMap<u64, u32>,
+	stats: Stats
+}
+
+impl Cache {
+	pub fn new(name: &'static str, config: Config) -> Cache {
+		Cache {
+			name,
+			config,
+			sets: vec![vec![Line::default(); config.ways as usize]; config.sets() as usize],
+			clock: 0,
+			random: 0x2545f491,
+			seen: HashSet::new(),
+			shadow: HashMap::new(),
+			shadow_order: BTreeMap::new(),
+			stats: Stats::default()
+		}
+	}
+
+	fn lookup(&mut self, address: u32, write: bool) -> Lookup {
+		let block = address / self.config.block;
+		let (set, tag) = ((block % self.config.sets()) as usize, block / self.config.sets());
+		let allocate = !write || self.config.allocate;
+		let through = write && !self.config.write_back;
+
+		self.clock += 1;
+		match write {
+			true => self.stats.writes += 1,
+			false => self.stats.reads += 1
+		}
+		let shadow_hit = self.shadow(block, allocate);
+
+		let clock = self.clock;
+		if let Some(line) = self.sets[set].iter_mut().find(|line| line.valid && line.tag == tag) {
+			self.stats.hits += 1;
+			line.used = clock;
+			line.dirty |= write && self.config.write_back;
+			return Lookup { through, ..Lookup::default() };
+		}
+
+		if self.seen.insert(block) {
+			self.stats.compulsory += 1;
+		} else if !shadow_hit {
+			self.stats.capacity += 1;
+		} else {
+			self.stats.conflict += 1;
+		}
+
+		if !allocate {
+			return Lookup { through: true, ..Lookup::default() };
+		}
+
+		let way = self.victim(set);
+		let line = &mut self.sets[set][way];
+		let evicted = (line.valid && line.dirty).then(|| (line.tag * self.config.sets() + set as u32) * self.config.block);
+		if evicted.is_some() {
+			self.stats.write_backs += 1;
+		}
+		*line = Line { valid: true, dirty: write && self.config.write_back, tag, used: clock, loaded: clock };
+
+		Lookup { fill: true, through, evicted }
+	}
+
+	// the way to replace in a set, preferring an empty one
+	fn victim(&mut self, set: usize) -> usize {
+		let lines = &self.sets[set];
+		if let Some(way) = lines.iter().position(|line| !line.valid) {
+			return way;
+		}
+
+		let oldest = |key: fn(&Line) -> u64| (0..lines.len()).min_by_key(|way| key(&lines[*way])).unwrap_or(0);
+		match self.config.policy {
+			Policy::Lru => oldest(|line| line.used),
+			Policy::Fifo => oldest(|line| line.loaded),
+			Policy::Random => {
+				// xorshift, seeded the same every run so results can be reproduced
+				self.random ^= self.random << 13;
+				self.random ^= self.random >> 17;
+				self.random ^= self.random << 5;
+				self.random as usize % lines.len()
+			}
+		}
+	}
+
+	// accesses the fully associative cache used to tell capacity misses from conflict misses, returning whether it hit
+	fn shadow(&mut self, block: u32, allocate: bool) -> bool {
+		let hit = match self.shadow.get(&block) {
+			Some(used) => {
+				self.shadow_order.remove(used);
+				true
+			},
+			None if !allocate => return false,
+			None => {
+				if self.shadow.len() as u32 == self.config.blocks() {
+					if let Some((_, oldest)) = self.shadow_order.pop_first() {
+						self.shadow.remove(&oldest);
+					}
+				}
+				false
+			}
+		};
+		self.shadow.insert(block, self.clock);
+		self.shadow_order.insert(self.clock, block);
+		hit
+	}
+
+	fn misses(&self) -> u64 {
+		self.stats.reads + self.stats.writes - self.stats.hits
+	}
+
+	fn miss_rate(&self) -> f64 {
+		self.misses() as f64 / (self.stats.reads + self.stats.writes).max(1) as f64
+	}
+
+	fn describe(&self) -> String {
+		let ways = match self.config.ways == self.config.blocks() {
+			true => "fully associative".to_string(),
+			false => format!("{}-way", self.config.ways)
+		};
+		let policy = match self.config.policy {
+			Policy::Lru => "LRU",
+			Policy::Fifo => "FIFO",
+			Policy::Random => "random"
+		};
+		format!("{} bytes, {} byte blocks, {}, {}, write-{}, {}",
+			self.config.size, self.config.block, ways, policy,
+			if self.config.write_back { "back" } else { "through" },
+			if self.config.allocate { "write-allocate" } else { "no-write-allocate" })
+	}
+
+	// the valid blocks of each set, with their tags
+	fn contents(&self) -> String {
+		let mut out = String::new();
+		for (idx, set) in self.sets.iter().enumerate() {
+			if !set.iter().any(|line| line.valid) {
+				continue;
+			}
+			let _ = write!(out, "  set {:4}:", idx);
+			for line in set {
+				match line.valid {
+					true => {
+						let address = (line.tag * self.config.sets() + idx as u32) * self.config.block;
+						let _ = write!(out, " [{:#010x}{}]", address, if line.dirty { " dirty" } else { "" });
+					},
+					false => out.push_str(" [empty]")
+				}
+			}
+			out.push('\n');
+		}
+		out
+	}
+}
+
+// separate instruction and data caches, either of which may be left out, in front of an optional unified second level
+pub struct Hierarchy {
+	pub instruction: Option<Cache>,
+	pub data: Option<Cache>,
+	pub unified: Option<Cache>,
+	pub memory_time: u32,
+	memory_reads: u64,
+	memory_writes: u64
+}
+
+impl Hierarchy {
+	pub fn new(instruction: Option<Config>, data: Option<Config>, unified: Option<Config>, memory_time: u32) -> Hierarchy {
+		Hierarchy {
+			instruction: instruction.map(|config| Cache::new("L1I", config)),
+			data: data.map(|config| Cache::new("L1D", config)),
+			unified: unified.map(|config| Cache::new("L2", config)),
+			memory_time,
+			memory_reads: 0,
+			memory_writes: 0
+		}
+	}
+
+	// the fetch and any load or store of the instruction the machine last executed
+	pub fn retire(&mut self, machine: &Machine) {
+		let Some(retired) = machine.retired else { return };
+		let lookup = self.instruction.as_mut().map(|cache| cache.lookup(retired.pc, false));
+		self.pass(lookup, retired.pc, false);
+
+		if let Some((address, store)) = retired.access {
+			let lookup = self.data.as_mut().map(|cache| cache.lookup(address, store));
+			self.pass(lookup, address, store);
+		}
+	}
+
+	// hands what the first level missed, wrote through or evicted to the second, or straight to memory without one
+	fn pass(&mut self, lookup: Option<Lookup>, address: u32, write: bool) {
+		let lookup = match lookup {
+			Some(lookup) => lookup,
+			None => Lookup { fill: !write, through: write, evicted: None }
+		};
+		if lookup.fill {
+			self.below(address, false);
+		}
+		if lookup.through {
+			self.below(address, true);
+		}
+		if let Some(evicted) = lookup.evicted {
+			self.below(evicted, true);
+		}
+	}
+
+	fn below(&mut self, address: u32, write: bool) {
+		let lookup = match &mut self.unified {
+			Some(cache) => cache.lookup(address, write),
+			None => Lookup { fill: !write, through: write, evicted: None }
+		};
+		self.memory_reads += lookup.fill as u64;
+		self.memory_writes += lookup.through as u64 + lookup.evicted.is_some() as u64;
+	}
+
+	// the hits and misses of each cache, and the average time taken by each access of the first level
+	pub fn report(&self, contents: bool) -> String {
+		let below = match &self.unified {
+			Some(cache) => cache.config.hit as f64 + cache.miss_rate() * self.memory_time as f64,
+			None => self.memory_time as f64
+		};
+
+		let mut out = String::new();
+		for cache in [&self.instruction, &self.data, &self.unified].into_iter().flatten() {
+			let stats = &cache.stats;
+			let accesses = stats.reads + stats.writes;
+			let _ = writeln!(out, "{}: {}", cache.name, cache.describe());
+			let _ = writeln!(out, "  {} accesses ({} reads, {} writes), {} hits, {} misses, {:.2}% hit rate",
+				accesses, stats.reads, stats.writes, stats.hits, cache.misses(), 100.0 * (1.0 - cache.miss_rate()));
+			let _ = writeln!(out, "  misses: {} compulsory, {} capacity, {} conflict; {} write-backs",
+				stats.compulsory, stats.capacity, stats.conflict, stats.write_backs);
+
+			let penalty = match cache.name {
+				"L2" => self.memory_time as f64,
+				_ => below
+			};
+			let _ = writeln!(out, "  average memory access time {:.2} cycles ({} hit + {:.4} miss rate x {:.2} penalty)",
+				cache.config.hit as f64 + cache.miss_rate() * penalty, cache.config.hit, cache.miss_rate(), penalty);
+			if contents {
+				out.push_str(&cache.contents());
+			}
+		}
+		let _ = writeln!(out, "memory: {} reads, {} writes of blocks or words, {} cycles each", self.memory_reads, self.memory_writes, self.memory_time);
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn shaped(config: &str) -> Cache {
+		Cache::new("test", Config::parse(config).unwrap())
+	}
+
+	// whether each access hit, by the hits counted
+	fn hits(cache: &mut Cache, accesses: &[(u32, bool)]) -> Vec<bool> {
+		accesses.iter().map(|&(address, write)| {
+			let before = cache.stats.hits;
+			cache.lookup(address, write);
+			cache.stats.hits > before
+		}).collect()
+	}
+
+	// compulsory, capacity and conflict misses, and write backs
+	fn misses(cache: &Cache) -> [u64; 4] {
+		[cache.stats.compulsory, cache.stats.capacity, cache.stats.conflict, cache.stats.write_backs]
+	}
+
+	#[test]
+	fn parses_the_shape_of_a_cache() {
+		let config = Config::parse("size=1k,block=32,ways=full,policy=fifo,write=through,allocate=no,hit=2").unwrap();
+		assert_eq!((config.size, config.block, config.ways, config.policy), (1024, 32, 32, Policy::Fifo));
+		assert_eq!((config.write_back, config.allocate, config.hit), (false, false, 2));
+		assert_eq!(Config::parse("size=4k").unwrap().sets(), 256);
+
+		for bad in ["size=48", "block=2", "ways=3", "ways=0", "policy=mru", "write=sometimes", "colour=red", "size"] {
+			assert!(Config::parse(bad).is_err(), "{}", bad);
+		}
+	}
+
+	#[test]
+	fn evicts_blocks_of_the_same_set_when_direct_mapped() {
+		// four sets of 16 bytes, so 0 and 64 share the first
+		let mut cache = shaped("size=64,block=16,ways=1");
+		assert_eq!(hits(&mut cache, &[(0, true), (4, false), (16, false), (64, false), (0, false), (20, false)]), [false, true, false, false, false, true]);
+		assert_eq!(misses(&cache), [3, 0, 1, 1]);
+		assert_eq!((cache.stats.reads, cache.stats.writes, cache.misses()), (5, 1, 4));
+	}
+
+	#[test]
+	fn writes_back_only_dirty_blocks_by_their_address() {
+		let mut cache = shaped("size=64,block=16,ways=1");
+		assert_eq!(cache.lookup(0x48, true).evicted, None);
+		assert_eq!(cache.lookup(0x08, false).evicted, Some(0x40));
+		assert_eq!(cache.lookup(0x40, false).evicted, None);
+
+		// writes through neither dirty a block nor bring one in when not allocating
+		let mut cache = shaped("size=64,block=16,write=through,allocate=no");
+		let lookup = cache.lookup(0, true);
+		assert!(lookup.through && !lookup.fill);
+		assert!(cache.lookup(0, false).fill);
+		assert_eq!(cache.lookup(64, false).evicted, None);
+	}
+
+	#[test]
+	fn replaces_the_least_recently_used_way() {
+		// two sets of two ways, so 0, 32 and 64 share the first
+		let mut cache = shaped("size=64,block=16,ways=2,policy=lru");
+		assert_eq!(hits(&mut cache, &[(0, false), (32, false), (0, false), (64, false), (0, false), (32, false)]), [false, false, true, false, true, false]);
+		assert_eq!(misses(&cache), [3, 0, 1, 0]);
+	}
+
+	#[test]
+	fn replaces_the_first_way_loaded() {
+		let mut cache = shaped("size=64,block=16,ways=2,policy=fifo");
+		assert_eq!(hits(&mut cache, &[(0, false), (32, false), (0, false), (64, false), (0, false), (32, false)]), [false, false, true, false, false, false]);
+		assert_eq!(misses(&cache), [3, 0, 2, 0]);
+	}
+
+	#[test]
+	fn counts_misses_of_a_full_cache_as_capacity() {
+		let mut cache = shaped("size=32,block=16,ways=full");
+		assert_eq!(hits(&mut cache, &[(0, false), (16, false), (32, false), (0, false), (32, false)]), [false, false, false, false, true]);
+		assert_eq!(misses(&cache), [3, 1, 0, 0]);
+	}
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+use colored::Colorize;
+
+use crate::runtime::{Exit, Machine};
+use crate::runtime::console::Buffer;
+use crate::source::SourceMap;
+use crate::trace::{Entry, Format, Tracer};
+
+// one side of the comparison, a program run from the start or a trace recorded earlier
+pub enum Side {
+	Run { machine: Box<Machine>, tracer: Box<Tracer> },
+	Recorded(std::vec::IntoIter<Entry>)
+}
+
+impl Side {
+	pub fn run(mut machine: Machine, input: &[u8]) -> Side {
+		machine.io = Box::new(Buffer::new(input.to_vec()));
+		let tracer = Tracer::new(&machine, Format::Text, &[], Box::new(std::io::sink()))
+			.unwrap_or_else(|_| unreachable!("a tracer without filters is always made"));
+		Side::Run { machine: Box::new(machine), tracer: Box::new(tracer) }
+	}
+
+	// a trace as written by --trace, in either format, reporting the first line which cannot be read
+	pub fn recorded(text: &str) -> Result<Side, String> {
+		let mut entries = Vec::new();
+		for (idx, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+			entries.push(Entry::parse(line).map_err(|why| format!("line {} of the trace cannot be read: {}", idx + 1, why))?);
+		}
+		Ok(Side::Recorded(entries.into_iter()))
+	}
+
+	// a file is taken for a trace when it starts as a line of one does
+	pub fn is_trace(bytes: &[u8]) -> bool {
+		let text = String::from_utf8_lossy(&bytes[..bytes.len().min(64)]).into_owned();
+		let text = text.trim_start();
+		text.starts_with('{') || text.starts_with("0x")
+	}
+
+	// the next instruction executed, or how the side ended
+	fn next(&mut self, sources: &SourceMap) -> Result<Entry, String> {
+		match self {
+			Side::Run { machine, tracer } => loop {
+				if let Some(exit) = machine.exit {
+					return Err(match exit {
+						Exit::Code(code) => format!("exited with code {}", code),
+						Exit::FellOff => "ran off the end of the text".to_string()
+					});
+				}
+
+				let stepped = machine.step();
+				let entry = tracer.entry(machine, sources);
+				if let Err(fault) = stepped {
+					return Err(format!("faulted at {:#010x}: {}", machine.pc, fault.to_string().trim_end_matches('.')));
+				}
+				if let Some(entry) = entry {
+					return Ok(entry);
+				}
+			},
+			Side::Recorded(entries) => entries.next().ok_or("reached the end of the trace".to_string())
+		}
+	}
+
+	// the line of source an instruction was assembled from, where it is known
+	fn code<'a>(&self, pc: u32, sources: &'a SourceMap) -> Option<&'a str> {
+		match self {
+			Side::Run { machine, .. } => machine.line(pc).map(|segment| sources.line(segment).trim()),
+			Side::Recorded(_) => None
+		}
+	}
+
+	fn output(&self) -> Option<&[u8]> {
+		match self {
+			Side::Run { machine, .. } => machine.io.written(),
+			Side::Recorded(_) => None
+		}
+	}
+}
+
+// what differs between two entries, which agree when they executed the same address and changed the same state to the same values
+fn differences(left: &Entry, right: &Entry) -> Vec<String> {
+	let mut differences = Vec::new();
+	if left.pc != right.pc {
+		differences.push(format!("pc: {:#010x} on the left, {:#010x} on the right", left.pc, right.pc));
+	}
+
+	let changed = |value: Option<&u32>| value.map_or("unchanged".to_string(), |value| format!("{:#010x}", value));
+	let compare = |differences: &mut Vec<String>, left: BTreeMap<String, u32>, right: BTreeMap<String, u32>| {
+		let mut names: Vec<&String> = left.keys().chain(right.keys()).collect();
+		names.sort();
+		names.dedup();
+		for name in names {
+			let (left, right) = (left.get(name), right.get(name));
+			if left != right {
+				differences.push(format!("{}: {} on the left, {} on the right", name, changed(left), changed(right)));
+			}
+		}
+	};
+	compare(&mut differences, left.registers.iter().cloned().collect(), right.registers.iter().cloned().collect());
+	compare(&mut differences,
+		left.memory.iter().map(|(address, value)| (format!("[{:#010x}]", address), *value)).collect(),
+		right.memory.iter().map(|(address, value)| (format!("[{:#010x}]", address), *value)).collect());
+	differences
+}
+
+fn instructions(count: u64) -> String {
+	format!("{} instruction{}", count, if count == 1 { "" } else { "s" })
+}
+
+// an entry as shown in the report, with the line it came from when it is known
+fn show(out: &mut String, side: &str, entry: &Entry, code: Option<&str>) {
+	let _ = writeln!(out, "  {:5}  {:#010x}  {:28}  {}", side.bold(), entry.pc, entry.text, entry.source.as_deref().unwrap_or("-").bright_black());
+	if let Some(code) = code {
+		let _ = writeln!(out, "         {}", code);
+	}
+	let changes: Vec<String> = entry.registers.iter().map(|(name, value)| format!("{}={:#010x}", name, value))
+		.chain(entry.memory.iter().map(|(address, value)| format!("[{:#010x}]={:#010x}", address, value)))
+		.collect();
+	if !changes.is_empty() {
+		let _ = writeln!(out, "         {}", changes.join("  "));
+	}
+}
+
+// steps both sides together until they differ or end, returning the report and whether they diverged
+pub fn compare(mut left: Side, mut right: Side, sources: &SourceMap, max_steps: u64, context: usize) -> (String, bool) {
+	let mut out = String::new();
+	let mut recent: VecDeque<Entry> = VecDeque::new();
+
+	let mut steps = 0;
+	while steps < max_steps {
+		let (left_entry, right_entry) = (left.next(sources), right.next(sources));
+		let (left_entry, right_entry) = match (left_entry, right_entry) {
+			(Ok(left_entry), Ok(right_entry)) => (left_entry, right_entry),
+			(Err(left_end), Err(right_end)) => {
+				let _ = writeln!(out, "Both sides executed the same {}, then the left {} and the right {}.", instructions(steps), left_end, right_end);
+				return match (left.output(), right.output()) {
+					(Some(left_output), Some(right_output)) if left_output != right_output => {
+						let _ = writeln!(out, "{} the programs printed different output.", "Diverged:".red().bold());
+						(out, true)
+					},
+					_ => (out, false)
+				};
+			},
+			(Ok(entry), Err(end)) => {
+				let _ = writeln!(out, "{} after {} the right {}, while the left went on to:\n", "Diverged:".red().bold(), instructions(steps), end);
+				show(&mut out, "left", &entry, left.code(entry.pc, sources));
+				return (out, true);
+			},
+			(Err(end), Ok(entry)) => {
+				let _ = writeln!(out, "{} after {} the left {}, while the right went on to:\n", "Diverged:".red().bold(), instructions(steps), end);
+				show(&mut out, "right", &entry, right.code(entry.pc, sources));
+				return (out, true);
+			}
+		};
+
+		let differences = differences(&left_entry, &right_entry);
+		if !differences.is_empty() {
+			let _ = writeln!(out, "{} after {} which matched, at instruction {}:\n", "Diverged:".red().bold(), instructions(steps), steps + 1);
+			show(&mut out, "left", &left_entry, left.code(left_entry.pc, sources));
+			show(&mut out, "right", &right_entry, right.code(right_entry.pc, sources));
+			out.push('\n');
+			for difference in differences {
+				let _ = writeln!(out, "  {}", difference);
+			}
+			if !recent.is_empty() {
+				let _ = writeln!(out, "\nThe last instructions before, on the left:");
+				for entry in &recent {
+					let _ = writeln!(out, "  {:#010x}  {:28}  {}", entry.pc, entry.text, entry.source.as_deref().unwrap_or("-").bright_black());
+				}
+			}
+			return (out, true);
+		}
+
+		recent.push_back(left_entry);
+		if recent.len() > context {
+			recent.pop_front();
+		}
+		steps += 1;
+	}
+
+	let _ = writeln!(out, "Both sides executed the same {}, stopping there.", instructions(steps));
+	(out, false)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn recorded(lines: &[Entry]) -> Side {
+		let text: Vec<String> = lines.iter().map(Entry::to_text).collect();
+		Side::recorded(&text.join("\n")).unwrap_or_else(|why| panic!("{}", why))
+	}
+
+	fn entry(pc: u32, text: &str, registers: &[(&str, u32)]) -> Entry {
+		Entry {
+			pc,
+			word: 0,
+			text: text.to_string(),
+			source: Some("main.asm:1".to_string()),
+			registers: registers.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+			memory: Vec::new()
+		}
+	}
+
+	#[test]
+	fn agrees_on_traces_differing_only_in_long_disassembly() {
+		let left = [
+			entry(0x00400000, "lw $t0, a_label_much_longer_than_the_column($zero)", &[("$t0", 1)]),
+			entry(0x00400004, "jal a_function_with_a_very_long_name_indeed", &[("$ra", 0x00400008)])
+		];
+		let right = [
+			entry(0x00400000, "lw $t0, 0x10010000($zero)", &[("$t0", 1)]),
+			entry(0x00400004, "jal 0x00400040", &[("$ra", 0x00400008)])
+		];
+		let (report, diverged) = compare(recorded(&left), recorded(&right), &SourceMap::new(), 100, 4);
+		assert!(!diverged, "{}", report);
+		assert!(report.starts_with("Both sides executed the same 2 instructions"), "{}", report);
+	}
+
+	#[test]
+	fn finds_changes_after_long_disassembly() {
+		let left = [entry(0x00400000, "lw $t0, a_label_much_longer_than_the_column($zero)", &[("$t0", 1)])];
+		let right = [entry(0x00400000, "lw $t0, a_label_much_longer_than_the_column($zero)", &[("$t0", 2)])];
+		let (report, diverged) = compare(recorded(&left), recorded(&right), &SourceMap::new(), 100, 4);
+		assert!(diverged);
+		assert!(report.contains("$t0: 0x00000001 on the left, 0x00000002 on the right"), "{}", report);
+	}
+}
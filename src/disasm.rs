@@ -0,0 +1,145 @@
+use crate::encoding::{self, Machine};
+use crate::link::Label;
+use crate::mips::Endian;
+use crate::parse::instructions::{Instruction, Register};
+
+fn register(number: u32) -> String {
+	format!("${}", Register::NAMES[number as usize & 0x1f])
+}
+
+fn float(number: u32) -> String {
+	format!("$f{}", number & 0x1f)
+}
+
+// the condition flag of the floating point unit, written before the operands only when it is not 0
+fn condition(cc: u32) -> String {
+	match cc {
+		0 => String::new(),
+		_ => format!("{}, ", cc)
+	}
+}
+
+// the label at an address, or the address itself
+fn target(address: u32, labels: &[Label]) -> String {
+	match labels.iter().find(|label| label.address == address) {
+		Some(label) => label.name.to_string(),
+		None => format!("{:#010x}", address)
+	}
+}
+
+// a word in canonical syntax, naming branch and jump targets by label where there is one
+// with pseudo set, instructions which are the expansion of a common pseudo-instruction are shown as it
+pub fn disassemble(word: u32, address: u32, labels: &[Label], pseudo: bool) -> String {
+	match encoding::decode(word) {
+		Some(machine) => instruction(&machine, address, labels, pseudo),
+		None => format!(".word {:#010x}", word)
+	}
+}
+
+pub fn instruction(machine: &Machine, address: u32, labels: &[Label], pseudo: bool) -> String {
+	use Instruction::*;
+
+	let (rs, rt, rd) = (register(machine.rs), register(machine.rt), register(machine.rd));
+	let (ft, fs, fd) = (float(machine.rt), float(machine.rd), float(machine.shamt));
+	let signed = machine.signed();
+	let branch = target(address.wrapping_add(4).wrapping_add((signed << 2) as u32), labels);
+
+	if pseudo {
+		if let Some(text) = idiom(machine, &rs, &rt, &rd, &branch) {
+			return text;
+		}
+	}
+
+	let mnemonic = machine.instruction.mnemonic();
+	let operands = match machine.instruction {
+		Add | AddUnsigned | Subtract | SubtractUnsigned | And | Or | Xor | Nor |
+		SetLessThan | SetLessThanUnsigned | MoveConditionalZero | MoveConditionalNotZero | MultiplyToRegister =>
+			format!("{}, {}, {}", rd, rs, rt),
+		ShiftLeftLogicalVariable | ShiftRightLogicalVariable | ShiftRightArithmeticVariable => format!("{}, {}, {}", rd, rt, rs),
+		ShiftLeftLogical | ShiftRightLogical | ShiftRightArithmetic => format!("{}, {}, {}", rd, rt, machine.shamt),
+		CountLeadingZeros | CountLeadingOnes => format!("{}, {}", rd, rs),
+
+		AddImmediate | AddImmediateUnsigned | SetLessThanImmediate | SetLessThanImmediateUnsigned => format!("{}, {}, {}", rt, rs, signed),
+		AndImmediate | OrImmediate | XorImmediate => format!("{}, {}, {:#x}", rt, rs, machine.immediate),
+		LoadUpperImmediate => format!("{}, {:#x}", rt, machine.immediate),
+
+		Multiply | MultiplyUnsigned | MultiplyAdd | MultiplyAddUnsigned | MultiplySubtract | MultiplySubtractUnsigned |
+		Divide | DivideUnsigned => format!("{}, {}", rs, rt),
+		MoveFromHi | MoveFromLo => rd,
+		MoveToHi | MoveToLo | JumpRegister => rs,
+		JumpAndLinkRegister => format!("{}, {}", rd, rs),
+
+		LoadByte | LoadByteUnsigned | LoadHalf | LoadHalfUnsigned | LoadWord | LoadWordLeft | LoadWordRight | LoadLinked |
+		StoreByte | StoreHalf | StoreWord | StoreWordLeft | StoreWordRight | StoreConditional => format!("{}, {}({})", rt, signed, rs),
+
+		BranchEqual | BranchNotEqual => format!("{}, {}, {}", rs, rt, branch),
+		BranchLessEqualZero | BranchGreaterThanZero | BranchLessThanZero | BranchGreaterEqualZero |
+		BranchLessThanZeroAndLink | BranchGreaterEqualZeroAndLink => format!("{}, {}", rs, branch),
+
+		Jump | JumpAndLink => target((address.wrapping_add(4) & 0xf0000000) | (machine.immediate << 2), labels),
+
+		SystemCall => String::new(),
+		Break if machine.immediate == 0 => String::new(),
+		Break => machine.immediate.to_string(),
+
+		TrapEqual | TrapNotEqual | TrapGreaterEqual | TrapGreaterEqualUnsigned | TrapLessThan | TrapLessThanUnsigned => format!("{}, {}", rs, rt),
+		TrapEqualImmediate | TrapNotEqualImmediate | TrapGreaterEqualImmediate | TrapGreaterEqualImmediateUnsigned |
+		TrapLessThanImmediate | TrapLessThanImmediateUnsigned => format!("{}, {}", rs, signed),
+		// coprocessor 0 registers have no names
+		MoveFromCoprocessor0 | MoveToCoprocessor0 => format!("{}, ${}", rt, machine.rd),
+		ExceptionReturn => String::new(),
+
+		AddSingle | AddDouble | SubtractSingle | SubtractDouble |
+		MultiplySingle | MultiplyDouble | DivideSingle | DivideDouble => format!("{}, {}, {}", fd, fs, ft),
+		AbsoluteSingle | AbsoluteDouble | NegateSingle | NegateDouble |
+		MoveSingle | MoveDouble | SquareRootSingle | SquareRootDouble |
+		ConvertSingleDouble | ConvertSingleWord | ConvertDoubleSingle | ConvertDoubleWord | ConvertWordSingle | ConvertWordDouble |
+		TruncateWordSingle | TruncateWordDouble | RoundWordSingle | RoundWordDouble |
+		FloorWordSingle | FloorWordDouble | CeilWordSingle | CeilWordDouble => format!("{}, {}", fd, fs),
+		CompareEqualSingle | CompareEqualDouble | CompareLessThanSingle | CompareLessThanDouble |
+		CompareLessEqualSingle | CompareLessEqualDouble => format!("{}{}, {}", condition(machine.shamt >> 2), fs, ft),
+		BranchFloatTrue | BranchFloatFalse => format!("{}{}", condition(machine.rt >> 2), branch),
+		MoveOnFalse | MoveOnTrue => format!("{}, {}, {}", rd, rs, machine.rt >> 2),
+		MoveOnFalseSingle | MoveOnFalseDouble | MoveOnTrueSingle | MoveOnTrueDouble => format!("{}, {}, {}", fd, fs, machine.rt >> 2),
+		MoveToFloat | MoveFromFloat => format!("{}, {}", rt, fs),
+		MoveControlToFloat | MoveControlFromFloat => format!("{}, ${}", rt, machine.rd),
+		LoadWordFloat | StoreWordFloat | LoadDoubleFloat | StoreDoubleFloat => format!("{}, {}({})", ft, signed, rs),
+
+		_ => unreachable!("{} is not a basic instruction", mnemonic)
+	};
+
+	match operands.is_empty() {
+		true => mnemonic.to_string(),
+		false => format!("{} {}", mnemonic, operands)
+	}
+}
+
+// the pseudo-instruction a basic instruction is the usual expansion of
+fn idiom(machine: &Machine, rs: &str, rt: &str, rd: &str, branch: &str) -> Option<String> {
+	use Instruction::*;
+
+	const ZERO: u32 = 0;
+	Some(match (machine.instruction, machine.rs, machine.rt) {
+		(ShiftLeftLogical, _, ZERO) if machine.rd == ZERO && machine.shamt == 0 => "nop".to_string(),
+		(AddUnsigned | Or, _, ZERO) => format!("move {}, {}", rd, rs),
+		(AddUnsigned | Or, ZERO, _) => format!("move {}, {}", rd, rt),
+		(Subtract, ZERO, _) => format!("neg {}, {}", rd, rt),
+		(Nor, _, ZERO) => format!("not {}, {}", rd, rs),
+		(AddImmediateUnsigned, ZERO, _) => format!("li {}, {}", rt, machine.signed()),
+		(OrImmediate, ZERO, _) => format!("li {}, {}", rt, machine.immediate),
+		(BranchEqual, ZERO, ZERO) => format!("b {}", branch),
+		(BranchEqual, _, ZERO) => format!("beqz {}, {}", rs, branch),
+		(BranchNotEqual, _, ZERO) => format!("bnez {}, {}", rs, branch),
+		(JumpAndLinkRegister, _, _) if machine.rd == 31 => format!("jalr {}", rs),
+		_ => return None
+	})
+}
+
+// every word of a section, with the address of each
+pub fn section(bytes: &[u8], base: u32, endian: Endian, labels: &[Label], pseudo: bool) -> Vec<(u32, u32, String)> {
+	bytes.chunks_exact(4).enumerate().map(|(idx, chunk)| {
+		let address = base.wrapping_add(idx as u32 * 4);
+		let word = endian.read_u32(chunk);
+		(address, word, disassemble(word, address, labels, pseudo))
+	}).collect()
+}
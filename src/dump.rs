@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use crate::link::Program;
+use crate::object::Section;
+
+// words on each line of a Logisim image, as Logisim writes them
+const LOGISIM_WIDTH: usize = 8;
+// bytes in each data record of Intel HEX
+const RECORD_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+	// a word per line in hexadecimal, as MARS writes HexText
+	Hex,
+	// a Logisim "v2.0 raw" image for a ROM or RAM with 32 bit words
+	Logisim,
+	// a word per line to be read with $readmemh
+	Verilog,
+	// Intel HEX records at the address of the segment
+	IntelHex,
+	// the bytes of the segment in the byte order of the program
+	Binary
+}
+
+// a segment to write after assembling, given as `segment:format:file`
+#[derive(Clone, Debug)]
+pub struct Dump {
+	pub segment: Section,
+	pub format: Format,
+	pub path: String
+}
+
+impl Dump {
+	pub fn parse(arg: &str) -> Result<Dump, String> {
+		let mut parts = arg.splitn(3, ':');
+		let (Some(segment), Some(format), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+			return Err(format!("\"{}\" is not of the form segment:format:file", arg));
+		};
+
+		let segment = match Section::ALL.into_iter().find(|section| section.name() == segment || section.name()[1..] == *segment) {
+			Some(section) => section,
+			None => return Err(format!("\"{}\" is not a segment, expected text, data, ktext or kdata", segment))
+		};
+
+		let format = match format {
+			"hex" | "hextext" => Format::Hex,
+			"logisim" => Format::Logisim,
+			"verilog" | "readmemh" => Format::Verilog,
+			"ihex" | "intel" => Format::IntelHex,
+			"binary" | "bin" => Format::Binary,
+			_ => return Err(format!("\"{}\" is not a format, expected hex, logisim, verilog, ihex or binary", format))
+		};
+
+		if path.is_empty() {
+			return Err(format!("\"{}\" does not name a file", arg));
+		}
+
+		Ok(Dump { segment, format, path: path.to_string() })
+	}
+}
+
+// the segment in the format of the dump, the last word being padded with zeros
+pub fn write(program: &Program, segment: Section, format: Format) -> Vec<u8> {
+	let (base, bytes) = (program.base(segment), program.section(segment));
+
+	let mut padded = bytes.clone();
+	padded.resize(bytes.len().next_multiple_of(4), 0);
+	let words: Vec<u32> = padded.chunks_exact(4).map(|chunk| program.endian.read_u32(chunk)).collect();
+
+	match format {
+		Format::Hex => words.iter().map(|word| format!("{:08x}\n", word)).collect::<String>().into_bytes(),
+		Format::Logisim => logisim(&words).into_bytes(),
+		Format::Verilog => verilog(&words, base).into_bytes(),
+		Format::IntelHex => intel_hex(bytes, base).into_bytes(),
+		Format::Binary => bytes.clone()
+	}
+}
+
+// repeated words are written as `count*word`, which Logisim reads back the same
+fn logisim(words: &[u32]) -> String {
+	let mut runs: Vec<String> = Vec::new();
+	let mut idx = 0;
+	while idx < words.len() {
+		let count = words[idx..].iter().take_while(|word| **word == words[idx]).count();
+		match count {
+			1..=3 => runs.extend(std::iter::repeat_n(format!("{:x}", words[idx]), count)),
+			_ => runs.push(format!("{}*{:x}", count, words[idx]))
+		}
+		idx += count;
+	}
+
+	let mut out = String::from("v2.0 raw\n");
+	for line in runs.chunks(LOGISIM_WIDTH) {
+		let _ = writeln!(out, "{}", line.join(" "));
+	}
+	out
+}
+
+// addresses in a memory are those of words counted from the start of the segment, so none are given
+fn verilog(words: &[u32], base: u32) -> String {
+	let mut out = format!("// {} words from {:#010x}\n", words.len(), base);
+	for word in words {
+		let _ = writeln!(out, "{:08x}", word);
+	}
+	out
+}
+
+fn intel_hex(bytes: &[u8], base: u32) -> String {
+	let mut out = String::new();
+	let mut upper = None;
+
+	for (idx, chunk) in bytes.chunks(RECORD_LEN).enumerate() {
+		let address = base.wrapping_add((idx * RECORD_LEN) as u32);
+
+		// records only hold the low half of an address, the high half is set by an extended linear address record
+		// a record which would wrap past the low half is split so that every byte is at its own address
+		let split = (0x10000 - (address & 0xffff) as usize).min(chunk.len());
+		for (offset, part) in [(0, &chunk[..split]), (split, &chunk[split..])] {
+			if part.is_empty() {
+				continue;
+			}
+
+			let address = address.wrapping_add(offset as u32);
+			if upper != Some(address >> 16) {
+				upper = Some(address >> 16);
+				record(&mut out, 0, 0x04, &((address >> 16) as u16).to_be_bytes());
+			}
+			record(&mut out, address as u16, 0x00, part);
+		}
+	}
+
+	record(&mut out, 0, 0x01, &[]);
+	out
+}
+
+fn record(out: &mut String, address: u16, kind: u8, data: &[u8]) {
+	let mut bytes = vec![data.len() as u8];
+	bytes.extend(address.to_be_bytes());
+	bytes.push(kind);
+	bytes.extend(data);
+
+	let checksum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+	bytes.push(checksum);
+
+	let _ = writeln!(out, ":{}", bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>());
+}
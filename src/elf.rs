@@ -0,0 +1,464 @@
+use crate::link::{Label, Program};
+use crate::mips::{self, Endian};
+use crate::object::{Binding, Section};
+
+const MAGIC: &[u8; 4] = b"\x7fELF";
+
+const CLASS_32: u8 = 1;
+const DATA_LITTLE: u8 = 1;
+const DATA_BIG: u8 = 2;
+const TYPE_EXECUTABLE: u16 = 2;
+const MACHINE_MIPS: u16 = 8;
+const FLAGS_MIPS32_O32: u32 = 0x50001000;	// EF_MIPS_ARCH_32 | EF_MIPS_ABI_O32
+
+const HEADER_SIZE: u32 = 52;
+const PROGRAM_HEADER_SIZE: u32 = 32;
+const SECTION_HEADER_SIZE: u32 = 40;
+const SYMBOL_SIZE: u32 = 16;
+const PAGE_SIZE: u32 = 0x1000;
+// the most memory the segments of an executable may take, which the file may claim far more of than it holds
+const MAX_MEMORY: u64 = 256 << 20;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+const SHN_LORESERVE: u16 = 0xff00;
+const SHN_ABS: u16 = 0xfff1;
+
+// a statically linked executable as it is loaded into memory
+pub struct Executable {
+	pub endian: Endian,
+	pub entry: u32,
+	pub segments: Vec<Segment>,
+	pub labels: Vec<Label>
+}
+
+pub struct Segment {
+	pub address: u32,
+	pub bytes: Vec<u8>,		// including the zeroed memory following the contents of the file
+	pub executable: bool
+}
+
+pub fn is_elf(bytes: &[u8]) -> bool {
+	bytes.starts_with(MAGIC)
+}
+
+struct Writer {
+	endian: Endian,
+	bytes: Vec<u8>
+}
+
+impl Writer {
+	fn u8(&mut self, value: u8) {
+		self.bytes.push(value);
+	}
+
+	fn u16(&mut self, value: u16) {
+		let mut bytes = [0; 2];
+		self.endian.write_u16(&mut bytes, value);
+		self.bytes.extend_from_slice(&bytes);
+	}
+
+	fn u32(&mut self, value: u32) {
+		let mut bytes = [0; 4];
+		self.endian.write_u32(&mut bytes, value);
+		self.bytes.extend_from_slice(&bytes);
+	}
+
+	fn pad(&mut self, len: u32) {
+		self.bytes.resize(len as usize, 0);
+	}
+}
+
+// the file offset at which a segment loaded at the address can be placed after the given offset
+fn page_offset(offset: u32, address: u32) -> u32 {
+	offset.next_multiple_of(PAGE_SIZE) + address % PAGE_SIZE
+}
+
+// strings of a string table, each null terminated, the table starting with an empty string
+fn string_table<'a>(strings: impl Iterator<Item = &'a str>) -> (Vec<u8>, Vec<u32>) {
+	let mut table = vec![0];
+	let mut offsets = Vec::new();
+	for string in strings {
+		offsets.push(table.len() as u32);
+		table.extend_from_slice(string.as_bytes());
+		table.push(0);
+	}
+	(table, offsets)
+}
+
+// an executable with a loadable segment for each section which is not empty, and a symbol table of the program's labels
+pub fn write(program: &Program) -> Vec<u8> {
+	let mut out = Writer { endian: program.endian, bytes: Vec::new() };
+
+	let loaded: Vec<Section> = Section::ALL.into_iter().filter(|section| !program.section(*section).is_empty()).collect();
+
+	// local symbols must precede global ones
+	let mut labels: Vec<&Label> = program.labels.iter().filter(|label| label.binding == Binding::Local).collect();
+	let first_global = labels.len() as u32 + 1;
+	labels.extend(program.labels.iter().filter(|label| label.binding == Binding::Global));
+
+	let (strtab, names) = string_table(labels.iter().map(|label| label.name.as_str()));
+	let (shstrtab, section_names) = string_table(Section::ALL.iter().map(|section| section.name()).chain([".symtab", ".strtab", ".shstrtab"]));
+
+	// file layout, with each section at the same offset within a page as its address
+	let mut offsets = [0; 4];
+	let mut end = HEADER_SIZE + PROGRAM_HEADER_SIZE * loaded.len() as u32;
+	for (idx, section) in Section::ALL.into_iter().enumerate() {
+		offsets[idx] = page_offset(end, program.base(section));
+		end = offsets[idx] + program.section(section).len() as u32;
+	}
+	let symtab_offset = end.next_multiple_of(4);
+	let strtab_offset = symtab_offset + SYMBOL_SIZE * (labels.len() as u32 + 1);
+	let shstrtab_offset = strtab_offset + strtab.len() as u32;
+	let sections_offset = (shstrtab_offset + shstrtab.len() as u32).next_multiple_of(4);
+
+	out.bytes.extend_from_slice(MAGIC);
+	out.u8(CLASS_32);
+	out.u8(if program.endian == Endian::Big { DATA_BIG } else { DATA_LITTLE });
+	out.u8(1);	// version
+	out.pad(16);
+	out.u16(TYPE_EXECUTABLE);
+	out.u16(MACHINE_MIPS);
+	out.u32(1);	// version
+	out.u32(program.entry);
+	out.u32(HEADER_SIZE);
+	out.u32(sections_offset);
+	out.u32(FLAGS_MIPS32_O32);
+	out.u16(HEADER_SIZE as u16);
+	out.u16(PROGRAM_HEADER_SIZE as u16);
+	out.u16(loaded.len() as u16);
+	out.u16(SECTION_HEADER_SIZE as u16);
+	out.u16(8);	// sections
+	out.u16(7);	// .shstrtab
+
+	for section in &loaded {
+		let (address, len) = (program.base(*section), program.section(*section).len() as u32);
+		out.u32(PT_LOAD);
+		out.u32(offsets[index(*section) - 1]);
+		out.u32(address);
+		out.u32(address);
+		out.u32(len);
+		out.u32(len);
+		out.u32(if section.executable() { PF_R | PF_X } else { PF_R | PF_W });
+		out.u32(PAGE_SIZE);
+	}
+
+	for (idx, section) in Section::ALL.into_iter().enumerate() {
+		out.pad(offsets[idx]);
+		out.bytes.extend_from_slice(program.section(section));
+	}
+
+	out.pad(symtab_offset);
+	out.pad(symtab_offset + SYMBOL_SIZE);	// the null symbol
+	for (label, name) in labels.iter().zip(names) {
+		let kind = if label.section.executable() { STT_FUNC } else { STT_OBJECT };
+		let section = index(label.section) as u16;
+		let binding = if label.binding == Binding::Global { STB_GLOBAL } else { STB_LOCAL };
+
+		out.u32(name);
+		out.u32(label.address);
+		out.u32(0);	// size
+		out.u8(binding << 4 | kind);
+		out.u8(0);
+		out.u16(section);
+	}
+
+	out.bytes.extend_from_slice(&strtab);
+	out.bytes.extend_from_slice(&shstrtab);
+	out.pad(sections_offset);
+
+	// name, type, flags, address, offset, size, link, info, alignment, entry size
+	let mut headers = vec![[0; 10]];
+	for (idx, section) in Section::ALL.into_iter().enumerate() {
+		let (flags, alignment) = if section.executable() { (SHF_ALLOC | SHF_EXECINSTR, 4) } else { (SHF_ALLOC | SHF_WRITE, 8) };
+		headers.push([section_names[idx], SHT_PROGBITS, flags, program.base(section), offsets[idx], program.section(section).len() as u32, 0, 0, alignment, 0]);
+	}
+	headers.extend([
+		[section_names[4], SHT_SYMTAB, 0, 0, symtab_offset, strtab_offset - symtab_offset, 6, first_global, 4, SYMBOL_SIZE],
+		[section_names[5], SHT_STRTAB, 0, 0, strtab_offset, strtab.len() as u32, 0, 0, 1, 0],
+		[section_names[6], SHT_STRTAB, 0, 0, shstrtab_offset, shstrtab.len() as u32, 0, 0, 1, 0]
+	]);
+	for header in headers {
+		for field in header {
+			out.u32(field);
+		}
+	}
+
+	out.bytes
+}
+
+// the index of the header of a section, after the null one
+fn index(section: Section) -> usize {
+	Section::ALL.iter().position(|other| *other == section).unwrap_or_default() + 1
+}
+
+struct Reader<'a> {
+	endian: Endian,
+	bytes: &'a [u8]
+}
+
+// offsets are taken as 64 bits, so that those made from the fields of the file cannot overflow
+impl<'a> Reader<'a> {
+	fn slice(&self, offset: u64, len: u64) -> Result<&'a [u8], String> {
+		let range = usize::try_from(offset).ok().zip(usize::try_from(offset + len).ok());
+		match range.and_then(|(start, end)| self.bytes.get(start..end)) {
+			Some(bytes) => Ok(bytes),
+			None => Err("is truncated".to_string())
+		}
+	}
+
+	fn u8(&self, offset: u64) -> Result<u8, String> {
+		Ok(self.slice(offset, 1)?[0])
+	}
+
+	fn u16(&self, offset: u64) -> Result<u16, String> {
+		Ok(self.endian.read_u16(self.slice(offset, 2)?))
+	}
+
+	fn u32(&self, offset: u64) -> Result<u32, String> {
+		Ok(self.endian.read_u32(self.slice(offset, 4)?))
+	}
+}
+
+struct SectionHeader {
+	kind: u32,
+	flags: u32,
+	offset: u32,
+	size: u32,
+	link: u32
+}
+
+// reads a statically linked MIPS32 executable, with the labels of its symbol table if it has one
+pub fn read(bytes: &[u8]) -> Result<Executable, String> {
+	if !is_elf(bytes) || bytes.len() < HEADER_SIZE as usize {
+		return Err("is not an ELF file".to_string());
+	}
+	if bytes[4] != CLASS_32 {
+		return Err("is not a 32 bit executable".to_string());
+	}
+
+	let endian = match bytes[5] {
+		DATA_LITTLE => Endian::Little,
+		DATA_BIG => Endian::Big,
+		other => return Err(format!("has unknown data encoding {}", other))
+	};
+	let reader = Reader { endian, bytes };
+
+	if reader.u16(18)? != MACHINE_MIPS {
+		return Err("is not a MIPS executable".to_string());
+	}
+	if reader.u16(16)? != TYPE_EXECUTABLE {
+		return Err("is not an executable, only statically linked executables can be loaded".to_string());
+	}
+
+	let entry = reader.u32(24)?;
+	let (program_headers, section_headers) = (reader.u32(28)? as u64, reader.u32(32)? as u64);
+	let (program_header_size, program_header_count) = (reader.u16(42)? as u64, reader.u16(44)? as u64);
+	let (section_header_size, section_header_count) = (reader.u16(46)? as u64, reader.u16(48)? as u64);
+
+	let mut segments = Vec::new();
+	let mut memory = 0;
+	for idx in 0..program_header_count {
+		let header = program_headers + idx * program_header_size;
+		match reader.u32(header)? {
+			PT_LOAD => {
+				let (offset, address) = (reader.u32(header + 4)?, reader.u32(header + 8)?);
+				let (file_size, memory_size) = (reader.u32(header + 16)?, reader.u32(header + 20)?);
+
+				let size = memory_size.max(file_size) as u64;
+				if address as u64 + size > 1 << 32 {
+					return Err(format!("has a segment of {} bytes at {:#010x}, which runs past the end of memory", size, address));
+				}
+				memory += size;
+				if memory > MAX_MEMORY {
+					return Err("has segments taking more than 256MB of memory".to_string());
+				}
+
+				let mut segment = reader.slice(offset.into(), file_size.into())?.to_vec();
+				segment.resize(size as usize, 0);
+				segments.push(Segment {
+					address,
+					bytes: segment,
+					executable: reader.u32(header + 24)? & PF_X != 0
+				});
+			},
+			PT_DYNAMIC | PT_INTERP => return Err("is dynamically linked, only statically linked executables can be loaded".to_string()),
+			_ => {}
+		}
+	}
+
+	let mut sections = Vec::new();
+	for idx in 0..section_header_count {
+		let header = section_headers + idx * section_header_size;
+		sections.push(SectionHeader {
+			kind: reader.u32(header + 4)?,
+			flags: reader.u32(header + 8)?,
+			offset: reader.u32(header + 16)?,
+			size: reader.u32(header + 20)?,
+			link: reader.u32(header + 24)?
+		});
+	}
+
+	let mut labels = Vec::new();
+	for symtab in sections.iter().filter(|section| section.kind == SHT_SYMTAB) {
+		let Some(strtab) = sections.get(symtab.link as usize) else { continue };
+		let strings = reader.slice(strtab.offset.into(), strtab.size.into())?;
+
+		for idx in 1..symtab.size / SYMBOL_SIZE {
+			let symbol = symtab.offset as u64 + (idx * SYMBOL_SIZE) as u64;
+			let info = reader.u8(symbol + 12)?;
+			let index = reader.u16(symbol + 14)?;
+
+			let binding = match info >> 4 {
+				STB_LOCAL => Binding::Local,
+				STB_GLOBAL | STB_WEAK => Binding::Global,
+				_ => continue
+			};
+			if !matches!(info & 0xf, STT_NOTYPE | STT_OBJECT | STT_FUNC) || index == SHN_UNDEF || (index >= SHN_LORESERVE && index != SHN_ABS) {
+				continue;
+			}
+
+			let name = reader.u32(symbol)? as usize;
+			let name = match strings.get(name..).and_then(|rest| rest.split(|byte| *byte == 0).next()) {
+				Some(name) if !name.is_empty() => String::from_utf8_lossy(name).to_string(),
+				_ => continue
+			};
+
+			// the upper half of the address space belongs to the kernel
+			let address = reader.u32(symbol + 4)?;
+			let executable = sections.get(index as usize).is_some_and(|section| section.flags & SHF_EXECINSTR != 0);
+			let section = match (executable, address >= mips::KTEXT_BASE) {
+				(true, false) => Section::Text,
+				(false, false) => Section::Data,
+				(true, true) => Section::KernelText,
+				(false, true) => Section::KernelData
+			};
+
+			labels.push(Label {
+				name,
+				address,
+				section,
+				binding,
+				segment: None
+			});
+		}
+	}
+
+	Ok(Executable { endian, entry, segments, labels })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::link;
+	use crate::source::SourceMap;
+
+	fn program(text: &str, endian: Endian) -> Program {
+		let mut sources = SourceMap::new();
+		let file = sources.add_text("main.asm", text);
+		let object = crate::assemble_file(&mut sources, file, endian).unwrap().1;
+		link::link(&[object], &link::Config::default()).unwrap()
+	}
+
+	const SOURCE: &str = ".globl main\n.data\nvalue: .word 42\n.text\nstart: nop\nmain: lw $t0, value\n.ktext 0x80000180\nhandler: eret\n";
+
+	#[test]
+	fn reads_back_what_it_writes() {
+		for endian in [Endian::Little, Endian::Big] {
+			let program = program(SOURCE, endian);
+			let executable = read(&write(&program)).unwrap();
+
+			assert_eq!(executable.endian, endian);
+			assert_eq!(executable.entry, program.entry);
+			for section in [Section::Text, Section::Data, Section::KernelText] {
+				let segment = executable.segments.iter().find(|segment| segment.address == program.base(section)).unwrap();
+				assert_eq!(&segment.bytes[..program.section(section).len()], program.section(section).as_slice());
+				assert_eq!(segment.executable, section.executable());
+			}
+
+			for label in &program.labels {
+				let read = executable.labels.iter().find(|other| other.name == label.name).unwrap();
+				assert_eq!((read.address, read.section, read.binding), (label.address, label.section, label.binding));
+			}
+			assert_eq!(executable.labels.len(), program.labels.len());
+		}
+	}
+
+	#[test]
+	fn refuses_what_is_not_an_executable() {
+		assert!(read(b"not an executable").is_err());
+		let bytes = write(&program(SOURCE, Endian::Little));
+		assert!(read(&bytes[..HEADER_SIZE as usize + 8]).is_err());
+	}
+
+	// the executable with a word of it changed
+	fn patched(bytes: &[u8], offset: usize, value: u32) -> Vec<u8> {
+		let mut bytes = bytes.to_vec();
+		Endian::Little.write_u32(&mut bytes[offset..], value);
+		bytes
+	}
+
+	#[test]
+	fn refuses_headers_which_lead_out_of_the_file() {
+		let bytes = write(&program(SOURCE, Endian::Little));
+		let reader = Reader { endian: Endian::Little, bytes: &bytes };
+		let (program_headers, section_headers) = (reader.u32(28).unwrap() as usize, reader.u32(32).unwrap() as usize);
+
+		// headers placed where adding to them would overflow
+		assert_eq!(read(&patched(&bytes, 28, 0xfffffff0)).err().as_deref(), Some("is truncated"));
+		assert_eq!(read(&patched(&bytes, 32, 0xffffffe0)).err().as_deref(), Some("is truncated"));
+		assert_eq!(read(&patched(&bytes, program_headers + 4, 0xffffff00)).err().as_deref(), Some("is truncated"));
+
+		// a symbol table at the very end of the address space
+		let symtab = (0..reader.u16(48).unwrap() as usize)
+			.map(|idx| section_headers + idx * SECTION_HEADER_SIZE as usize)
+			.find(|header| reader.u32(*header as u64 + 4).unwrap() == SHT_SYMTAB)
+			.unwrap();
+		assert_eq!(read(&patched(&bytes, symtab + 16, 0xfffffff8)).err().as_deref(), Some("is truncated"));
+	}
+
+	#[test]
+	fn refuses_segments_claiming_too_much_memory() {
+		let bytes = write(&program(SOURCE, Endian::Little));
+		let program_headers = Reader { endian: Endian::Little, bytes: &bytes }.u32(28).unwrap() as usize;
+
+		let error = read(&patched(&bytes, program_headers + 20, 0xffffff00)).err().unwrap_or_default();
+		assert!(error.contains("runs past the end of memory"), "{}", error);
+		let error = read(&patched(&bytes, program_headers + 20, 0x20000000)).err().unwrap_or_default();
+		assert!(error.contains("more than 256MB"), "{}", error);
+	}
+
+	#[test]
+	fn never_panics_on_a_damaged_file() {
+		let bytes = write(&program(SOURCE, Endian::Little));
+		for len in 0..bytes.len() {
+			let _ = read(&bytes[..len]);
+		}
+		for offset in 0..bytes.len().min(1024) {
+			for value in [0xff, 0x80] {
+				let mut damaged = bytes.clone();
+				damaged[offset] = value;
+				let _ = read(&damaged);
+			}
+		}
+	}
+}
@@ -0,0 +1,303 @@
+use crate::parse::instructions::Instruction;
+
+// how a basic instruction is laid out in a word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+	Special(u32),		// opcode 0, operation given by funct
+	Special2(u32),		// opcode 0x1c, operation given by funct
+	RegImm(u32),		// opcode 1, operation given in the rt field
+	Immediate(u32),		// I-type, by opcode
+	Jump(u32),			// J-type, by opcode
+	MoveCondition(u32),	// movf and movt, opcode 0 with funct 1, by the true bit of the condition field in rt
+	Cop1(u32, u32),		// opcode 0x11, by format in the rs field and funct
+	Cop1Condition(u32),	// movf.fmt and movt.fmt, opcode 0x11 with funct 0x11, by format and the true bit, as (fmt << 1 | tf)
+	Cop1Move(u32),		// mfc1, cfc1, mtc1 and ctc1, by the rs field
+	Cop1Branch(u32),	// bc1f and bc1t, by the true bit in rt
+	Cop0(u32),			// opcode 0x10 with the CO bit set, by funct
+	Cop0Move(u32)		// mfc0 and mtc0, by the rs field
+}
+
+// formats of coprocessor 1 operations, given in the rs field
+const SINGLE: u32 = 0x10;
+const DOUBLE: u32 = 0x11;
+const WORD: u32 = 0x14;
+
+// a basic instruction with its fields, as stored in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Machine {
+	pub instruction: Instruction,
+	pub rs: u32,
+	pub rt: u32,
+	pub rd: u32,
+	pub shamt: u32,
+	pub immediate: u32	// 16 bits for I-type, 26 bits for J-type, the code of syscall and break
+}
+// floating point operations keep ft in rt, fs in rd and fd in shamt, where their fields lie in the word
+// condition flags are kept as cc << 2, in shamt for comparisons and in rt for branches and conditional moves
+
+impl Machine {
+	pub fn new(instruction: Instruction) -> Machine {
+		Machine {
+			instruction,
+			rs: 0,
+			rt: 0,
+			rd: 0,
+			shamt: 0,
+			immediate: 0
+		}
+	}
+
+	// the immediate as a signed 16 bit value
+	pub fn signed(&self) -> i32 {
+		self.immediate as u16 as i16 as i32
+	}
+}
+
+impl Instruction {
+	// pseudo-instructions have no encoding of their own
+	pub fn encoding(&self) -> Option<Encoding> {
+		use Instruction::*;
+		use Encoding::*;
+		Some(match self {
+			ShiftLeftLogical => Special(0x00),
+			ShiftRightLogical => Special(0x02),
+			ShiftRightArithmetic => Special(0x03),
+			ShiftLeftLogicalVariable => Special(0x04),
+			ShiftRightLogicalVariable => Special(0x06),
+			ShiftRightArithmeticVariable => Special(0x07),
+			JumpRegister => Special(0x08),
+			JumpAndLinkRegister => Special(0x09),
+			MoveConditionalZero => Special(0x0a),
+			MoveConditionalNotZero => Special(0x0b),
+			SystemCall => Special(0x0c),
+			Break => Special(0x0d),
+			MoveFromHi => Special(0x10),
+			MoveToHi => Special(0x11),
+			MoveFromLo => Special(0x12),
+			MoveToLo => Special(0x13),
+			Multiply => Special(0x18),
+			MultiplyUnsigned => Special(0x19),
+			Divide => Special(0x1a),
+			DivideUnsigned => Special(0x1b),
+			Add => Special(0x20),
+			AddUnsigned => Special(0x21),
+			Subtract => Special(0x22),
+			SubtractUnsigned => Special(0x23),
+			And => Special(0x24),
+			Or => Special(0x25),
+			Xor => Special(0x26),
+			Nor => Special(0x27),
+			SetLessThan => Special(0x2a),
+			SetLessThanUnsigned => Special(0x2b),
+			TrapGreaterEqual => Special(0x30),
+			TrapGreaterEqualUnsigned => Special(0x31),
+			TrapLessThan => Special(0x32),
+			TrapLessThanUnsigned => Special(0x33),
+			TrapEqual => Special(0x34),
+			TrapNotEqual => Special(0x36),
+
+			MultiplyAdd => Special2(0x00),
+			MultiplyAddUnsigned => Special2(0x01),
+			MultiplyToRegister => Special2(0x02),
+			MultiplySubtract => Special2(0x04),
+			MultiplySubtractUnsigned => Special2(0x05),
+			CountLeadingZeros => Special2(0x20),
+			CountLeadingOnes => Special2(0x21),
+
+			BranchLessThanZero => RegImm(0x00),
+			BranchGreaterEqualZero => RegImm(0x01),
+			TrapGreaterEqualImmediate => RegImm(0x08),
+			TrapGreaterEqualImmediateUnsigned => RegImm(0x09),
+			TrapLessThanImmediate => RegImm(0x0a),
+			TrapLessThanImmediateUnsigned => RegImm(0x0b),
+			TrapEqualImmediate => RegImm(0x0c),
+			TrapNotEqualImmediate => RegImm(0x0e),
+			BranchLessThanZeroAndLink => RegImm(0x10),
+			BranchGreaterEqualZeroAndLink => RegImm(0x11),
+
+			Instruction::Jump => Encoding::Jump(0x02),
+			JumpAndLink => Encoding::Jump(0x03),
+
+			BranchEqual => Immediate(0x04),
+			BranchNotEqual => Immediate(0x05),
+			BranchLessEqualZero => Immediate(0x06),
+			BranchGreaterThanZero => Immediate(0x07),
+			AddImmediate => Immediate(0x08),
+			AddImmediateUnsigned => Immediate(0x09),
+			SetLessThanImmediate => Immediate(0x0a),
+			SetLessThanImmediateUnsigned => Immediate(0x0b),
+			AndImmediate => Immediate(0x0c),
+			OrImmediate => Immediate(0x0d),
+			XorImmediate => Immediate(0x0e),
+			LoadUpperImmediate => Immediate(0x0f),
+			LoadByte => Immediate(0x20),
+			LoadHalf => Immediate(0x21),
+			LoadWordLeft => Immediate(0x22),
+			LoadWord => Immediate(0x23),
+			LoadByteUnsigned => Immediate(0x24),
+			LoadHalfUnsigned => Immediate(0x25),
+			LoadWordRight => Immediate(0x26),
+			StoreByte => Immediate(0x28),
+			StoreHalf => Immediate(0x29),
+			StoreWordLeft => Immediate(0x2a),
+			StoreWord => Immediate(0x2b),
+			StoreWordRight => Immediate(0x2e),
+			LoadLinked => Immediate(0x30),
+			StoreConditional => Immediate(0x38),
+			LoadWordFloat => Immediate(0x31),
+			LoadDoubleFloat => Immediate(0x35),
+			StoreWordFloat => Immediate(0x39),
+			StoreDoubleFloat => Immediate(0x3d),
+
+			MoveOnFalse => MoveCondition(0),
+			MoveOnTrue => MoveCondition(1),
+
+			AddSingle => Cop1(SINGLE, 0x00),
+			AddDouble => Cop1(DOUBLE, 0x00),
+			SubtractSingle => Cop1(SINGLE, 0x01),
+			SubtractDouble => Cop1(DOUBLE, 0x01),
+			MultiplySingle => Cop1(SINGLE, 0x02),
+			MultiplyDouble => Cop1(DOUBLE, 0x02),
+			DivideSingle => Cop1(SINGLE, 0x03),
+			DivideDouble => Cop1(DOUBLE, 0x03),
+			SquareRootSingle => Cop1(SINGLE, 0x04),
+			SquareRootDouble => Cop1(DOUBLE, 0x04),
+			AbsoluteSingle => Cop1(SINGLE, 0x05),
+			AbsoluteDouble => Cop1(DOUBLE, 0x05),
+			MoveSingle => Cop1(SINGLE, 0x06),
+			MoveDouble => Cop1(DOUBLE, 0x06),
+			NegateSingle => Cop1(SINGLE, 0x07),
+			NegateDouble => Cop1(DOUBLE, 0x07),
+			RoundWordSingle => Cop1(SINGLE, 0x0c),
+			RoundWordDouble => Cop1(DOUBLE, 0x0c),
+			TruncateWordSingle => Cop1(SINGLE, 0x0d),
+			TruncateWordDouble => Cop1(DOUBLE, 0x0d),
+			CeilWordSingle => Cop1(SINGLE, 0x0e),
+			CeilWordDouble => Cop1(DOUBLE, 0x0e),
+			FloorWordSingle => Cop1(SINGLE, 0x0f),
+			FloorWordDouble => Cop1(DOUBLE, 0x0f),
+			ConvertSingleDouble => Cop1(DOUBLE, 0x20),
+			ConvertSingleWord => Cop1(WORD, 0x20),
+			ConvertDoubleSingle => Cop1(SINGLE, 0x21),
+			ConvertDoubleWord => Cop1(WORD, 0x21),
+			ConvertWordSingle => Cop1(SINGLE, 0x24),
+			ConvertWordDouble => Cop1(DOUBLE, 0x24),
+			CompareEqualSingle => Cop1(SINGLE, 0x32),
+			CompareEqualDouble => Cop1(DOUBLE, 0x32),
+			CompareLessThanSingle => Cop1(SINGLE, 0x3c),
+			CompareLessThanDouble => Cop1(DOUBLE, 0x3c),
+			CompareLessEqualSingle => Cop1(SINGLE, 0x3e),
+			CompareLessEqualDouble => Cop1(DOUBLE, 0x3e),
+
+			MoveOnFalseSingle => Cop1Condition(SINGLE << 1),
+			MoveOnTrueSingle => Cop1Condition(SINGLE << 1 | 1),
+			MoveOnFalseDouble => Cop1Condition(DOUBLE << 1),
+			MoveOnTrueDouble => Cop1Condition(DOUBLE << 1 | 1),
+
+			MoveFromFloat => Cop1Move(0x00),
+			MoveToFloat => Cop1Move(0x04),
+			MoveControlFromFloat => Cop1Move(0x02),
+			MoveControlToFloat => Cop1Move(0x06),
+
+			BranchFloatFalse => Cop1Branch(0),
+			BranchFloatTrue => Cop1Branch(1),
+
+			MoveFromCoprocessor0 => Cop0Move(0x00),
+			MoveToCoprocessor0 => Cop0Move(0x04),
+			ExceptionReturn => Cop0(0x18),
+
+			_ => return None
+		})
+	}
+
+	// instructions of the floating point unit, or which refer to its condition flags
+	pub fn is_float(&self) -> bool {
+		matches!(self.encoding(), Some(
+			Encoding::MoveCondition(_) | Encoding::Cop1(_, _) | Encoding::Cop1Condition(_) | Encoding::Cop1Move(_) | Encoding::Cop1Branch(_) |
+			Encoding::Immediate(0x31 | 0x35 | 0x39 | 0x3d)
+		))
+	}
+
+	// conditional branches, of the cpu and on the condition flags of the floating point unit
+	pub fn is_branch(&self) -> bool {
+		matches!(self.encoding(), Some(
+			Encoding::RegImm(0x00 | 0x01 | 0x10 | 0x11) | Encoding::Immediate(0x04..=0x07) | Encoding::Cop1Branch(_)
+		))
+	}
+
+	// j, jal, jr and jalr
+	pub fn is_jump(&self) -> bool {
+		matches!(self.encoding(), Some(Encoding::Jump(_) | Encoding::Special(0x08 | 0x09)))
+	}
+}
+
+pub fn encode(machine: &Machine) -> u32 {
+	let encoding = machine.instruction.encoding().expect("Pseudo-instructions are expanded before encoding.");
+
+	let registers = (machine.rs & 0x1f) << 21 | (machine.rt & 0x1f) << 16;
+	match encoding {
+		Encoding::Special(funct) => match machine.instruction {
+			Instruction::SystemCall | Instruction::Break => (machine.immediate & 0xfffff) << 6 | funct,
+			_ => registers | (machine.rd & 0x1f) << 11 | (machine.shamt & 0x1f) << 6 | funct
+		},
+		Encoding::Special2(funct) => 0x1c << 26 | registers | (machine.rd & 0x1f) << 11 | funct,
+		Encoding::RegImm(code) => 0x01 << 26 | (machine.rs & 0x1f) << 21 | code << 16 | (machine.immediate & 0xffff),
+		Encoding::Immediate(opcode) => opcode << 26 | registers | (machine.immediate & 0xffff),
+		Encoding::Jump(opcode) => opcode << 26 | (machine.immediate & 0x3ffffff),
+		Encoding::MoveCondition(tf) => (machine.rs & 0x1f) << 21 | (machine.rt & 0x1c | tf) << 16 | (machine.rd & 0x1f) << 11 | 0x01,
+		Encoding::Cop1(fmt, funct) => 0x11 << 26 | fmt << 21 | (machine.rt & 0x1f) << 16 | (machine.rd & 0x1f) << 11 | (machine.shamt & 0x1f) << 6 | funct,
+		Encoding::Cop1Condition(code) => 0x11 << 26 | (code >> 1) << 21 | (machine.rt & 0x1c | code & 1) << 16 | (machine.rd & 0x1f) << 11 | (machine.shamt & 0x1f) << 6 | 0x11,
+		Encoding::Cop1Move(code) => 0x11 << 26 | code << 21 | (machine.rt & 0x1f) << 16 | (machine.rd & 0x1f) << 11,
+		Encoding::Cop1Branch(tf) => 0x11 << 26 | 0x08 << 21 | (machine.rt & 0x1c | tf) << 16 | (machine.immediate & 0xffff),
+		Encoding::Cop0(funct) => 0x10 << 26 | 0x10 << 21 | funct,
+		Encoding::Cop0Move(code) => 0x10 << 26 | code << 21 | (machine.rt & 0x1f) << 16 | (machine.rd & 0x1f) << 11
+	}
+}
+
+pub fn decode(word: u32) -> Option<Machine> {
+	let opcode = word >> 26;
+	let funct = word & 0x3f;
+	let rt = (word >> 16) & 0x1f;
+
+	let rs = (word >> 21) & 0x1f;
+	let encoding = match opcode {
+		0x00 if funct == 0x01 => Encoding::MoveCondition(rt & 1),
+		0x00 => Encoding::Special(funct),
+		0x11 => match rs {
+			0x00 | 0x02 | 0x04 | 0x06 => Encoding::Cop1Move(rs),
+			0x08 => Encoding::Cop1Branch(rt & 1),
+			_ if funct == 0x11 => Encoding::Cop1Condition(rs << 1 | rt & 1),
+			_ => Encoding::Cop1(rs, funct)
+		},
+		0x10 => match rs {
+			0x10 => Encoding::Cop0(funct),
+			_ => Encoding::Cop0Move(rs)
+		},
+		0x1c => Encoding::Special2(funct),
+		0x01 => Encoding::RegImm(rt),
+		0x02 | 0x03 => Encoding::Jump(opcode),
+		_ => Encoding::Immediate(opcode)
+	};
+
+	let instruction = *Instruction::ALL.iter().find(|instruction| instruction.encoding() == Some(encoding))?;
+
+	let mut machine = Machine {
+		instruction,
+		rs,
+		rt,
+		rd: (word >> 11) & 0x1f,
+		shamt: (word >> 6) & 0x1f,
+		immediate: word & 0xffff
+	};
+
+	match encoding {
+		Encoding::Jump(_) => machine.immediate = word & 0x3ffffff,
+		Encoding::Special(_) if matches!(instruction, Instruction::SystemCall | Instruction::Break) => machine.immediate = (word >> 6) & 0xfffff,
+		Encoding::Special(_) | Encoding::Special2(_) | Encoding::MoveCondition(_) |
+		Encoding::Cop1(_, _) | Encoding::Cop1Condition(_) | Encoding::Cop1Move(_) |
+		Encoding::Cop0(_) | Encoding::Cop0Move(_) => machine.immediate = 0,
+		_ => {}
+	}
+
+	Some(machine)
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use crate::lexer::tokens::CodeSegment;
+use crate::source::SourceMap;
 
 use crate::mips;
 
@@ -15,7 +16,11 @@ pub enum Msg {
 #[derive(Debug)]
 pub enum ErrType {
 	Syntax,
-	Assemble
+	Assemble,
+	Include,
+	Link,
+	Runtime,
+	Limit
 }
 
 #[derive(Debug)]
@@ -27,59 +32,98 @@ pub struct Err {
 
 pub struct DisplayableErr {
 	pub err: Err,
-	pub line: String
+	pub lines: Vec<(String, String)>	// "file:line:col" and text of the error's line, followed by those of each macro invocation it was expanded from
 }
 
 impl DisplayableErr {
-	pub fn new(err: Err, line: &String) -> DisplayableErr {
+	pub fn new(err: Err, sources: &SourceMap) -> DisplayableErr {
+		let mut lines = Vec::new();
+
+		let mut segment = Some(&err.segment);
+		while let Some(current) = segment {
+			lines.push((sources.location(current), sources.line(current).to_string()));
+			segment = current.expanded_from.as_deref();
+		}
+
 		DisplayableErr {
 			err,
-			line: line.to_string()
+			lines
 		}
 	}
 }
 
+// writes the line with the segment highlighted, ending on a newline
+fn highlight(f: &mut fmt::Formatter, line: &str, segment: &CodeSegment) -> fmt::Result {
+	let line = mips::syntax_highlight(line.trim().to_string());
+
+	let range = segment.idx..(segment.idx + segment.len);
+
+	for (idx, character) in line.chars().enumerate() {
+		if range.contains(&idx) {
+			write!(f, "{}", character.to_string().red())?;
+		} else {
+			write!(f, "{}", character)?;
+		}
+	}
+
+	writeln!(f)
+}
+
 impl fmt::Display for DisplayableErr {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		let errtype = match self.err.errtype {
 			ErrType::Syntax => "syntax error",
-			ErrType::Assemble => "parse error"
+			ErrType::Assemble => "parse error",
+			ErrType::Include => "include error",
+			ErrType::Link => "link error",
+			ErrType::Runtime => "runtime error",
+			ErrType::Limit => "limit exceeded"
 		}.to_string().bright_black();
 
-		let prelude = format!("{} ({}) on line {} at {}.", "Error".red().bold(), errtype, self.err.segment.line + 1, self.err.segment.idx + 1);
-
-		let mut line = &self.line;
-		let mut line = line.trim().to_string();
-		let mut line = mips::syntax_highlight(line);
-
-		write!(f, "{}\n", prelude);
-
-		let range = self.err.segment.idx..(self.err.segment.idx + self.err.segment.len);
-
-		for idx in 0..line.len() {
-            if range.contains(&idx) {
-                write!(f, "{}", line.chars().nth(idx).expect("This should not fail.").to_string().red());
-            } else {
-                write!(f, "{}", line.chars().nth(idx).expect("This should not fail."));
-            }
-        }
-
-        write!(f, "\n");
-
-        match &self.err.msg {
-        	Msg::One(msg) => {
-        		write!(f, "{}{} {}", " ".repeat(self.err.segment.idx), "^".repeat(self.err.segment.len).red().bold(), msg.bold());
-        	},
-        	Msg::Many(msgs) => {
-        		for (idx, msg) in msgs.iter().enumerate() {
-        			write!(f, "{}{} {}", " ".repeat(self.err.segment.idx), "^".repeat(self.err.segment.len).red().bold(), msg.bold());
-        			if idx <(msgs.len() - 1) {
-        				write!(f, "\n");
-        			}
-        		}
-        	}
-        }
-
-        Ok(())
+		let segment = &self.err.segment;
+		let (location, line) = &self.lines[0];
+		let prelude = format!("{} ({}) at {}.", "Error".red().bold(), errtype, location);
+
+		writeln!(f, "{}", prelude)?;
+		highlight(f, line, segment)?;
+
+		match &self.err.msg {
+			Msg::One(msg) => {
+				write!(f, "{}{} {}", " ".repeat(segment.idx), "^".repeat(segment.len).red().bold(), msg.bold())?;
+			},
+			Msg::Many(msgs) => {
+				for (idx, msg) in msgs.iter().enumerate() {
+					write!(f, "{}{} {}", " ".repeat(segment.idx), "^".repeat(segment.len).red().bold(), msg.bold())?;
+					if idx < (msgs.len() - 1) {
+						writeln!(f)?;
+					}
+				}
+			}
+		}
+
+		let mut frames = Vec::new();
+		let mut expansion = &segment.expanded_from;
+		let mut lines = self.lines.iter().skip(1);
+		while let (Some(segment), Some((location, line))) = (expansion, lines.next()) {
+			frames.push((segment, location, line));
+			expansion = &segment.expanded_from;
+		}
+
+		// a macro invoking itself is invoked from the same place over and over, which is noted once
+		let mut idx = 0;
+		while let Some((segment, location, line)) = frames.get(idx) {
+			let times = frames[idx..].iter()
+				.take_while(|(other, other_location, _)| other_location == location && other.idx == segment.idx && other.len == segment.len)
+				.count();
+			let repeated = if times > 1 { format!(" ({} times)", times) } else { String::new() };
+
+			writeln!(f)?;
+			writeln!(f, "{} expanded from macro invoked at {}{}.", "Note:".bright_black().bold(), location, repeated)?;
+			highlight(f, line, segment)?;
+			write!(f, "{}{}", " ".repeat(segment.idx), "^".repeat(segment.len).bright_black().bold())?;
+			idx += times;
+		}
+
+		Ok(())
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::runtime::{Exit, Fault, Limit, Limits, Machine};
+use crate::runtime::clock::Instant;
+use crate::runtime::console::Buffer;
+
+// the limits of a case when neither it nor the spec gives them, so that no submission can run away with the grader
+pub const MAX_STEPS: u64 = 1_000_000;
+pub const MAX_MEMORY: u64 = 256 << 20;
+pub const MAX_OUTPUT: u64 = 1 << 20;
+pub const MAX_TIME: Duration = Duration::from_secs(10);
+
+// the cases a program is tested against, read from toml or yaml
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+	#[serde(default)]
+	pub program: Vec<String>,		// the files of the program, relative to the spec, unless others are given
+	#[serde(default)]
+	pub max_steps: Option<u64>,
+	#[serde(default)]
+	pub max_memory: Option<u64>,	// in bytes
+	#[serde(default)]
+	pub max_output: Option<u64>,
+	#[serde(default)]
+	pub timeout: Option<f64>,		// in seconds
+	#[serde(default)]
+	pub delay_slots: bool,
+	#[serde(default)]
+	pub fs: Option<String>,			// a directory or tar archive the virtual filesystem of every case starts with
+	#[serde(rename = "case", alias = "cases")]
+	pub cases: Vec<Case>
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+	pub name: String,
+	#[serde(default)]
+	pub input: String,
+	pub stdout: Option<String>,			// the whole output, exactly
+	pub stdout_regex: Option<String>,	// found anywhere in the output
+	pub stderr: Option<String>,			// the whole of what was written to stderr, which is otherwise not looked at
+	pub exit: Option<i32>,
+	#[serde(default)]
+	pub registers: BTreeMap<String, Value>,
+	#[serde(default)]
+	pub memory: BTreeMap<String, Value>,	// words by address or label, such as `0x10010000` or `result+4`
+	#[serde(default)]
+	pub files: BTreeMap<String, String>,		// in the virtual filesystem before the case runs
+	#[serde(default)]
+	pub expect_files: BTreeMap<String, String>,	// in it afterwards, exactly
+	pub max_steps: Option<u64>,
+	pub max_memory: Option<u64>,
+	pub max_output: Option<u64>,
+	pub timeout: Option<f64>
+}
+
+// a value expected at exit, as a number or a string such as "0xffffffff"
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Value {
+	Number(i64),
+	Text(String)
+}
+
+impl Value {
+	fn word(&self) -> Result<u32, String> {
+		match self {
+			Value::Number(number) if (i32::MIN as i64..=u32::MAX as i64).contains(number) => Ok(*number as u32),
+			Value::Number(number) => Err(format!("{} does not fit in a word", number)),
+			Value::Text(text) => number(text).ok_or(format!("\"{}\" is not a number", text))
+		}
+	}
+}
+
+fn number(text: &str) -> Option<u32> {
+	match text.strip_prefix("0x") {
+		Some(hex) => u32::from_str_radix(hex, 16).ok(),
+		None => text.parse::<i32>().map(|value| value as u32).or_else(|_| text.parse::<u32>()).ok()
+	}
+}
+
+impl Spec {
+	// the limits of a case, each given by the case, or else by the spec, or else the default
+	pub fn limits(&self, case: &Case) -> Result<Limits, String> {
+		let timeout = case.timeout.or(self.timeout)
+			.map(|seconds| Duration::try_from_secs_f64(seconds).map_err(|_| format!("{} is not a number of seconds", seconds)))
+			.transpose()?
+			.unwrap_or(MAX_TIME);
+		Ok(Limits {
+			steps: Some(case.max_steps.or(self.max_steps).unwrap_or(MAX_STEPS)),
+			memory: Some(case.max_memory.or(self.max_memory).unwrap_or(MAX_MEMORY)),
+			output: Some(case.max_output.or(self.max_output).unwrap_or(MAX_OUTPUT)),
+			time: Some(timeout)
+		})
+	}
+
+	pub fn read(path: &Path) -> Result<Spec, String> {
+		let text = std::fs::read_to_string(path).map_err(|why| why.to_string())?;
+		match path.extension().and_then(|extension| extension.to_str()) {
+			Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|why| why.to_string()),
+			_ => toml::from_str(&text).map_err(|why| why.to_string())
+		}
+	}
+}
+
+// how a case went, with everything it got wrong
+pub struct Outcome {
+	pub name: String,
+	pub failures: Vec<String>,
+	pub steps: u64,
+	pub time: Duration
+}
+
+impl Outcome {
+	pub fn passed(&self) -> bool {
+		self.failures.is_empty()
+	}
+}
+
+// runs a case on a machine of its own, made with the limits of the case, checking what it printed and left behind once it ends
+pub fn run(case: &Case, mut machine: Machine) -> Outcome {
+	let start = Instant::now();
+	machine.io = Box::new(Buffer::new(case.input.clone().into_bytes()));
+
+	let mut failures = Vec::new();
+	for (name, contents) in &case.files {
+		if let Err(why) = machine.files.add(name, contents.clone().into_bytes()) {
+			failures.push(why);
+		}
+	}
+
+	let exit = match machine.run_until(|_| false) {
+		Ok(exit) => exit,
+		Err(Fault::Limit(Limit::Steps(steps))) => {
+			failures.push(format!("did not finish within {} instructions", steps));
+			None
+		},
+		Err(fault @ Fault::Limit(_)) => {
+			failures.push(format!("stopped at {:#010x}: {}", machine.pc, fault));
+			None
+		},
+		Err(fault) => {
+			failures.push(format!("faulted at {:#010x}: {}", machine.pc, fault));
+			None
+		}
+	};
+
+	let output = String::from_utf8_lossy(machine.io.written().unwrap_or_default()).into_owned();
+	if let Some(expected) = &case.stdout {
+		if output != *expected {
+			failures.push(format!("printed {:?} where {:?} was expected", output, expected));
+		}
+	}
+	if let Some(expected) = &case.stderr {
+		let error = String::from_utf8_lossy(machine.io.written_error().unwrap_or_default()).into_owned();
+		if error != *expected {
+			failures.push(format!("wrote {:?} to stderr where {:?} was expected", error, expected));
+		}
+	}
+	if let Some(pattern) = &case.stdout_regex {
+		match Regex::new(pattern) {
+			Ok(regex) if !regex.is_match(&output) => failures.push(format!("printed {:?}, which does not match /{}/", output, pattern)),
+			Ok(_) => {},
+			Err(why) => failures.push(format!("the pattern /{}/ is not valid: {}", pattern, why))
+		}
+	}
+
+	// running off the end of the text is taken as exiting with 0, as MARS does
+	if let (Some(expected), Some(exit)) = (case.exit, exit) {
+		let code = match exit {
+			Exit::Code(code) => code,
+			Exit::FellOff => 0
+		};
+		if code != expected {
+			failures.push(format!("exited with {} where {} was expected", code, expected));
+		}
+	}
+
+	// state is only checked once the program has exited, a fault or running too long having already failed the case
+	if exit.is_some() {
+		for (name, expected) in &case.registers {
+			match (machine.named_register(name), expected.word()) {
+				(Some(found), Ok(expected)) if found != expected =>
+					failures.push(format!("{} was {:#010x} ({}) where {:#010x} ({}) was expected", name, found, found as i32, expected, expected as i32)),
+				(Some(_), Ok(_)) => {},
+				(None, _) => failures.push(format!("\"{}\" is not a register", name)),
+				(_, Err(why)) => failures.push(format!("the value expected in {} is not valid: {}", name, why))
+			}
+		}
+		for (place, expected) in &case.memory {
+			match (address(&machine, place), expected.word()) {
+				(Some(address), Ok(expected)) => {
+					let found = machine.read_word(address);
+					if found != expected {
+						failures.push(format!("the word at {} was {:#010x} ({}) where {:#010x} ({}) was expected", place, found, found as i32, expected, expected as i32));
+					}
+				},
+				(None, _) => failures.push(format!("\"{}\" is neither an address nor a label", place)),
+				(_, Err(why)) => failures.push(format!("the value expected at {} is not valid: {}", place, why))
+			}
+		}
+		for (name, expected) in &case.expect_files {
+			match machine.files.get(name) {
+				Some(found) if found == expected.as_bytes() => {},
+				Some(found) => failures.push(format!("the file {} held {:?} where {:?} was expected", name, String::from_utf8_lossy(found), expected)),
+				None => failures.push(format!("the file {} was not written", name))
+			}
+		}
+	}
+
+	Outcome { name: case.name.clone(), failures, steps: machine.steps, time: start.elapsed() }
+}
+
+// an address written as a number, or a label with an optional offset such as `array+8`
+fn address(machine: &Machine, place: &str) -> Option<u32> {
+	if let Some(address) = number(place) {
+		return Some(address);
+	}
+
+	let (name, offset) = match place.split_once('+') {
+		Some((name, offset)) => (name, number(offset.trim())?),
+		None => (place, 0)
+	};
+	machine.labels.iter().find(|label| label.name == name.trim()).map(|label| label.address.wrapping_add(offset))
+}
+
+// the outcomes as a JUnit test suite, each failure carrying all that the case got wrong
+pub fn junit(suite: &str, outcomes: &[Outcome]) -> String {
+	let failed = outcomes.iter().filter(|outcome| !outcome.passed()).count();
+	let time: f64 = outcomes.iter().map(|outcome| outcome.time.as_secs_f64()).sum();
+
+	let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+	let _ = writeln!(out, "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">", xml(suite), outcomes.len(), failed, time);
+	for outcome in outcomes {
+		let _ = write!(out, "  <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"", xml(&outcome.name), xml(suite), outcome.time.as_secs_f64());
+		match outcome.failures.first() {
+			None => out.push_str("/>\n"),
+			Some(first) => {
+				let _ = writeln!(out, ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>", xml(first), xml(&outcome.failures.join("\n")));
+			}
+		}
+	}
+	out.push_str("</testsuite>\n");
+	out
+}
+
+fn xml(text: &str) -> String {
+	let mut out = String::new();
+	for c in text.chars() {
+		match c {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\n' => out.push_str("&#10;"),
+			c if (c as u32) < 0x20 && c != '\t' => {},
+			c => out.push(c)
+		}
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runtime::Config;
+	use crate::source::SourceMap;
+
+	// reads a number, leaves one more in $t0 and result and prints it, writes "warn" to stderr and exits with 3
+	const PROGRAM: &str = ".data
+		result: .word 0
+		warning: .ascii \"warn\"
+		.text
+		main: li $v0, 5
+		syscall
+		addi $t0, $v0, 1
+		sw $t0, result
+		move $a0, $t0
+		li $v0, 1
+		syscall
+		li $v0, 15
+		li $a0, 2
+		la $a1, warning
+		li $a2, 4
+		syscall
+		li $v0, 17
+		li $a0, 3
+		syscall";
+
+	const SPEC: &str = r#"
+		max_steps = 1000
+		max_output = 100
+
+		[[case]]
+		name = "passes"
+		input = "41\n"
+		stdout = "42"
+		stderr = "warn"
+		exit = 3
+		registers = { t0 = 42, "$v0" = "0x11" }
+		memory = { result = 42, "result+4" = "0x6e726177", "0x10010000" = 42 }
+
+		[[case]]
+		name = "fails"
+		input = "1\n"
+		stdout_regex = "^4"
+		exit = 0
+		registers = { t0 = -1, t99 = 0 }
+		memory = { nowhere = 0 }
+		max_output = 5
+
+		[[case]]
+		name = "runs too long"
+		input = "1\n"
+		max_steps = 3
+	"#;
+
+	fn outcomes() -> Vec<Outcome> {
+		let spec: Spec = toml::from_str(SPEC).unwrap();
+		let mut sources = SourceMap::new();
+		sources.add_text("program.asm", PROGRAM);
+		let program = crate::assemble(&mut sources).unwrap();
+		spec.cases.iter().map(|case| {
+			let config = Config { limits: spec.limits(case).unwrap(), ..Config::default() };
+			run(case, Machine::new(&program, config))
+		}).collect()
+	}
+
+	#[test]
+	fn takes_the_limits_of_the_case_over_those_of_the_spec() {
+		let spec: Spec = toml::from_str(SPEC).unwrap();
+		let limits: Vec<(Option<u64>, Option<u64>, Option<u64>)> = spec.cases.iter()
+			.map(|case| spec.limits(case).unwrap())
+			.map(|limits| (limits.steps, limits.memory, limits.output))
+			.collect();
+		assert_eq!(limits, [
+			(Some(1000), Some(MAX_MEMORY), Some(100)),
+			(Some(1000), Some(MAX_MEMORY), Some(5)),
+			(Some(3), Some(MAX_MEMORY), Some(100))
+		]);
+
+		let spec: Spec = toml::from_str("timeout = 2.5\n[[case]]\nname = \"a\"\n[[case]]\nname = \"b\"\ntimeout = -1.0").unwrap();
+		assert_eq!(spec.limits(&spec.cases[0]).unwrap().steps, Some(MAX_STEPS));
+		assert_eq!(spec.limits(&spec.cases[0]).unwrap().time, Some(Duration::from_millis(2500)));
+		assert!(spec.limits(&spec.cases[1]).is_err());
+
+		let spec: Spec = toml::from_str("[[case]]\nname = \"a\"").unwrap();
+		assert_eq!(spec.limits(&spec.cases[0]).unwrap().time, Some(MAX_TIME));
+	}
+
+	#[test]
+	fn stops_a_case_which_sleeps_past_the_time_limit() {
+		let spec: Spec = toml::from_str("timeout = 0.1\n[[case]]\nname = \"sleeps\"\nexit = 0").unwrap();
+		let mut sources = SourceMap::new();
+		sources.add_text("sleep.asm", "li $a0, -1\nli $v0, 32\nsyscall");
+		let program = crate::assemble(&mut sources).unwrap();
+
+		let start = Instant::now();
+		let config = Config { limits: spec.limits(&spec.cases[0]).unwrap(), ..Config::default() };
+		let outcome = run(&spec.cases[0], Machine::new(&program, config));
+		assert!(start.elapsed() < Duration::from_secs(5));
+		assert_eq!(outcome.failures, ["stopped at 0x00400008: Exceeded the time limit of 0.1s."]);
+	}
+
+	#[test]
+	fn passes_a_case_which_does_what_it_expects() {
+		let outcomes = outcomes();
+		assert_eq!(outcomes[0].failures, Vec::<String>::new());
+		assert!(outcomes[0].passed());
+	}
+
+	#[test]
+	fn reports_everything_a_case_got_wrong() {
+		let outcomes = outcomes();
+		assert_eq!(outcomes[1].failures, [
+			"printed \"2\", which does not match /^4/",
+			"exited with 3 where 0 was expected",
+			"t0 was 0x00000002 (2) where 0xffffffff (-1) was expected",
+			"\"t99\" is not a register",
+			"\"nowhere\" is neither an address nor a label"
+		]);
+		assert_eq!(outcomes[2].failures, ["did not finish within 3 instructions"]);
+	}
+
+	#[test]
+	fn reads_expected_values_as_words() {
+		assert_eq!(Value::Number(-1).word(), Ok(u32::MAX));
+		assert_eq!(Value::Text("0xff".to_string()).word(), Ok(255));
+		assert_eq!(Value::Text("-2".to_string()).word(), Ok(-2i32 as u32));
+		assert!(Value::Number(1 << 32).word().is_err());
+		assert!(Value::Text("ten".to_string()).word().is_err());
+	}
+
+	#[test]
+	fn writes_failures_as_junit() {
+		let outcomes = [
+			Outcome { name: "a < b".to_string(), failures: Vec::new(), steps: 1, time: Duration::ZERO },
+			Outcome { name: "b".to_string(), failures: vec!["printed \"x\"".to_string(), "second".to_string()], steps: 1, time: Duration::ZERO }
+		];
+		let out = junit("suite", &outcomes);
+		assert!(out.contains("<testsuite name=\"suite\" tests=\"2\" failures=\"1\""));
+		assert!(out.contains("<testcase name=\"a &lt; b\" classname=\"suite\" time=\"0.000\"/>"));
+		assert!(out.contains("<failure message=\"printed &quot;x&quot;\">printed &quot;x&quot;&#10;second</failure>"));
+	}
+}
@@ -11,6 +11,7 @@ use colored::Colorize;
 pub struct Lexer {
 	// the lexer is initialized for each line
 
+	file: usize,	// file id
 	line: usize, 	// line number
 	text: String,	// line text
 	buffer: String,	// encountered symbols that cannot yet be tokenized
@@ -26,23 +27,28 @@ impl Lexer {
 
 	pub fn error(&self, idx: usize, len: usize, msg: errors::Msg) -> std::result::Result<Token, errors::Err> {
 		std::result::Result::Err(errors::Err {
-			segment: CodeSegment {
-				line: self.line,
-				idx,
-				len
-			},
+			segment: self.segment(idx, len),
 			errtype: errors::ErrType::Syntax,
 			msg
 		})
 	}
 
-	// return buffer size
-	pub fn len(&self) -> usize {
-		self.buffer.len()
+	pub fn segment(&self, idx: usize, len: usize) -> CodeSegment {
+		CodeSegment {
+			file: self.file,
+			line: self.line,
+			idx,
+			len,
+			expanded_from: None
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.buffer.is_empty()
 	}
 
 	pub fn verify_buffer(&self, consumer: &str) -> Result<Token, errors::Err> {
-		if self.buffer.len() > 0 {
+		if !self.buffer.is_empty() {
 			let msgs = errors::Msg::Many(vec![
 				format!("Trying to consume {} but buffer length = {}", consumer, self.buffer.len()),
 				format!("buffer = \"{}\"", self.buffer.red()),
@@ -53,27 +59,27 @@ impl Lexer {
 
 		Ok(Token::Empty)
 	}
+
+	// a buffered identifier ends at whitespace, punctuation, or the end of the line
+	fn flush(&mut self, idx: &mut usize, tokens: &mut Vec<Token>) -> LexRes<()> {
+		if !self.is_empty() {
+			tokens.push(consumers::Identifier::consume(idx, self)?);
+		}
+
+		Ok(())
+	}
 }
 
 type LexRes<T> = Result<T, errors::Err>;
 
-pub fn tokenize(program: &Vec<String>) -> LexRes<Vec<Token>> {
+pub fn tokenize(program: &[String], file: usize) -> LexRes<Vec<Token>> {
 	let mut tokens: Vec<Token> = Vec::new();
 
 	for (line_num, line) in program.iter().enumerate() {
-		let line = line.trim();
-		let line = line.to_string();
-
-		let final_call = || {
-			if line_num == program.len() - 1 { return " # autogenerated"; }
-			else { return ""; }
-		};
-
-		let line = format!("{}{}", line, final_call());
-
 		let mut lexer = Lexer {
+			file,
 			line: line_num,
-			text: line,
+			text: line.trim().to_string(),
 			buffer: String::new()
 		};
 
@@ -82,54 +88,42 @@ pub fn tokenize(program: &Vec<String>) -> LexRes<Vec<Token>> {
 			let character = lexer.idx(idx);
 			let token: Option<Token> = match character {
 				'#' => {
-					consumers::Comment::consume(&mut idx, &mut lexer);
+					lexer.flush(&mut idx, &mut tokens)?;
+					consumers::Comment::consume(&mut idx, &mut lexer)?;
 					None
 				}
 
-				'.' => {
-					match consumers::Directive::consume(&mut idx, &mut lexer) {
-						Ok(token) => Some(token),
-						Err(err) => return Err(err)
-					}
-				}
+				// a dot within an identifier is part of it, as in "add.s"
+				'.' if !lexer.is_empty() => {
+					lexer.buffer.push(character);
+					None
+				},
 
-				':' => {
-					match consumers::DefLabel::consume(&mut idx, &mut lexer) {
-						Ok(token) => Some(token),
-						Err(err) => return Err(err)
-					}
-				}
+				'.' => Some(consumers::Directive::consume(&mut idx, &mut lexer)?),
 
-				'$' => {
-					match consumers::Register::consume(&mut idx, &mut lexer) {
-						Ok(token) => Some(token),
-						Err(err) => return Err(err)
-					}
-				}
+				':' => Some(consumers::DefLabel::consume(&mut idx, &mut lexer)?),
+
+				'$' => Some(consumers::Register::consume(&mut idx, &mut lexer)?),
+
+				'%' => Some(consumers::MacroParameter::consume(&mut idx, &mut lexer)?),
+
+				'"' => Some(consumers::StringLiteral::consume(&mut idx, &mut lexer)?),
+
+				'0'..='9' | '-' if lexer.is_empty() => Some(consumers::NumberLiteral::consume(&mut idx, &mut lexer)?),
 
-				'"' => {
-					match consumers::StringLiteral::consume(&mut idx, &mut lexer) {
-						Ok(token) => Some(token),
-						Err(err) => return Err(err)
-					}
+				'(' => {
+					lexer.flush(&mut idx, &mut tokens)?;
+					Some(Token::LeftParen(lexer.segment(idx, 1)))
 				}
 
-				'0'..='9' | '-' => {
-					match consumers::NumberLiteral::consume(&mut idx, &mut lexer) {
-						Ok(token) => Some(token),
-						Err(err) => return Err(err)
-					}
+				')' => {
+					lexer.flush(&mut idx, &mut tokens)?;
+					Some(Token::RightParen(lexer.segment(idx, 1)))
 				}
 
-				' ' => {
-					if lexer.len() > 0 {
-						match consumers::Identifier::consume(&mut idx, &mut lexer) {
-							Ok(token) => Some(token),
-							Err(err) => return Err(err)
-						}
-					} else {
-						None
-					}
+				' ' | '\t' | ',' => {
+					lexer.flush(&mut idx, &mut tokens)?;
+					None
 				}
 
 				_ => {
@@ -138,31 +132,16 @@ pub fn tokenize(program: &Vec<String>) -> LexRes<Vec<Token>> {
 				}
 			};
 
-			if let Some(token) = token {
-				tokens.push(token);
+			match token {
+				Some(Token::Empty) | None => {},
+				Some(token) => tokens.push(token)
 			}
 
 			idx += 1;
 		}
 
-		if lexer.buffer.len() > 0 {
-			let msg = errors::Msg::Many(vec![
-				format!("Line ended but buffer length = {}", lexer.buffer.len()),
-				format!("buffer = \"{}\"", lexer.buffer.red()),
-				"Buffer should be empty.".to_string()
-			]);
-			let text = String::from(lexer.text);
-			return std::result::Result::Err(errors::Err {
-				segment: CodeSegment {
-					line: lexer.line,
-					idx,
-					len: 0
-				},
-				errtype: errors::ErrType::Syntax,
-				msg
-			});
-		}
+		lexer.flush(&mut idx, &mut tokens)?;
 	}
 
 	Ok(tokens)
-}
\ No newline at end of file
+}
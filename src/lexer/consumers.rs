@@ -1,5 +1,6 @@
 use crate::lexer::tokens::*;
-use crate::lexer::{self, *};
+use crate::lexer::*;
+use crate::parse::instructions;
 use crate::errors;
 use colored::Colorize;
 
@@ -7,6 +8,9 @@ pub trait Consumer {
 	fn consume(idx: &mut usize, lexer: &mut Lexer)  -> Result<Token, errors::Err>;
 }
 
+// consumers either stop on a character that ends the token without belonging to any other (whitespace, commas)
+// or stop one before a character which the main loop must see again (parentheses, comments)
+
 pub struct Comment{}
 impl Consumer for Comment {
 	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
@@ -18,17 +22,20 @@ impl Consumer for Comment {
 pub struct Directive{}
 impl Consumer for Directive {
 	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
+		let start = *idx;
 		*idx += 1; // skip the initial "."
 
-		if let Err(err) = lexer.verify_buffer("directive") {
-			return Err(err);
-		}
+		lexer.verify_buffer("directive")?;
 
 		while *idx < lexer.text.len() {
 			let character = lexer.idx(*idx);
 			match character {
 				'A'..='z' => lexer.buffer.push(character),
-				' ' => break,
+				' ' | '\t' => break,
+				'#' => {
+					*idx -= 1;
+					break;
+				},
 				_ => {
 					let msgs = errors::Msg::Many(vec![
 						format!("Illegal symbol \"{}\" while consuming directive.", character.to_string().red()),
@@ -44,11 +51,7 @@ impl Consumer for Directive {
 		let identifier = String::from(&lexer.buffer);
 		let len = identifier.len();
 		lexer.buffer.clear(); // remove our work
-		Ok(Token::Directive(identifier, CodeSegment {
-			line: lexer.line,
-			idx: *idx - len - 1,
-			len: len + 1
-		}))
+		Ok(Token::Directive(identifier, lexer.segment(start, len + 1)))
 	}
 }
 
@@ -58,11 +61,7 @@ impl Consumer for DefLabel {
 		// label definitions are given as "{name}:", so we have to use the buffered value
 		let identifier = lexer.buffer.drain(..).collect::<String>(); // we retain the value but clear the buffer
 		let len = identifier.len();
-		Ok(Token::DefLabel(identifier, CodeSegment {
-			line: lexer.line,
-			idx: *idx - len,
-			len: len + 1
-		}))
+		Ok(Token::DefLabel(identifier, lexer.segment(*idx - len, len + 1)))
 	}
 }
 
@@ -71,29 +70,27 @@ impl Consumer for Identifier {
 	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
 		let identifier = lexer.buffer.drain(..).collect::<String>(); // we retain the value but clear the buffer
 		let len = identifier.len();
-		Ok(Token::Identifier(identifier, CodeSegment {
-			line: lexer.line,
-			idx: *idx - len,
-			len: len
-		}))
+		Ok(Token::Identifier(identifier, lexer.segment(*idx - len, len)))
 	}
 }
 
 pub struct Register{}
 impl Consumer for Register {
 	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
+		let start = *idx;
 		*idx += 1; // registers are always ${id}, skip the $
-		
-		if let Err(err) = lexer.verify_buffer("register") {
-			return Err(err);
-		}
+
+		lexer.verify_buffer("register")?;
 
 		while *idx < lexer.text.len() {
 			let character = lexer.idx(*idx);
 			match character {
-				'a'..='z' => lexer.buffer.push(character),
-				'0'..='9' => lexer.buffer.push(character),
-				',' => break,
+				'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => lexer.buffer.push(character),
+				' ' | '\t' | ',' => break,
+				')' | '#' | ':' => {
+					*idx -= 1;
+					break;
+				},
 				_ => {
 					let msgs = errors::Msg::Many(vec![
 						format!("Illegal symbol \"{}\" while consuming register.", character.to_string().red()),
@@ -105,75 +102,128 @@ impl Consumer for Register {
 			*idx += 1;
 		}
 
-		if lexer.len() > 2 {
+		let identifier = String::from(&lexer.buffer);
+		let len = identifier.len();
+
+		if instructions::Register::from_name(&identifier).is_none() && instructions::FloatRegister::from_name(&identifier).is_none() {
+			// compilers name their local labels like "$L3", which we read as any other label
+			if identifier.starts_with(|c: char| c.is_ascii_uppercase() || c == '_') {
+				lexer.buffer.insert(0, '$');
+				if *idx + 1 < lexer.text.len() && lexer.idx(*idx + 1) == ':' {
+					return Ok(Token::Empty); // leave the name buffered for the label definition
+				}
+				return Identifier::consume(&mut (start + len + 1), lexer);
+			}
+
 			let msgs = errors::Msg::Many(vec![
-				format!("Illegal register form \"{}\".", lexer.buffer.red()),
-				"Registers' names do not exceed two characters.".to_string()
+				format!("Unknown register \"{}\".", lexer.buffer.red()),
+				"Registers are named $0 to $31 or by their conventional names such as $t0 or $sp, and $f0 to $f31 for floating point.".to_string()
 			]);
-			return lexer.error(*idx - lexer.len(), lexer.len(), msgs);
+			return lexer.error(start, len + 1, msgs);
 		}
 
+		lexer.buffer.clear(); // remove our work
+		Ok(Token::Register(identifier, lexer.segment(start, len + 1)))
+	}
+}
+
+pub struct MacroParameter{}
+impl Consumer for MacroParameter {
+	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
+		let start = *idx;
+		*idx += 1; // macro parameters are always %{id}, skip the %
+
+		lexer.verify_buffer("macro parameter")?;
+
+		while *idx < lexer.text.len() {
+			let character = lexer.idx(*idx);
+			match character {
+				'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => lexer.buffer.push(character),
+				' ' | '\t' | ',' => break,
+				')' | '#' => {
+					*idx -= 1;
+					break;
+				},
+				_ => {
+					let msgs = errors::Msg::Many(vec![
+						format!("Illegal symbol \"{}\" while consuming macro parameter.", character.to_string().red()),
+						"Macro parameters may only have alphanumeric names.".to_string()
+					]);
+					return lexer.error(*idx, 1, msgs);
+				}
+			}
+			*idx += 1;
+		}
+
+		if lexer.is_empty() {
+			return lexer.error(start, 1, errors::Msg::One("Expected a name after \"%\".".to_string()));
+		}
 
 		let identifier = String::from(&lexer.buffer);
 		let len = identifier.len();
-		lexer.buffer.clear(); // remove our work
-		Ok(Token::Register(identifier, CodeSegment {
-			line: lexer.line,
-			idx: *idx - len - 1,
-			len: len + 1
-		}))
+		lexer.buffer.clear();
+		Ok(Token::MacroParameter(identifier, lexer.segment(start, len + 1)))
 	}
 }
 
 pub struct StringLiteral {}
 impl Consumer for StringLiteral {
 	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
+		let start = *idx;
 		*idx += 1; // remove initial "
 
-		if let Err(err) = lexer.verify_buffer("string") {
-			return Err(err);
-		}
+		lexer.verify_buffer("string")?;
 
+		let mut closed = false;
 		while *idx < lexer.text.len() {
 			let character = lexer.idx(*idx);
 			match character {
-				'"' => break,
+				'"' => {
+					closed = true;
+					break;
+				},
+				'\\' if *idx + 1 < lexer.text.len() => {
+					// escapes are kept as written and only interpreted by the assembler
+					lexer.buffer.push(character);
+					*idx += 1;
+					lexer.buffer.push(lexer.idx(*idx));
+				},
 				_ => lexer.buffer.push(character)
 			}
 
 			*idx += 1;
 		}
 
+		if !closed {
+			lexer.buffer.clear();
+			return lexer.error(start, lexer.text.len() - start, errors::Msg::One("Unterminated string literal.".to_string()));
+		}
+
 		let string = String::from(&lexer.buffer);
 		let len = string.len();
 		lexer.buffer.clear();
-		Ok(Token::StringLiteral(string, CodeSegment {
-			line: lexer.line,
-			idx: *idx - len - 1,
-			len: len + 2
-		}))
+		Ok(Token::StringLiteral(string, lexer.segment(start, len + 2)))
 	}
 }
 
 pub struct NumberLiteral {}
 impl Consumer for NumberLiteral {
 	fn consume(idx: &mut usize, lexer: &mut Lexer) -> Result<Token, errors::Err> {
-		if let Err(err) = lexer.verify_buffer("number") {
-			return Err(err);
-		}
+		let start = *idx;
+
+		lexer.verify_buffer("number")?;
 
 		while *idx < lexer.text.len() {
 			let character = lexer.idx(*idx);
 			match character {
-				'0'..='9' => lexer.buffer.push(character),
-				',' => break,
-				'#' => {
-					Comment::consume(idx, lexer);
-				},
-				'-' => lexer.buffer.push(character),
-				' ' => match skip_whitespace(idx, lexer) {
-					Ok(token) => {},
-					Err(err) => return Err(err)
+				'0'..='9' | 'a'..='f' | 'A'..='F' | 'x' | 'X' | '.' => lexer.buffer.push(character),
+				'-' if lexer.is_empty() => lexer.buffer.push(character),
+				// the sign of an exponent
+				'+' | '-' if lexer.buffer.ends_with(['e', 'E']) => lexer.buffer.push(character),
+				' ' | '\t' | ',' => break,
+				'(' | ')' | '#' => {
+					*idx -= 1;
+					break;
 				},
 				_ => {
 					let msgs = errors::Msg::One(format!("Illegal character \"{}\" while consuming number.", character));
@@ -186,43 +236,49 @@ impl Consumer for NumberLiteral {
 
 		let number = String::from(&lexer.buffer);
 		let len = number.len();
-		let index = lexer.text.find(&number).unwrap();
 		lexer.buffer.clear();
-		let number = match number.parse::<i32>() {
-			Ok(number) => number,
-			Err(err) => {
-				let msgs = errors::Msg::One(format!("Could not cast {} to a number: {}", number.red(), err));
-				return lexer.error(index, len, msgs);
-			}
+
+		let hex = number.trim_start_matches('-').starts_with("0x") || number.trim_start_matches('-').starts_with("0X");
+		if !hex && number.contains(['.', 'e', 'E']) {
+			return match number.parse::<f64>() {
+				Ok(_) => Ok(Token::FloatLiteral(number, lexer.segment(start, len))),
+				Err(_) => {
+					let msgs = errors::Msg::One(format!("Could not read {} as a floating point number.", number.red()));
+					lexer.error(start, len, msgs)
+				}
+			};
+		}
+
+		let (negative, digits) = match number.strip_prefix('-') {
+			Some(digits) => (true, digits),
+			None => (false, number.as_str())
 		};
 
-		Ok(Token::NumberLiteral(number, CodeSegment {
-			line: lexer.line,
-			idx: index,
-			len: len
-		}))
-	}
-}
+		let parsed = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+			Some(hex) => i64::from_str_radix(hex, 16),
+			None => digits.parse::<i64>()
+		};
 
-// skips whitespace until one of a comma, comment, or line end is found
-fn skip_whitespace(idx: &mut usize, lexer: &Lexer) -> Result<Token, errors::Err> {
-	while *idx < lexer.text.len() {
-		let character = lexer.idx(*idx);
-		match character {
-			' ' => {},
-			',' => break,
-			'#' => {
-				*idx -= 1; // put comment back into view
-				break;
+		// values up to 32 bits are accepted whether they are written signed or unsigned
+		let number = match parsed {
+			Ok(value) if value <= u32::MAX as i64 => {
+				let value = if negative { -value } else { value };
+				if value < i32::MIN as i64 {
+					let msgs = errors::Msg::One(format!("Number {} does not fit in 32 bits.", number.red()));
+					return lexer.error(start, len, msgs);
+				}
+				value as u32 as i32
 			},
-			_ => {
-				let msgs = errors::Msg::One(format!("Unexpected character {} while reading whitespace.", character));
-				return lexer.error(*idx, 1, msgs);
+			Ok(_) => {
+				let msgs = errors::Msg::One(format!("Number {} does not fit in 32 bits.", number.red()));
+				return lexer.error(start, len, msgs);
+			},
+			Err(err) => {
+				let msgs = errors::Msg::One(format!("Could not cast {} to a number: {}", number.red(), err));
+				return lexer.error(start, len, msgs);
 			}
-		}
+		};
 
-		*idx += 1;
+		Ok(Token::NumberLiteral(number, lexer.segment(start, len)))
 	}
-
-	Ok(Token::Empty)
-}
\ No newline at end of file
+}
@@ -1,13 +1,22 @@
 use std::fmt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CodeSegment {
 	// identifiers the location of a token within the text of a program
 
+	pub file: usize,		// id of the file within the source map
 	pub line: usize,		// line number
 	pub idx: usize,			// character index
-	pub len: usize			// how many characters included
+	pub len: usize,			// how many characters included
 						// line[idx..=len] being the whole code segment
+	pub expanded_from: Option<Box<CodeSegment>>	// the macro invocation this segment was copied from, if any
+}
+
+impl CodeSegment {
+	// segments are on the same line if they come from the same line of the same file and macro expansion
+	pub fn same_line(&self, other: &CodeSegment) -> bool {
+		self.file == other.file && self.line == other.line && self.expanded_from == other.expanded_from
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -16,26 +25,32 @@ pub enum Token {
 	DefLabel(String, CodeSegment),		// given as "{name}:", stores the following instruction as a named memory location
 	Identifier(String, CodeSegment),	// some yet unknown identifier in the form {id}, will have to be parsed to its correct value
 	Register(String, CodeSegment),		// ${id}
+	MacroParameter(String, CodeSegment),	// %{id}, only valid inside of a macro body
 
 	StringLiteral(String, CodeSegment),
 	NumberLiteral(i32, CodeSegment),	// numbers are always
+	FloatLiteral(String, CodeSegment),	// numbers written with a decimal point or exponent, kept as written to be rounded once to their size
+
+	LeftParen(CodeSegment),
+	RightParen(CodeSegment),
 
-	Newline,
 	Empty
 }
 
 impl fmt::Display for Token {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			Token::Directive(id, _) => write!(f, "Directive"),
-			Token::DefLabel(id, _) => write!(f, "DefineLabel"),
-			Token::Identifier(id, _) => write!(f, "Identifier"),
-			Token::Register(id, _) => write!(f, "Register"),
-			Token::StringLiteral(id, _) => write!(f, "StringLiteral"),
-			Token::NumberLiteral(num, _) => write!(f, "NumberLiteral"),
+			Token::Directive(_, _) => write!(f, "Directive"),
+			Token::DefLabel(_, _) => write!(f, "DefineLabel"),
+			Token::Identifier(_, _) => write!(f, "Identifier"),
+			Token::Register(_, _) => write!(f, "Register"),
+			Token::MacroParameter(_, _) => write!(f, "MacroParameter"),
+			Token::StringLiteral(_, _) => write!(f, "StringLiteral"),
+			Token::NumberLiteral(_, _) => write!(f, "NumberLiteral"),
+			Token::FloatLiteral(_, _) => write!(f, "FloatLiteral"),
+			Token::LeftParen(_) => write!(f, "LeftParen"),
+			Token::RightParen(_) => write!(f, "RightParen"),
 			_ => panic!("Unknown token {:?}", self)
-		};
-
-		Ok(())
+		}
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,66 @@
+// the assembler, linker and emulator, for embedding in other programs as well as behind the command line
+
+pub mod lexer;
+pub mod errors;
+pub mod macros;
+pub mod parse;
+pub mod source;
+pub mod mips;
+pub mod encoding;
+pub mod object;
+pub mod assemble;
+pub mod link;
+pub mod elf;
+pub mod runtime;
+pub mod disasm;
+pub mod listing;
+pub mod dump;
+pub mod bitmap;
+pub mod pipeline;
+pub mod cache;
+pub mod predictor;
+pub mod stats;
+pub mod profile;
+pub mod trace;
+pub mod diff;
+pub mod grade;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+#[cfg(feature = "python")]
+mod python;
+
+pub use errors::{DisplayableErr, Err};
+pub use link::{LinkErr, Program};
+pub use mips::Endian;
+pub use object::Object;
+pub use parse::instructions::{FloatRegister, Register};
+pub use runtime::{Config, Exit, Fault, Limit, Limits, Machine};
+pub use runtime::console::{Buffer, Io};
+pub use runtime::terminal::Console;
+pub use source::SourceMap;
+
+// the parsed source of a file, from which its object and listing are made
+pub type Tree = parse::ast::BaseASTree<parse::symbols::Symbol>;
+
+// reads, expands and parses a file of the map, adding any it includes
+pub fn parse_file(sources: &mut SourceMap, file: usize) -> Result<Tree, Err> {
+	let tokens = source::tokenize(sources, file).and_then(|tokens| macros::expand(&tokens))?;
+	Ok(parse::transform(parse::parse(&tokens)?))
+}
+
+pub fn assemble_file(sources: &mut SourceMap, file: usize, endian: Endian) -> Result<(Tree, Object), Err> {
+	let tree = parse_file(sources, file)?;
+	let object = assemble::assemble(&tree, endian)?;
+	Ok((tree, object))
+}
+
+// assembles every file added to the map, little endian, and links them at the usual addresses
+// files read for an .include are assembled as part of the file including them
+pub fn assemble(sources: &mut SourceMap) -> Result<Program, LinkErr> {
+	let files: Vec<usize> = sources.ids().collect();
+	let mut objects = Vec::new();
+	for file in files {
+		objects.push(assemble_file(sources, file, Endian::Little).map_err(LinkErr::Source)?.1);
+	}
+	link::link(&objects, &link::Config::default())
+}
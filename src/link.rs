@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use crate::lexer::tokens::CodeSegment;
+use crate::mips::{self, Endian};
+use crate::object::{self, Object, Section, Binding, RelocationKind, Layout, Line};
+use crate::errors;
+use crate::source::SourceMap;
+
+use colored::Colorize;
+
+// where the linker places the sections of the program
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+	pub text_base: u32,
+	pub data_base: u32,
+	pub ktext_base: u32,
+	pub kdata_base: u32
+}
+
+impl Config {
+	pub fn base(&self, section: Section) -> u32 {
+		match section {
+			Section::Text => self.text_base,
+			Section::Data => self.data_base,
+			Section::KernelText => self.ktext_base,
+			Section::KernelData => self.kdata_base
+		}
+	}
+}
+
+impl Default for Config {
+	fn default() -> Config {
+		Config {
+			text_base: mips::TEXT_BASE,
+			data_base: mips::DATA_BASE,
+			ktext_base: mips::KTEXT_BASE,
+			kdata_base: mips::KDATA_BASE
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+	pub name: String,
+	pub address: u32,
+	pub section: Section,
+	pub binding: Binding,
+	pub segment: Option<CodeSegment>	// where the label is defined, when assembled from source
+}
+
+// a program ready to be loaded into memory
+#[derive(Debug, Clone)]
+pub struct Program {
+	pub endian: Endian,
+	pub text_base: u32,
+	pub text: Vec<u8>,
+	pub data_base: u32,
+	pub data: Vec<u8>,
+	pub ktext_base: u32,
+	pub ktext: Vec<u8>,
+	pub kdata_base: u32,
+	pub kdata: Vec<u8>,
+	pub entry: u32,
+	pub labels: Vec<Label>,
+	pub lines: Vec<Line>
+}
+
+#[derive(Debug)]
+pub enum LinkErr {
+	Source(errors::Err),				// located in the source of an object assembled alongside
+	Object { object: usize, msg: String },	// in an object read from disk, by its position in the input
+	Layout(String)
+}
+
+impl LinkErr {
+	// the error as it is shown, those in objects without the name of their file, which only the caller knows
+	pub fn report(self, sources: &SourceMap) -> String {
+		match self {
+			LinkErr::Source(err) => errors::DisplayableErr::new(err, sources).to_string(),
+			LinkErr::Object { msg, .. } | LinkErr::Layout(msg) => format!("{} {}.", "Error:".red().bold(), msg)
+		}
+	}
+}
+
+type LinkRes<T> = Result<T, LinkErr>;
+
+// merges objects into a program, with the text and data of each placed after those of the objects before it
+pub fn link(objects: &[Object], config: &Config) -> LinkRes<Program> {
+	let endian = objects.first().map_or(Endian::Little, |object| object.endian);
+
+	let mut program = Program {
+		endian,
+		text_base: config.text_base,
+		text: Vec::new(),
+		data_base: config.data_base,
+		data: Vec::new(),
+		ktext_base: config.ktext_base,
+		ktext: Vec::new(),
+		kdata_base: config.kdata_base,
+		kdata: Vec::new(),
+		entry: config.text_base,
+		labels: Vec::new(),
+		lines: Vec::new()
+	};
+
+	// where the sections of each object begin in the program
+	let mut bases = Vec::new();
+	for (idx, object) in objects.iter().enumerate() {
+		if object.layout.is_some() {
+			return Err(LinkErr::Object { object: idx, msg: "is already linked".to_string() });
+		}
+		if object.endian != endian {
+			return Err(LinkErr::Object { object: idx, msg: format!("is {:?} endian while the program is {:?} endian", object.endian, endian).to_lowercase() });
+		}
+
+		let mut offsets = [0; 4];
+		for (idx, section) in Section::ALL.into_iter().enumerate() {
+			let bytes = program.section_mut(section);
+			align(bytes, if section.executable() { 4 } else { 8 });
+			offsets[idx] = bytes.len() as u32;
+			bytes.extend_from_slice(object.section(section));
+		}
+		bases.push(offsets);
+	}
+
+	for (idx, &a) in Section::ALL.iter().enumerate() {
+		for &b in &Section::ALL[idx + 1..] {
+			if overlaps(config.base(a), program.section(a).len(), config.base(b), program.section(b).len()) {
+				return Err(LinkErr::Layout(format!("the {} ({} bytes at {:#010x}) and {} ({} bytes at {:#010x}) overlap",
+					&a.name()[1..], program.section(a).len(), config.base(a), &b.name()[1..], program.section(b).len(), config.base(b))));
+			}
+		}
+	}
+
+	// where a section of an object begins once linked
+	let start = |object: usize, section: Section| -> u32 {
+		let idx = Section::ALL.iter().position(|other| *other == section).unwrap();
+		config.base(section).wrapping_add(bases[object][idx])
+	};
+	let address = |object: usize, symbol: &object::Symbol| -> Option<u32> {
+		Some(start(object, symbol.section?).wrapping_add(symbol.offset))
+	};
+
+	let mut globals: HashMap<&str, (usize, u32)> = HashMap::new();
+	for (idx, object) in objects.iter().enumerate() {
+		for symbol in &object.symbols {
+			let Some(symbol_address) = address(idx, symbol) else { continue };
+
+			program.labels.push(Label {
+				name: symbol.name.to_string(),
+				address: symbol_address,
+				section: symbol.section.unwrap_or(Section::Text),
+				binding: symbol.binding,
+				segment: symbol.segment.clone()
+			});
+
+			if symbol.binding == Binding::Global {
+				if let Some((other, _)) = globals.get(symbol.name.as_str()) {
+					let msg = format!("Global label {} is already defined by another file.", symbol.name.red());
+					return Err(located(idx, symbol.segment.as_ref(), errors::Msg::One(msg), &format!("defines {} which is also defined by input {}", symbol.name, other + 1)));
+				}
+				globals.insert(&symbol.name, (idx, symbol_address));
+			}
+		}
+	}
+
+	for (idx, object) in objects.iter().enumerate() {
+		for relocation in &object.relocations {
+			let symbol = &object.symbols[relocation.symbol];
+			let value = match address(idx, symbol).or_else(|| globals.get(symbol.name.as_str()).map(|(_, address)| *address)) {
+				Some(value) => value.wrapping_add(relocation.addend as u32),
+				None => return Err(located(idx, relocation.segment.as_ref(), errors::Msg::Many(vec![
+					format!("Undefined label {}.", symbol.name.red()),
+					"Labels from other files must be declared with .globl.".to_string()
+				]), &format!("refers to undefined label {}", symbol.name)))
+			};
+
+			let place = start(idx, relocation.section).wrapping_add(relocation.offset);
+			let bytes = &mut program.section_mut(relocation.section)[place.wrapping_sub(config.base(relocation.section)) as usize..];
+			let word = endian.read_u32(bytes);
+
+			let patched = match relocation.kind {
+				RelocationKind::Word32 => value,
+				RelocationKind::Hi16 => (word & !0xffff) | (value.wrapping_add(0x8000) >> 16),
+				RelocationKind::Lo16 => (word & !0xffff) | (value & 0xffff),
+				RelocationKind::Mips26 => {
+					// jumps stay within the 256MB region of the instruction after them
+					if !value.is_multiple_of(4) || value & 0xf0000000 != place.wrapping_add(4) & 0xf0000000 {
+						return Err(located(idx, relocation.segment.as_ref(), errors::Msg::One(format!("Jump target {} cannot be reached.", symbol.name.red())),
+							&format!("cannot reach jump target {}", symbol.name)));
+					}
+					(word & !0x3ffffff) | ((value >> 2) & 0x3ffffff)
+				},
+				RelocationKind::Pc16 => {
+					let distance = (value as i64 - (place as i64 + 4)) / 4;
+					if !value.is_multiple_of(4) || !(-32768..=32767).contains(&distance) {
+						return Err(located(idx, relocation.segment.as_ref(), errors::Msg::One(format!("Branch target {} is too far away.", symbol.name.red())),
+							&format!("cannot reach branch target {}", symbol.name)));
+					}
+					(word & !0xffff) | (distance as u32 & 0xffff)
+				}
+			};
+
+			endian.write_u32(bytes, patched);
+		}
+
+		for line in &object.lines {
+			program.lines.push(Line {
+				address: start(idx, line.section).wrapping_add(line.address),
+				segment: line.segment.clone(),
+				..*line
+			});
+		}
+	}
+
+	// execution starts at main, preferring a global one
+	let main = globals.get("main").map(|(_, address)| *address).or_else(|| {
+		objects.first()?.symbols.iter()
+			.find(|symbol| symbol.name == "main")
+			.and_then(|symbol| address(0, symbol))
+	});
+	if let Some(main) = main {
+		program.entry = main;
+	}
+
+	Ok(program)
+}
+
+impl Program {
+	// an executable object, keeping the labels for debugging
+	pub fn to_object(&self) -> Object {
+		let mut object = Object::new(self.endian);
+		object.layout = Some(Layout {
+			bases: Section::ALL.map(|section| self.base(section)),
+			entry: self.entry
+		});
+		for section in Section::ALL {
+			*object.section_mut(section) = self.section(section).clone();
+		}
+
+		for label in &self.labels {
+			object.symbols.push(object::Symbol {
+				name: label.name.to_string(),
+				section: Some(label.section),
+				offset: label.address.wrapping_sub(self.base(label.section)),
+				binding: label.binding,
+				segment: None
+			});
+		}
+
+		object
+	}
+
+	pub fn from_object(object: Object) -> Result<Program, String> {
+		let Some(layout) = object.layout else {
+			return Err("is not linked".to_string());
+		};
+
+		let [text_base, data_base, ktext_base, kdata_base] = layout.bases;
+		let labels = object.symbols.iter().filter_map(|symbol| {
+			let section = symbol.section?;
+			let idx = Section::ALL.iter().position(|other| *other == section)?;
+			Some(Label {
+				name: symbol.name.to_string(),
+				address: layout.bases[idx].wrapping_add(symbol.offset),
+				section,
+				binding: symbol.binding,
+				segment: None
+			})
+		}).collect();
+
+		Ok(Program {
+			endian: object.endian,
+			text_base,
+			text: object.text,
+			data_base,
+			data: object.data,
+			ktext_base,
+			ktext: object.ktext,
+			kdata_base,
+			kdata: object.kdata,
+			entry: layout.entry,
+			labels,
+			lines: Vec::new()
+		})
+	}
+
+	pub fn base(&self, section: Section) -> u32 {
+		match section {
+			Section::Text => self.text_base,
+			Section::Data => self.data_base,
+			Section::KernelText => self.ktext_base,
+			Section::KernelData => self.kdata_base
+		}
+	}
+
+	pub fn section(&self, section: Section) -> &Vec<u8> {
+		match section {
+			Section::Text => &self.text,
+			Section::Data => &self.data,
+			Section::KernelText => &self.ktext,
+			Section::KernelData => &self.kdata
+		}
+	}
+
+	fn section_mut(&mut self, section: Section) -> &mut Vec<u8> {
+		match section {
+			Section::Text => &mut self.text,
+			Section::Data => &mut self.data,
+			Section::KernelText => &mut self.ktext,
+			Section::KernelData => &mut self.kdata
+		}
+	}
+}
+
+fn align(section: &mut Vec<u8>, alignment: usize) {
+	while !section.len().is_multiple_of(alignment) {
+		section.push(0);
+	}
+}
+
+fn overlaps(a: u32, a_len: usize, b: u32, b_len: usize) -> bool {
+	let (a, b) = (a as u64, b as u64);
+	a_len > 0 && b_len > 0 && a < b + b_len as u64 && b < a + a_len as u64
+}
+
+// an error at the source responsible if it is known, otherwise at the object
+fn located(object: usize, segment: Option<&CodeSegment>, msg: errors::Msg, fallback: &str) -> LinkErr {
+	match segment {
+		Some(segment) => LinkErr::Source(errors::Err {
+			segment: segment.clone(),
+			errtype: errors::ErrType::Link,
+			msg
+		}),
+		None => LinkErr::Object { object, msg: fallback.to_string() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn objects(files: &[(&str, &str)]) -> Vec<Object> {
+		let mut sources = SourceMap::new();
+		let ids: Vec<usize> = files.iter().map(|(name, text)| sources.add_text(name, text)).collect();
+		ids.into_iter().map(|file| crate::assemble_file(&mut sources, file, Endian::Little).unwrap().1).collect()
+	}
+
+	fn word(program: &Program, address: u32) -> u32 {
+		program.endian.read_u32(&program.text[(address - program.text_base) as usize..])
+	}
+
+	fn label(program: &Program, name: &str) -> u32 {
+		program.labels.iter().find(|label| label.name == name).unwrap().address
+	}
+
+	#[test]
+	fn patches_references_to_other_objects() {
+		let a = ".globl main\n.data\nown: .word 1\n.text\nmain: lw $t1, value\njal helper\n";
+		// the padding puts value where the lower half is negative, so the upper half is rounded up
+		let b = ".globl helper\n.globl value\n.data\npad: .space 0x8000\nvalue: .word 42\n.text\nhelper: jr $ra\n";
+		let program = link(&objects(&[("a.asm", a), ("b.asm", b)]), &Config::default()).unwrap();
+
+		let value = label(&program, "value");
+		assert_eq!(value, mips::DATA_BASE + 8 + 0x8000);
+		let main = label(&program, "main");
+		assert_eq!(program.entry, main);
+		assert_eq!(word(&program, main) & 0xffff, (value >> 16) + 1);
+		assert_eq!(word(&program, main + 4) & 0xffff, value & 0xffff);
+
+		let helper = label(&program, "helper");
+		assert_eq!(helper, main + 12);
+		assert_eq!(word(&program, main + 8), (0x03 << 26) | (helper >> 2));
+	}
+
+	#[test]
+	fn refuses_labels_which_are_not_global() {
+		let a = ".globl main\nmain: jal helper\n";
+		let b = "helper: jr $ra\n";
+		assert!(matches!(link(&objects(&[("a.asm", a), ("b.asm", b)]), &Config::default()), Err(LinkErr::Source(_))));
+	}
+
+	#[test]
+	fn refuses_globals_defined_twice() {
+		let a = ".globl main\nmain: nop\n";
+		assert!(matches!(link(&objects(&[("a.asm", a), ("b.asm", a)]), &Config::default()), Err(LinkErr::Source(_))));
+	}
+
+	#[test]
+	fn links_objects_read_back_from_disk() {
+		let a = ".globl main\nmain: lw $t1, value\n";
+		let b = ".globl value\n.data\nvalue: .word 42\n";
+		let objects: Vec<Object> = objects(&[("a.asm", a), ("b.asm", b)]).iter().map(|object| {
+			let mut bytes = Vec::new();
+			object.write(&mut bytes).unwrap();
+			Object::read(&bytes).unwrap()
+		}).collect();
+		let program = link(&objects, &Config::default()).unwrap();
+
+		let value = label(&program, "value");
+		assert_eq!(word(&program, program.entry) & 0xffff, value >> 16);
+		assert_eq!(word(&program, program.entry + 4) & 0xffff, value & 0xffff);
+	}
+}
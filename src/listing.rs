@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::disasm;
+use crate::lexer::tokens::CodeSegment;
+use crate::link::{Label, Program};
+use crate::object::{Binding, Line};
+use crate::parse::ast::*;
+use crate::parse::symbols::Symbol;
+use crate::source::SourceMap;
+
+// rows of data shown for one line before the rest is left out
+const MAX_DATA_ROWS: usize = 8;
+
+// the line a segment was written on, which for code expanded from a macro is where the macro was invoked
+fn root(segment: &CodeSegment) -> &CodeSegment {
+	match &segment.expanded_from {
+		Some(invocation) => root(invocation),
+		None => segment
+	}
+}
+
+// every source line with the address and encoding of what it assembled to, pseudo-instructions and macros being
+// followed by the basic instructions they expand to, then the symbol table and where each label is defined and used
+// the trees are those of each assembled file, in the order they were linked
+pub fn write(sources: &SourceMap, units: &[BaseASTree<Symbol>], program: &Program) -> String {
+	let mut rows: HashMap<(usize, usize), Vec<&Line>> = HashMap::new();
+	for line in &program.lines {
+		let segment = root(&line.segment);
+		rows.entry((segment.file, segment.line)).or_default().push(line);
+	}
+
+	let mut out = String::new();
+	for file in sources.ids() {
+		let _ = writeln!(out, "{}", sources.name(file));
+		let _ = writeln!(out, "{:>5}  {:8}  {:8}  Source", "Line", "Address", "Code");
+
+		for (idx, text) in sources.file(file).lines.iter().enumerate() {
+			let mut lines = rows.remove(&(file, idx)).unwrap_or_default();
+			lines.sort_by_key(|line| line.address);
+
+			match lines.first().map(|line| line.section) {
+				None => {
+					let _ = writeln!(out, "{:>5}  {:8}  {:8}  {}", idx + 1, "", "", text);
+				},
+				Some(section) if section.executable() => code(&mut out, program, idx, text, &lines),
+				Some(_) => data(&mut out, program, idx, text, &lines)
+			}
+		}
+
+		let _ = writeln!(out);
+	}
+
+	symbols(&mut out, sources, units, program);
+	out
+}
+
+fn data(out: &mut String, program: &Program, idx: usize, text: &str, lines: &[&Line]) {
+	let mut chunks = Vec::new();
+	for line in lines {
+		let start = line.address.wrapping_sub(program.base(line.section)) as usize;
+		let bytes = &program.section(line.section)[start..start + line.len as usize];
+		for (offset, chunk) in bytes.chunks(4).enumerate() {
+			chunks.push((line.address + offset as u32 * 4, chunk));
+		}
+	}
+
+	let hex = |chunk: &[u8]| chunk.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+	match chunks.first() {
+		Some((address, chunk)) => {
+			let _ = writeln!(out, "{:>5}  {:08x}  {:8}  {}", idx + 1, address, hex(chunk), text);
+		},
+		// directives such as .space 0 take no memory
+		None => {
+			let _ = writeln!(out, "{:>5}  {:08x}  {:8}  {}", idx + 1, lines[0].address, "", text);
+		}
+	}
+
+	for (address, chunk) in chunks.iter().skip(1).take(MAX_DATA_ROWS - 1) {
+		let _ = writeln!(out, "{:>5}  {:08x}  {:8}", "", address, hex(chunk));
+	}
+	if chunks.len() > MAX_DATA_ROWS {
+		let _ = writeln!(out, "{:>5}  {:8}  {:8}", "", "...", "");
+	}
+}
+
+fn code(out: &mut String, program: &Program, idx: usize, text: &str, lines: &[&Line]) {
+	let words: Vec<(u32, u32)> = lines.iter().map(|line| {
+		let offset = line.address.wrapping_sub(program.base(line.section)) as usize;
+		(line.address, program.endian.read_u32(&program.section(line.section)[offset..offset + 4]))
+	}).collect();
+	let disassembled: Vec<String> = words.iter().map(|(address, word)| disasm::disassemble(*word, *address, &program.labels, false)).collect();
+
+	// a basic instruction is shown on its own line, anything else is expanded underneath
+	let written: String = text.trim().chars().skip(lines[0].segment.idx).collect();
+	let basic = words.len() == 1 && written.split_whitespace().next() == disassembled[0].split_whitespace().next();
+
+	if basic {
+		let _ = writeln!(out, "{:>5}  {:08x}  {:08x}  {}", idx + 1, words[0].0, words[0].1, text);
+		return;
+	}
+
+	let _ = writeln!(out, "{:>5}  {:08x}  {:8}  {}", idx + 1, words[0].0, "", text);
+	for ((address, word), text) in words.iter().zip(disassembled) {
+		let _ = writeln!(out, "{:>5}  {:08x}  {:08x}      {}", "", address, word, text);
+	}
+}
+
+#[derive(Default)]
+struct Labels<'a> {
+	defined: Vec<(&'a str, &'a CodeSegment)>,
+	referenced: Vec<(&'a str, &'a CodeSegment)>
+}
+
+fn collect<'a>(node: &'a ASTNode<Symbol>, labels: &mut Labels<'a>) {
+	let symbol = match node {
+		ASTNode::Node(symbol) => symbol,
+		ASTNode::Tree(tree) => {
+			// declarations are not uses
+			if let Symbol::Directive(id, _) = tree.root() {
+				if id == "globl" || id == "extern" {
+					return;
+				}
+			}
+
+			for child in tree.children() {
+				collect(child, labels);
+			}
+			tree.root()
+		}
+	};
+
+	match symbol {
+		Symbol::DefLabel(label, segment) => labels.defined.push((label, segment)),
+		Symbol::Label(label, segment) => labels.referenced.push((label, segment)),
+		_ => {}
+	}
+}
+
+fn symbols(out: &mut String, sources: &SourceMap, units: &[BaseASTree<Symbol>], program: &Program) {
+	let mut labels: Vec<&Label> = program.labels.iter().collect();
+	labels.sort_by_key(|label| (label.address, label.name.to_string()));
+
+	let _ = writeln!(out, "Symbols");
+	let _ = writeln!(out, "{:8}  {:7}  {:7}  Name", "Address", "Section", "Binding");
+	for label in &labels {
+		let section = &label.section.name()[1..];
+		let binding = if label.binding == Binding::Global { "global" } else { "local" };
+		let _ = writeln!(out, "{:08x}  {:7}  {:7}  {}", label.address, section, binding, label.name);
+	}
+	let _ = writeln!(out);
+
+	// a reference is to the label of the same name in its own file, otherwise to the global label
+	let mut uses: HashMap<usize, Vec<&CodeSegment>> = HashMap::new();
+	for unit in units {
+		let mut collected = Labels::default();
+		for node in unit.children() {
+			collect(node, &mut collected);
+		}
+
+		for (name, segment) in &collected.referenced {
+			let definition = match collected.defined.iter().find(|(defined, _)| defined == name) {
+				Some((_, defined)) => labels.iter().position(|label| label.segment.as_ref() == Some(*defined)),
+				None => labels.iter().position(|label| label.name == *name && label.binding == Binding::Global)
+			};
+
+			if let Some(label) = definition {
+				uses.entry(label).or_default().push(*segment);
+			}
+		}
+	}
+
+	let location = |segment: &CodeSegment| {
+		let segment = root(segment);
+		format!("{}:{}", sources.name(segment.file), segment.line + 1)
+	};
+
+	let _ = writeln!(out, "Cross-reference");
+	let _ = writeln!(out, "{:24}  {:24}  Used", "Name", "Defined");
+	for (idx, label) in labels.iter().enumerate() {
+		let defined = label.segment.as_ref().map_or("-".to_string(), location);
+		let mut used: Vec<String> = uses.get(&idx).map_or(Vec::new(), |uses| uses.iter().map(|segment| location(segment)).collect());
+		used.dedup();
+		let _ = writeln!(out, "{:24}  {:24}  {}", label.name, defined, used.join(", "));
+	}
+}
@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+
+use crate::lexer::tokens::{Token, CodeSegment};
+use crate::parse;
+use crate::errors;
+
+use colored::Colorize;
+
+// macros invoking macros may not nest any deeper than this, which catches recursive definitions
+const MAX_DEPTH: usize = 64;
+
+type MacRes<T> = Result<T, errors::Err>;
+
+struct Macro {
+	params: Vec<String>,	// parameter names without the leading %
+	body: Vec<Token>,
+	labels: Vec<String>		// labels defined within the body, renamed for every expansion
+}
+
+struct Expander {
+	macros: HashMap<(String, usize), Macro>,	// macros are overloaded on their number of parameters
+	expansions: usize						// used to give every expansion its own labels
+}
+
+// expands every macro invocation, removing the definitions from the token stream
+// macros must be defined before they are used
+pub fn expand(tokens: &[Token]) -> MacRes<Vec<Token>> {
+	let mut expander = Expander {
+		macros: HashMap::new(),
+		expansions: 0
+	};
+
+	expander.expand(tokens, 0)
+}
+
+impl Expander {
+	fn expand(&mut self, tokens: &[Token], depth: usize) -> MacRes<Vec<Token>> {
+		let mut output = Vec::new();
+
+		let mut idx = 0;
+		while idx < tokens.len() {
+			let token = &tokens[idx];
+			match token {
+				Token::Directive(id, segment) if id == "macro" => {
+					if depth > 0 {
+						return err(segment, errors::Msg::One("Macros cannot be defined inside of other macros.".to_string()));
+					}
+
+					self.define(&mut idx, tokens)?;
+				},
+
+				Token::Directive(id, segment) if id == "end_macro" => {
+					return err(segment, errors::Msg::One(format!("Found {} without a matching {}.", ".end_macro".red(), ".macro".bold())));
+				},
+
+				Token::MacroParameter(id, segment) => {
+					return err(segment, errors::Msg::One(format!("Macro parameter {} used outside of a macro.", format!("%{}", id).red())));
+				},
+
+				Token::Identifier(id, segment) if self.is_defined(id) && starts_statement(idx, tokens) => {
+					if depth >= MAX_DEPTH {
+						return err(segment, errors::Msg::Many(vec![
+							format!("Macro {} nested more than {} times.", id.red(), MAX_DEPTH),
+							"Macros cannot invoke themselves.".to_string()
+						]));
+					}
+
+					let args = arguments(&mut idx, tokens)?;
+					let body = self.instantiate(id, segment, &args)?;
+					output.append(&mut self.expand(&body, depth + 1)?);
+				},
+
+				_ => output.push(token.clone())
+			}
+
+			idx += 1;
+		}
+
+		Ok(output)
+	}
+
+	fn is_defined(&self, name: &str) -> bool {
+		self.macros.keys().any(|(id, _)| id == name)
+	}
+
+	// reads ".macro name(%a, %b)" or ".macro name %a %b" and the body up to ".end_macro"
+	// leaves idx on the ".end_macro" directive
+	fn define(&mut self, idx: &mut usize, tokens: &[Token]) -> MacRes<()> {
+		let directive = parse::extract_segment(&tokens[*idx]);
+		*idx += 1;
+
+		let (name, segment) = match tokens.get(*idx) {
+			Some(Token::Identifier(name, segment)) if segment.same_line(&directive) => (name.to_string(), segment.clone()),
+			Some(token) if parse::extract_segment(token).same_line(&directive) => {
+				return err(&parse::extract_segment(token), errors::Msg::Many(vec![
+					format!("Unexpected token {}.", token.to_string().red()),
+					"Expected macro name.".to_string()
+				]));
+			},
+			_ => return err(&directive, errors::Msg::One("Expected macro name.".to_string()))
+		};
+
+		let header = rest_of_line(*idx, tokens);
+		let mut params = Vec::new();
+		for token in header {
+			match token {
+				Token::MacroParameter(param, segment) => {
+					if params.contains(param) {
+						return err(segment, errors::Msg::One(format!("Duplicate macro parameter {}.", format!("%{}", param).red())));
+					}
+					params.push(param.to_string());
+				},
+				Token::LeftParen(_) | Token::RightParen(_) => {},
+				_ => {
+					return err(&parse::extract_segment(token), errors::Msg::Many(vec![
+						format!("Unexpected token {}.", token.to_string().red()),
+						"Expected macro parameter.".to_string()
+					]));
+				}
+			}
+		}
+		*idx += header.len() + 1;
+
+		let mut body = Vec::new();
+		let mut labels = Vec::new();
+		loop {
+			match tokens.get(*idx) {
+				None => {
+					return err(&directive, errors::Msg::One(format!("Macro {} is missing {}.", name.red(), ".end_macro".bold())));
+				},
+				Some(Token::Directive(id, _)) if id == "end_macro" => break,
+				Some(Token::Directive(id, segment)) if id == "macro" => {
+					return err(segment, errors::Msg::One("Macros cannot be defined inside of other macros.".to_string()));
+				},
+				Some(Token::MacroParameter(param, segment)) if !params.contains(param) => {
+					return err(segment, errors::Msg::One(format!("Unknown macro parameter {}.", format!("%{}", param).red())));
+				},
+				Some(Token::DefLabel(label, _)) => {
+					labels.push(label.to_string());
+					body.push(tokens[*idx].clone());
+				},
+				Some(token) => body.push(token.clone())
+			}
+
+			*idx += 1;
+		}
+
+		let key = (name.to_string(), params.len());
+		if self.macros.contains_key(&key) {
+			return err(&segment, errors::Msg::One(format!("Macro {} with {} parameter(s) is already defined.", name.red(), params.len())));
+		}
+
+		self.macros.insert(key, Macro { params, body, labels });
+		Ok(())
+	}
+
+	// copies the body of a macro, substituting its arguments and renaming its labels
+	fn instantiate(&mut self, name: &str, invocation: &CodeSegment, args: &[Token]) -> MacRes<Vec<Token>> {
+		let mac = match self.macros.get(&(name.to_string(), args.len())) {
+			Some(mac) => mac,
+			None => {
+				let mut counts: Vec<String> = self.macros.keys()
+					.filter(|(id, _)| id == name)
+					.map(|(_, count)| count.to_string())
+					.collect();
+				counts.sort();
+
+				return err(invocation, errors::Msg::Many(vec![
+					format!("Macro {} does not take {} argument(s).", name.red(), args.len()),
+					format!("It is defined with {} parameter(s).", counts.join(" or "))
+				]));
+			}
+		};
+
+		let suffix = format!("_M{}", self.expansions);
+		self.expansions += 1;
+
+		let origin = Some(Box::new(invocation.clone()));
+		let mut body = Vec::new();
+		for token in &mac.body {
+			let mut token = match token {
+				Token::MacroParameter(param, segment) => {
+					// the argument takes the place of the parameter, so errors point to where it is used
+					let position = mac.params.iter().position(|p| p == param).expect("Parameters are checked on definition.");
+					let mut arg = args[position].clone();
+					*segment_mut(&mut arg) = segment.clone();
+					arg
+				},
+				Token::DefLabel(label, segment) if mac.labels.contains(label) => Token::DefLabel(format!("{}{}", label, suffix), segment.clone()),
+				Token::Identifier(label, segment) if mac.labels.contains(label) => Token::Identifier(format!("{}{}", label, suffix), segment.clone()),
+				_ => token.clone()
+			};
+
+			segment_mut(&mut token).expanded_from = origin.clone();
+			body.push(token);
+		}
+
+		Ok(body)
+	}
+}
+
+// a macro invocation must be the first thing in its statement, or follow a label definition
+fn starts_statement(idx: usize, tokens: &[Token]) -> bool {
+	if idx == 0 {
+		return true;
+	}
+
+	match &tokens[idx - 1] {
+		Token::DefLabel(_, _) => true,
+		token => !parse::extract_segment(token).same_line(&parse::extract_segment(&tokens[idx]))
+	}
+}
+
+// the tokens following idx on the same line
+fn rest_of_line(idx: usize, tokens: &[Token]) -> &[Token] {
+	let line = parse::extract_segment(&tokens[idx]);
+
+	let mut end = idx + 1;
+	while end < tokens.len() && line.same_line(&parse::extract_segment(&tokens[end])) {
+		end += 1;
+	}
+
+	&tokens[idx + 1..end]
+}
+
+// collects the arguments following a macro name, given either as "name(a, b)" or "name a b"
+// leaves idx on the last token of the invocation
+fn arguments(idx: &mut usize, tokens: &[Token]) -> MacRes<Vec<Token>> {
+	let rest = rest_of_line(*idx, tokens);
+	*idx += rest.len();
+
+	let inner = match (rest.first(), rest.last()) {
+		(Some(Token::LeftParen(_)), Some(Token::RightParen(_))) => &rest[1..rest.len() - 1],
+		(Some(Token::LeftParen(segment)), _) => {
+			return err(segment, errors::Msg::One("Unclosed parenthesis in macro invocation.".to_string()));
+		},
+		_ => rest
+	};
+
+	let mut args = Vec::new();
+	for token in inner {
+		match token {
+			Token::LeftParen(segment) | Token::RightParen(segment) => {
+				return err(segment, errors::Msg::One("Unexpected parenthesis in macro arguments.".to_string()));
+			},
+			Token::Directive(_, segment) | Token::DefLabel(_, segment) => {
+				return err(segment, errors::Msg::Many(vec![
+					format!("Unexpected token {}.", token.to_string().red()),
+					"Expected macro argument.".to_string()
+				]));
+			},
+			_ => args.push(token.clone())
+		}
+	}
+
+	Ok(args)
+}
+
+fn segment_mut(token: &mut Token) -> &mut CodeSegment {
+	match token {
+		Token::Directive(_, segment) => segment,
+		Token::DefLabel(_, segment) => segment,
+		Token::Identifier(_, segment) => segment,
+		Token::Register(_, segment) => segment,
+		Token::MacroParameter(_, segment) => segment,
+		Token::StringLiteral(_, segment) => segment,
+		Token::NumberLiteral(_, segment) => segment,
+		Token::FloatLiteral(_, segment) => segment,
+		Token::LeftParen(segment) => segment,
+		Token::RightParen(segment) => segment,
+		_ => {
+			panic!("Illegal token {:?}", token);
+		}
+	}
+}
+
+fn err<T>(segment: &CodeSegment, msg: errors::Msg) -> MacRes<T> {
+	Err(errors::Err {
+		segment: segment.clone(),
+		errtype: errors::ErrType::Assemble,
+		msg
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::errors::DisplayableErr;
+	use crate::runtime::{Config, Exit, Machine};
+	use crate::source::{self, SourceMap};
+
+	fn expansion(text: &str) -> (SourceMap, MacRes<Vec<Token>>) {
+		let mut sources = SourceMap::new();
+		let file = sources.add_text("macros.asm", text);
+		let tokens = source::tokenize(&mut sources, file).unwrap_or_else(|err| panic!("{:?}", err));
+		let expanded = expand(&tokens);
+		(sources, expanded)
+	}
+
+	// the lines of the invocations an error was expanded from, innermost first
+	fn invocations(segment: &CodeSegment) -> Vec<usize> {
+		let mut lines = Vec::new();
+		let mut expansion = &segment.expanded_from;
+		while let Some(segment) = expansion {
+			lines.push(segment.line);
+			expansion = &segment.expanded_from;
+		}
+		lines
+	}
+
+	#[test]
+	fn picks_the_macro_by_its_number_of_arguments() {
+		let text = ".macro set %r
+				li %r, 1
+			.end_macro
+			.macro set %r %value
+				li %r, %value
+			.end_macro
+			.macro set(%a, %b, %c)
+				set %a
+				set(%b, 2)
+				set %c 3
+			.end_macro
+			main: set $t0
+			set($t1, 7)
+			set $t2 $t3 $t4
+			li $v0, 10
+			syscall";
+		let mut sources = SourceMap::new();
+		sources.add_text("overloads.asm", text);
+		let mut machine = Machine::new(&crate::assemble(&mut sources).unwrap_or_else(|err| panic!("{}", err.report(&sources))), Config::default());
+		assert_eq!(machine.run_until(|_| false), Ok(Some(Exit::Code(0))));
+		assert_eq!(machine.registers[8..13], [1, 7, 1, 2, 3]);
+
+		let (_, expanded) = expansion(".macro set %r
+				li %r, 1
+			.end_macro
+			set $t0 $t1 $t2 $t3");
+		let err = expanded.err().unwrap_or_else(|| panic!("four arguments are refused"));
+		assert_eq!(err.segment.line, 3);
+		match err.msg {
+			errors::Msg::Many(msgs) => assert_eq!(msgs[1], "It is defined with 1 parameter(s)."),
+			errors::Msg::One(msg) => panic!("{}", msg)
+		}
+	}
+
+	#[test]
+	fn gives_every_expansion_its_own_labels() {
+		let (_, expanded) = expansion(".macro spin %r
+				loop: addiu %r, %r, -1
+				bnez %r, loop
+			.end_macro
+			main: spin $t0
+			spin $t1
+			loop: j loop");
+		let tokens = expanded.unwrap_or_else(|err| panic!("{:?}", err));
+		let labels: Vec<&str> = tokens.iter().filter_map(|token| match token {
+			Token::DefLabel(label, _) => Some(label.as_str()),
+			_ => None
+		}).collect();
+		assert_eq!(labels, ["main", "loop_M0", "loop_M1", "loop"]);
+
+		// branches go to the label of their own expansion, and labels outside of macros are left alone
+		let targets: Vec<&str> = tokens.iter().filter_map(|token| match token {
+			Token::Identifier(label, _) if label.starts_with("loop") => Some(label.as_str()),
+			_ => None
+		}).collect();
+		assert_eq!(targets, ["loop_M0", "loop_M1", "loop"]);
+	}
+
+	#[test]
+	fn reports_errors_where_the_macros_were_invoked() {
+		let (_, expanded) = expansion(".macro inner
+				addi $t0
+			.end_macro
+			.macro outer
+				inner
+			.end_macro
+			main: outer");
+		let tokens = expanded.unwrap_or_else(|err| panic!("{:?}", err));
+		let addi = tokens.iter().find(|token| matches!(token, Token::Identifier(id, _) if id == "addi")).unwrap_or_else(|| panic!("addi is expanded"));
+		let segment = parse::extract_segment(addi);
+		assert_eq!(segment.line, 1);
+		assert_eq!(invocations(&segment), [4, 6]);
+
+		let (_, expanded) = expansion(".macro forever
+				forever
+			.end_macro
+			main: forever");
+		let err = expanded.err().unwrap_or_else(|| panic!("recursion is refused"));
+		assert_eq!(err.segment.line, 1);
+		let lines = invocations(&err.segment);
+		assert_eq!(lines.len(), MAX_DEPTH);
+		assert_eq!(lines[MAX_DEPTH - 1], 3);
+		assert!(lines[..MAX_DEPTH - 1].iter().all(|line| *line == 1));
+	}
+
+	#[test]
+	fn notes_a_macro_invoking_itself_once() {
+		let (sources, expanded) = expansion(".macro forever
+				forever
+			.end_macro
+			main: forever");
+		let err = expanded.err().unwrap_or_else(|| panic!("recursion is refused"));
+		let report = DisplayableErr::new(err, &sources).to_string();
+		assert_eq!(report.matches("expanded from").count(), 2, "{}", report);
+		assert!(report.contains(&format!("expanded from macro invoked at macros.asm:2:1 ({} times).", MAX_DEPTH - 1)), "{}", report);
+		assert!(report.contains("expanded from macro invoked at macros.asm:4:7."), "{}", report);
+	}
+}
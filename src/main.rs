@@ -1,72 +1,1033 @@
-use std::fs::File;
-use std::path::Path;
-use std::io::{self, BufRead};
-use clap::Parser;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
 
-mod lexer;
-mod errors;
-mod parse;
+use rustic_mips::{
+    assemble_file as assemble_source, bitmap, cache, diff, disasm, dump, elf, errors, grade, link, listing,
+    mips, object, pipeline, predictor, profile, runtime, source, stats, trace, Tree
+};
 
 /// A light-weight MIPS emulator and debugger.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// The assembly file to be executed.
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Does not show the interface with stack, heap, and variables and runs the program straight without steps.
-    #[arg(short, long, default_value_t = false)]
-    no_debug: bool,
+    /// The assembly or object files to be executed, or a single executable. Labels are shared between files with `.globl`.
+    #[arg(required_unless_present = "resume")]
+    files: Vec<String>,
+
+    /// Executes the instruction following a branch or jump before it is taken, as expected by optimised compiler output.
+    #[arg(long, default_value_t = false)]
+    delay_slots: bool,
+
+    /// The instructions the memory mapped display takes to show a character before it is ready for the next.
+    #[arg(long, value_name = "INSTRUCTIONS", default_value_t = runtime::mmio::DELAY)]
+    mmio_delay: u32,
+
+    #[command(flatten)]
+    bitmap: Bitmap,
+
+    /// Times the program on a five-stage pipeline and shows the stage of each instruction in each cycle, with its hazards.
+    #[arg(long, default_value_t = false)]
+    pipeline: bool,
+
+    /// Stalls the pipeline until results are written back, rather than forwarding them to the stages which need them.
+    #[arg(long, default_value_t = false, requires = "pipeline")]
+    no_forwarding: bool,
+
+    /// The instructions shown in the pipeline diagram, after which they are only counted.
+    #[arg(long, value_name = "INSTRUCTIONS", default_value_t = pipeline::LIMIT)]
+    pipeline_limit: usize,
+
+    #[command(flatten)]
+    caches: Caches,
+
+    #[command(flatten)]
+    predictors: Predictors,
+
+    #[command(flatten)]
+    limits: Limits,
+
+    /// Preloads the virtual filesystem which the file syscalls open from a directory or a tar archive.
+    #[arg(long, value_name = "PATH", conflicts_with = "allow_fs")]
+    fs: Option<PathBuf>,
+
+    /// Writes the files the program wrote to the virtual filesystem into a directory when it ends.
+    #[arg(long, value_name = "DIR", conflicts_with = "allow_fs")]
+    fs_out: Option<PathBuf>,
+
+    /// Lets the file syscalls open files on the host within a directory, rather than in a virtual filesystem.
+    #[arg(long, value_name = "DIR")]
+    allow_fs: Option<PathBuf>,
+
+    /// Counts the instructions executed by mnemonic, format, class, source line and label, and reports them at exit as text, csv or json.
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    stats: Option<stats::Format>,
+
+    /// The cycles taken by each class of instruction, for the weighted cycles per instruction, such as `load=5,branch=3`.
+    #[arg(long, value_name = "CYCLES", value_parser = stats::Cycles::parse, default_value = "")]
+    cpi: stats::Cycles,
+
+    /// Writes the statistics to a file rather than after the output of the program.
+    #[arg(long, value_name = "FILE", requires = "stats")]
+    stats_out: Option<PathBuf>,
+
+    /// Follows the calls and returns of the program, reporting at exit the instructions executed in each function and what it called.
+    #[arg(long, default_value_t = false)]
+    profile: bool,
+
+    /// Writes the stacks of calls and the instructions executed in each to a file, folded for flame graph tools.
+    #[arg(long, value_name = "FILE")]
+    profile_folded: Option<PathBuf>,
+
+    /// Writes the state of the machine to a file where the run stops, for another run to carry on from with `--resume`.
+    #[arg(long, value_name = "FILE")]
+    snapshot: Option<PathBuf>,
+
+    /// Stops the run to write the snapshot once this many instructions have been executed, or on reaching a label.
+    #[arg(long, value_name = "STEPS|LABEL", requires = "snapshot")]
+    snapshot_at: Option<String>,
+
+    /// Carries on from a snapshot rather than starting a program, taking the lines of source from any files of the program given alongside.
+    #[arg(long, value_name = "SNAPSHOT")]
+    resume: Option<PathBuf>,
+
+    /// Logs every instruction executed, with its address, word, disassembly, source line and the registers and memory it changed, as text or json lines.
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    trace: Option<trace::Format>,
+
+    /// Writes the trace to a file rather than to standard error.
+    #[arg(long, value_name = "FILE", requires = "trace")]
+    trace_out: Option<PathBuf>,
+
+    /// Traces only the instructions in a range of addresses, such as `0x400000..0x400040`, or after a label up to the next, separated by commas.
+    #[arg(long, value_name = "FILTERS", value_parser = trace::Filter::parse, value_delimiter = ',', requires = "trace")]
+    trace_only: Vec<trace::Filter>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Assembles source files into an executable, or into an object file for each with `-c`.
+    Assemble {
+        /// The assembly files.
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// Writes an object file for each source instead of linking them.
+        #[arg(short = 'c', default_value_t = false)]
+        compile_only: bool,
+
+        /// The file to write, by default `a.out`, or the name of the source with `.o` for `-c`.
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(flatten)]
+        layout: Layout,
+
+        /// The format of the executable.
+        #[arg(long, value_enum, default_value_t = Emit::Object)]
+        emit: Emit,
+
+        /// The byte order of the program.
+        #[arg(long, value_enum, default_value_t = ByteOrder::Little)]
+        endian: ByteOrder,
+
+        /// Writes a listing of the source with the address and encoding of each line, the symbol table and a cross-reference.
+        #[arg(long, conflicts_with = "compile_only")]
+        listing: Option<String>,
+
+        /// Writes a segment as a memory image, such as `text:hex:out.txt`, for text, data, ktext or kdata in hex, logisim, verilog, ihex or binary.
+        #[arg(long, value_parser = dump::Dump::parse, conflicts_with = "compile_only")]
+        dump: Vec<dump::Dump>,
+    },
+
+    /// Links object files, and any assembly files given alongside, into an executable.
+    Link {
+        /// The object and assembly files.
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// The file to write.
+        #[arg(short, long, default_value = "a.out")]
+        output: String,
+
+        #[command(flatten)]
+        layout: Layout,
+
+        /// The format of the executable.
+        #[arg(long, value_enum, default_value_t = Emit::Object)]
+        emit: Emit,
+
+        /// The byte order of any assembly files.
+        #[arg(long, value_enum, default_value_t = ByteOrder::Little)]
+        endian: ByteOrder,
+    },
+
+    /// Disassembles executables, object files, files of hexadecimal words or raw binaries, or words given as arguments.
+    Disasm {
+        /// The files or words, such as `0x27bdffe0`.
+        #[arg(required = true)]
+        inputs: Vec<String>,
+
+        /// Shows instructions as the pseudo-instructions they commonly expand from.
+        #[arg(short, long, default_value_t = false)]
+        pseudo: bool,
+
+        /// The address of the first word, for anything other than executables.
+        #[arg(long, value_parser = parse_address, default_value = "0x00400000")]
+        base: u32,
+
+        /// The byte order of binary files.
+        #[arg(long, value_enum, default_value_t = ByteOrder::Little)]
+        endian: ByteOrder,
+
+        /// Reads files as raw binary even when they look like hexadecimal text.
+        #[arg(long, default_value_t = false)]
+        binary: bool,
+    },
+
+    /// Runs two programs with the same input, or one against a trace recorded with `--trace`, and reports the first instruction where they differ.
+    DiffTrace {
+        /// The first program, its files separated by commas, or a trace.
+        left: String,
+
+        /// The second program, its files separated by commas, or a trace.
+        right: String,
+
+        /// A file given to both programs as their input, which is otherwise empty.
+        #[arg(long)]
+        input: Option<PathBuf>,
+
+        /// The instructions compared before stopping.
+        #[arg(long, default_value_t = 10_000_000)]
+        max_steps: u64,
+
+        /// The instructions shown before the first difference.
+        #[arg(long, default_value_t = 5)]
+        context: usize,
+
+        /// Executes the instruction after each branch and jump, before it is taken, in both programs.
+        #[arg(long, default_value_t = false)]
+        delay_slots: bool,
+    },
+
+    /// Runs a program against the cases of a spec in toml or yaml, checking its output, exit code, registers and memory.
+    Test {
+        /// The spec, read as yaml when it ends in `.yaml` or `.yml` and as toml otherwise.
+        spec: PathBuf,
+
+        /// The files of the program, in place of those named by the spec.
+        files: Vec<String>,
+
+        /// Writes the results as JUnit XML.
+        #[arg(long, value_name = "FILE")]
+        junit: Option<PathBuf>,
+
+        /// Shows only the cases which fail.
+        #[arg(short, long, default_value_t = false)]
+        quiet: bool,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Emit {
+    /// An object file marked executable, as read by this program.
+    Object,
+    /// An ELF32 MIPS executable.
+    Elf,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn endian(self) -> mips::Endian {
+        match self {
+            ByteOrder::Little => mips::Endian::Little,
+            ByteOrder::Big => mips::Endian::Big
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct Layout {
+    /// The address of the text segment.
+    #[arg(long, value_parser = parse_address, default_value = "0x00400000")]
+    text_base: u32,
+
+    /// The address of the data segment.
+    #[arg(long, value_parser = parse_address, default_value = "0x10010000")]
+    data_base: u32,
+
+    /// The address of the kernel text segment, where the exception handler is placed.
+    #[arg(long, value_parser = parse_address, default_value = "0x80000000")]
+    ktext_base: u32,
+
+    /// The address of the kernel data segment.
+    #[arg(long, value_parser = parse_address, default_value = "0x90000000")]
+    kdata_base: u32,
+}
+
+impl Layout {
+    fn config(&self) -> link::Config {
+        link::Config {
+            text_base: self.text_base,
+            data_base: self.data_base,
+            ktext_base: self.ktext_base,
+            kdata_base: self.kdata_base
+        }
+    }
+}
+#[derive(clap::Args, Debug)]
+struct Bitmap {
+    /// Shows a bitmap display of the given size in pixels, such as `512x256`, drawn from a framebuffer in memory.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = bitmap::parse_size)]
+    bitmap: Option<(u32, u32)>,
+
+    /// The size in pixels of each unit of the display, of which the framebuffer holds a word each.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = bitmap::parse_size, default_value = "1x1")]
+    bitmap_unit: (u32, u32),
+
+    /// The address of the framebuffer, or one of gp, global, data, heap or mmio.
+    #[arg(long, value_name = "ADDRESS", value_parser = bitmap::parse_base, default_value = "data")]
+    bitmap_base: u32,
+
+    /// Writes the display as a PNG or PPM image, by its extension, when the program exits.
+    #[arg(long, value_name = "FILE", requires = "bitmap")]
+    bitmap_out: Option<PathBuf>,
+
+    /// Also shows the display, and writes a numbered image beside the one at exit, every given number of instructions whenever it has changed.
+    #[arg(long, value_name = "INSTRUCTIONS", requires = "bitmap")]
+    bitmap_frames: Option<u64>,
+
+    /// Draws the display in the terminal, at each frame and when the program exits.
+    #[arg(long, default_value_t = false, requires = "bitmap")]
+    bitmap_show: bool,
+}
+
+impl Bitmap {
+    fn recorder(&self) -> Result<Option<bitmap::Recorder>, String> {
+        let Some(size) = self.bitmap else { return Ok(None) };
+        let display = bitmap::Display::new(self.bitmap_unit, size, self.bitmap_base)?;
+        // drawn on stderr, leaving the output of the program alone
+        let show = self.bitmap_show.then(|| Box::new(io::stderr()) as Box<dyn Write>);
+        Ok(Some(bitmap::Recorder::new(display, self.bitmap_out.clone(), show)))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct Caches {
+    /// Simulates a first level instruction cache, such as `size=4k,block=16,ways=2,policy=lru`, and reports its hits and misses at exit.
+    #[arg(long, value_name = "CONFIG", value_parser = cache::Config::parse)]
+    cache_l1i: Option<cache::Config>,
+
+    /// Simulates a first level data cache, such as `size=4k,block=16,ways=full,write=through,allocate=no`.
+    #[arg(long, value_name = "CONFIG", value_parser = cache::Config::parse)]
+    cache_l1d: Option<cache::Config>,
+
+    /// Simulates a second level cache shared by instructions and data, such as `size=64k,block=32,ways=8,hit=10`.
+    #[arg(long, value_name = "CONFIG", value_parser = cache::Config::parse)]
+    cache_l2: Option<cache::Config>,
+
+    /// The cycles taken by main memory, for the average memory access time.
+    #[arg(long, value_name = "CYCLES", default_value_t = cache::MEMORY_TIME)]
+    cache_memory: u32,
+
+    /// Also shows the blocks each cache holds when the program exits.
+    #[arg(long, default_value_t = false)]
+    cache_contents: bool,
+}
+
+impl Caches {
+    fn hierarchy(&self) -> Option<cache::Hierarchy> {
+        if self.cache_l1i.is_none() && self.cache_l1d.is_none() && self.cache_l2.is_none() {
+            return None;
+        }
+        Some(cache::Hierarchy::new(self.cache_l1i, self.cache_l1d, self.cache_l2, self.cache_memory))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct Predictors {
+    /// Simulates branch predictors on every conditional branch, such as `two-bit,gshare`, and compares their accuracy at each.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    predictor: Vec<predictor::Kind>,
+
+    /// The entries of the tables of the predictors as a power of two, which is also the length of the global history.
+    #[arg(long, value_name = "BITS", default_value_t = predictor::BITS, value_parser = clap::value_parser!(u32).range(1..=20))]
+    predictor_bits: u32,
+
+    /// Simulates a branch target buffer of the given entries for taken branches and jumps.
+    #[arg(long, value_name = "ENTRIES")]
+    btb: Option<u32>,
+}
+
+impl Predictors {
+    fn predictors(&self) -> Option<predictor::Predictors> {
+        if self.predictor.is_empty() && self.btb.is_none() {
+            return None;
+        }
+        Some(predictor::Predictors::new(&self.predictor, self.predictor_bits, self.btb))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct Limits {
+    /// Ends the run after this many instructions.
+    #[arg(long, value_name = "STEPS")]
+    max_steps: Option<u64>,
+
+    /// Ends the run once the heap given out by sbrk, or the pages of memory written, exceed this many bytes, such as `64m`.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes)]
+    max_memory: Option<u64>,
+
+    /// Ends the run once the program prints more than this many bytes, such as `1m`.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes)]
+    max_output: Option<u64>,
+
+    /// Ends the run after this long on the wall clock, such as `10s` or `500ms`.
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    timeout: Option<Duration>,
+}
+
+impl Limits {
+    fn limits(&self) -> runtime::Limits {
+        runtime::Limits { steps: self.max_steps, memory: self.max_memory, output: self.max_output, time: self.timeout }
+    }
+}
+
+fn parse_address(arg: &str) -> Result<u32, String> {
+    let parsed = match arg.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => arg.parse()
+    };
+
+    parsed.map_err(|_| format!("\"{}\" is not an address", arg))
+}
+
+// a number of bytes, optionally in kilobytes, megabytes or gigabytes such as `64k`
+fn parse_bytes(arg: &str) -> Result<u64, String> {
+    let lower = arg.to_ascii_lowercase();
+    let (number, scale) = match lower.strip_suffix('b').unwrap_or(&lower) {
+        number if number.ends_with('k') => (&number[..number.len() - 1], 1 << 10),
+        number if number.ends_with('m') => (&number[..number.len() - 1], 1 << 20),
+        number if number.ends_with('g') => (&number[..number.len() - 1], 1 << 30),
+        number => (number, 1)
+    };
+    number.parse::<u64>().ok().and_then(|number| number.checked_mul(scale)).ok_or(format!("\"{}\" is not a number of bytes", arg))
+}
+
+// a length of time in seconds, or in milliseconds or minutes with `ms` or `m`
+fn parse_time(arg: &str) -> Result<Duration, String> {
+    let (number, scale) = match arg {
+        _ if arg.ends_with("ms") => (&arg[..arg.len() - 2], 0.001),
+        _ if arg.ends_with('s') => (&arg[..arg.len() - 1], 1.0),
+        _ if arg.ends_with('m') => (&arg[..arg.len() - 1], 60.0),
+        _ => (arg, 1.0)
+    };
+    number.parse::<f64>().ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * scale).ok())
+        .ok_or(format!("\"{}\" is not a length of time", arg))
+}
+
+// a hexadecimal word, with or without its prefix
+fn parse_word(word: &str) -> Option<u32> {
+    u32::from_str_radix(word.strip_prefix("0x").unwrap_or(word), 16).ok()
 }
 
 fn main() {
     let args = Args::parse();
+    let mut sources = source::SourceMap::new();
+
+    match args.command {
+        Some(Command::Assemble { files, compile_only: true, output, endian, .. }) => {
+            if output.is_some() && files.len() > 1 {
+                println!("{} -o cannot be used with -c for several files.", "Error:".red().bold());
+                return;
+            }
+
+            for path in &files {
+                let Some(object) = assemble_file(&mut sources, path, endian.endian()) else { return };
+                let out = output.clone().map(PathBuf::from).unwrap_or_else(|| Path::new(path).with_extension("o"));
+                if !write_object(&object, &out) {
+                    return;
+                }
+            }
+        },
+
+        Some(Command::Assemble { files, output, layout, emit, endian, listing, dump, .. }) => {
+            let Some((program, units)) = link_files(&mut sources, &files, &layout.config(), endian.endian()) else { return };
+            if !write_program(&program, emit, Path::new(&output.unwrap_or("a.out".to_string()))) {
+                return;
+            }
+
+            if let Some(listing) = listing {
+                if !write_bytes(listing::write(&sources, &units, &program).as_bytes(), Path::new(&listing)) {
+                    return;
+                }
+            }
+
+            for image in dump {
+                if !write_bytes(&dump::write(&program, image.segment, image.format), Path::new(&image.path)) {
+                    return;
+                }
+            }
+        },
+
+        Some(Command::Link { files, output, layout, emit, endian }) => {
+            if let Some((program, _)) = link_files(&mut sources, &files, &layout.config(), endian.endian()) {
+                write_program(&program, emit, Path::new(&output));
+            }
+        },
+
+        Some(Command::Disasm { inputs, pseudo, base, endian, binary }) => {
+            let mut words = Vec::new();
+            for input in &inputs {
+                if Path::new(input).exists() {
+                    let Some(bytes) = read_file(input) else { return };
+                    if !disassemble_file(input, &bytes, pseudo, base, endian.endian(), binary) {
+                        return;
+                    }
+                } else {
+                    match parse_word(input) {
+                        Some(word) => words.push(word),
+                        None => return println!("{} \"{}\" is neither a file nor a word.", "Error:".red().bold(), input.bright_black())
+                    }
+                }
+            }
+
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+            print_disassembly(&disasm::section(&bytes, base, mips::Endian::Little, &[], pseudo), &[]);
+        },
+
+        Some(Command::DiffTrace { left, right, input, max_steps, context, delay_slots }) => {
+            let input = match &input {
+                Some(path) => match fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(why) => {
+                        println!("{} failed to read \"{}\": {}", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+                        std::process::exit(2);
+                    }
+                },
+                None => Vec::new()
+            };
+            // 1 is kept for runs which diverge
+            let Some(left) = diff_side(&mut sources, &left, &input, delay_slots) else { std::process::exit(2) };
+            let Some(right) = diff_side(&mut sources, &right, &input, delay_slots) else { std::process::exit(2) };
+
+            let (report, diverged) = diff::compare(left, right, &sources, max_steps, context);
+            print!("{}", report);
+            if diverged {
+                std::process::exit(1);
+            }
+        },
+
+        Some(Command::Test { spec: path, files, junit, quiet }) => {
+            let spec = match grade::Spec::read(&path) {
+                Ok(spec) => spec,
+                Err(why) => {
+                    println!("{} \"{}\" cannot be read: {}", "Error:".red().bold(), path.display().to_string().bright_black(), why.trim_end());
+                    std::process::exit(2);
+                }
+            };
+            // the files named by the spec are found beside it
+            let files = match files.is_empty() {
+                true => spec.program.iter().map(|file| path.parent().unwrap_or(Path::new("")).join(file).display().to_string()).collect(),
+                false => files
+            };
+            if files.is_empty() {
+                println!("{} there is no program to test, given either after the spec or by `program` within it.", "Error:".red().bold());
+                std::process::exit(2);
+            }
+
+            // the program is assembled once, each case running it on a machine of its own so that none sees another's state
+            let program = program(&mut sources, &files);
+            let outcomes: Vec<grade::Outcome> = spec.cases.iter().map(|case| {
+                let prepared = spec.limits(case).map_err(|why| format!("the limits of the case are not valid: {}", why)).and_then(|limits| {
+                    let config = runtime::Config { delay_slots: spec.delay_slots, limits, ..Default::default() };
+                    let mut machine = program.as_ref().map(|program| program.machine(config)).ok_or("the program could not be assembled".to_string())?;
+                    if let Some(fs) = &spec.fs {
+                        let fs = path.parent().unwrap_or(Path::new("")).join(fs);
+                        machine.files.preload(&fs).map_err(|why| format!("\"{}\" could not be loaded into the filesystem: {}", fs.display(), why))?;
+                    }
+                    Ok(machine)
+                });
+                match prepared {
+                    Ok(machine) => grade::run(case, machine),
+                    Err(why) => grade::Outcome {
+                        name: case.name.clone(),
+                        failures: vec![why],
+                        steps: 0,
+                        time: Default::default()
+                    }
+                }
+            }).collect();
+
+            for outcome in &outcomes {
+                match outcome.passed() {
+                    true if quiet => {},
+                    true => println!("{}  {} {}", "PASS".green().bold(), outcome.name, format!("({} instructions)", outcome.steps).bright_black()),
+                    false => {
+                        println!("{}  {}", "FAIL".red().bold(), outcome.name);
+                        for failure in &outcome.failures {
+                            println!("      {}", failure);
+                        }
+                    }
+                }
+            }
+            let passed = outcomes.iter().filter(|outcome| outcome.passed()).count();
+            println!("{} of {} cases passed", passed, outcomes.len());
+
+            if let Some(junit) = &junit {
+                let suite = path.file_stem().map_or("spec".to_string(), |stem| stem.to_string_lossy().into_owned());
+                if let Err(why) = fs::write(junit, grade::junit(&suite, &outcomes)) {
+                    println!("{} failed to write \"{}\": {}", "Error:".red().bold(), junit.display().to_string().bright_black(), why);
+                }
+            }
+            if passed < outcomes.len() {
+                std::process::exit(1);
+            }
+        },
+
+        None => {
+            let config = runtime::Config { delay_slots: args.delay_slots, mmio_delay: args.mmio_delay, limits: args.limits.limits() };
+            let machine = match &args.resume {
+                Some(path) => resume(&mut sources, path, &args.files, config.limits),
+                None => load(&mut sources, &args.files, config)
+            };
+            // failing before the program runs exits with 2, which it cannot be mistaken for having exited with itself
+            let Some(mut machine) = machine else { std::process::exit(2) };
+            machine.io = Box::new(runtime::terminal::Console::new());
+            if let Some(directory) = &args.allow_fs {
+                machine.files = runtime::files::Files::host(directory.clone());
+            }
+            if let Some(path) = &args.fs {
+                if let Err(why) = machine.files.preload(path) {
+                    println!("{} failed to load \"{}\" into the filesystem: {}.", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+                    std::process::exit(2);
+                }
+            }
+
+            let mut recorder = match args.bitmap.recorder() {
+                Ok(recorder) => recorder,
+                Err(why) => {
+                    println!("{} {}.", "Error:".red().bold(), why);
+                    std::process::exit(2);
+                }
+            };
+            let mut pipeline = args.pipeline.then(|| pipeline::Pipeline::new(!args.no_forwarding, machine.delay_slots, args.pipeline_limit));
+            let mut caches = args.caches.hierarchy();
+            let mut predictors = args.predictors.predictors();
+            let mut stats = args.stats.map(|_| stats::Stats::new());
+            let mut profile = (args.profile || args.profile_folded.is_some()).then(|| profile::Profile::new(machine.pc));
+            let mut tracer = match args.trace {
+                Some(format) => {
+                    let out: Box<dyn Write> = match &args.trace_out {
+                        Some(path) => match fs::File::create(path) {
+                            Ok(file) => Box::new(io::BufWriter::new(file)),
+                            Err(why) => {
+                                println!("{} failed to create \"{}\": {}", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+                                std::process::exit(2);
+                            }
+                        },
+                        None => Box::new(io::BufWriter::new(io::stderr()))
+                    };
+                    match trace::Tracer::new(&machine, format, &args.trace_only, out) {
+                        Ok(tracer) => Some(tracer),
+                        Err(why) => {
+                            println!("{} {}.", "Error:".red().bold(), why);
+                            std::process::exit(2);
+                        }
+                    }
+                },
+                None => None
+            };
+            let mut frames = args.bitmap.bitmap_frames.filter(|frames| *frames > 0);
+            let stop = match args.snapshot_at.as_deref().map(|at| (at, at.parse::<u64>())) {
+                None => None,
+                Some((_, Ok(steps))) => Some((Some(steps), None)),
+                Some((label, Err(_))) => match machine.label(label) {
+                    Some(label) => Some((None, Some(label.address))),
+                    None => {
+                        println!("{} there is no label \"{}\" to stop at for the snapshot.", "Error:".red().bold(), label);
+                        std::process::exit(2);
+                    }
+                }
+            };
+
+            let mut steps = 0u64;
+            let result = loop {
+                if let Some(exit) = machine.exit {
+                    break Ok(Some(exit));
+                }
+                if let Some((steps, address)) = stop {
+                    if steps.is_some_and(|steps| machine.steps >= steps) || address == Some(machine.pc) {
+                        break Ok(None);
+                    }
+                }
+                let stepped = machine.step();
+                if let Some(pipeline) = &mut pipeline {
+                    pipeline.retire(&machine);
+                }
+                if let Some(caches) = &mut caches {
+                    caches.retire(&machine);
+                }
+                if let Some(predictors) = &mut predictors {
+                    predictors.retire(&machine);
+                }
+                if let Some(stats) = &mut stats {
+                    stats.retire(&machine);
+                }
+                if let Some(profile) = &mut profile {
+                    profile.retire(&machine);
+                }
+                if let Some(trace) = &mut tracer {
+                    if let Err(why) = trace.retire(&machine, &sources) {
+                        println!("{} failed to write the trace: {}", "Error:".red().bold(), why);
+                        tracer = None;
+                    }
+                }
+                if let Err(fault) = stepped {
+                    break Err(fault);
+                }
 
-    let path = Path::new(&args.file);
+                steps += 1;
+                if let (Some(recorder), Some(every)) = (&mut recorder, frames) {
+                    if steps.is_multiple_of(every) {
+                        if let Err(why) = recorder.frame(&machine.memory) {
+                            println!("{} failed to write a frame of the bitmap display: {}", "Error:".red().bold(), why);
+                            frames = None;
+                        }
+                    }
+                }
+            };
 
-    let file = match File::open(path) {
+            if let Some(trace) = &mut tracer {
+                if let Err(why) = trace.flush() {
+                    println!("{} failed to write the trace: {}", "Error:".red().bold(), why);
+                }
+            }
+            if let Some(path) = &args.snapshot {
+                let mut bytes = Vec::new();
+                if let Err(why) = machine.save(&mut bytes).and_then(|_| fs::write(path, bytes)) {
+                    println!("{} failed to write \"{}\": {}.", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+                }
+            }
+            if let Some(directory) = &args.fs_out {
+                if let Err(why) = machine.files.save(directory) {
+                    println!("{} failed to write the files of the program: {}.", "Error:".red().bold(), why);
+                }
+            }
+            if let Some(recorder) = &mut recorder {
+                if let Err(why) = recorder.finish(&machine.memory) {
+                    println!("{} failed to write the bitmap display: {}", "Error:".red().bold(), why);
+                }
+            }
+            if let Some(pipeline) = &pipeline {
+                print!("\n{}", pipeline.diagram());
+            }
+            if let Some(caches) = &caches {
+                print!("\n{}", caches.report(args.caches.cache_contents));
+            }
+            if let Some(predictors) = &predictors {
+                print!("\n{}", predictors.report(&machine, &sources));
+            }
+            if let (Some(stats), Some(format)) = (&stats, args.stats) {
+                let report = stats.report(&machine, &sources, &args.cpi, format);
+                match &args.stats_out {
+                    Some(path) => if let Err(why) = fs::write(path, report) {
+                        println!("{} failed to write \"{}\": {}", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+                    },
+                    None => print!("\n{}", report)
+                }
+            }
+            if let Some(profile) = &profile {
+                if args.profile {
+                    print!("\n{}", profile.report(&machine));
+                }
+                if let Some(path) = &args.profile_folded {
+                    if let Err(why) = fs::write(path, profile.folded(&machine)) {
+                        println!("{} failed to write \"{}\": {}", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+                    }
+                }
+            }
+
+            match result {
+                Ok(Some(runtime::Exit::Code(code))) => std::process::exit(code),
+                Ok(Some(runtime::Exit::FellOff) | None) => {},
+                Err(fault) => {
+                    println!("{}", machine.report(&fault, &sources));
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn assemble_file(sources: &mut source::SourceMap, path: &str, endian: mips::Endian) -> Option<object::Object> {
+    assemble_tree(sources, path, endian).map(|(_, object)| object)
+}
+
+// reads, expands, parses and assembles a source file, reporting any error
+fn assemble_tree(sources: &mut source::SourceMap, path: &str, endian: mips::Endian) -> Option<(Tree, object::Object)> {
+    let file = match sources.load(Path::new(path)) {
         Err(why) => {
-            println!("{} failed to open \"{}\": {}", "Error:".red().bold(), args.file.bright_black(), why);
-            return;
+            println!("{} failed to open \"{}\": {}", "Error:".red().bold(), path.bright_black(), why);
+            return None;
         },
 
         Ok(file) => file,
     };
 
-    let program: Vec<String> = io::BufReader::new(file).lines().map(|l| {
-        match l {
-            Ok(line) => line,
-            Err(why) => {
-                println!("{} failed to open \"{}\": {}", "Error:".red().bold(), args.file.bright_black(), why);
-                std::process::exit(0);
+    match assemble_source(sources, file, endian) {
+        Ok(assembled) => Some(assembled),
+        Err(err) => {
+            handle_err(sources, err);
+            None
+        }
+    }
+}
+
+fn read_file(path: &str) -> Option<Vec<u8>> {
+    match fs::read(path) {
+        Ok(bytes) => Some(bytes),
+        Err(why) => {
+            println!("{} failed to open \"{}\": {}", "Error:".red().bold(), path.bright_black(), why);
+            None
+        }
+    }
+}
+
+// a program ready to be run, as many times as needed
+enum Loaded {
+    Program(link::Program),
+    Elf(elf::Executable)
+}
+
+impl Loaded {
+    fn machine(&self, config: runtime::Config) -> runtime::Machine {
+        match self {
+            Loaded::Program(program) => runtime::Machine::new(program, config),
+            Loaded::Elf(executable) => runtime::Machine::from_elf(executable, config)
+        }
+    }
+}
+
+// a single executable is loaded as it is, anything else is linked first
+fn program(sources: &mut source::SourceMap, files: &[String]) -> Option<Loaded> {
+    if let [path] = files {
+        let bytes = read_file(path)?;
+
+        if elf::is_elf(&bytes) {
+            return match elf::read(&bytes) {
+                Ok(executable) => Some(Loaded::Elf(executable)),
+                Err(why) => {
+                    println!("{} \"{}\" {}.", "Error:".red().bold(), path.bright_black(), why);
+                    None
+                }
+            };
+        }
+
+        if let Ok(object) = object::Object::read(&bytes) {
+            if let Ok(program) = link::Program::from_object(object) {
+                return Some(Loaded::Program(program));
             }
         }
-    }).collect();
+    }
 
-    match lexer::tokenize(&program) {
-        Ok(tokens) => {
-            match parse::parse(&tokens) {
-                Ok(nodes) => {
-                    let tree = parse::transform(nodes);
-                    println!("{:#?}", tree);
-                },
-                Err(err) => handle_err(program, err)
+    let (program, _) = link_files(sources, files, &link::Config::default(), mips::Endian::Little)?;
+    Some(Loaded::Program(program))
+}
+
+fn load(sources: &mut source::SourceMap, files: &[String], config: runtime::Config) -> Option<runtime::Machine> {
+    Some(program(sources, files)?.machine(config))
+}
+
+// a machine carried on from a snapshot, with the lines of its source taken from the files given alongside
+fn resume(sources: &mut source::SourceMap, path: &Path, files: &[String], limits: runtime::Limits) -> Option<runtime::Machine> {
+    let restored = fs::read(path).and_then(|bytes| runtime::Machine::restore(&bytes, limits));
+    let mut machine = match restored {
+        Ok(machine) => machine,
+        Err(why) => {
+            println!("{} failed to resume from \"{}\": {}.", "Error:".red().bold(), path.display().to_string().bright_black(), why);
+            return None;
+        }
+    };
+    if !files.is_empty() {
+        machine.lines = load(sources, files, runtime::Config::default())?.lines;
+    }
+    Some(machine)
+}
+
+// a side of diff-trace, being a recorded trace or the files of a program
+fn diff_side(sources: &mut source::SourceMap, arg: &str, input: &[u8], delay_slots: bool) -> Option<diff::Side> {
+    let files: Vec<String> = arg.split(',').map(|file| file.to_string()).collect();
+    if let [path] = files.as_slice() {
+        let bytes = read_file(path)?;
+        if diff::Side::is_trace(&bytes) {
+            return match diff::Side::recorded(&String::from_utf8_lossy(&bytes)) {
+                Ok(side) => Some(side),
+                Err(why) => {
+                    println!("{} \"{}\": {}.", "Error:".red().bold(), path.bright_black(), why);
+                    None
+                }
+            };
+        }
+    }
+
+    let machine = load(sources, &files, runtime::Config { delay_slots, ..Default::default() })?;
+    Some(diff::Side::run(machine, input))
+}
+
+// object files are read as they are, anything else is assembled, its tree being kept for the listing
+fn link_files(sources: &mut source::SourceMap, files: &[String], config: &link::Config, endian: mips::Endian) -> Option<(link::Program, Vec<Tree>)> {
+    let mut objects = Vec::new();
+    let mut units = Vec::new();
+    for path in files {
+        let bytes = read_file(path)?;
+
+        if object::Object::is_object(&bytes) {
+            match object::Object::read(&bytes) {
+                Ok(object) => objects.push(object),
+                Err(why) => {
+                    println!("{} failed to read \"{}\": {}", "Error:".red().bold(), path.bright_black(), why);
+                    return None;
+                }
             }
+        } else {
+            let (tree, object) = assemble_tree(sources, path, endian)?;
+            units.push(tree);
+            objects.push(object);
+        }
+    }
+
+    match link::link(&objects, config) {
+        Ok(program) => Some((program, units)),
+        Err(link::LinkErr::Source(err)) => {
+            handle_err(sources, err);
+            None
         },
-        Err(err) => handle_err(program, err)
+        Err(link::LinkErr::Object { object, msg }) => {
+            println!("{} \"{}\" {}.", "Error:".red().bold(), files[object].bright_black(), msg);
+            None
+        },
+        Err(link::LinkErr::Layout(msg)) => {
+            println!("{} {}.", "Error:".red().bold(), msg);
+            None
+        }
+    }
+}
+
+fn write_program(program: &link::Program, emit: Emit, out: &Path) -> bool {
+    match emit {
+        Emit::Object => write_object(&program.to_object(), out),
+        Emit::Elf => write_bytes(&elf::write(program), out)
     }
 }
 
-fn handle_err(program: Vec<String>, err: errors::Err) {
-    let line = &program[err.segment.line];
-    println!("{}", errors::DisplayableErr::new(err, line));
+fn write_object(object: &object::Object, out: &Path) -> bool {
+    let mut bytes = Vec::new();
+    match object.write(&mut bytes) {
+        Ok(_) => write_bytes(&bytes, out),
+        Err(why) => {
+            println!("{} failed to write \"{}\": {}", "Error:".red().bold(), out.display().to_string().bright_black(), why);
+            false
+        }
+    }
+}
+
+fn write_bytes(bytes: &[u8], out: &Path) -> bool {
+    let written = fs::write(out, bytes);
+    if let Err(why) = &written {
+        println!("{} failed to write \"{}\": {}", "Error:".red().bold(), out.display().to_string().bright_black(), why);
+    }
+
+    written.is_ok()
 }
 
-pub mod mips {
-    // highlight snytax for one line of code
-    pub fn syntax_highlight(code: String) -> String {
-        // note that red is only to be used for errors
-        code
+fn disassemble_file(path: &str, bytes: &[u8], pseudo: bool, base: u32, endian: mips::Endian, binary: bool) -> bool {
+    if elf::is_elf(bytes) {
+        let executable = match elf::read(bytes) {
+            Ok(executable) => executable,
+            Err(why) => {
+                println!("{} \"{}\" {}.", "Error:".red().bold(), path.bright_black(), why);
+                return false;
+            }
+        };
+
+        for segment in executable.segments.iter().filter(|segment| segment.executable) {
+            let lines = disasm::section(&segment.bytes, segment.address, executable.endian, &executable.labels, pseudo);
+            print_disassembly(&lines, &executable.labels);
+        }
+        return true;
     }
-}
\ No newline at end of file
+
+    if object::Object::is_object(bytes) {
+        let object = match object::Object::read(bytes) {
+            Ok(object) => object,
+            Err(why) => {
+                println!("{} failed to read \"{}\": {}", "Error:".red().bold(), path.bright_black(), why);
+                return false;
+            }
+        };
+
+        // objects which are not linked are shown at the base, with references to other objects left unresolved
+        let bases = |section: object::Section| match (object.layout, section) {
+            (Some(layout), _) => layout.bases[object::Section::ALL.iter().position(|other| *other == section).unwrap_or_default()],
+            (None, object::Section::Text) => base,
+            (None, _) => mips::KTEXT_BASE
+        };
+        let labels = match object.layout {
+            Some(_) => link::Program::from_object(object.clone()).map(|program| program.labels).unwrap_or_default(),
+            None => object.symbols.iter().filter(|symbol| symbol.section.is_some_and(|section| section.executable())).map(|symbol| link::Label {
+                name: symbol.name.to_string(),
+                address: bases(symbol.section.unwrap()).wrapping_add(symbol.offset),
+                section: symbol.section.unwrap(),
+                binding: symbol.binding,
+                segment: None
+            }).collect()
+        };
+
+        for section in [object::Section::Text, object::Section::KernelText] {
+            if section == object::Section::Text || !object.ktext.is_empty() {
+                print_disassembly(&disasm::section(object.section(section), bases(section), object.endian, &labels, pseudo), &labels);
+            }
+        }
+        return true;
+    }
+
+    // text of whitespace separated words, as students write by hand
+    let words: Option<Vec<u32>> = std::str::from_utf8(bytes).ok().filter(|_| !binary)
+        .and_then(|text| text.split_whitespace().map(parse_word).collect());
+
+    match words {
+        Some(words) => {
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+            print_disassembly(&disasm::section(&bytes, base, mips::Endian::Little, &[], pseudo), &[]);
+        },
+        None => print_disassembly(&disasm::section(bytes, base, endian, &[], pseudo), &[])
+    }
+
+    true
+}
+
+fn print_disassembly(lines: &[(u32, u32, String)], labels: &[link::Label]) {
+    for (address, word, text) in lines {
+        for label in labels.iter().filter(|label| label.address == *address && label.section.executable()) {
+            println!("{}:", label.name);
+        }
+        println!("  {:08x}:  {:08x}  {}", address, word, text);
+    }
+}
+
+fn handle_err(sources: &source::SourceMap, err: errors::Err) {
+    println!("{}", errors::DisplayableErr::new(err, sources));
+}
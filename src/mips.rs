@@ -0,0 +1,68 @@
+// highlight snytax for one line of code
+pub fn syntax_highlight(code: String) -> String {
+	// note that red is only to be used for errors
+	code
+}
+
+// default memory layout, matching MARS
+pub const TEXT_BASE: u32 = 0x00400000;
+pub const DATA_BASE: u32 = 0x10010000;
+pub const KTEXT_BASE: u32 = 0x80000000;
+pub const KDATA_BASE: u32 = 0x90000000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+	Little,
+	Big
+}
+
+impl Endian {
+	pub fn read_u32(&self, bytes: &[u8]) -> u32 {
+		let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+		match self {
+			Endian::Little => u32::from_le_bytes(bytes),
+			Endian::Big => u32::from_be_bytes(bytes)
+		}
+	}
+
+	pub fn write_u32(&self, bytes: &mut [u8], value: u32) {
+		let value = match self {
+			Endian::Little => value.to_le_bytes(),
+			Endian::Big => value.to_be_bytes()
+		};
+		bytes[..4].copy_from_slice(&value);
+	}
+
+	pub fn read_u16(&self, bytes: &[u8]) -> u16 {
+		let bytes = [bytes[0], bytes[1]];
+		match self {
+			Endian::Little => u16::from_le_bytes(bytes),
+			Endian::Big => u16::from_be_bytes(bytes)
+		}
+	}
+
+	pub fn write_u16(&self, bytes: &mut [u8], value: u16) {
+		let value = match self {
+			Endian::Little => value.to_le_bytes(),
+			Endian::Big => value.to_be_bytes()
+		};
+		bytes[..2].copy_from_slice(&value);
+	}
+
+	pub fn read_u64(&self, bytes: &[u8]) -> u64 {
+		let mut array = [0; 8];
+		array.copy_from_slice(&bytes[..8]);
+		match self {
+			Endian::Little => u64::from_le_bytes(array),
+			Endian::Big => u64::from_be_bytes(array)
+		}
+	}
+
+	pub fn write_u64(&self, bytes: &mut [u8], value: u64) {
+		let value = match self {
+			Endian::Little => value.to_le_bytes(),
+			Endian::Big => value.to_be_bytes()
+		};
+		bytes[..8].copy_from_slice(&value);
+	}
+}
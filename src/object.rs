@@ -0,0 +1,328 @@
+use std::io::{self, Write};
+
+use crate::lexer::tokens::CodeSegment;
+use crate::mips::Endian;
+
+// object files start with this, followed by the format version
+const MAGIC: &[u8; 4] = b"\x7fRMO";
+const VERSION: u16 = 2;
+
+const FLAG_BIG_ENDIAN: u16 = 1 << 0;
+const FLAG_EXECUTABLE: u16 = 1 << 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+	Text,
+	Data,
+	KernelText,		// .ktext, where the exception handler is
+	KernelData
+}
+
+impl Section {
+	pub const ALL: [Section; 4] = [Section::Text, Section::Data, Section::KernelText, Section::KernelData];
+
+	pub fn executable(&self) -> bool {
+		matches!(self, Section::Text | Section::KernelText)
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Section::Text => ".text",
+			Section::Data => ".data",
+			Section::KernelText => ".ktext",
+			Section::KernelData => ".kdata"
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+	Local,		// only visible within its own object
+	Global		// declared with .globl when defined, or with .extern (or by use) when not
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+	pub name: String,
+	pub section: Option<Section>,	// none when the symbol is defined by another object
+	pub offset: u32,				// from the start of its section
+	pub binding: Binding,
+	pub segment: Option<CodeSegment>	// where the symbol is defined, only known when assembled from source
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+	Mips26,		// 26 bit word index of a jump target
+	Hi16,		// upper half of an address, adjusted for the sign of the lower half
+	Lo16,		// lower half of an address
+	Word32,		// whole address
+	Pc16		// branch offset in words, relative to the following instruction
+}
+
+#[derive(Debug, Clone)]
+pub struct Relocation {
+	pub section: Section,
+	pub offset: u32,		// of the word to patch within its section
+	pub kind: RelocationKind,
+	pub symbol: usize,		// index into the symbol table
+	pub addend: i32,
+	pub segment: Option<CodeSegment>	// the code referencing the symbol, only known when assembled from source
+}
+
+// the source of a run of bytes, at an offset within its section in an object and at an address once linked
+#[derive(Debug, Clone)]
+pub struct Line {
+	pub section: Section,
+	pub address: u32,
+	pub len: u32,
+	pub segment: CodeSegment
+}
+
+// where the sections of a linked program are placed in memory
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+	pub bases: [u32; 4],	// of each section, in the order of Section::ALL
+	pub entry: u32
+}
+
+#[derive(Debug, Clone)]
+pub struct Object {
+	pub endian: Endian,
+	pub layout: Option<Layout>,		// only executables are placed in memory
+	pub text: Vec<u8>,
+	pub data: Vec<u8>,
+	pub ktext: Vec<u8>,
+	pub kdata: Vec<u8>,
+	pub symbols: Vec<Symbol>,
+	pub relocations: Vec<Relocation>,
+	pub lines: Vec<Line>	// the source of every instruction and data directive, when assembled from source
+}
+
+impl Object {
+	pub fn new(endian: Endian) -> Object {
+		Object {
+			endian,
+			layout: None,
+			text: Vec::new(),
+			data: Vec::new(),
+			ktext: Vec::new(),
+			kdata: Vec::new(),
+			symbols: Vec::new(),
+			relocations: Vec::new(),
+			lines: Vec::new()
+		}
+	}
+
+	pub fn section(&self, section: Section) -> &Vec<u8> {
+		match section {
+			Section::Text => &self.text,
+			Section::Data => &self.data,
+			Section::KernelText => &self.ktext,
+			Section::KernelData => &self.kdata
+		}
+	}
+
+	pub fn section_mut(&mut self, section: Section) -> &mut Vec<u8> {
+		match section {
+			Section::Text => &mut self.text,
+			Section::Data => &mut self.data,
+			Section::KernelText => &mut self.ktext,
+			Section::KernelData => &mut self.kdata
+		}
+	}
+
+	pub fn is_object(bytes: &[u8]) -> bool {
+		bytes.starts_with(MAGIC)
+	}
+
+	// source information is not written, as objects are meant to be shared without their source
+	pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+		let mut flags = 0;
+		if self.endian == Endian::Big {
+			flags |= FLAG_BIG_ENDIAN;
+		}
+		if self.layout.is_some() {
+			flags |= FLAG_EXECUTABLE;
+		}
+
+		out.write_all(MAGIC)?;
+		out.write_all(&VERSION.to_le_bytes())?;
+		out.write_all(&flags.to_le_bytes())?;
+
+		if let Some(layout) = &self.layout {
+			for base in layout.bases {
+				write_u32(out, base)?;
+			}
+			write_u32(out, layout.entry)?;
+		}
+
+		for section in Section::ALL {
+			let bytes = self.section(section);
+			write_u32(out, bytes.len() as u32)?;
+			out.write_all(bytes)?;
+		}
+
+		write_u32(out, self.symbols.len() as u32)?;
+		for symbol in &self.symbols {
+			write_u32(out, symbol.name.len() as u32)?;
+			out.write_all(symbol.name.as_bytes())?;
+			out.write_all(&[section_id(symbol.section), symbol.binding as u8])?;
+			write_u32(out, symbol.offset)?;
+		}
+
+		write_u32(out, self.relocations.len() as u32)?;
+		for relocation in &self.relocations {
+			out.write_all(&[section_id(Some(relocation.section)), relocation.kind as u8])?;
+			write_u32(out, relocation.offset)?;
+			write_u32(out, relocation.symbol as u32)?;
+			write_u32(out, relocation.addend as u32)?;
+		}
+
+		Ok(())
+	}
+
+	pub fn read(bytes: &[u8]) -> io::Result<Object> {
+		let mut reader = Reader { bytes, idx: 0 };
+
+		if reader.take(4)? != MAGIC {
+			return Err(invalid("not an object file"));
+		}
+
+		let version = reader.u16()?;
+		if version != VERSION {
+			return Err(invalid(&format!("unsupported object file version {}", version)));
+		}
+
+		let flags = reader.u16()?;
+		let endian = if flags & FLAG_BIG_ENDIAN != 0 { Endian::Big } else { Endian::Little };
+		let mut object = Object::new(endian);
+
+		if flags & FLAG_EXECUTABLE != 0 {
+			object.layout = Some(Layout {
+				bases: [reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?],
+				entry: reader.u32()?
+			});
+		}
+
+		for section in Section::ALL {
+			let len = reader.u32()? as usize;
+			*object.section_mut(section) = reader.take(len)?.to_vec();
+		}
+
+		for _ in 0..reader.u32()? {
+			let len = reader.u32()? as usize;
+			let name = match String::from_utf8(reader.take(len)?.to_vec()) {
+				Ok(name) => name,
+				Err(_) => return Err(invalid("symbol name is not valid UTF-8"))
+			};
+			let section = section_from_id(reader.u8()?)?;
+			let binding = match reader.u8()? {
+				0 => Binding::Local,
+				1 => Binding::Global,
+				other => return Err(invalid(&format!("unknown symbol binding {}", other)))
+			};
+			let offset = reader.u32()?;
+
+			object.symbols.push(Symbol { name, section, offset, binding, segment: None });
+		}
+
+		for _ in 0..reader.u32()? {
+			let section = match section_from_id(reader.u8()?)? {
+				Some(section) => section,
+				None => return Err(invalid("relocation outside of any section"))
+			};
+			let kind = match reader.u8()? {
+				0 => RelocationKind::Mips26,
+				1 => RelocationKind::Hi16,
+				2 => RelocationKind::Lo16,
+				3 => RelocationKind::Word32,
+				4 => RelocationKind::Pc16,
+				other => return Err(invalid(&format!("unknown relocation kind {}", other)))
+			};
+			let offset = reader.u32()?;
+			let symbol = reader.u32()? as usize;
+			let addend = reader.u32()? as i32;
+
+			if symbol >= object.symbols.len() {
+				return Err(invalid("relocation refers to a missing symbol"));
+			}
+			if offset as usize + 4 > object.section(section).len() {
+				return Err(invalid("relocation outside of its section"));
+			}
+
+			object.relocations.push(Relocation { section, offset, kind, symbol, addend, segment: None });
+		}
+
+		Ok(object)
+	}
+}
+
+pub(crate) fn section_id(section: Option<Section>) -> u8 {
+	match section {
+		None => 0,
+		Some(Section::Text) => 1,
+		Some(Section::Data) => 2,
+		Some(Section::KernelText) => 3,
+		Some(Section::KernelData) => 4
+	}
+}
+
+pub(crate) fn section_from_id(id: u8) -> io::Result<Option<Section>> {
+	match id {
+		0 => Ok(None),
+		1 => Ok(Some(Section::Text)),
+		2 => Ok(Some(Section::Data)),
+		3 => Ok(Some(Section::KernelText)),
+		4 => Ok(Some(Section::KernelData)),
+		other => Err(invalid(&format!("unknown section {}", other)))
+	}
+}
+
+pub(crate) fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+	out.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn invalid(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// reads the little endian fields of objects and snapshots
+pub(crate) struct Reader<'a> {
+	pub bytes: &'a [u8],
+	pub idx: usize
+}
+
+impl<'a> Reader<'a> {
+	pub fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+		if self.idx + len > self.bytes.len() {
+			return Err(invalid("unexpected end of file"));
+		}
+
+		let bytes = &self.bytes[self.idx..self.idx + len];
+		self.idx += len;
+		Ok(bytes)
+	}
+
+	pub fn u8(&mut self) -> io::Result<u8> {
+		Ok(self.take(1)?[0])
+	}
+
+	pub fn u16(&mut self) -> io::Result<u16> {
+		let bytes = self.take(2)?;
+		Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+	}
+
+	pub fn u32(&mut self) -> io::Result<u32> {
+		let bytes = self.take(4)?;
+		Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+
+	pub fn u64(&mut self) -> io::Result<u64> {
+		Ok(self.u32()? as u64 | (self.u32()? as u64) << 32)
+	}
+
+	pub fn string(&mut self) -> io::Result<String> {
+		let len = self.u32()? as usize;
+		String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("a name is not valid UTF-8"))
+	}
+}
@@ -1,7 +1,7 @@
-mod ast;
-mod symbols;
+pub mod ast;
+pub mod symbols;
 mod parsers;
-mod instructions;
+pub mod instructions;
 
 use crate::parse::symbols::*;
 use crate::parse::parsers::Parser;
@@ -15,31 +15,18 @@ use colored::Colorize;
 type ParRes<T> = Result<T, errors::Err>;
 type Return = ParRes<Vec<ASTNode<Symbol>>>;
 
-pub fn parse_one(idx: &mut usize, program: &Vec<Token>) -> ParRes<ASTNode<Symbol>> {
+pub fn parse_one(idx: &mut usize, program: &[Token]) -> ParRes<ASTNode<Symbol>> {
 	let token = &program[*idx];
 	let symbol: Option<ASTNode<Symbol>> = match token {
-		Token::Directive(id, segment) => {
-			match parsers::Directive::parse(idx, &program) {
-				Ok(node) => Some(node),
-				Err(err) => return Err(err)
-			}
-		},
+		Token::Directive(_, _) => Some(parsers::Directive::parse(idx, program)?),
 
-		Token::DefLabel(id, segment) => {
-			match parsers::DefLabel::parse(idx, &program) {
-				Ok(node) => Some(node),
-				Err(err) => return Err(err)
-			}
-		},
+		Token::DefLabel(_, _) => Some(parsers::DefLabel::parse(idx, program)?),
 
-		Token::Identifier(id, segment) => {
+		Token::Identifier(_, _) => {
 			// we can assume this to be an instruction, because labels can only occur after instructions
 			// thus labels will be consumed elsewhere
 			// labels included here will fail to validate as instructions for one reason or anotehr
-			match parsers::Instruction::parse(idx, &program) {
-				Ok(node) => Some(node),
-				Err(err) => return Err(err)
-			}
+			Some(parsers::Instruction::parse(idx, program)?)
 		}
 
 		_ => {
@@ -55,25 +42,22 @@ pub fn parse_one(idx: &mut usize, program: &Vec<Token>) -> ParRes<ASTNode<Symbol
 	match symbol {
 		Some(symbol) => Ok(symbol),
 		None => {
-			let msg = errors::Msg::One(format!("Token failed to parse."));
-			return Err(errors::Err {
+			let msg = errors::Msg::One("Token failed to parse.".to_string());
+			Err(errors::Err {
 				segment: extract_segment(token),
 				errtype: errors::ErrType::Assemble,
 				msg
-			});
+			})
 		}
 	}
 }
 
-pub fn parse(program: &Vec<Token>) -> Return {
+pub fn parse(program: &[Token]) -> Return {
 	let mut nodes = Vec::new();
 
 	let mut idx = 0;
 	while idx < program.len() {
-		match parse_one(&mut idx, program) {
-			Ok(symbol) => nodes.push(symbol),
-			Err(err) => return Err(err)
-		}
+		nodes.push(parse_one(&mut idx, program)?);
 
 		idx += 1;
 	}
@@ -87,27 +71,23 @@ pub fn transform(nodes: Vec<ASTNode<Symbol>>) -> BaseASTree<Symbol> {
 		basetree.add_node(node);
 	}
 
-	return basetree;
+	basetree
 }
 
-fn call_err(segment: &CodeSegment, msg: errors::Msg) -> Return {
-	Err(errors::Err {
-		segment: segment.clone(),
-		errtype: errors::ErrType::Assemble,
-		msg
-	})
-}
-
-fn extract_segment(token: &Token) -> CodeSegment {
+pub fn extract_segment(token: &Token) -> CodeSegment {
 	match token {
 		Token::Directive(_, segment) => segment.clone(),
 		Token::DefLabel(_, segment) => segment.clone(),
 		Token::Identifier(_, segment) => segment.clone(),
 		Token::Register(_, segment) => segment.clone(),
+		Token::MacroParameter(_, segment) => segment.clone(),
 		Token::StringLiteral(_, segment) => segment.clone(),
 		Token::NumberLiteral(_, segment) => segment.clone(),
+		Token::FloatLiteral(_, segment) => segment.clone(),
+		Token::LeftParen(segment) => segment.clone(),
+		Token::RightParen(segment) => segment.clone(),
 		_ => {
 			panic!("Illegal token {:?}", token);
 		}
 	}
-}
\ No newline at end of file
+}
@@ -23,8 +23,8 @@ impl<T> ASTree<T> {
 		}
 	}
 
-	pub fn root(self) -> T {
-		self.root
+	pub fn root(&self) -> &T {
+		&self.root
 	}
 }
 
@@ -37,12 +37,8 @@ impl<T> Tree<T> for ASTree<T> {
 		self.children.push(child);
 	}
 
-	fn add_subtree(&mut self, child: ASTree<T>) {
-		self.children.push(ASTNode::Tree(child));
-	}
-
-	fn get_children(self) -> Vec<ASTNode<T>> {
-		self.children
+	fn children(&self) -> &[ASTNode<T>] {
+		&self.children
 	}
 }
 
@@ -55,12 +51,8 @@ impl<T> Tree<T> for BaseASTree<T> {
 		self.children.push(child);
 	}
 
-	fn add_subtree(&mut self, child: ASTree<T>) {
-		self.children.push(ASTNode::Tree(child));
-	}
-
-	fn get_children(self) -> Vec<ASTNode<T>> {
-		self.children
+	fn children(&self) -> &[ASTNode<T>] {
+		&self.children
 	}
 }
 
@@ -72,10 +64,15 @@ impl<T> BaseASTree<T> {
 	}
 }
 
+impl<T> Default for BaseASTree<T> {
+	fn default() -> BaseASTree<T> {
+		BaseASTree::new()
+	}
+}
+
 
 pub trait Tree<T> {
 	fn add_child(&mut self, child: T);
 	fn add_node(&mut self, child: ASTNode<T>);
-	fn add_subtree(&mut self, child: ASTree<T>);
-	fn get_children(self) -> Vec<ASTNode<T>>;
+	fn children(&self) -> &[ASTNode<T>];
 }
\ No newline at end of file
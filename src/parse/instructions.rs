@@ -1,20 +1,292 @@
 use crate::lexer::tokens::{Token, CodeSegment};
-use crate::parse::parsers::Parser;
-use crate::parse::parsers;
 use crate::parse::symbols::*;
 use crate::parse::ast::*;
+use crate::parse::parsers::{self, Parser};
 use crate::parse;
 use crate::errors;
 
+use colored::Colorize;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Instruction {
-	LoadImmediate,
-	LoadAddress,
-	SystemCall
+	// arithmetic and logic
+	Add, AddUnsigned, Subtract, SubtractUnsigned,
+	And, Or, Xor, Nor,
+	SetLessThan, SetLessThanUnsigned,
+	ShiftLeftLogical, ShiftRightLogical, ShiftRightArithmetic,
+	ShiftLeftLogicalVariable, ShiftRightLogicalVariable, ShiftRightArithmeticVariable,
+	AddImmediate, AddImmediateUnsigned,
+	SetLessThanImmediate, SetLessThanImmediateUnsigned,
+	AndImmediate, OrImmediate, XorImmediate,
+	LoadUpperImmediate,
+	MoveConditionalZero, MoveConditionalNotZero,
+	CountLeadingZeros, CountLeadingOnes,
+
+	// multiplication and division
+	Multiply, MultiplyUnsigned, Divide, DivideUnsigned,
+	MultiplyToRegister,
+	MultiplyAdd, MultiplyAddUnsigned, MultiplySubtract, MultiplySubtractUnsigned,
+	MoveFromHi, MoveFromLo, MoveToHi, MoveToLo,
+
+	// memory
+	LoadByte, LoadByteUnsigned, LoadHalf, LoadHalfUnsigned, LoadWord,
+	LoadWordLeft, LoadWordRight, LoadLinked,
+	StoreByte, StoreHalf, StoreWord,
+	StoreWordLeft, StoreWordRight, StoreConditional,
+
+	// control
+	BranchEqual, BranchNotEqual,
+	BranchLessEqualZero, BranchGreaterThanZero,
+	BranchLessThanZero, BranchGreaterEqualZero,
+	BranchLessThanZeroAndLink, BranchGreaterEqualZeroAndLink,
+	Jump, JumpAndLink, JumpRegister, JumpAndLinkRegister,
+	SystemCall, Break,
+
+	// traps, and the exception handling of coprocessor 0
+	TrapEqual, TrapNotEqual, TrapGreaterEqual, TrapGreaterEqualUnsigned, TrapLessThan, TrapLessThanUnsigned,
+	TrapEqualImmediate, TrapNotEqualImmediate, TrapGreaterEqualImmediate, TrapGreaterEqualImmediateUnsigned,
+	TrapLessThanImmediate, TrapLessThanImmediateUnsigned,
+	MoveFromCoprocessor0, MoveToCoprocessor0, ExceptionReturn,
+
+	// floating point, on coprocessor 1
+	AddSingle, AddDouble, SubtractSingle, SubtractDouble,
+	MultiplySingle, MultiplyDouble, DivideSingle, DivideDouble,
+	AbsoluteSingle, AbsoluteDouble, NegateSingle, NegateDouble,
+	MoveSingle, MoveDouble, SquareRootSingle, SquareRootDouble,
+	ConvertSingleDouble, ConvertSingleWord, ConvertDoubleSingle, ConvertDoubleWord, ConvertWordSingle, ConvertWordDouble,
+	TruncateWordSingle, TruncateWordDouble, RoundWordSingle, RoundWordDouble,
+	FloorWordSingle, FloorWordDouble, CeilWordSingle, CeilWordDouble,
+	CompareEqualSingle, CompareEqualDouble, CompareLessThanSingle, CompareLessThanDouble,
+	CompareLessEqualSingle, CompareLessEqualDouble,
+	BranchFloatTrue, BranchFloatFalse,
+	MoveToFloat, MoveFromFloat, MoveControlToFloat, MoveControlFromFloat,
+	LoadWordFloat, StoreWordFloat, LoadDoubleFloat, StoreDoubleFloat,
+	MoveOnFalse, MoveOnTrue, MoveOnFalseSingle, MoveOnFalseDouble, MoveOnTrueSingle, MoveOnTrueDouble,
+
+	// pseudo-instructions, expanded by the assembler
+	NoOperation, Move, LoadImmediate, LoadAddress,
+	Negate, Not, Remainder,
+	Branch, BranchEqualZero, BranchNotEqualZero,
+	BranchLessThan, BranchGreaterThan, BranchLessEqual, BranchGreaterEqual,
+	BranchLessThanUnsigned, BranchGreaterThanUnsigned, BranchLessEqualUnsigned, BranchGreaterEqualUnsigned,
+	LoadSingle, StoreSingle, LoadDouble, StoreDouble
+}
+
+// the kinds of operand an instruction may be written with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+	Register,
+	FloatRegister,
+	Immediate,
+	Label,
+	Address		// "label", "offset($base)", "($base)" or "label($base)"
 }
 
-#[derive(Debug)]
+use Operand::{Register as R, FloatRegister as F, Immediate as I, Label as L, Address as A};
+
+const NONE: &[&[Operand]] = &[&[]];
+const RRR: &[&[Operand]] = &[&[R, R, R]];
+const RRI: &[&[Operand]] = &[&[R, R, I]];
+const RR: &[&[Operand]] = &[&[R, R]];
+const RI: &[&[Operand]] = &[&[R, I]];
+const RA: &[&[Operand]] = &[&[R, A]];
+const RRL: &[&[Operand]] = &[&[R, R, L]];
+const RL: &[&[Operand]] = &[&[R, L]];
+const ONE_R: &[&[Operand]] = &[&[R]];
+const ONE_L: &[&[Operand]] = &[&[L]];
+const FFF: &[&[Operand]] = &[&[F, F, F]];
+const FF: &[&[Operand]] = &[&[F, F]];
+const RF: &[&[Operand]] = &[&[R, F]];
+const FA: &[&[Operand]] = &[&[F, A]];
+
+impl Instruction {
+	pub const ALL: [Instruction; 158] = {
+		use Instruction::*;
+		[
+			Add, AddUnsigned, Subtract, SubtractUnsigned, And, Or, Xor, Nor, SetLessThan, SetLessThanUnsigned,
+			ShiftLeftLogical, ShiftRightLogical, ShiftRightArithmetic,
+			ShiftLeftLogicalVariable, ShiftRightLogicalVariable, ShiftRightArithmeticVariable,
+			AddImmediate, AddImmediateUnsigned, SetLessThanImmediate, SetLessThanImmediateUnsigned,
+			AndImmediate, OrImmediate, XorImmediate, LoadUpperImmediate,
+			MoveConditionalZero, MoveConditionalNotZero, CountLeadingZeros, CountLeadingOnes,
+			Multiply, MultiplyUnsigned, Divide, DivideUnsigned, MultiplyToRegister,
+			MultiplyAdd, MultiplyAddUnsigned, MultiplySubtract, MultiplySubtractUnsigned,
+			MoveFromHi, MoveFromLo, MoveToHi, MoveToLo,
+			LoadByte, LoadByteUnsigned, LoadHalf, LoadHalfUnsigned, LoadWord, LoadWordLeft, LoadWordRight, LoadLinked,
+			StoreByte, StoreHalf, StoreWord, StoreWordLeft, StoreWordRight, StoreConditional,
+			BranchEqual, BranchNotEqual, BranchLessEqualZero, BranchGreaterThanZero,
+			BranchLessThanZero, BranchGreaterEqualZero, BranchLessThanZeroAndLink, BranchGreaterEqualZeroAndLink,
+			Jump, JumpAndLink, JumpRegister, JumpAndLinkRegister, SystemCall, Break,
+			TrapEqual, TrapNotEqual, TrapGreaterEqual, TrapGreaterEqualUnsigned, TrapLessThan, TrapLessThanUnsigned,
+			TrapEqualImmediate, TrapNotEqualImmediate, TrapGreaterEqualImmediate, TrapGreaterEqualImmediateUnsigned,
+			TrapLessThanImmediate, TrapLessThanImmediateUnsigned, MoveFromCoprocessor0, MoveToCoprocessor0, ExceptionReturn,
+			AddSingle, AddDouble, SubtractSingle, SubtractDouble, MultiplySingle, MultiplyDouble, DivideSingle, DivideDouble,
+			AbsoluteSingle, AbsoluteDouble, NegateSingle, NegateDouble, MoveSingle, MoveDouble, SquareRootSingle, SquareRootDouble,
+			ConvertSingleDouble, ConvertSingleWord, ConvertDoubleSingle, ConvertDoubleWord, ConvertWordSingle, ConvertWordDouble,
+			TruncateWordSingle, TruncateWordDouble, RoundWordSingle, RoundWordDouble,
+			FloorWordSingle, FloorWordDouble, CeilWordSingle, CeilWordDouble,
+			CompareEqualSingle, CompareEqualDouble, CompareLessThanSingle, CompareLessThanDouble,
+			CompareLessEqualSingle, CompareLessEqualDouble, BranchFloatTrue, BranchFloatFalse,
+			MoveToFloat, MoveFromFloat, MoveControlToFloat, MoveControlFromFloat, LoadWordFloat, StoreWordFloat, LoadDoubleFloat, StoreDoubleFloat,
+			MoveOnFalse, MoveOnTrue, MoveOnFalseSingle, MoveOnFalseDouble, MoveOnTrueSingle, MoveOnTrueDouble,
+			NoOperation, Move, LoadImmediate, LoadAddress, Negate, Not, Remainder,
+			Branch, BranchEqualZero, BranchNotEqualZero,
+			BranchLessThan, BranchGreaterThan, BranchLessEqual, BranchGreaterEqual,
+			BranchLessThanUnsigned, BranchGreaterThanUnsigned, BranchLessEqualUnsigned, BranchGreaterEqualUnsigned,
+			LoadSingle, StoreSingle, LoadDouble, StoreDouble,
+		]
+	};
+
+	pub fn mnemonic(&self) -> &'static str {
+		use Instruction::*;
+		match self {
+			Add => "add", AddUnsigned => "addu", Subtract => "sub", SubtractUnsigned => "subu",
+			And => "and", Or => "or", Xor => "xor", Nor => "nor",
+			SetLessThan => "slt", SetLessThanUnsigned => "sltu",
+			ShiftLeftLogical => "sll", ShiftRightLogical => "srl", ShiftRightArithmetic => "sra",
+			ShiftLeftLogicalVariable => "sllv", ShiftRightLogicalVariable => "srlv", ShiftRightArithmeticVariable => "srav",
+			AddImmediate => "addi", AddImmediateUnsigned => "addiu",
+			SetLessThanImmediate => "slti", SetLessThanImmediateUnsigned => "sltiu",
+			AndImmediate => "andi", OrImmediate => "ori", XorImmediate => "xori",
+			LoadUpperImmediate => "lui",
+			MoveConditionalZero => "movz", MoveConditionalNotZero => "movn",
+			CountLeadingZeros => "clz", CountLeadingOnes => "clo",
+			Multiply => "mult", MultiplyUnsigned => "multu", Divide => "div", DivideUnsigned => "divu",
+			MultiplyToRegister => "mul",
+			MultiplyAdd => "madd", MultiplyAddUnsigned => "maddu", MultiplySubtract => "msub", MultiplySubtractUnsigned => "msubu",
+			MoveFromHi => "mfhi", MoveFromLo => "mflo", MoveToHi => "mthi", MoveToLo => "mtlo",
+			LoadByte => "lb", LoadByteUnsigned => "lbu", LoadHalf => "lh", LoadHalfUnsigned => "lhu", LoadWord => "lw",
+			LoadWordLeft => "lwl", LoadWordRight => "lwr", LoadLinked => "ll",
+			StoreByte => "sb", StoreHalf => "sh", StoreWord => "sw",
+			StoreWordLeft => "swl", StoreWordRight => "swr", StoreConditional => "sc",
+			BranchEqual => "beq", BranchNotEqual => "bne",
+			BranchLessEqualZero => "blez", BranchGreaterThanZero => "bgtz",
+			BranchLessThanZero => "bltz", BranchGreaterEqualZero => "bgez",
+			BranchLessThanZeroAndLink => "bltzal", BranchGreaterEqualZeroAndLink => "bgezal",
+			Jump => "j", JumpAndLink => "jal", JumpRegister => "jr", JumpAndLinkRegister => "jalr",
+			SystemCall => "syscall", Break => "break",
+			TrapEqual => "teq", TrapNotEqual => "tne", TrapGreaterEqual => "tge", TrapGreaterEqualUnsigned => "tgeu",
+			TrapLessThan => "tlt", TrapLessThanUnsigned => "tltu",
+			TrapEqualImmediate => "teqi", TrapNotEqualImmediate => "tnei", TrapGreaterEqualImmediate => "tgei",
+			TrapGreaterEqualImmediateUnsigned => "tgeiu", TrapLessThanImmediate => "tlti", TrapLessThanImmediateUnsigned => "tltiu",
+			MoveFromCoprocessor0 => "mfc0", MoveToCoprocessor0 => "mtc0", ExceptionReturn => "eret",
+			AddSingle => "add.s", AddDouble => "add.d", SubtractSingle => "sub.s", SubtractDouble => "sub.d",
+			MultiplySingle => "mul.s", MultiplyDouble => "mul.d", DivideSingle => "div.s", DivideDouble => "div.d",
+			AbsoluteSingle => "abs.s", AbsoluteDouble => "abs.d", NegateSingle => "neg.s", NegateDouble => "neg.d",
+			MoveSingle => "mov.s", MoveDouble => "mov.d", SquareRootSingle => "sqrt.s", SquareRootDouble => "sqrt.d",
+			ConvertSingleDouble => "cvt.s.d", ConvertSingleWord => "cvt.s.w", ConvertDoubleSingle => "cvt.d.s",
+			ConvertDoubleWord => "cvt.d.w", ConvertWordSingle => "cvt.w.s", ConvertWordDouble => "cvt.w.d",
+			TruncateWordSingle => "trunc.w.s", TruncateWordDouble => "trunc.w.d", RoundWordSingle => "round.w.s", RoundWordDouble => "round.w.d",
+			FloorWordSingle => "floor.w.s", FloorWordDouble => "floor.w.d", CeilWordSingle => "ceil.w.s", CeilWordDouble => "ceil.w.d",
+			CompareEqualSingle => "c.eq.s", CompareEqualDouble => "c.eq.d", CompareLessThanSingle => "c.lt.s", CompareLessThanDouble => "c.lt.d",
+			CompareLessEqualSingle => "c.le.s", CompareLessEqualDouble => "c.le.d",
+			BranchFloatTrue => "bc1t", BranchFloatFalse => "bc1f",
+			MoveToFloat => "mtc1", MoveFromFloat => "mfc1", MoveControlToFloat => "ctc1", MoveControlFromFloat => "cfc1",
+			LoadWordFloat => "lwc1", StoreWordFloat => "swc1", LoadDoubleFloat => "ldc1", StoreDoubleFloat => "sdc1",
+			MoveOnFalse => "movf", MoveOnTrue => "movt",
+			MoveOnFalseSingle => "movf.s", MoveOnFalseDouble => "movf.d", MoveOnTrueSingle => "movt.s", MoveOnTrueDouble => "movt.d",
+			NoOperation => "nop", Move => "move", LoadImmediate => "li", LoadAddress => "la",
+			Negate => "neg", Not => "not", Remainder => "rem",
+			Branch => "b", BranchEqualZero => "beqz", BranchNotEqualZero => "bnez",
+			BranchLessThan => "blt", BranchGreaterThan => "bgt", BranchLessEqual => "ble", BranchGreaterEqual => "bge",
+			BranchLessThanUnsigned => "bltu", BranchGreaterThanUnsigned => "bgtu",
+			BranchLessEqualUnsigned => "bleu", BranchGreaterEqualUnsigned => "bgeu",
+			LoadSingle => "l.s", StoreSingle => "s.s", LoadDouble => "l.d", StoreDouble => "s.d"
+		}
+	}
+
+	pub fn from_mnemonic(mnemonic: &str) -> Option<Instruction> {
+		Instruction::ALL.iter().find(|instruction| instruction.mnemonic() == mnemonic).copied()
+	}
+
+	// the forms an instruction may be written in
+	pub fn syntax(&self) -> &'static [&'static [Operand]] {
+		use Instruction::*;
+		match self {
+			Add | AddUnsigned | Subtract | SubtractUnsigned | And | Or | Xor | Nor |
+			SetLessThan | SetLessThanUnsigned | MoveConditionalZero | MoveConditionalNotZero |
+			ShiftLeftLogicalVariable | ShiftRightLogicalVariable | ShiftRightArithmeticVariable |
+			MultiplyToRegister | Remainder => RRR,
+
+			ShiftLeftLogical | ShiftRightLogical | ShiftRightArithmetic |
+			AddImmediate | AddImmediateUnsigned | SetLessThanImmediate | SetLessThanImmediateUnsigned |
+			AndImmediate | OrImmediate | XorImmediate => RRI,
+
+			Multiply | MultiplyUnsigned | MultiplyAdd | MultiplyAddUnsigned | MultiplySubtract | MultiplySubtractUnsigned |
+			CountLeadingZeros | CountLeadingOnes | Move | Negate | Not |
+			TrapEqual | TrapNotEqual | TrapGreaterEqual | TrapGreaterEqualUnsigned | TrapLessThan | TrapLessThanUnsigned => RR,
+
+			// the coprocessor 0 register is named by its number, as in "mfc0 $k0, $14"
+			MoveFromCoprocessor0 | MoveToCoprocessor0 => RR,
+
+			// division may also name the register receiving the quotient
+			Divide | DivideUnsigned => &[&[R, R], &[R, R, R]],
+
+			LoadUpperImmediate | LoadImmediate |
+			TrapEqualImmediate | TrapNotEqualImmediate | TrapGreaterEqualImmediate | TrapGreaterEqualImmediateUnsigned |
+			TrapLessThanImmediate | TrapLessThanImmediateUnsigned => RI,
+
+			LoadByte | LoadByteUnsigned | LoadHalf | LoadHalfUnsigned | LoadWord | LoadWordLeft | LoadWordRight | LoadLinked |
+			StoreByte | StoreHalf | StoreWord | StoreWordLeft | StoreWordRight | StoreConditional | LoadAddress => RA,
+
+			BranchEqual | BranchNotEqual |
+			BranchLessThan | BranchGreaterThan | BranchLessEqual | BranchGreaterEqual |
+			BranchLessThanUnsigned | BranchGreaterThanUnsigned | BranchLessEqualUnsigned | BranchGreaterEqualUnsigned => RRL,
+
+			BranchLessEqualZero | BranchGreaterThanZero | BranchLessThanZero | BranchGreaterEqualZero |
+			BranchLessThanZeroAndLink | BranchGreaterEqualZeroAndLink | BranchEqualZero | BranchNotEqualZero => RL,
+
+			MoveFromHi | MoveFromLo | MoveToHi | MoveToLo | JumpRegister => ONE_R,
+
+			// "jalr $rs" links through $ra
+			JumpAndLinkRegister => &[&[R], &[R, R]],
+
+			Jump | JumpAndLink | Branch => ONE_L,
+
+			SystemCall | NoOperation | ExceptionReturn => NONE,
+
+			Break => &[&[], &[I]],
+
+			AddSingle | AddDouble | SubtractSingle | SubtractDouble |
+			MultiplySingle | MultiplyDouble | DivideSingle | DivideDouble => FFF,
+
+			AbsoluteSingle | AbsoluteDouble | NegateSingle | NegateDouble |
+			MoveSingle | MoveDouble | SquareRootSingle | SquareRootDouble |
+			ConvertSingleDouble | ConvertSingleWord | ConvertDoubleSingle | ConvertDoubleWord | ConvertWordSingle | ConvertWordDouble |
+			TruncateWordSingle | TruncateWordDouble | RoundWordSingle | RoundWordDouble |
+			FloorWordSingle | FloorWordDouble | CeilWordSingle | CeilWordDouble => FF,
+
+			// the condition flag is 0 unless given first
+			CompareEqualSingle | CompareEqualDouble | CompareLessThanSingle | CompareLessThanDouble |
+			CompareLessEqualSingle | CompareLessEqualDouble => &[&[F, F], &[I, F, F]],
+			BranchFloatTrue | BranchFloatFalse => &[&[L], &[I, L]],
+			MoveOnFalse | MoveOnTrue => &[&[R, R], &[R, R, I]],
+			MoveOnFalseSingle | MoveOnFalseDouble | MoveOnTrueSingle | MoveOnTrueDouble => &[&[F, F], &[F, F, I]],
+
+			MoveToFloat | MoveFromFloat => RF,
+			// the control register is given by its number, as those of coprocessor 0
+			MoveControlToFloat | MoveControlFromFloat => RR,
+
+			LoadWordFloat | StoreWordFloat | LoadDoubleFloat | StoreDoubleFloat |
+			LoadSingle | StoreSingle | LoadDouble | StoreDouble => FA
+		}
+	}
+
+	// whether the destination and the sources of a floating point instruction hold doubles, which take an even register
+	// and the one following it
+	pub fn doubles(&self) -> (bool, bool) {
+		use Instruction::*;
+		match self {
+			AddDouble | SubtractDouble | MultiplyDouble | DivideDouble | AbsoluteDouble | NegateDouble | MoveDouble | SquareRootDouble |
+			CompareEqualDouble | CompareLessThanDouble | CompareLessEqualDouble | MoveOnFalseDouble | MoveOnTrueDouble => (true, true),
+			ConvertSingleDouble | ConvertWordDouble | TruncateWordDouble | RoundWordDouble | FloorWordDouble | CeilWordDouble => (false, true),
+			ConvertDoubleSingle | ConvertDoubleWord | LoadDoubleFloat | StoreDoubleFloat | LoadDouble | StoreDouble => (true, false),
+			_ => (false, false)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Register {
 	Z0,									// zero = 0
 	AT,									// reserved for assembler
@@ -30,68 +302,203 @@ pub enum Register {
 	RA,									// return address
 }
 
-pub fn parse_syscall(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
-	let instruction = Symbol::Instruction(Instruction::SystemCall, parse::extract_segment(&tokens[*idx]));
-	Ok(ASTNode::Node(instruction))
+impl Register {
+	pub const ALL: [Register; 32] = {
+		use Register::*;
+		[
+			Z0, AT, V0, V1, A0, A1, A2, A3,
+			T0, T1, T2, T3, T4, T5, T6, T7,
+			S0, S1, S2, S3, S4, S5, S6, S7,
+			T8, T9, K0, K1, GP, SP, FP, RA
+		]
+	};
+
+	pub const NAMES: [&'static str; 32] = [
+		"zero", "at", "v0", "v1", "a0", "a1", "a2", "a3",
+		"t0", "t1", "t2", "t3", "t4", "t5", "t6", "t7",
+		"s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7",
+		"t8", "t9", "k0", "k1", "gp", "sp", "fp", "ra"
+	];
+
+	// registers may be named by number or by convention, "s8" being another name for the frame pointer
+	pub fn from_name(name: &str) -> Option<Register> {
+		if let Some(idx) = Register::NAMES.iter().position(|n| *n == name) {
+			return Some(Register::ALL[idx]);
+		}
+
+		match name {
+			"s8" => Some(Register::FP),
+			_ if name == "0" || (name.chars().all(|c| c.is_ascii_digit()) && !name.starts_with('0')) => {
+				name.parse::<usize>().ok().filter(|number| *number < 32).map(|number| Register::ALL[number])
+			},
+			_ => None
+		}
+	}
+
+	pub fn number(&self) -> u32 {
+		*self as u32
+	}
 }
 
-pub fn parse_la(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
-	*idx += 1;
-	let register = match parsers::Register::parse(idx, tokens) {
-		Ok(node) => node,
-		Err(err) => return Err(err)
-	};
+// a register of the floating point unit, $f0 to $f31
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatRegister(pub u32);
 
-	*idx += 1;
-	let value = match parsers::Label::parse(idx, tokens) {
-		Ok(node) => node,
-		Err(err) => return Err(err)
-	};
+impl FloatRegister {
+	pub fn from_name(name: &str) -> Option<FloatRegister> {
+		let number = name.strip_prefix('f')?;
+		if number != "0" && (number.starts_with('0') || !number.chars().all(|c| c.is_ascii_digit())) {
+			return None;
+		}
+
+		number.parse::<u32>().ok().filter(|number| *number < 32).map(FloatRegister)
+	}
+
+	pub fn number(&self) -> u32 {
+		self.0
+	}
+}
+
+// reads the operands following an instruction on the same line and checks them against its syntax
+pub fn parse_instruction(idx: &mut usize, tokens: &[Token], instruction: Instruction) -> Result<ASTNode<Symbol>, errors::Err> {
+	let head_segment = parse::extract_segment(&tokens[*idx]);
+
+	let mut operands: Vec<(Operand, ASTNode<Symbol>)> = Vec::new();
+	while *idx + 1 < tokens.len() && parse::extract_segment(&tokens[*idx + 1]).same_line(&head_segment) {
+		*idx += 1;
+		operands.push(parse_operand(idx, tokens)?);
+	}
 
-	let head_segment = parse::extract_segment(&tokens[*idx - 2]);
 	let tail_segment = parse::extract_segment(&tokens[*idx]);
 	let full_segment = CodeSegment {
+		file: head_segment.file,
 		line: head_segment.line,
 		idx: head_segment.idx,
-		len: (tail_segment.len + tail_segment.idx) - head_segment.idx
+		len: (tail_segment.len + tail_segment.idx) - head_segment.idx,
+		expanded_from: head_segment.expanded_from.clone()
 	};
 
-	let instruction = Symbol::Instruction(Instruction::LoadAddress, full_segment.clone());
+	let kinds: Vec<Operand> = operands.iter().map(|(kind, _)| *kind).collect();
+	let valid = instruction.syntax().iter().any(|form| {
+		form.len() == kinds.len() && form.iter().zip(kinds.iter()).all(|(expected, found)| {
+			// a plain label or number is a valid address
+			expected == found || (*expected == Operand::Address && (*found == Operand::Label || *found == Operand::Immediate))
+		})
+	});
 
-	let mut tree = ASTree::<Symbol>::new(instruction);
-	tree.add_node(register);
-	tree.add_node(value);
+	if !valid {
+		let mut msgs = vec![format!("Invalid operands for {}.", instruction.mnemonic().red())];
+		for form in instruction.syntax() {
+			let names: Vec<&str> = form.iter().map(|operand| match operand {
+				Operand::Register => "register",
+				Operand::FloatRegister => "float register",
+				Operand::Immediate => "immediate",
+				Operand::Label => "label",
+				Operand::Address => "address"
+			}).collect();
+			msgs.push(format!("Expected: {} {}", instruction.mnemonic(), names.join(", ")));
+		}
+
+		return Err(errors::Err {
+			segment: full_segment,
+			msg: errors::Msg::Many(msgs),
+			errtype: errors::ErrType::Assemble
+		});
+	}
+
+	let symbol = Symbol::Instruction(instruction, full_segment);
+	if operands.is_empty() {
+		return Ok(ASTNode::Node(symbol));
+	}
+
+	let mut tree = ASTree::<Symbol>::new(symbol);
+	for (_, node) in operands {
+		tree.add_node(node);
+	}
 
 	Ok(ASTNode::Tree(tree))
 }
 
+// one of "$reg", "number", "label" or an address "offset($reg)", "label($reg)", "($reg)"
+fn parse_operand(idx: &mut usize, tokens: &[Token]) -> Result<(Operand, ASTNode<Symbol>), errors::Err> {
+	let token = &tokens[*idx];
+	let (kind, node) = match token {
+		Token::Register(_, _) => {
+			let node = parsers::Register::parse(idx, tokens)?;
+			let kind = match node {
+				ASTNode::Node(Symbol::FloatRegister(_, _)) => Operand::FloatRegister,
+				_ => Operand::Register
+			};
+			return Ok((kind, node));
+		},
+		Token::NumberLiteral(_, _) => (Operand::Immediate, parsers::NumberLiteral::parse(idx, tokens)?),
+		Token::Identifier(_, _) => (Operand::Label, parsers::Label::parse(idx, tokens)?),
+		Token::LeftParen(_) => {
+			*idx -= 1; // there is no offset
+			return parse_base(idx, tokens, None);
+		},
+		_ => {
+			return Err(errors::Err {
+				segment: parse::extract_segment(token),
+				msg: errors::Msg::Many(vec![
+					format!("Unexpected token {}.", token.to_string().red()),
+					"Expected register, number, label or address.".to_string()
+				]),
+				errtype: errors::ErrType::Assemble
+			});
+		}
+	};
+
+	if let Some(Token::LeftParen(_)) = tokens.get(*idx + 1) {
+		return parse_base(idx, tokens, Some(node));
+	}
 
-pub fn parse_li(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
-	*idx += 1;
-	let register = match parsers::Register::parse(idx, tokens) {
-		Ok(node) => node,
-		Err(err) => return Err(err)
+	Ok((kind, node))
+}
+
+// reads "($reg)" following the offset of an address, leaving idx on the closing parenthesis
+fn parse_base(idx: &mut usize, tokens: &[Token], offset: Option<ASTNode<Symbol>>) -> Result<(Operand, ASTNode<Symbol>), errors::Err> {
+	let open = parse::extract_segment(&tokens[*idx + 1]);
+	*idx += 2;
+
+	let base = match tokens.get(*idx) {
+		Some(Token::Register(name, _)) if Register::from_name(name).is_some() => parsers::Register::parse(idx, tokens)?,
+		_ => {
+			return Err(errors::Err {
+				segment: open,
+				msg: errors::Msg::One("Expected register after \"(\".".to_string()),
+				errtype: errors::ErrType::Assemble
+			});
+		}
 	};
 
 	*idx += 1;
-	let value = match parsers::NumberLiteral::parse(idx, tokens) {
-		Ok(node) => node,
-		Err(err) => return Err(err)
+	let close = match tokens.get(*idx) {
+		Some(Token::RightParen(segment)) => segment.clone(),
+		_ => {
+			return Err(errors::Err {
+				segment: open,
+				msg: errors::Msg::One("Unclosed parenthesis.".to_string()),
+				errtype: errors::ErrType::Assemble
+			});
+		}
 	};
 
-	let head_segment = parse::extract_segment(&tokens[*idx - 2]);
-	let tail_segment = parse::extract_segment(&tokens[*idx]);
-	let full_segment = CodeSegment {
-		line: head_segment.line,
-		idx: head_segment.idx,
-		len: (tail_segment.len + tail_segment.idx) - head_segment.idx
+	let start = match &offset {
+		Some(ASTNode::Node(Symbol::NumberLiteral(_, segment))) | Some(ASTNode::Node(Symbol::Label(_, segment))) => segment.clone(),
+		_ => open
 	};
 
-	let instruction = Symbol::Instruction(Instruction::LoadImmediate, full_segment.clone());
+	let segment = CodeSegment {
+		len: close.idx + close.len - start.idx,
+		..start
+	};
 
-	let mut tree = ASTree::<Symbol>::new(instruction);
-	tree.add_node(register);
-	tree.add_node(value);
+	let mut tree = ASTree::<Symbol>::new(Symbol::Address(segment));
+	if let Some(offset) = offset {
+		tree.add_node(offset);
+	}
+	tree.add_node(base);
 
-	Ok(ASTNode::Tree(tree))
-}
\ No newline at end of file
+	Ok((Operand::Address, ASTNode::Tree(tree)))
+}
@@ -1,6 +1,6 @@
 use crate::lexer::tokens::Token;
 use crate::parse::instructions;
-use crate::parse::symbols::{self, *};
+use crate::parse::symbols::*;
 use crate::parse::ast::*;
 use crate::errors;
 use crate::parse;
@@ -8,12 +8,12 @@ use crate::parse;
 use colored::Colorize;
 
 pub trait Parser {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err>;
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err>;
 }
 
 pub struct Label;
 impl Parser for Label {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err> {
 		if let Token::Identifier(id, segment) = &tokens[*idx] {
 			let symbol = Symbol::Label(id.to_string(), segment.clone());
 
@@ -35,7 +35,7 @@ impl Parser for Label {
 
 pub struct NumberLiteral;
 impl Parser for NumberLiteral {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err> {
 		if let Token::NumberLiteral(num, segment) = &tokens[*idx] {
 			let symbol = Symbol::NumberLiteral(*num, segment.clone());
 
@@ -57,27 +57,15 @@ impl Parser for NumberLiteral {
 
 pub struct Register;
 impl Parser for Register {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err> {
 		if let Token::Register(id, segment) = &tokens[*idx] {
-			let register = match id.as_str() {
-				 "0" | "zero" => instructions::Register::Z0,
-				 "1" | "at" => instructions::Register::AT,
-				 "2" | "v0" => instructions::Register::V0,
-				 "3" | "v1" => instructions::Register::V1,
-				 "4" | "a0" => instructions::Register::A0,
-				 "5" | "a1" => instructions::Register::A1,
-				 "6" | "a2" => instructions::Register::A2,
-				 "7" | "a3" => instructions::Register::A3,
-				 "8" | "t0" => instructions::Register::T0,
-				 "9" | "t1" => instructions::Register::T1,
-				"10" | "t2" => instructions::Register::T2,
-				"11" | "t3" => instructions::Register::T3,
-				"12" | "t4" => instructions::Register::T4,
-				"13" | "t5" => instructions::Register::T5,
-				"14" | "t6" => instructions::Register::T6,
-				"15" | "t7" => instructions::Register::T7,
+			if let Some(register) = instructions::FloatRegister::from_name(id) {
+				return Ok(ASTNode::Node(Symbol::FloatRegister(register, segment.clone())));
+			}
 
-				_ => {
+			let register = match instructions::Register::from_name(id) {
+				Some(register) => register,
+				None => {
 					return Err(errors::Err {
 						segment: segment.clone(),
 						msg: errors::Msg::One(format!("Unknown register {}.", id.red())),
@@ -106,24 +94,19 @@ impl Parser for Register {
 
 pub struct Instruction;
 impl Parser for Instruction {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
-		if let Token::Identifier(id, segment) = &tokens[*idx] {
-			match id.as_str() {
-				"li" => return instructions::parse_li(idx, tokens),
-
-				"la" => return instructions::parse_la(idx, tokens),
-
-				"syscall" => return instructions::parse_syscall(idx, tokens),
-
-				_ => {
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err> {
+		if let Token::Identifier(id, _) = &tokens[*idx] {
+			return match instructions::Instruction::from_mnemonic(id) {
+				Some(instruction) => instructions::parse_instruction(idx, tokens, instruction),
+				None => {
 					let msg = errors::Msg::One(format!("Unknown instruction {}.", id.red()));
-					return Err(errors::Err {
+					Err(errors::Err {
 						segment: parse::extract_segment(&tokens[*idx]),
 						msg,
 						errtype: errors::ErrType::Assemble
-					});
+					})
 				}
-			}
+			};
 		}
 
 		let msg = errors::Msg::Many(vec![
@@ -141,15 +124,16 @@ impl Parser for Instruction {
 
 pub struct DefLabel{}
 impl Parser for DefLabel {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err> {
 		if let Token::DefLabel(id, segment) = &tokens[*idx] {
 			let symbol = Symbol::DefLabel(id.to_string(), segment.clone());
 			let mut tree = ASTree::<Symbol>::new(symbol);
 
-			*idx += 1; // the label will attach to the memory location of the next symbol, whether instruction or directive-allocation
-			match parse::parse_one(idx, tokens) {
-				Ok(symbol) => tree.add_node(symbol),
-				Err(err) => return Err(err)
+			// the label will attach to the memory location of the next symbol, whether instruction or directive-allocation
+			// a label at the end of a section marks the address following it
+			if *idx + 1 < tokens.len() {
+				*idx += 1;
+				tree.add_node(parse::parse_one(idx, tokens)?);
 			}
 
 			return Ok(ASTNode::Tree(tree));
@@ -157,7 +141,7 @@ impl Parser for DefLabel {
 
 		let msg = errors::Msg::Many(vec![
 			format!("Unexpected token {}", &tokens[*idx].to_string().red()),
-			format!("Expected label definition.")
+			"Expected label definition.".to_string()
 		]);
 
 		Err(errors::Err {
@@ -170,14 +154,22 @@ impl Parser for DefLabel {
 
 pub struct Directive {}
 impl Parser for Directive {
-	fn parse(idx: &mut usize, tokens: &Vec<Token>) -> Result<ASTNode<Symbol>, errors::Err> {
+	fn parse(idx: &mut usize, tokens: &[Token]) -> Result<ASTNode<Symbol>, errors::Err> {
 		let token = &tokens[*idx];
 		if let Token::Directive(id, segment) = token {
 			let symbol = Symbol::Directive(id.to_string(), segment.clone());
 			let mut tree = ASTree::<Symbol>::new(symbol);
 
 			match id.as_str() {
-				"data" | "text" => {
+				"data" | "text" | "kdata" | "ktext" => {
+					// the section may be given an address to continue from, on the same line
+					if let Some(Token::NumberLiteral(num, num_segment)) = tokens.get(*idx + 1) {
+						if num_segment.same_line(segment) {
+							tree.add_child(Symbol::NumberLiteral(*num, num_segment.clone()));
+							*idx += 1;
+						}
+					}
+
 					// parse the tokens until the next such directive or until all tokens are added
 					// every token belongs to one of these in a tree
 					match parse_until_next_directive(idx, tokens) {
@@ -191,25 +183,27 @@ impl Parser for Directive {
 						}
 					}
 				},
-				"asciiz" => {
-					// next token must be a string literal
-					*idx += 1;
-					let token = &tokens[*idx];
-					if let Token::StringLiteral(string, segment) = token {
-						let node = Symbol::StringLiteral(string.to_string(), segment.clone());
-						tree.add_node(ASTNode::Node(node));
-					} else {
-						let msg = errors::Msg::Many(vec![
-							format!("Unexpected token {}.", token.to_string().red()),
-							format!("Expected string literal.")
-						]);
-						return Err(errors::Err {
-							segment: parse::extract_segment(token),
-							msg,
-							errtype: errors::ErrType::Assemble
-						});
-					}
-				}
+				"ascii" | "asciiz" => parse_arguments(idx, tokens, &mut tree, 1, None, "string literal", |token| match token {
+					Token::StringLiteral(string, segment) => Some(Symbol::StringLiteral(string.to_string(), segment.clone())),
+					_ => None
+				})?,
+				"word" => parse_arguments(idx, tokens, &mut tree, 1, None, "number or label", |token| match token {
+					Token::NumberLiteral(num, segment) => Some(Symbol::NumberLiteral(*num, segment.clone())),
+					Token::Identifier(label, segment) => Some(Symbol::Label(label.to_string(), segment.clone())),
+					_ => None
+				})?,
+				"half" | "byte" => parse_arguments(idx, tokens, &mut tree, 1, None, "number", number)?,
+				"float" | "double" => parse_arguments(idx, tokens, &mut tree, 1, None, "number", |token| match token {
+					Token::FloatLiteral(num, segment) => Some(Symbol::FloatLiteral(num.to_string(), segment.clone())),
+					Token::NumberLiteral(num, segment) => Some(Symbol::FloatLiteral(num.to_string(), segment.clone())),
+					_ => None
+				})?,
+				"space" | "align" => parse_arguments(idx, tokens, &mut tree, 1, Some(1), "number", number)?,
+				// every label on the same line is made visible to the other files of the program, or declared to come from one
+				"globl" | "extern" => parse_arguments(idx, tokens, &mut tree, 1, None, "label", |token| match token {
+					Token::Identifier(label, segment) => Some(Symbol::Label(label.to_string(), segment.clone())),
+					_ => None
+				})?,
 				_ => {
 					let msg = errors::Msg::One(format!("Unknown directive {}.", id.red()));
 					return Err(errors::Err{
@@ -223,23 +217,23 @@ impl Parser for Directive {
 			Ok(ASTNode::Tree(tree))
 		} else {
 			let msg = errors::Msg::One(format!("Expected directive token, found {}", token.to_string().red()));
-			return Err(errors::Err{
-				segment: parse::extract_segment(&token),
+			Err(errors::Err{
+				segment: parse::extract_segment(token),
 				errtype: errors::ErrType::Assemble,
 				msg
-			});
+			})
 		}
 	}
 }
 
-fn parse_until_next_directive(idx: &mut usize, tokens: &Vec<Token>) -> Result<Vec<ASTNode<Symbol>>, errors::Err> {
+fn parse_until_next_directive(idx: &mut usize, tokens: &[Token]) -> Result<Vec<ASTNode<Symbol>>, errors::Err> {
 	*idx += 1; // skip initial token
 	let mut nodes: Vec<Token> = Vec::new();
 	while *idx < tokens.len() {
 		let token = &tokens[*idx];
-		if let Token::Directive(id, segment) = token {
+		if let Token::Directive(id, _) = token {
 			match id.as_str() {
-				"data" | "text" => {
+				"data" | "text" | "kdata" | "ktext" => {
 					*idx -= 1;
 					break
 				},
@@ -251,8 +245,50 @@ fn parse_until_next_directive(idx: &mut usize, tokens: &Vec<Token>) -> Result<Ve
 		*idx += 1;
 	}
 
-	match parse::parse(&nodes) {
-		Ok(nodes) => Ok(nodes),
-		Err(err) => {Err(err)}
+	parse::parse(&nodes)
+}
+fn number(token: &Token) -> Option<Symbol> {
+	match token {
+		Token::NumberLiteral(num, segment) => Some(Symbol::NumberLiteral(*num, segment.clone())),
+		_ => None
 	}
-}
\ No newline at end of file
+}
+
+// the arguments of a directive are the tokens following it on the same line
+fn parse_arguments(idx: &mut usize, tokens: &[Token], tree: &mut ASTree<Symbol>, min: usize, max: Option<usize>, expected: &str, accept: fn(&Token) -> Option<Symbol>) -> Result<(), errors::Err> {
+	let directive = parse::extract_segment(&tokens[*idx]);
+
+	let mut count = 0;
+	while *idx + 1 < tokens.len() && parse::extract_segment(&tokens[*idx + 1]).same_line(&directive) {
+		*idx += 1;
+		let token = &tokens[*idx];
+
+		match accept(token) {
+			Some(symbol) if max.is_none_or(|max| count < max) => tree.add_child(symbol),
+			_ => {
+				let msg = errors::Msg::Many(vec![
+					format!("Unexpected token {}.", token.to_string().red()),
+					format!("Expected {}.", expected)
+				]);
+				return Err(errors::Err {
+					segment: parse::extract_segment(token),
+					msg,
+					errtype: errors::ErrType::Assemble
+				});
+			}
+		}
+
+		count += 1;
+	}
+
+	if count < min {
+		let msg = errors::Msg::One(format!("Expected {} after directive.", expected));
+		return Err(errors::Err {
+			segment: directive,
+			msg,
+			errtype: errors::ErrType::Assemble
+		});
+	}
+
+	Ok(())
+}
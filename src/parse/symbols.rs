@@ -1,5 +1,5 @@
 use crate::lexer::tokens::CodeSegment;
-use crate::parse::instructions::{Instruction, Register};
+use crate::parse::instructions::{Instruction, Register, FloatRegister};
 
 #[derive(Debug)]
 pub enum Symbol {
@@ -8,6 +8,18 @@ pub enum Symbol {
 	Label(String, CodeSegment),
 	Instruction(Instruction, CodeSegment), // instruction and label must be parsed out here
 	Register(Register, CodeSegment),
+	FloatRegister(FloatRegister, CodeSegment),
+	Address(CodeSegment),				// root of an optional offset (number or label) and a base register
 	StringLiteral(String, CodeSegment),
-	NumberLiteral(i32, CodeSegment)
-}
\ No newline at end of file
+	NumberLiteral(i32, CodeSegment),
+	FloatLiteral(String, CodeSegment)
+}
+impl Symbol {
+	pub fn segment(&self) -> &CodeSegment {
+		match self {
+			Symbol::Directive(_, segment) | Symbol::DefLabel(_, segment) | Symbol::Label(_, segment) |
+			Symbol::Instruction(_, segment) | Symbol::Register(_, segment) | Symbol::FloatRegister(_, segment) | Symbol::Address(segment) |
+			Symbol::StringLiteral(_, segment) | Symbol::NumberLiteral(_, segment) | Symbol::FloatLiteral(_, segment) => segment
+		}
+	}
+}
@@ -0,0 +1,390 @@
+use std::fmt::Write;
+
+use crate::disasm;
+use crate::encoding::{self, Encoding, Machine as Word};
+use crate::parse::instructions::{Instruction, Register};
+use crate::runtime::Machine;
+
+// instructions shown in the diagram by default, after which they are only counted
+pub const LIMIT: usize = 200;
+
+// instructions to a table, so each stays narrow enough to read
+const BLOCK: usize = 12;
+
+// registers as the pipeline tracks them, with hi, lo, the floating point registers and their condition flags after those of the cpu
+const HI: usize = 32;
+const LO: usize = 33;
+const FLOAT: usize = 34;
+const CONDITION: usize = 66;
+const REGISTERS: usize = 67;
+
+// where an instruction needs the value of a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+	Decode,		// branches and jumps, which are resolved in ID
+	Execute,
+	Memory		// the data of stores
+}
+
+// the cycles an instruction enters IF, ID and EX, those between them being stalls
+#[derive(Debug, Clone, Copy)]
+struct Timing {
+	fetch: u64,
+	decode: u64,
+	execute: u64
+}
+
+impl Timing {
+	fn memory(&self) -> u64 {
+		self.execute + 1
+	}
+
+	fn write_back(&self) -> u64 {
+		self.execute + 2
+	}
+}
+
+// the last instruction to write a register
+#[derive(Debug, Clone, Copy)]
+struct Producer {
+	timing: Timing,
+	load: bool		// whose value is only ready after MEM
+}
+
+// an instruction in the diagram
+struct Row {
+	pc: u32,
+	text: String,
+	timing: Timing,
+	flushed: bool,		// fetched after a taken branch and discarded, never leaving IF
+	notes: Vec<String>
+}
+
+// the classic five stage pipeline of IF, ID, EX, MEM and WB, timed from the instructions the machine executes
+// branches and jumps are resolved in ID, and fetching carries on past them as though they were not taken
+pub struct Pipeline {
+	pub forwarding: bool,		// results are forwarded to the stages that need them rather than waiting for WB
+	pub delay_slots: bool,		// the instruction after a branch is executed rather than flushed
+	pub limit: usize,
+	rows: Vec<Row>,
+	last: Option<Timing>,
+	redirect: u64,				// the first cycle the target of the last taken branch may be fetched
+	slot: bool,					// the next instruction is in the delay slot of a branch
+	producers: [Option<Producer>; REGISTERS],
+	instructions: u64,
+	stalls: u64,
+	load_use: u64,				// stalls waiting on loads
+	flushes: u64,
+	forwards: u64
+}
+
+impl Pipeline {
+	pub fn new(forwarding: bool, delay_slots: bool, limit: usize) -> Pipeline {
+		Pipeline {
+			forwarding,
+			delay_slots,
+			limit,
+			rows: Vec::new(),
+			last: None,
+			redirect: 0,
+			slot: false,
+			producers: [None; REGISTERS],
+			instructions: 0,
+			stalls: 0,
+			load_use: 0,
+			flushes: 0,
+			forwards: 0
+		}
+	}
+
+	// times the instruction the machine last executed
+	pub fn retire(&mut self, machine: &Machine) {
+		let Some(retired) = machine.retired else { return };
+		let word = retired.word;
+		let (reads, writes) = operands(&word);
+		let text = |pc: u32, word: u32| disasm::disassemble(word, pc, &machine.labels, true);
+
+		// each stage holds one instruction, so this one waits in IF and ID for the one before to move on
+		let fetch = match self.last {
+			Some(last) => (last.fetch + 1).max(last.decode).max(self.redirect),
+			None => 1
+		};
+		let decode = (fetch + 1).max(self.last.map_or(0, |last| last.execute));
+		let unstalled = (decode + 1).max(self.last.map_or(0, |last| last.execute + 1));
+
+		let mut execute = unstalled;
+		let mut cause = None;
+		for &(register, stage) in &reads {
+			let Some(producer) = self.producers[register] else { continue };
+			let earliest = self.earliest(&producer, stage);
+			if earliest > execute {
+				execute = earliest;
+				cause = Some((register, producer.load));
+			}
+		}
+
+		let timing = Timing { fetch, decode, execute };
+		let mut notes = Vec::new();
+		if self.slot {
+			notes.push("delay slot".to_string());
+			self.slot = false;
+		}
+		if let Some((register, load)) = cause {
+			let stalls = execute - unstalled;
+			self.stalls += stalls;
+			if load {
+				self.load_use += stalls;
+			}
+			notes.push(format!("{} for {}{}", count(stalls, "stall"), name(register), if load { " (load-use)" } else { "" }));
+		}
+		if self.forwarding {
+			for &(register, stage) in &reads {
+				let Some(producer) = self.producers[register] else { continue };
+				if let Some(path) = path(&producer, &timing, stage) {
+					self.forwards += 1;
+					notes.push(format!("{} forwarded {}", name(register), path));
+				}
+			}
+		}
+
+		for &register in &writes {
+			self.producers[register] = Some(Producer { timing, load: load(word.instruction) });
+		}
+
+		self.instructions += 1;
+		self.last = Some(timing);
+		if self.rows.len() < self.limit {
+			self.rows.push(Row { pc: retired.pc, text: text(retired.pc, encoding::encode(&word)), timing, flushed: false, notes });
+		}
+
+		if !control(word.instruction) {
+			return;
+		}
+		// the instruction fetched behind a taken branch is flushed once it is resolved at the end of ID
+		// unless it is in the delay slot, which eret does not have
+		if retired.target.is_some() && (!self.delay_slots || word.instruction == Instruction::ExceptionReturn) {
+			self.flushes += 1;
+			self.redirect = execute;
+			if self.rows.len() < self.limit {
+				let pc = retired.pc.wrapping_add(4);
+				let fetch = (fetch + 1).max(decode);
+				self.rows.push(Row {
+					pc,
+					text: text(pc, machine.memory.read_u32(pc)),
+					timing: Timing { fetch, decode: execute, execute },
+					flushed: true,
+					notes: vec!["flushed by the taken branch".to_string()]
+				});
+			}
+		} else if self.delay_slots {
+			self.slot = true;
+		}
+	}
+
+	// the first cycle an instruction needing a register in a stage may enter EX
+	fn earliest(&self, producer: &Producer, stage: Stage) -> u64 {
+		// the register file is written in the first half of WB and read in the second half of ID
+		if !self.forwarding {
+			return producer.timing.write_back() + 1;
+		}
+
+		let ready = match producer.load {
+			true => producer.timing.memory() + 1,
+			false => producer.timing.execute + 1
+		};
+		match stage {
+			Stage::Decode => ready + 1,
+			Stage::Execute => ready,
+			Stage::Memory => ready - 1
+		}
+	}
+
+	// the textbook table of instructions against cycles, in blocks, followed by the totals
+	pub fn diagram(&self) -> String {
+		let mut out = String::new();
+
+		for block in self.rows.chunks(BLOCK) {
+			let first = block.iter().map(|row| row.timing.fetch).min().unwrap_or(1);
+			let last = block.iter().map(|row| row.timing.write_back()).max().unwrap_or(first);
+			let width = block.iter().map(|row| row.text.len()).max().unwrap_or(0).max(11);
+
+			let _ = write!(out, "{:10}  {:width$}", "", "instruction");
+			for cycle in first..=last {
+				let _ = write!(out, " {:>4}", cycle);
+			}
+			out.push('\n');
+
+			for row in block {
+				let mut line = format!("{:#010x}  {:width$}", row.pc, row.text);
+				for cycle in first..=last {
+					let _ = write!(line, " {:>4}", cell(row, cycle));
+				}
+				if !row.notes.is_empty() {
+					let _ = write!(line, "   {}", row.notes.join(", "));
+				}
+				let _ = writeln!(out, "{}", line.trim_end());
+			}
+			out.push('\n');
+		}
+
+		let shown = self.rows.iter().filter(|row| !row.flushed).count() as u64;
+		if shown < self.instructions {
+			let _ = writeln!(out, "... and {} more\n", count(self.instructions - shown, "instruction"));
+		}
+
+		let cycles = self.last.map_or(0, |last| last.write_back());
+		let _ = writeln!(out, "{} for {}, {:.2} cycles per instruction", count(cycles, "cycle"), count(self.instructions, "instruction"),
+			cycles as f64 / self.instructions.max(1) as f64);
+		let _ = writeln!(out, "{} ({} load-use), {}, {}, forwarding {}, delay slots {}",
+			count(self.stalls, "stall"), self.load_use, count(self.flushes, "flush"), count(self.forwards, "forward"),
+			if self.forwarding { "on" } else { "off" }, if self.delay_slots { "on" } else { "off" });
+		out
+	}
+}
+
+// the stage of an instruction in a cycle, with stalls shown as dashes
+fn cell(row: &Row, cycle: u64) -> &'static str {
+	let timing = &row.timing;
+	if row.flushed {
+		return match cycle {
+			_ if cycle == timing.fetch => "IF",
+			_ if cycle > timing.fetch && cycle < timing.decode => "--",
+			_ => ""
+		};
+	}
+
+	match cycle {
+		_ if cycle == timing.fetch => "IF",
+		_ if cycle == timing.decode => "ID",
+		_ if cycle == timing.execute => "EX",
+		_ if cycle == timing.memory() => "MEM",
+		_ if cycle == timing.write_back() => "WB",
+		_ if cycle > timing.fetch && cycle < timing.execute => "--",
+		_ => ""
+	}
+}
+
+// the forwarding path a register takes to reach a stage, if it is not read from the register file
+fn path(producer: &Producer, timing: &Timing, stage: Stage) -> Option<String> {
+	let (cycle, to) = match stage {
+		Stage::Decode => (timing.execute - 1, "ID"),
+		Stage::Execute => (timing.execute, "EX"),
+		Stage::Memory => (timing.memory(), "MEM")
+	};
+
+	let from = match cycle {
+		_ if cycle == producer.timing.memory() && !producer.load => "EX/MEM",
+		// the register file already holds what is written in WB by the time ID reads it
+		_ if cycle == producer.timing.write_back() && stage != Stage::Decode => "MEM/WB",
+		_ => return None
+	};
+	Some(format!("{} -> {}", from, to))
+}
+
+fn count(n: u64, noun: &str) -> String {
+	match (n, noun.ends_with('h')) {
+		(1, _) => format!("1 {}", noun),
+		(_, true) => format!("{} {}es", n, noun),
+		(_, false) => format!("{} {}s", n, noun)
+	}
+}
+
+fn name(register: usize) -> String {
+	match register {
+		HI => "$hi".to_string(),
+		LO => "$lo".to_string(),
+		CONDITION => "the condition flags".to_string(),
+		_ if register >= FLOAT => format!("$f{}", register - FLOAT),
+		_ => format!("${}", Register::NAMES[register])
+	}
+}
+
+// instructions resolved in ID, which redirect fetching when taken
+fn control(instruction: Instruction) -> bool {
+	instruction == Instruction::ExceptionReturn || instruction.is_branch() || instruction.is_jump()
+}
+
+fn load(instruction: Instruction) -> bool {
+	matches!(instruction.encoding(), Some(Encoding::Immediate(0x20..=0x26 | 0x30 | 0x31 | 0x35)))
+}
+
+fn store(instruction: Instruction) -> bool {
+	matches!(instruction.encoding(), Some(Encoding::Immediate(0x28..=0x2e | 0x38 | 0x39 | 0x3d)))
+}
+
+// the registers an instruction reads, with the stage it needs each in, and those it writes
+fn operands(word: &Word) -> (Vec<(usize, Stage)>, Vec<usize>) {
+	use Instruction::*;
+
+	let (rs, rt, rd) = (word.rs as usize, word.rt as usize, word.rd as usize);
+	let (ft, fs, fd) = (FLOAT + rt, FLOAT + rd, FLOAT + word.shamt as usize);
+	let (double_destination, double_source) = word.instruction.doubles();
+	let float = |register: usize, double: bool| match double {
+		true => vec![register, register + 1],
+		false => vec![register]
+	};
+
+	let (reads, writes): (Vec<usize>, Vec<usize>) = match word.instruction {
+		Add | AddUnsigned | Subtract | SubtractUnsigned | And | Or | Xor | Nor | SetLessThan | SetLessThanUnsigned |
+		ShiftLeftLogicalVariable | ShiftRightLogicalVariable | ShiftRightArithmeticVariable |
+		MoveConditionalZero | MoveConditionalNotZero | MultiplyToRegister => (vec![rs, rt], vec![rd]),
+		ShiftLeftLogical | ShiftRightLogical | ShiftRightArithmetic => (vec![rt], vec![rd]),
+		CountLeadingZeros | CountLeadingOnes => (vec![rs], vec![rd]),
+		AddImmediate | AddImmediateUnsigned | SetLessThanImmediate | SetLessThanImmediateUnsigned |
+		AndImmediate | OrImmediate | XorImmediate => (vec![rs], vec![rt]),
+		LoadUpperImmediate | MoveFromCoprocessor0 => (vec![], vec![rt]),
+
+		Multiply | MultiplyUnsigned | Divide | DivideUnsigned => (vec![rs, rt], vec![HI, LO]),
+		MultiplyAdd | MultiplyAddUnsigned | MultiplySubtract | MultiplySubtractUnsigned => (vec![rs, rt, HI, LO], vec![HI, LO]),
+		MoveFromHi => (vec![HI], vec![rd]),
+		MoveFromLo => (vec![LO], vec![rd]),
+		MoveToHi => (vec![rs], vec![HI]),
+		MoveToLo => (vec![rs], vec![LO]),
+
+		LoadByte | LoadByteUnsigned | LoadHalf | LoadHalfUnsigned | LoadWord | LoadLinked => (vec![rs], vec![rt]),
+		LoadWordLeft | LoadWordRight => (vec![rs, rt], vec![rt]),
+		StoreByte | StoreHalf | StoreWord | StoreWordLeft | StoreWordRight => (vec![rs, rt], vec![]),
+		StoreConditional => (vec![rs, rt], vec![rt]),
+		LoadWordFloat | LoadDoubleFloat => (vec![rs], float(ft, double_destination)),
+		StoreWordFloat | StoreDoubleFloat => ([vec![rs], float(ft, double_destination)].concat(), vec![]),
+
+		BranchEqual | BranchNotEqual => (vec![rs, rt], vec![]),
+		BranchLessEqualZero | BranchGreaterThanZero | BranchLessThanZero | BranchGreaterEqualZero | JumpRegister => (vec![rs], vec![]),
+		BranchLessThanZeroAndLink | BranchGreaterEqualZeroAndLink => (vec![rs], vec![31]),
+		JumpAndLink => (vec![], vec![31]),
+		JumpAndLinkRegister => (vec![rs], vec![rd]),
+		BranchFloatTrue | BranchFloatFalse => (vec![CONDITION], vec![]),
+		// the arguments and result of a system call
+		SystemCall => (vec![2, 4, 5, 6, 7], vec![2]),
+
+		TrapEqual | TrapNotEqual | TrapGreaterEqual | TrapGreaterEqualUnsigned | TrapLessThan | TrapLessThanUnsigned => (vec![rs, rt], vec![]),
+		TrapEqualImmediate | TrapNotEqualImmediate | TrapGreaterEqualImmediate | TrapGreaterEqualImmediateUnsigned |
+		TrapLessThanImmediate | TrapLessThanImmediateUnsigned => (vec![rs], vec![]),
+		MoveToCoprocessor0 => (vec![rt], vec![]),
+
+		MoveToFloat => (vec![rt], vec![fs]),
+		MoveFromFloat => (vec![fs], vec![rt]),
+		MoveControlToFloat => (vec![rt], vec![CONDITION]),
+		MoveControlFromFloat => (vec![CONDITION], vec![rt]),
+		MoveOnFalse | MoveOnTrue => (vec![rs, CONDITION], vec![rd]),
+		MoveOnFalseSingle | MoveOnFalseDouble | MoveOnTrueSingle | MoveOnTrueDouble =>
+			([float(fs, double_source), vec![CONDITION]].concat(), float(fd, double_destination)),
+		_ => match word.instruction.encoding() {
+			// comparisons, then operations of two operands and of one
+			Some(Encoding::Cop1(_, 0x30..)) => ([float(fs, double_source), float(ft, double_source)].concat(), vec![CONDITION]),
+			Some(Encoding::Cop1(_, 0x00..=0x03)) => ([float(fs, double_source), float(ft, double_source)].concat(), float(fd, double_destination)),
+			Some(Encoding::Cop1(_, _)) => (float(fs, double_source), float(fd, double_destination)),
+			_ => (vec![], vec![])
+		}
+	};
+
+	let stage = |register: usize| match () {
+		_ if control(word.instruction) => Stage::Decode,
+		_ if store(word.instruction) && register != rs => Stage::Memory,
+		_ => Stage::Execute
+	};
+	let mut reads: Vec<_> = reads.into_iter().filter(|register| *register != 0).map(|register| (register, stage(register))).collect();
+	reads.dedup();
+	let writes = writes.into_iter().filter(|register| *register != 0).collect();
+	(reads, writes)
+}
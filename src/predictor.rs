@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+use crate::disasm;
+use crate::runtime::Machine;
+use crate::source::SourceMap;
+
+// the entries of each table, as a power of two, by default
+pub const BITS: u32 = 10;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+	/// Always predicts taken.
+	Taken,
+	/// Always predicts not taken.
+	NotTaken,
+	/// Predicts what the branch last did.
+	OneBit,
+	/// A two bit saturating counter for each branch, which must mispredict twice to change its mind.
+	TwoBit,
+	/// Two bit counters indexed by the address of the branch exclusive-ored with the global history.
+	Gshare,
+	/// Chooses between two bit counters for each branch and gshare, by which has lately been right.
+	Tournament
+}
+
+impl Kind {
+	fn name(&self) -> String {
+		self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+	}
+}
+
+pub struct Predictor {
+	pub kind: Kind,
+	mask: u32,
+	local: Vec<u8>,		// counters by the address of the branch
+	global: Vec<u8>,	// counters by the address and the global history
+	chooser: Vec<u8>,	// for the tournament, counting up when gshare is right and the local counters wrong
+	history: u32,		// the outcomes of the last branches, the latest in the lowest bit
+	correct: u64
+}
+
+impl Predictor {
+	pub fn new(kind: Kind, bits: u32) -> Predictor {
+		let entries = 1 << bits;
+		Predictor {
+			kind,
+			mask: entries as u32 - 1,
+			// counters start weakly not taken, and single bits not taken
+			local: vec![(kind != Kind::OneBit) as u8; entries],
+			global: vec![1; entries],
+			chooser: vec![1; entries],
+			history: 0,
+			correct: 0
+		}
+	}
+
+	fn index(&self, pc: u32) -> usize {
+		((pc >> 2) & self.mask) as usize
+	}
+
+	fn shared(&self, pc: u32) -> usize {
+		(((pc >> 2) ^ self.history) & self.mask) as usize
+	}
+
+	fn predict(&self, pc: u32) -> bool {
+		let (local, global) = (self.local[self.index(pc)], self.global[self.shared(pc)]);
+		match self.kind {
+			Kind::Taken => true,
+			Kind::NotTaken => false,
+			Kind::OneBit => local != 0,
+			Kind::TwoBit => local >= 2,
+			Kind::Gshare => global >= 2,
+			Kind::Tournament => match self.chooser[self.index(pc)] >= 2 {
+				true => global >= 2,
+				false => local >= 2
+			}
+		}
+	}
+
+	// learns the outcome of a branch, returning whether it was predicted
+	fn update(&mut self, pc: u32, taken: bool) -> bool {
+		let predicted = self.predict(pc) == taken;
+		self.correct += predicted as u64;
+
+		let (index, shared) = (self.index(pc), self.shared(pc));
+		let (local, global) = (self.local[index] >= 2, self.global[shared] >= 2);
+		if self.kind == Kind::Tournament && local != global {
+			count(&mut self.chooser[index], global == taken);
+		}
+		match self.kind {
+			Kind::OneBit => self.local[index] = taken as u8,
+			_ => {
+				count(&mut self.local[index], taken);
+				count(&mut self.global[shared], taken);
+			}
+		}
+		self.history = (self.history << 1 | taken as u32) & self.mask;
+		predicted
+	}
+}
+
+// a two bit saturating counter
+fn count(counter: &mut u8, up: bool) {
+	*counter = match up {
+		true => (*counter + 1).min(3),
+		false => counter.saturating_sub(1)
+	};
+}
+
+// a direct mapped cache of the targets of taken branches and jumps, by their address
+pub struct Btb {
+	entries: Vec<Option<(u32, u32)>>,
+	lookups: u64,
+	hits: u64
+}
+
+impl Btb {
+	pub fn new(entries: u32) -> Btb {
+		Btb { entries: vec![None; entries.max(1) as usize], lookups: 0, hits: 0 }
+	}
+
+	fn update(&mut self, pc: u32, target: u32) {
+		let index = (pc >> 2) as usize % self.entries.len();
+		self.lookups += 1;
+		self.hits += (self.entries[index] == Some((pc, target))) as u64;
+		self.entries[index] = Some((pc, target));
+	}
+}
+
+// a branch in the program, with how often each predictor was right about it
+#[derive(Default)]
+struct Site {
+	executed: u64,
+	taken: u64,
+	correct: Vec<u64>
+}
+
+// the predictors, watching every branch the machine executes
+pub struct Predictors {
+	pub predictors: Vec<Predictor>,
+	pub btb: Option<Btb>,
+	sites: BTreeMap<u32, Site>,
+	branches: u64
+}
+
+impl Predictors {
+	pub fn new(kinds: &[Kind], bits: u32, btb: Option<u32>) -> Predictors {
+		Predictors {
+			predictors: kinds.iter().map(|kind| Predictor::new(*kind, bits)).collect(),
+			btb: btb.map(Btb::new),
+			sites: BTreeMap::new(),
+			branches: 0
+		}
+	}
+
+	pub fn retire(&mut self, machine: &Machine) {
+		let Some(retired) = machine.retired else { return };
+		let instruction = retired.word.instruction;
+
+		if let (Some(btb), Some(target)) = (&mut self.btb, retired.target) {
+			if instruction.is_branch() || instruction.is_jump() {
+				btb.update(retired.pc, target);
+			}
+		}
+		if !instruction.is_branch() {
+			return;
+		}
+
+		let taken = retired.target.is_some();
+		let site = self.sites.entry(retired.pc).or_default();
+		site.correct.resize(self.predictors.len(), 0);
+		site.executed += 1;
+		site.taken += taken as u64;
+		for (idx, predictor) in self.predictors.iter_mut().enumerate() {
+			site.correct[idx] += predictor.update(retired.pc, taken) as u64;
+		}
+		self.branches += 1;
+	}
+
+	// the accuracy of each predictor, overall and at each branch, found by its label and the line it was written on
+	pub fn report(&self, machine: &Machine, sources: &SourceMap) -> String {
+		let percent = |part: u64, whole: u64| 100.0 * part as f64 / whole.max(1) as f64;
+		let mut out = String::new();
+
+		let _ = writeln!(out, "{} branches executed at {} sites", self.branches, self.sites.len());
+		for predictor in &self.predictors {
+			let _ = writeln!(out, "  {:12} {:6.2}% ({} of {})", predictor.kind.name(), percent(predictor.correct, self.branches), predictor.correct, self.branches);
+		}
+		if let Some(btb) = &self.btb {
+			let _ = writeln!(out, "  branch target buffer of {} entries: {:.2}% of taken branches and jumps found ({} of {})",
+				btb.entries.len(), percent(btb.hits, btb.lookups), btb.hits, btb.lookups);
+		}
+		if self.sites.is_empty() {
+			return out;
+		}
+
+		let rows: Vec<(String, String, &Site)> = self.sites.iter().map(|(pc, site)| {
+			let place = format!("{:#010x} {}", pc, site_label(machine, *pc));
+			let text = disasm::disassemble(machine.memory.read_u32(*pc), *pc, &machine.labels, true);
+			let text = match machine.line(*pc) {
+				Some(segment) => format!("{}  {}:{}", text, sources.name(segment.file), segment.line + 1),
+				None => text
+			};
+			(place, text, site)
+		}).collect();
+		let place_width = rows.iter().map(|(place, _, _)| place.len()).max().unwrap_or(0);
+		let text_width = rows.iter().map(|(_, text, _)| text.len()).max().unwrap_or(0);
+
+		let _ = write!(out, "\n{:place_width$}  {:text_width$}  {:>8}  {:>6}", "site", "branch", "executed", "taken");
+		for predictor in &self.predictors {
+			let _ = write!(out, "  {:>10}", predictor.kind.name());
+		}
+		out.push('\n');
+
+		for (place, text, site) in rows {
+			let _ = write!(out, "{:place_width$}  {:text_width$}  {:>8}  {:>5.1}%", place, text, site.executed, percent(site.taken, site.executed));
+			for correct in &site.correct {
+				let _ = write!(out, "  {:>9.1}%", percent(*correct, site.executed));
+			}
+			out.push('\n');
+		}
+		out
+	}
+}
+
+// the closest label before a branch, with the distance from it
+fn site_label(machine: &Machine, address: u32) -> String {
+	match machine.label_before(address) {
+		Some((label, 0)) => label.name.to_string(),
+		Some((label, distance)) => format!("{}+{}", label.name, distance),
+		None => String::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PC: u32 = 0x00400010;
+
+	// whether each outcome was predicted, in turn
+	fn predicted(predictor: &mut Predictor, outcomes: &[bool]) -> Vec<bool> {
+		outcomes.iter().map(|taken| predictor.update(PC, *taken)).collect()
+	}
+
+	#[test]
+	fn saturates_two_bit_counters() {
+		let mut counter = 2;
+		for (up, expected) in [(true, 3), (true, 3), (false, 2), (false, 1), (false, 0), (false, 0), (true, 1)] {
+			count(&mut counter, up);
+			assert_eq!(counter, expected);
+		}
+	}
+
+	#[test]
+	fn moves_through_the_states_of_a_two_bit_counter() {
+		let mut predictor = Predictor::new(Kind::TwoBit, 4);
+		let index = predictor.index(PC);
+		// from weakly not taken to strongly taken, and back down one step at a time
+		let states: Vec<(bool, u8)> = [true, true, true, false, false, false, false].into_iter().map(|taken| {
+			let predicted = predictor.update(PC, taken);
+			(predicted, predictor.local[index])
+		}).collect();
+		assert_eq!(states, [(false, 2), (true, 3), (true, 3), (false, 2), (false, 1), (true, 0), (true, 0)]);
+	}
+
+	#[test]
+	fn mispredicts_a_loop_once_with_two_bits_and_twice_with_one() {
+		let outcomes: Vec<bool> = (0..4).flat_map(|_| [true, true, true, false]).collect();
+		let mut two_bit = Predictor::new(Kind::TwoBit, 4);
+		let mut one_bit = Predictor::new(Kind::OneBit, 4);
+		assert_eq!(predicted(&mut two_bit, &outcomes)[4..], [true, true, true, false].repeat(3));
+		assert_eq!(predicted(&mut one_bit, &outcomes)[4..], [false, true, true, false].repeat(3));
+		assert_eq!((two_bit.correct, one_bit.correct), (11, 8));
+	}
+
+	#[test]
+	fn learns_alternation_from_the_global_history() {
+		let outcomes: Vec<bool> = (0..16).map(|idx| idx % 2 == 0).collect();
+		let mut gshare = Predictor::new(Kind::Gshare, 4);
+		let mut two_bit = Predictor::new(Kind::TwoBit, 4);
+		assert!(predicted(&mut gshare, &outcomes)[8..].iter().all(|predicted| *predicted));
+		assert!(predicted(&mut two_bit, &outcomes)[8..].iter().all(|predicted| !*predicted));
+
+		// the tournament comes to trust gshare for it
+		let mut tournament = Predictor::new(Kind::Tournament, 4);
+		assert!(predicted(&mut tournament, &outcomes)[8..].iter().all(|predicted| *predicted));
+		assert!(tournament.chooser[tournament.index(PC)] >= 2);
+	}
+
+	#[test]
+	fn predicts_the_same_whatever_happens_when_static() {
+		let outcomes = [true, false, true, true];
+		assert_eq!(predicted(&mut Predictor::new(Kind::Taken, 4), &outcomes), outcomes);
+		assert_eq!(predicted(&mut Predictor::new(Kind::NotTaken, 4), &outcomes), outcomes.map(|taken| !taken));
+	}
+
+	#[test]
+	fn finds_targets_in_the_branch_target_buffer() {
+		let mut btb = Btb::new(4);
+		for (pc, target) in [(PC, 0x100), (PC, 0x100), (PC + 16, 0x200), (PC, 0x100), (PC, 0x104)] {
+			btb.update(pc, target);
+		}
+		assert_eq!((btb.lookups, btb.hits), (5, 1));
+	}
+}
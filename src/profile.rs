@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::parse::instructions::Instruction;
+use crate::runtime::Machine;
+
+const RA: u32 = 31;
+
+// a function as called along a particular path from the entry, in the tree of every path taken
+struct Node {
+	function: u32,
+	parent: Option<usize>,
+	count: u64		// instructions executed in the function itself along this path
+}
+
+// a change to the call stack, made once the delay slot of the call or return has executed
+#[derive(Clone, Copy)]
+enum Change {
+	Call { function: u32, returns: u32 },
+	Return(u32)
+}
+
+// follows calls made with jal, jalr and the linking branches, and returns through the address they left,
+// attributing each instruction to the functions on the stack when it executed
+pub struct Profile {
+	nodes: Vec<Node>,
+	children: HashMap<(usize, u32), usize>,
+	stack: Vec<(usize, u32)>,		// the node of each function called and the address it returns to
+	calls: HashMap<(u32, u32), u64>,	// by caller and callee
+	pending: Option<Change>
+}
+
+impl Profile {
+	pub fn new(entry: u32) -> Profile {
+		Profile {
+			nodes: vec![Node { function: entry, parent: None, count: 0 }],
+			children: HashMap::new(),
+			stack: vec![(0, 0)],
+			calls: HashMap::new(),
+			pending: None
+		}
+	}
+
+	pub fn retire(&mut self, machine: &Machine) {
+		let Some(retired) = machine.retired else { return };
+		let word = retired.word;
+
+		let (node, _) = self.stack[self.stack.len() - 1];
+		self.nodes[node].count += 1;
+		if let Some(change) = self.pending.take() {
+			self.change(change);
+		}
+
+		let change = match (word.instruction, retired.target) {
+			(Instruction::JumpAndLink | Instruction::BranchLessThanZeroAndLink | Instruction::BranchGreaterEqualZeroAndLink, Some(target)) =>
+				Change::Call { function: target, returns: machine.registers[RA as usize] },
+			(Instruction::JumpAndLinkRegister, Some(target)) =>
+				Change::Call { function: target, returns: machine.registers[word.rd as usize] },
+			// any jump back to where a function on the stack returns to, usually jr $ra, the entry having nowhere to return to
+			(Instruction::JumpRegister, Some(target)) if self.stack[1..].iter().any(|(_, returns)| *returns == target) => Change::Return(target),
+			_ => return
+		};
+
+		match machine.delay_slots && word.instruction != Instruction::ExceptionReturn {
+			true => self.pending = Some(change),
+			false => self.change(change)
+		}
+	}
+
+	fn change(&mut self, change: Change) {
+		match change {
+			Change::Call { function, returns } => {
+				let (parent, _) = self.stack[self.stack.len() - 1];
+				*self.calls.entry((self.nodes[parent].function, function)).or_default() += 1;
+
+				let next = self.nodes.len();
+				let node = *self.children.entry((parent, function)).or_insert(next);
+				if node == next {
+					self.nodes.push(Node { function, parent: Some(parent), count: 0 });
+				}
+				self.stack.push((node, returns));
+			},
+			// functions which never returned themselves are unwound along with the one returning, never the entry
+			Change::Return(target) => {
+				while self.stack.len() > 1 {
+					let (_, returns) = self.stack.pop().unwrap_or_else(|| unreachable!("the stack holds more than the entry"));
+					if returns == target {
+						break;
+					}
+				}
+			}
+		}
+	}
+
+	// the functions along the path to a node, from the entry
+	fn path(&self, mut node: usize) -> Vec<u32> {
+		let mut path = vec![self.nodes[node].function];
+		while let Some(parent) = self.nodes[node].parent {
+			path.push(self.nodes[parent].function);
+			node = parent;
+		}
+		path.reverse();
+		path
+	}
+
+	// the stacks and the instructions executed at the top of each, as read by flamegraph.pl and similar tools
+	pub fn folded(&self, machine: &Machine) -> String {
+		let mut lines: Vec<String> = self.nodes.iter().enumerate()
+			.filter(|(_, node)| node.count > 0)
+			.map(|(idx, node)| {
+				let names: Vec<String> = self.path(idx).into_iter().map(|function| name(machine, function)).collect();
+				format!("{} {}", names.join(";"), node.count)
+			})
+			.collect();
+		lines.sort();
+		lines.into_iter().map(|line| line + "\n").collect()
+	}
+
+	// the instructions executed in each function and in everything it called, with its callers and callees
+	pub fn report(&self, machine: &Machine) -> String {
+		let total: u64 = self.nodes.iter().map(|node| node.count).sum();
+		let mut own: HashMap<u32, u64> = HashMap::new();
+		let mut inclusive: HashMap<u32, u64> = HashMap::new();
+		for (idx, node) in self.nodes.iter().enumerate() {
+			*own.entry(node.function).or_default() += node.count;
+
+			// recursive functions count once for each instruction, however deep
+			let mut path = self.path(idx);
+			path.sort();
+			path.dedup();
+			for function in path {
+				*inclusive.entry(function).or_default() += node.count;
+			}
+		}
+
+		let mut called: HashMap<u32, u64> = HashMap::new();
+		for ((_, callee), count) in &self.calls {
+			*called.entry(*callee).or_default() += count;
+		}
+
+		let mut functions: Vec<u32> = inclusive.keys().copied().collect();
+		functions.sort_by(|a, b| inclusive[b].cmp(&inclusive[a]).then(a.cmp(b)));
+		let names: BTreeMap<u32, String> = functions.iter().map(|function| (*function, name(machine, *function))).collect();
+		let width = names.values().map(|name| name.len()).max().unwrap_or(0).max(8);
+		let percent = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+
+		let mut out = String::new();
+		let _ = writeln!(out, "{:width$}  {:>8}  {:>10}  {:>7}  {:>10}  {:>7}", "function", "calls", "self", "", "total", "");
+		for function in &functions {
+			let (own, inclusive) = (own.get(function).copied().unwrap_or(0), inclusive[function]);
+			let _ = writeln!(out, "{:width$}  {:>8}  {:>10}  {:>6.2}%  {:>10}  {:>6.2}%",
+				names[function], called.get(function).copied().unwrap_or(0), own, percent(own), inclusive, percent(inclusive));
+		}
+
+		out.push_str("\ncall graph\n");
+		for function in &functions {
+			let list = |pairs: Vec<(u32, u64)>| -> String {
+				let mut pairs = pairs;
+				pairs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+				pairs.iter().map(|(other, count)| format!("{} ({})", names.get(other).cloned().unwrap_or_else(|| name(machine, *other)), count)).collect::<Vec<_>>().join(", ")
+			};
+			let callers = list(self.calls.iter().filter(|((_, callee), _)| callee == function).map(|((caller, _), count)| (*caller, *count)).collect());
+			let callees = list(self.calls.iter().filter(|((caller, _), _)| caller == function).map(|((_, callee), count)| (*callee, *count)).collect());
+
+			let _ = writeln!(out, "{}", names[function]);
+			if !callers.is_empty() {
+				let _ = writeln!(out, "  called by {}", callers);
+			}
+			if !callees.is_empty() {
+				let _ = writeln!(out, "  calls {}", callees);
+			}
+		}
+		out
+	}
+}
+
+// a function by the label at its entry, or by its address
+fn name(machine: &Machine, function: u32) -> String {
+	match machine.labels.iter().find(|label| label.section.executable() && label.address == function) {
+		Some(label) => label.name.to_string(),
+		None => format!("{:#010x}", function)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::link;
+	use crate::mips::Endian;
+	use crate::runtime::Config;
+	use crate::source::SourceMap;
+
+	// the folded stacks of the program run to its end, with its text placed at the address given
+	fn folded(text: &str, text_base: u32) -> String {
+		let mut sources = SourceMap::new();
+		let file = sources.add_text("program.asm", text);
+		let object = crate::assemble_file(&mut sources, file, Endian::Little).unwrap().1;
+		let program = link::link(&[object], &link::Config { text_base, ..link::Config::default() }).unwrap();
+
+		let mut machine = Machine::new(&program, Config::default());
+		let mut profile = Profile::new(program.entry);
+		while machine.exit.is_none() {
+			machine.step().unwrap();
+			profile.retire(&machine);
+		}
+		profile.folded(&machine)
+	}
+
+	#[test]
+	fn follows_calls_and_returns() {
+		let text = "main: jal f
+			li $v0, 10
+			syscall
+			f: addiu $sp, $sp, -4
+			sw $ra, 0($sp)
+			jal g
+			lw $ra, 0($sp)
+			addiu $sp, $sp, 4
+			jr $ra
+			g: jr $ra";
+		assert_eq!(folded(text, link::Config::default().text_base), "main 3\nmain;f 6\nmain;f;g 1\n");
+	}
+
+	#[test]
+	fn never_returns_from_the_entry() {
+		// jumping back to the entry at address 0 is where the entry would return to, were it a call
+		let text = "main: addiu $t0, $t0, 1
+			slti $t1, $t0, 3
+			beqz $t1, done
+			jr $zero
+			done: li $v0, 10
+			syscall";
+		assert_eq!(folded(text, 0), "main 13\n");
+	}
+}
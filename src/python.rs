@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::link::Program as Linked;
+use crate::parse::instructions::Register;
+use crate::runtime::console::Shared;
+use crate::runtime::{Config, Exit, Fault as Faulted, Limits, Machine as Runtime};
+use crate::source::SourceMap;
+
+// instructions run between checks for ctrl-c, which python only sees when asked
+const SIGNALS: u64 = 1 << 16;
+
+create_exception!(rustic_mips, AssemblyError, PyException, "The source could not be assembled or linked.");
+create_exception!(rustic_mips, Fault, PyException, "The program faulted.");
+create_exception!(rustic_mips, LimitExceeded, Fault, "The program went over one of the limits of its machine.");
+
+// a program assembled from source, with the source kept to report where faults happen
+#[pyclass(frozen)]
+pub struct Program {
+	program: Linked,
+	sources: Arc<SourceMap>
+}
+
+#[pymethods]
+impl Program {
+	#[getter]
+	fn entry(&self) -> u32 {
+		self.program.entry
+	}
+
+	// the symbol table, from each label to its address
+	#[getter]
+	fn symbols(&self) -> BTreeMap<String, u32> {
+		self.program.labels.iter().map(|label| (label.name.clone(), label.address)).collect()
+	}
+}
+
+// assembles and links a program from the text of its source, named as its errors should show it
+#[pyfunction]
+#[pyo3(signature = (source, name = "main.asm"))]
+fn assemble(source: &str, name: &str) -> PyResult<Program> {
+	let mut sources = SourceMap::new();
+	sources.add_text(name, source);
+	let program = crate::assemble(&mut sources).map_err(|err| AssemblyError::new_err(err.report(&sources)))?;
+	Ok(Program { program, sources: Arc::new(sources) })
+}
+
+// a machine running a program, reading stdin from what it is given and keeping what it prints
+#[pyclass(unsendable)]
+pub struct Machine {
+	machine: Runtime,
+	sources: Arc<SourceMap>,
+	terminal: Shared
+}
+
+#[pymethods]
+impl Machine {
+	// limits left out are unlimited, with max_memory and max_output in bytes and the timeout in seconds
+	#[new]
+	#[pyo3(signature = (program, stdin = "", *, delay_slots = false, max_steps = None, max_memory = None, max_output = None, timeout = None))]
+	fn new(program: &Program, stdin: &str, delay_slots: bool, max_steps: Option<u64>, max_memory: Option<u64>, max_output: Option<u64>, timeout: Option<f64>) -> PyResult<Machine> {
+		let limits = limits(max_steps, max_memory, max_output, timeout)?;
+		let machine = Runtime::new(&program.program, Config { delay_slots, limits, ..Default::default() });
+		Ok(Machine::attach(machine, program.sources.clone(), stdin))
+	}
+
+	// a machine carried on from a snapshot, with the program it was running to report faults by its lines
+	#[staticmethod]
+	#[pyo3(signature = (snapshot, stdin = "", *, program = None, max_steps = None, max_memory = None, max_output = None, timeout = None))]
+	fn restore(snapshot: &[u8], stdin: &str, program: Option<&Program>, max_steps: Option<u64>, max_memory: Option<u64>, max_output: Option<u64>, timeout: Option<f64>) -> PyResult<Machine> {
+		let limits = limits(max_steps, max_memory, max_output, timeout)?;
+		let mut machine = Runtime::restore(snapshot, limits).map_err(|why| PyValueError::new_err(format!("not a usable snapshot: {}", why)))?;
+		let sources = match program {
+			Some(program) => {
+				machine.lines = program.program.lines.clone();
+				program.sources.clone()
+			},
+			None => Arc::new(SourceMap::new())
+		};
+		Ok(Machine::attach(machine, sources, stdin))
+	}
+
+	// the state of the machine, for Machine.restore to carry on from, as many times as needed
+	fn snapshot(&self) -> Cow<'static, [u8]> {
+		let mut bytes = Vec::new();
+		self.machine.save(&mut bytes).expect("writing to memory does not fail");
+		Cow::Owned(bytes)
+	}
+
+	// more for the program to read, after what it was given before
+	fn input(&mut self, text: &str) {
+		self.terminal.0.borrow_mut().give(text.as_bytes());
+	}
+
+	// everything the program has printed
+	#[getter]
+	fn stdout(&self) -> String {
+		String::from_utf8_lossy(&self.terminal.0.borrow().output).into_owned()
+	}
+
+	// everything the program has written to stderr
+	#[getter]
+	fn stderr(&self) -> String {
+		String::from_utf8_lossy(&self.terminal.0.borrow().error).into_owned()
+	}
+
+	// executes one instruction, giving whether the program is still running
+	fn step(&mut self) -> PyResult<bool> {
+		self.machine.step().map_err(|fault| self.raise(fault))?;
+		Ok(self.machine.exit.is_none())
+	}
+
+	// runs until the program exits, giving its exit code, or until the pc reaches the breakpoint, giving None
+	#[pyo3(signature = (breakpoint = None))]
+	fn run(&mut self, py: Python<'_>, breakpoint: Option<u32>) -> PyResult<Option<i32>> {
+		let mut interrupted = Ok(());
+		let ran = self.machine.run_until(|machine| {
+			if machine.steps.is_multiple_of(SIGNALS) {
+				interrupted = py.check_signals();
+			}
+			interrupted.is_err() || breakpoint == Some(machine.pc)
+		});
+		interrupted?;
+		let exit = ran.map_err(|fault| self.raise(fault))?;
+		Ok(exit.map(code))
+	}
+
+	#[getter]
+	fn pc(&self) -> u32 {
+		self.machine.pc
+	}
+
+	#[getter]
+	fn steps(&self) -> u64 {
+		self.machine.steps
+	}
+
+	// the code the program exited with, running off the end of the text counting as 0, or None while it runs
+	#[getter]
+	fn exit_code(&self) -> Option<i32> {
+		self.machine.exit.map(code)
+	}
+
+	// a register by its name, such as "$t0", "v0", "hi" or "$f12"
+	fn register(&self, name: &str) -> PyResult<u32> {
+		self.machine.named_register(name).ok_or_else(|| PyKeyError::new_err(format!("\"{}\" is not a register", name)))
+	}
+
+	// a general purpose register, hi, lo or the pc, where the program continues from
+	fn set_register(&mut self, name: &str, value: u32) -> PyResult<()> {
+		match name.strip_prefix('$').unwrap_or(name) {
+			"hi" => self.machine.hi = value,
+			"lo" => self.machine.lo = value,
+			"pc" => self.machine.jump(value),
+			register => match Register::from_name(register) {
+				Some(register) => self.machine.set_register(register, value),
+				None => return Err(PyKeyError::new_err(format!("\"{}\" is not a register", name)))
+			}
+		}
+		Ok(())
+	}
+
+	// the general purpose registers, by number
+	#[getter]
+	fn registers(&self) -> Vec<u32> {
+		self.machine.registers.to_vec()
+	}
+
+	fn read_word(&self, address: u32) -> u32 {
+		self.machine.read_word(address)
+	}
+
+	fn write_word(&mut self, address: u32, value: u32) {
+		self.machine.write_word(address, value);
+	}
+
+	fn read_memory(&self, address: u32, length: usize) -> Vec<u8> {
+		self.machine.read_bytes(address, length)
+	}
+
+	fn write_memory(&mut self, address: u32, data: &[u8]) {
+		self.machine.write_bytes(address, data);
+	}
+
+	// the address of a label, or None when the program has no such label
+	fn label(&self, name: &str) -> Option<u32> {
+		self.machine.label(name).map(|label| label.address)
+	}
+}
+
+impl Machine {
+	fn attach(mut machine: Runtime, sources: Arc<SourceMap>, stdin: &str) -> Machine {
+		let terminal = Shared::default();
+		terminal.0.borrow_mut().give(stdin.as_bytes());
+		machine.io = Box::new(terminal.clone());
+		Machine { machine, sources, terminal }
+	}
+
+	// a fault as the exception it is raised as, with the message the command line shows
+	fn raise(&self, fault: Faulted) -> PyErr {
+		let report = self.machine.report(&fault, &self.sources);
+		match fault {
+			Faulted::Limit(_) => LimitExceeded::new_err(report),
+			_ => Fault::new_err(report)
+		}
+	}
+}
+
+// limits left out are unlimited
+fn limits(steps: Option<u64>, memory: Option<u64>, output: Option<u64>, timeout: Option<f64>) -> PyResult<Limits> {
+	let time = timeout
+		.map(|seconds| Duration::try_from_secs_f64(seconds).map_err(|_| PyValueError::new_err(format!("{} is not a number of seconds", seconds))))
+		.transpose()?;
+	Ok(Limits { steps, memory, output, time })
+}
+
+fn code(exit: Exit) -> i32 {
+	match exit {
+		Exit::Code(code) => code,
+		Exit::FellOff => 0
+	}
+}
+
+#[pymodule]
+fn rustic_mips(module: &Bound<'_, PyModule>) -> PyResult<()> {
+	// messages are read by scripts, which have no use for the colours of the terminal
+	colored::control::set_override(false);
+
+	module.add_function(wrap_pyfunction!(assemble, module)?)?;
+	module.add_class::<Program>()?;
+	module.add_class::<Machine>()?;
+	module.add("AssemblyError", module.py().get_type::<AssemblyError>())?;
+	module.add("Fault", module.py().get_type::<Fault>())?;
+	module.add("LimitExceeded", module.py().get_type::<LimitExceeded>())?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use pyo3::types::PyDict;
+
+	use super::*;
+
+	// the module as a script sees it, taken through assembling, running and reading back a program
+	#[test]
+	fn runs_a_program_from_python() {
+		Python::initialize();
+		Python::attach(|py| {
+			let globals = PyDict::new(py);
+			globals.set_item("rustic_mips", pyo3::wrap_pymodule!(rustic_mips)(py)).unwrap();
+			py.run(c"
+program = rustic_mips.assemble('''
+	.data
+result:	.word 0
+	.text
+main:	li $v0, 5
+	syscall
+	addiu $t0, $v0, 1
+	sw $t0, result
+	move $a0, $t0
+	li $v0, 1
+	syscall
+	li $v0, 17
+	li $a0, 3
+	syscall
+''')
+machine = rustic_mips.Machine(program, '41\\n', max_steps=1000)
+assert machine.run() == 3
+assert machine.exit_code == 3
+assert machine.stdout == '42'
+assert machine.register('$t0') == 42
+assert machine.registers[8] == 42
+assert machine.read_word(program.symbols['result']) == 42
+
+try:
+	rustic_mips.assemble('main: addi $t0')
+	assert False
+except rustic_mips.AssemblyError:
+	pass
+
+looping = rustic_mips.Machine(rustic_mips.assemble('main: j main'), max_steps=10)
+try:
+	looping.run()
+	assert False
+except rustic_mips.LimitExceeded:
+	assert looping.steps == 10
+", Some(&globals), None).unwrap_or_else(|err| panic!("{}", err));
+		});
+	}
+}
@@ -1,4 +1,701 @@
+pub mod memory;
+mod syscalls;
+mod fpu;
+mod cp0;
+pub mod mmio;
+pub mod files;
+pub mod console;
+pub mod terminal;
+pub mod clock;
+pub mod snapshot;
+
+use std::fmt;
+use std::time::Duration;
+use std::ops::Range;
+
+use colored::Colorize;
+
+use crate::elf::Executable;
+use crate::errors;
+use crate::encoding::{self, Encoding, Machine as Word};
 use crate::lexer::tokens::CodeSegment;
-use crate::parse::symbols::Symbol;
-use crate::parse::ast::*;
+use crate::link::{Label, Program};
+use crate::mips::Endian;
+use crate::object::{Line, Section};
+use crate::parse::instructions::{FloatRegister, Instruction, Register};
+use crate::source::SourceMap;
+
+use memory::Memory;
+use mmio::Mmio;
+use files::Files;
+use console::{Buffer, Io};
+use clock::Instant;
+
+// initial register values, matching MARS
+pub const STACK_POINTER: u32 = 0x7fffeffc;
+pub const GLOBAL_POINTER: u32 = 0x10008000;
+pub const HEAP_BASE: u32 = 0x10040000;
+
+const SP: usize = 29;
+const GP: usize = 28;
+const RA: usize = 31;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+	AddressError { address: u32, store: bool },		// unaligned access
+	KernelAddress { address: u32, store: bool },	// access to kernel memory in user mode
+	Overflow,
+	ReservedInstruction(u32),
+	Break(u32),
+	Trap,
+	Syscall(String),
+	Limit(Limit)		// a resource used up, which ends the run rather than being handled
+}
+
+// a resource a run may be limited in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+	Steps(u64),			// instructions executed
+	Memory(u64),		// bytes of heap given out by sbrk, and of pages and virtual files written
+	Output(u64),		// bytes printed, to stdout and stderr together
+	Time(Duration)		// wall clock time
+}
+
+// the limits of a run, each unlimited when not given
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+	pub steps: Option<u64>,
+	pub memory: Option<u64>,
+	pub output: Option<u64>,
+	pub time: Option<Duration>
+}
+
+// how a machine runs a program, beyond what the program itself holds
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+	pub delay_slots: bool,		// whether the instruction following a branch is executed before it is taken
+	pub mmio_delay: u32,		// instructions the display takes to show a character
+	pub limits: Limits
+}
+
+impl Default for Config {
+	fn default() -> Config {
+		Config { delay_slots: false, mmio_delay: mmio::DELAY, limits: Limits::default() }
+	}
+}
+
+impl fmt::Display for Fault {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Fault::AddressError { address, store: false } => write!(f, "Unaligned load from {:#010x}.", address),
+			Fault::AddressError { address, store: true } => write!(f, "Unaligned store to {:#010x}.", address),
+			Fault::KernelAddress { address, store: false } => write!(f, "Load from kernel address {:#010x} in user mode.", address),
+			Fault::KernelAddress { address, store: true } => write!(f, "Store to kernel address {:#010x} in user mode.", address),
+			Fault::Overflow => write!(f, "Arithmetic overflow."),
+			Fault::ReservedInstruction(word) => write!(f, "Unknown instruction {:#010x}.", word),
+			Fault::Break(code) => write!(f, "Break {}.", code),
+			Fault::Trap => write!(f, "Trap."),
+			Fault::Syscall(msg) => write!(f, "{}", msg),
+			Fault::Limit(Limit::Steps(steps)) => write!(f, "Exceeded the limit of {} instructions.", steps),
+			Fault::Limit(Limit::Memory(bytes)) => write!(f, "Exceeded the limit of {} bytes of memory.", bytes),
+			Fault::Limit(Limit::Output(bytes)) => write!(f, "Exceeded the limit of {} bytes of output.", bytes),
+			Fault::Limit(Limit::Time(time)) => write!(f, "Exceeded the time limit of {}s.", time.as_secs_f64())
+		}
+	}
+}
+
+// an instruction the machine has executed, as seen by the models of its timing
+#[derive(Debug, Clone, Copy)]
+pub struct Retired {
+	pub pc: u32,
+	pub word: Word,
+	pub access: Option<(u32, bool)>,	// the address loaded or stored, and whether it was a store
+	pub target: Option<u32>				// where a branch or jump went, when taken
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+	Code(i32),		// by syscall 10 or 17
+	FellOff			// ran past the end of the text
+}
+
+pub struct Machine {
+	pub registers: [u32; 32],
+	pub hi: u32,
+	pub lo: u32,
+	pub floats: [u32; 32],		// coprocessor 1
+	pub fcsr: u32,				// floating point control and status, holding the condition flags and the rounding mode
+	pub cp0: [u32; 32],			// coprocessor 0, of which Count, Compare, Status, Cause, EPC and BadVAddr are used
+	pub mmio: Mmio,				// the keyboard and display
+	pub pc: u32,
+	next_pc: u32,				// differs from pc + 4 only for the delay slot of a branch
+	in_delay_slot: bool,		// whether the instruction at pc follows a branch or jump, taken or not, in its delay slot
+	pub delay_slots: bool,		// whether the instruction following a branch is executed before it is taken
+	pub memory: Memory,
+	pub labels: Vec<Label>,
+	pub lines: Vec<Line>,		// the source of instructions and data
+	text: Vec<Range<u32>>,		// executable memory
+	heap: u32,					// the next address given out by sbrk
+	pub retired: Option<Retired>,	// the instruction executed by the last step, unless it was interrupted or faulted
+	pub io: Box<dyn Io>,		// what syscalls and the keyboard and display read and write, no input and kept output unless replaced
+	pub files: Files,			// what the file syscalls open
+	pub limits: Limits,
+	pub steps: u64,				// taken so far
+	printed: u64,				// bytes of output to stdout and stderr, including any not printed for being over the limit
+	started: Option<Instant>,	// when the first step was taken, once a time limit is set
+	pub exit: Option<Exit>
+}
+
+impl Machine {
+	fn empty(endian: Endian, config: Config) -> Machine {
+		let mut registers = [0; 32];
+		registers[SP] = STACK_POINTER;
+		registers[GP] = GLOBAL_POINTER;
+
+		let mut machine = Machine {
+			registers,
+			hi: 0,
+			lo: 0,
+			floats: [0; 32],
+			fcsr: 0,
+			cp0: {
+				let mut cp0 = [0; 32];
+				cp0[cp0::STATUS] = cp0::INITIAL_STATUS;
+				cp0
+			},
+			mmio: Mmio::new(),
+			pc: 0,
+			next_pc: 4,
+			in_delay_slot: false,
+			delay_slots: config.delay_slots,
+			memory: Memory::new(endian),
+			labels: Vec::new(),
+			lines: Vec::new(),
+			text: Vec::new(),
+			heap: HEAP_BASE,
+			retired: None,
+			io: Box::new(Buffer::default()),
+			files: Files::new(),
+			limits: config.limits,
+			steps: 0,
+			printed: 0,
+			started: None,
+			exit: None
+		};
+		machine.mmio.delay = config.mmio_delay;
+		machine.mirror();
+		machine
+	}
+
+	// a machine about to run a linked program from its entry
+	pub fn new(program: &Program, config: Config) -> Machine {
+		let mut machine = Machine::empty(program.endian, config);
+		for section in Section::ALL {
+			machine.load(program.base(section), program.section(section), section.executable());
+		}
+		machine.labels = program.labels.clone();
+		machine.lines = program.lines.clone();
+		machine.jump(program.entry);
+		machine
+	}
+
+	// code from compilers expects $gp to point to _gp
+	pub fn from_elf(executable: &Executable, config: Config) -> Machine {
+		let mut machine = Machine::empty(executable.endian, config);
+		for segment in &executable.segments {
+			machine.load(segment.address, &segment.bytes, segment.executable);
+		}
+		machine.labels = executable.labels.clone();
+		if let Some(gp) = machine.label("_gp") {
+			machine.registers[GP] = gp.address;
+		}
+		machine.jump(executable.entry);
+		machine
+	}
+
+	pub fn load(&mut self, address: u32, bytes: &[u8], executable: bool) {
+		self.memory.write(address, bytes);
+		if executable {
+			self.text.push(address..address.wrapping_add(bytes.len() as u32));
+		}
+	}
+
+	// continues execution at an address
+	pub fn jump(&mut self, address: u32) {
+		self.pc = address;
+		self.next_pc = address.wrapping_add(4);
+		self.in_delay_slot = false;
+	}
+
+	pub fn label(&self, name: &str) -> Option<&Label> {
+		self.labels.iter().find(|label| label.name == name)
+	}
+
+	// the source of the instruction at an address
+	pub fn line(&self, address: u32) -> Option<&CodeSegment> {
+		self.lines.iter()
+			.find(|line| line.section.executable() && line.address == address)
+			.map(|line| &line.segment)
+	}
+
+	// how a fault which ended the run is reported, at the line of source it happened on when it is known
+	pub fn report(&self, fault: &Fault, sources: &SourceMap) -> String {
+		let (errtype, name) = match fault {
+			Fault::Limit(_) => (errors::ErrType::Limit, "limit exceeded"),
+			_ => (errors::ErrType::Runtime, "runtime error")
+		};
+		match self.line(self.pc) {
+			Some(segment) => errors::DisplayableErr::new(errors::Err {
+				segment: segment.clone(),
+				errtype,
+				msg: errors::Msg::One(fault.to_string())
+			}, sources).to_string(),
+			None => format!("{} ({}) at {:#010x}: {}", "Error".red().bold(), name.bright_black(), self.pc, fault)
+		}
+	}
+
+	// the closest label at or before an address in the text, with the distance from it
+	pub fn label_before(&self, address: u32) -> Option<(&Label, u32)> {
+		self.labels.iter()
+			.filter(|label| label.section.executable() && label.address <= address)
+			.max_by_key(|label| label.address)
+			.map(|label| (label, address - label.address))
+	}
+
+	pub fn register(&self, register: Register) -> u32 {
+		self.registers[register.number() as usize]
+	}
+
+	// writes to $zero are ignored, as they are by instructions
+	pub fn set_register(&mut self, register: Register, value: u32) {
+		if register != Register::Z0 {
+			self.registers[register.number() as usize] = value;
+		}
+	}
+
+	// a register by its name, with or without the dollar sign, including hi, lo and those of the floating point unit
+	pub fn named_register(&self, name: &str) -> Option<u32> {
+		let name = name.strip_prefix('$').unwrap_or(name);
+		match name {
+			"hi" => Some(self.hi),
+			"lo" => Some(self.lo),
+			"pc" => Some(self.pc),
+			_ => match Register::from_name(name) {
+				Some(register) => Some(self.register(register)),
+				None => FloatRegister::from_name(name).map(|register| self.floats[register.number() as usize])
+			}
+		}
+	}
+
+	pub fn read_word(&self, address: u32) -> u32 {
+		self.memory.read_u32(address)
+	}
+
+	pub fn write_word(&mut self, address: u32, value: u32) {
+		self.memory.write_u32(address, value);
+	}
+
+	pub fn read_bytes(&self, address: u32, length: usize) -> Vec<u8> {
+		self.memory.read(address, length)
+	}
+
+	pub fn write_bytes(&mut self, address: u32, bytes: &[u8]) {
+		self.memory.write(address, bytes);
+	}
+
+	// steps until the program exits, faults or the condition holds after an instruction, giving the exit in the first case
+	pub fn run_until(&mut self, mut condition: impl FnMut(&Machine) -> bool) -> Result<Option<Exit>, Fault> {
+		loop {
+			if let Some(exit) = self.exit {
+				return Ok(Some(exit));
+			}
+			self.step()?;
+			if self.exit.is_none() && condition(self) {
+				return Ok(None);
+			}
+		}
+	}
+
+	// executes one instruction, leaving the pc on the faulting instruction if it fails
+	// faults are taken to the exception handler instead when the program has one
+	pub fn step(&mut self) -> Result<(), Fault> {
+		if self.exit.is_some() {
+			return Ok(());
+		}
+		if let Some(limit) = self.exceeded() {
+			return Err(Fault::Limit(limit));
+		}
+
+		self.retired = None;
+		self.steps += 1;
+		self.tick();
+		if self.interrupted() {
+			self.interrupt();
+			return Ok(());
+		}
+
+		match self.cycle() {
+			Err(fault) if self.raise(&fault) => Ok(()),
+			result => result
+		}
+	}
+
+	// the first limit the run has gone over, the clock being read only every so often
+	fn exceeded(&mut self) -> Option<Limit> {
+		let limits = self.limits;
+		if let Some(steps) = limits.steps.filter(|steps| self.steps >= *steps) {
+			return Some(Limit::Steps(steps));
+		}
+		if let Some(memory) = limits.memory.filter(|memory| self.memory.size() + self.files.size() > *memory) {
+			return Some(Limit::Memory(memory));
+		}
+		if let Some(output) = limits.output.filter(|output| self.printed > *output) {
+			return Some(Limit::Output(output));
+		}
+		if limits.time.is_some() && self.steps.is_multiple_of(1024) {
+			return self.time_left().err();
+		}
+		None
+	}
+
+	// the time the run has left before its time limit, if it has one, failing once it is up
+	fn time_left(&mut self) -> Result<Option<Duration>, Limit> {
+		let Some(time) = self.limits.time else { return Ok(None) };
+		let started = *self.started.get_or_insert_with(Instant::now);
+		match time.checked_sub(started.elapsed()) {
+			Some(left) if !left.is_zero() => Ok(Some(left)),
+			_ => Err(Limit::Time(time))
+		}
+	}
+
+	fn cycle(&mut self) -> Result<(), Fault> {
+		if !self.text.iter().any(|range| range.contains(&self.pc)) {
+			self.exit = Some(Exit::FellOff);
+			return Ok(());
+		}
+
+		if !self.pc.is_multiple_of(4) {
+			return Err(Fault::AddressError { address: self.pc, store: false });
+		}
+		if self.user_mode() && Machine::kernel_address(self.pc) {
+			return Err(Fault::KernelAddress { address: self.pc, store: false });
+		}
+
+		let word = self.memory.read_u32(self.pc);
+		let instruction = encoding::decode(word).ok_or(Fault::ReservedInstruction(word))?;
+
+		// eret has no delay slot
+		if instruction.instruction == Instruction::ExceptionReturn {
+			let epc = self.cp0(&instruction).unwrap_or(self.pc);
+			self.retired = Some(Retired { pc: self.pc, word: instruction, access: None, target: Some(epc) });
+			self.jump(epc);
+			return Ok(());
+		}
+
+		// the keyboard and display see their registers being loaded and stored once it is done
+		let access = match instruction.instruction.encoding() {
+			Some(Encoding::Immediate(opcode)) if opcode >= 0x20 => {
+				let address = self.registers[instruction.rs as usize].wrapping_add(instruction.signed() as u32);
+				Some((address, opcode & 0x08 != 0))
+			},
+			_ => None
+		};
+
+		let target = self.execute(&instruction)?;
+		if let Some((address, store)) = access.filter(|(address, _)| mmio::contains(*address)) {
+			self.device(address, store, &instruction);
+		}
+		self.retired = Some(Retired { pc: self.pc, word: instruction, access, target });
+
+		if self.delay_slots {
+			self.pc = self.next_pc;
+			self.next_pc = target.unwrap_or(self.next_pc.wrapping_add(4));
+			self.in_delay_slot = instruction.instruction.is_branch() || instruction.instruction.is_jump();
+		} else {
+			self.jump(target.unwrap_or(self.pc.wrapping_add(4)));
+		}
+
+		self.registers[0] = 0;
+		Ok(())
+	}
+
+	// the address of the instruction to return to from a call
+	fn link(&self) -> u32 {
+		match self.delay_slots {
+			true => self.pc.wrapping_add(8),
+			false => self.pc.wrapping_add(4)
+		}
+	}
+
+	// executes an instruction, returning where it branches to if it does
+	fn execute(&mut self, word: &Word) -> Result<Option<u32>, Fault> {
+		use Instruction::*;
+
+		let rs = self.registers[word.rs as usize];
+		let rt = self.registers[word.rt as usize];
+		let (rd, rt_index) = (word.rd as usize, word.rt as usize);
+		let signed = word.signed() as u32;
+		let address = rs.wrapping_add(signed);
+		let branch = self.pc.wrapping_add(4).wrapping_add(signed << 2);
+
+		match word.instruction {
+			Add => self.registers[rd] = (rs as i32).checked_add(rt as i32).ok_or(Fault::Overflow)? as u32,
+			AddUnsigned => self.registers[rd] = rs.wrapping_add(rt),
+			Subtract => self.registers[rd] = (rs as i32).checked_sub(rt as i32).ok_or(Fault::Overflow)? as u32,
+			SubtractUnsigned => self.registers[rd] = rs.wrapping_sub(rt),
+			And => self.registers[rd] = rs & rt,
+			Or => self.registers[rd] = rs | rt,
+			Xor => self.registers[rd] = rs ^ rt,
+			Nor => self.registers[rd] = !(rs | rt),
+			SetLessThan => self.registers[rd] = ((rs as i32) < (rt as i32)) as u32,
+			SetLessThanUnsigned => self.registers[rd] = (rs < rt) as u32,
+			ShiftLeftLogical => self.registers[rd] = rt << word.shamt,
+			ShiftRightLogical => self.registers[rd] = rt >> word.shamt,
+			ShiftRightArithmetic => self.registers[rd] = ((rt as i32) >> word.shamt) as u32,
+			ShiftLeftLogicalVariable => self.registers[rd] = rt << (rs & 0x1f),
+			ShiftRightLogicalVariable => self.registers[rd] = rt >> (rs & 0x1f),
+			ShiftRightArithmeticVariable => self.registers[rd] = ((rt as i32) >> (rs & 0x1f)) as u32,
+			MoveConditionalZero => if rt == 0 { self.registers[rd] = rs },
+			MoveConditionalNotZero => if rt != 0 { self.registers[rd] = rs },
+			CountLeadingZeros => self.registers[rd] = rs.leading_zeros(),
+			CountLeadingOnes => self.registers[rd] = (!rs).leading_zeros(),
+
+			AddImmediate => self.registers[rt_index] = (rs as i32).checked_add(signed as i32).ok_or(Fault::Overflow)? as u32,
+			AddImmediateUnsigned => self.registers[rt_index] = address,
+			SetLessThanImmediate => self.registers[rt_index] = ((rs as i32) < (signed as i32)) as u32,
+			SetLessThanImmediateUnsigned => self.registers[rt_index] = (rs < signed) as u32,
+			AndImmediate => self.registers[rt_index] = rs & word.immediate,
+			OrImmediate => self.registers[rt_index] = rs | word.immediate,
+			XorImmediate => self.registers[rt_index] = rs ^ word.immediate,
+			LoadUpperImmediate => self.registers[rt_index] = word.immediate << 16,
+
+			Multiply => self.set_hilo((rs as i32 as i64 * rt as i32 as i64) as u64),
+			MultiplyUnsigned => self.set_hilo(rs as u64 * rt as u64),
+			MultiplyToRegister => self.registers[rd] = (rs as i32).wrapping_mul(rt as i32) as u32,
+			MultiplyAdd => self.set_hilo(self.hilo().wrapping_add((rs as i32 as i64 * rt as i32 as i64) as u64)),
+			MultiplyAddUnsigned => self.set_hilo(self.hilo().wrapping_add(rs as u64 * rt as u64)),
+			MultiplySubtract => self.set_hilo(self.hilo().wrapping_sub((rs as i32 as i64 * rt as i32 as i64) as u64)),
+			MultiplySubtractUnsigned => self.set_hilo(self.hilo().wrapping_sub(rs as u64 * rt as u64)),
+			// division by zero leaves hi and lo unchanged
+			Divide => if rt != 0 {
+				self.lo = (rs as i32).wrapping_div(rt as i32) as u32;
+				self.hi = (rs as i32).wrapping_rem(rt as i32) as u32;
+			},
+			DivideUnsigned => if let (Some(lo), Some(hi)) = (rs.checked_div(rt), rs.checked_rem(rt)) {
+				self.lo = lo;
+				self.hi = hi;
+			},
+			MoveFromHi => self.registers[rd] = self.hi,
+			MoveFromLo => self.registers[rd] = self.lo,
+			MoveToHi => self.hi = rs,
+			MoveToLo => self.lo = rs,
+
+			LoadByte => self.registers[rt_index] = self.memory.read_u8(self.access(address, 1, false)?) as i8 as u32,
+			LoadByteUnsigned => self.registers[rt_index] = self.memory.read_u8(self.access(address, 1, false)?) as u32,
+			LoadHalf => self.registers[rt_index] = self.memory.read_u16(self.access(address, 2, false)?) as i16 as u32,
+			LoadHalfUnsigned => self.registers[rt_index] = self.memory.read_u16(self.access(address, 2, false)?) as u32,
+			LoadWord | LoadLinked => self.registers[rt_index] = self.memory.read_u32(self.access(address, 4, false)?),
+			StoreByte => self.memory.write_u8(self.access(address, 1, true)?, rt as u8),
+			StoreHalf => self.memory.write_u16(self.access(address, 2, true)?, rt as u16),
+			StoreWord => self.memory.write_u32(self.access(address, 4, true)?, rt),
+			StoreConditional => {
+				self.memory.write_u32(self.access(address, 4, true)?, rt);
+				self.registers[rt_index] = 1;
+			},
+			LoadWordLeft | LoadWordRight => {
+				self.access(address & !3, 4, false)?;
+				self.unaligned(word.instruction, rt_index, address);
+			},
+			StoreWordLeft | StoreWordRight => {
+				self.access(address & !3, 4, true)?;
+				self.unaligned(word.instruction, rt_index, address);
+			},
+
+			BranchEqual => return Ok((rs == rt).then_some(branch)),
+			BranchNotEqual => return Ok((rs != rt).then_some(branch)),
+			BranchLessEqualZero => return Ok((rs as i32 <= 0).then_some(branch)),
+			BranchGreaterThanZero => return Ok((rs as i32 > 0).then_some(branch)),
+			BranchLessThanZero => return Ok(((rs as i32) < 0).then_some(branch)),
+			BranchGreaterEqualZero => return Ok((rs as i32 >= 0).then_some(branch)),
+			BranchLessThanZeroAndLink => {
+				self.registers[RA] = self.link();
+				return Ok(((rs as i32) < 0).then_some(branch));
+			},
+			BranchGreaterEqualZeroAndLink => {
+				self.registers[RA] = self.link();
+				return Ok((rs as i32 >= 0).then_some(branch));
+			},
+
+			Jump => return Ok(Some(self.region(word.immediate))),
+			JumpAndLink => {
+				self.registers[RA] = self.link();
+				return Ok(Some(self.region(word.immediate)));
+			},
+			JumpRegister => return Ok(Some(rs)),
+			JumpAndLinkRegister => {
+				self.registers[rd] = self.link();
+				return Ok(Some(rs));
+			},
+
+			SystemCall => self.syscall()?,
+			Break => return Err(Fault::Break(word.immediate)),
+
+			TrapEqual => trap(rs == rt)?,
+			TrapNotEqual => trap(rs != rt)?,
+			TrapGreaterEqual => trap(rs as i32 >= rt as i32)?,
+			TrapGreaterEqualUnsigned => trap(rs >= rt)?,
+			TrapLessThan => trap((rs as i32) < rt as i32)?,
+			TrapLessThanUnsigned => trap(rs < rt)?,
+			TrapEqualImmediate => trap(rs == signed)?,
+			TrapNotEqualImmediate => trap(rs != signed)?,
+			TrapGreaterEqualImmediate => trap(rs as i32 >= signed as i32)?,
+			TrapGreaterEqualImmediateUnsigned => trap(rs >= signed)?,
+			TrapLessThanImmediate => trap((rs as i32) < signed as i32)?,
+			TrapLessThanImmediateUnsigned => trap(rs < signed)?,
+
+			MoveFromCoprocessor0 | MoveToCoprocessor0 => return Ok(self.cp0(word)),
+
+			_ if word.instruction.is_float() => return self.fpu(word),
+
+			_ => unreachable!("{} is not a basic instruction", word.instruction.mnemonic())
+		}
+
+		Ok(None)
+	}
+
+	// a jump target within the 256MB region of the delay slot
+	fn region(&self, target: u32) -> u32 {
+		(self.pc.wrapping_add(4) & 0xf0000000) | (target << 2)
+	}
+
+	fn hilo(&self) -> u64 {
+		(self.hi as u64) << 32 | self.lo as u64
+	}
+
+	fn set_hilo(&mut self, value: u64) {
+		self.hi = (value >> 32) as u32;
+		self.lo = value as u32;
+	}
+
+	// lwl, lwr, swl and swr, which move the part of a register that falls within the word containing the address
+	fn unaligned(&mut self, instruction: Instruction, rt: usize, address: u32) {
+		let word_address = address & !3;
+		let memory = self.memory.read_u32(word_address);
+		let register = self.registers[rt];
+
+		// the byte's position counted from the most significant end of the word
+		let byte = match self.memory.endian {
+			Endian::Big => address & 3,
+			Endian::Little => 3 - (address & 3)
+		};
+		let left = byte * 8;
+		let right = (3 - byte) * 8;
+
+		match instruction {
+			Instruction::LoadWordLeft => self.registers[rt] = (memory << left) | (register & ((1 << left) - 1)),
+			Instruction::LoadWordRight => self.registers[rt] = (memory >> right) | (register & !(u32::MAX >> right)),
+			Instruction::StoreWordLeft => self.memory.write_u32(word_address, (register >> left) | (memory & !(u32::MAX >> left))),
+			_ => self.memory.write_u32(word_address, (register << right) | (memory & ((1 << right) - 1)))
+		}
+	}
+
+	// an address which may be accessed, being aligned to the size of the access and outside the kernel in user mode
+	fn access(&self, address: u32, size: u32, store: bool) -> Result<u32, Fault> {
+		if !address.is_multiple_of(size) {
+			return Err(Fault::AddressError { address, store });
+		}
+		if self.user_mode() && Machine::kernel_address(address) && !mmio::contains(address) {
+			return Err(Fault::KernelAddress { address, store });
+		}
+		Ok(address)
+	}
+}
+
+fn trap(condition: bool) -> Result<(), Fault> {
+	match condition {
+		true => Err(Fault::Trap),
+		false => Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn limited(text: &str, limits: Limits) -> Machine {
+		let mut sources = SourceMap::new();
+		sources.add_text("limits.asm", text);
+		Machine::new(&crate::assemble(&mut sources).unwrap(), Config { limits, ..Config::default() })
+	}
+
+	#[test]
+	fn stops_at_the_step_limit() {
+		let mut machine = limited("main: j main", Limits { steps: Some(100), ..Limits::default() });
+		assert_eq!(machine.run_until(|_| false), Err(Fault::Limit(Limit::Steps(100))));
+		assert_eq!(machine.steps, 100);
+	}
+
+	#[test]
+	fn stops_sbrk_past_the_memory_limit() {
+		let text = "main: li $a0, 0x10000
+			li $v0, 9
+			syscall
+			move $s0, $v0
+			li $a0, 1
+			li $v0, 9
+			syscall";
+		let mut machine = limited(text, Limits { memory: Some(0x10000), ..Limits::default() });
+		assert_eq!(machine.run_until(|_| false), Err(Fault::Limit(Limit::Memory(0x10000))));
+		assert_eq!(machine.registers[16], HEAP_BASE);
+		assert_eq!(machine.heap, HEAP_BASE + 0x10000);
+	}
+
+	#[test]
+	fn stops_writing_fresh_pages_past_the_memory_limit() {
+		let text = "main: li $t0, 0x10040000
+			loop: sw $t0, 0($t0)
+			addiu $t0, $t0, 4096
+			j loop";
+		let limit = 16 * memory::PAGE_SIZE as u64;
+		let mut machine = limited(text, Limits { memory: Some(limit), ..Limits::default() });
+		assert_eq!(machine.run_until(|_| false), Err(Fault::Limit(Limit::Memory(limit))));
+		assert_eq!(machine.memory.size(), limit + memory::PAGE_SIZE as u64);
+	}
+
+	#[test]
+	fn cuts_output_at_exactly_the_limit() {
+		let text = ".data
+			hello: .asciiz \"hello world\"
+			.text
+			main: la $a0, hello
+			li $v0, 4
+			syscall
+			li $v0, 10
+			syscall";
+		let mut machine = limited(text, Limits { output: Some(5), ..Limits::default() });
+		assert_eq!(machine.run_until(|_| false), Err(Fault::Limit(Limit::Output(5))));
+		assert_eq!(machine.io.written(), Some(&b"hello"[..]));
+
+		let mut machine = limited(text, Limits { output: Some(11), ..Limits::default() });
+		assert_eq!(machine.run_until(|_| false), Ok(Some(Exit::Code(0))));
+		assert_eq!(machine.io.written(), Some(&b"hello world"[..]));
+	}
+
+	#[test]
+	fn stops_at_the_time_limit() {
+		let time = Duration::from_millis(100);
+		let mut machine = limited("main: j main", Limits { time: Some(time), ..Limits::default() });
+		let started = std::time::Instant::now();
+		assert_eq!(machine.run_until(|_| false), Err(Fault::Limit(Limit::Time(time))));
+		assert!(started.elapsed() >= time);
 
+		// sleeping past the limit
+		let text = "main: li $a0, 60000
+			li $v0, 32
+			syscall";
+		let mut machine = limited(text, Limits { time: Some(time), ..Limits::default() });
+		let started = std::time::Instant::now();
+		assert_eq!(machine.run_until(|_| false), Err(Fault::Limit(Limit::Time(time))));
+		assert!(started.elapsed() < Duration::from_secs(10));
+	}
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+// the wall clock, which in the browser only javascript can read
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy)]
+pub struct Instant(f64);		// milliseconds since 1970
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+	pub fn now() -> Instant {
+		Instant(js_sys::Date::now())
+	}
+
+	pub fn elapsed(&self) -> Duration {
+		Duration::from_secs_f64((js_sys::Date::now() - self.0).max(0.0) / 1000.0)
+	}
+}
+
+// milliseconds since 1970, for syscall 30
+#[cfg(not(target_arch = "wasm32"))]
+pub fn millis() -> u64 {
+	use std::time::{SystemTime, UNIX_EPOCH};
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn millis() -> u64 {
+	js_sys::Date::now() as u64
+}
+
+// the page cannot be blocked, so syscall 32 returns at once there
+#[cfg(not(target_arch = "wasm32"))]
+pub fn sleep(duration: Duration) {
+	std::thread::sleep(duration);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn sleep(_: Duration) {}
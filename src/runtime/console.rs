@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Cursor, Read};
+use std::rc::Rc;
+use std::time::Duration;
+
+// where a program reads its input and writes its output, through syscalls and the memory mapped device
+pub trait Io {
+	// reads at most as many bytes as the buffer holds, none at the end of the input
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+
+	// reads a line onto the end of the bytes, keeping its newline, of which at most limit bytes are kept and the rest skipped
+	fn read_line(&mut self, line: &mut Vec<u8>, limit: usize) -> io::Result<usize>;
+
+	// a key typed on the keyboard of the device, without waiting for one
+	fn key(&mut self) -> Option<u8>;
+
+	// how long the reads which follow may wait for input, failing with a timeout after it, by those which wait at all
+	fn wait(&mut self, _timeout: Option<Duration>) {}
+
+	fn write(&mut self, bytes: &[u8]);
+
+	// what the program writes to stderr, which goes with the rest of its output unless kept apart
+	fn write_error(&mut self, bytes: &[u8]) {
+		self.write(bytes);
+	}
+
+	// everything written to stdout so far, by those which keep it
+	fn written(&self) -> Option<&[u8]> {
+		None
+	}
+
+	// everything written to stderr so far, by those which keep it apart
+	fn written_error(&self) -> Option<&[u8]> {
+		None
+	}
+}
+
+// input given beforehand, with the output kept rather than shown, as for tests
+#[derive(Default)]
+pub struct Buffer {
+	input: Cursor<Vec<u8>>,
+	pub output: Vec<u8>,		// stdout
+	pub error: Vec<u8>			// stderr
+}
+
+impl Buffer {
+	pub fn new(input: Vec<u8>) -> Buffer {
+		Buffer { input: Cursor::new(input), ..Buffer::default() }
+	}
+
+	// more input, read after what was given before
+	pub fn give(&mut self, input: &[u8]) {
+		self.input.get_mut().extend_from_slice(input);
+	}
+}
+
+impl Io for Buffer {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		self.input.read(buffer)
+	}
+
+	fn read_line(&mut self, line: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+		read_line(&mut self.input, line, limit)
+	}
+
+	// the keyboard takes from the same input as the syscalls
+	fn key(&mut self) -> Option<u8> {
+		let mut byte = [0];
+		match self.input.read(&mut byte) {
+			Ok(1) => Some(byte[0]),
+			_ => None
+		}
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		self.output.extend_from_slice(bytes);
+	}
+
+	fn write_error(&mut self, bytes: &[u8]) {
+		self.error.extend_from_slice(bytes);
+	}
+
+	fn written(&self) -> Option<&[u8]> {
+		Some(&self.output)
+	}
+
+	fn written_error(&self) -> Option<&[u8]> {
+		Some(&self.error)
+	}
+}
+
+// a buffer which whoever made the machine holds on to as well, to give input and look at the output as it runs
+#[derive(Clone, Default)]
+pub struct Shared(pub Rc<RefCell<Buffer>>);
+
+impl Io for Shared {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		self.0.borrow_mut().read(buffer)
+	}
+
+	fn read_line(&mut self, line: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+		self.0.borrow_mut().read_line(line, limit)
+	}
+
+	fn key(&mut self) -> Option<u8> {
+		self.0.borrow_mut().key()
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		self.0.borrow_mut().write(bytes);
+	}
+
+	fn write_error(&mut self, bytes: &[u8]) {
+		self.0.borrow_mut().write_error(bytes);
+	}
+}
+
+// a line read without holding more of it than the limit, however long it is, giving the bytes kept
+pub fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+	let mut kept = 0;
+	loop {
+		let available = reader.fill_buf()?;
+		if available.is_empty() {
+			return Ok(kept);
+		}
+
+		let (used, ended) = match available.iter().position(|c| *c == b'\n') {
+			Some(newline) => (newline + 1, true),
+			None => (available.len(), false)
+		};
+		let keep = used.min(limit - kept);
+		line.extend_from_slice(&available[..keep]);
+		kept += keep;
+		reader.consume(used);
+
+		if ended {
+			return Ok(kept);
+		}
+	}
+}
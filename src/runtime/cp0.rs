@@ -0,0 +1,210 @@
+use super::{Machine, Fault, mmio};
+
+use crate::encoding::Machine as Word;
+use crate::mips;
+use crate::parse::instructions::Instruction;
+
+// where exceptions are delivered, as in MARS
+pub const HANDLER: u32 = 0x80000180;
+
+// registers of coprocessor 0, by number
+pub const BAD_VADDR: usize = 8;
+pub const COUNT: usize = 9;
+pub const COMPARE: usize = 11;
+pub const STATUS: usize = 12;
+pub const CAUSE: usize = 13;
+pub const EPC: usize = 14;
+
+// user mode with interrupts enabled and unmasked, as MARS starts
+pub const INITIAL_STATUS: u32 = 0x0000ff11;
+
+// bits of the status register
+const IE: u32 = 1 << 0;		// interrupts enabled
+const EXL: u32 = 1 << 1;	// handling an exception, in kernel mode with interrupts disabled
+const UM: u32 = 1 << 4;		// user mode
+const IM: u32 = 0xff00;		// interrupt mask
+
+// bits of the cause register
+const BD: u32 = 1 << 31;		// the exception was raised in a delay slot
+const IP: u32 = 0xff00;			// pending interrupts
+const IP_DEVICES: u32 = mmio::KEYBOARD_INTERRUPT | mmio::DISPLAY_INTERRUPT;
+const IP_TIMER: u32 = 1 << 15;
+const EXC_CODE: u32 = 0x7c;
+
+// exception codes, given in the cause register
+const INTERRUPT: u32 = 0;
+const ADDRESS_LOAD: u32 = 4;
+const ADDRESS_STORE: u32 = 5;
+const SYSCALL: u32 = 8;
+const BREAKPOINT: u32 = 9;
+const RESERVED_INSTRUCTION: u32 = 10;
+const OVERFLOW: u32 = 12;
+const TRAP: u32 = 13;
+
+// coprocessor 0, which takes the machine to the exception handler and back
+impl Machine {
+	// kernel memory may only be accessed outside of user mode
+	pub fn user_mode(&self) -> bool {
+		self.cp0[STATUS] & (UM | EXL) == UM
+	}
+
+	pub fn kernel_address(address: u32) -> bool {
+		address >= mips::KTEXT_BASE
+	}
+
+	// executes mfc0, mtc0 or eret, returning where it jumps to if it does
+	pub(super) fn cp0(&mut self, word: &Word) -> Option<u32> {
+		let (rt, rd) = (word.rt as usize, word.rd as usize);
+
+		match word.instruction {
+			Instruction::MoveFromCoprocessor0 => self.registers[rt] = self.cp0[rd],
+			Instruction::MoveToCoprocessor0 => {
+				let value = self.registers[rt];
+				match rd {
+					// the pending interrupts are raised by the devices
+					BAD_VADDR | CAUSE => {},
+					// the timer interrupt is acknowledged by setting the next time
+					COMPARE => {
+						self.cp0[COMPARE] = value;
+						self.cp0[CAUSE] &= !IP_TIMER;
+					},
+					_ => self.cp0[rd] = value
+				}
+			},
+			Instruction::ExceptionReturn => {
+				self.cp0[STATUS] &= !EXL;
+				return Some(self.cp0[EPC]);
+			},
+			_ => unreachable!("{} is not an instruction of coprocessor 0", word.instruction.mnemonic())
+		}
+
+		None
+	}
+
+	// counts the cycle, raising the timer interrupt when the count reaches the compare register
+	// and the interrupts of the keyboard and display while they are ready
+	pub(super) fn tick(&mut self) {
+		self.cp0[COUNT] = self.cp0[COUNT].wrapping_add(1);
+		if self.cp0[COUNT] == self.cp0[COMPARE] {
+			self.cp0[CAUSE] |= IP_TIMER;
+		}
+
+		if self.mmio.active() {
+			self.poll();
+		}
+		self.cp0[CAUSE] = (self.cp0[CAUSE] & !IP_DEVICES) | self.mmio.interrupts();
+	}
+
+	// whether an interrupt is pending, enabled and not masked
+	pub(super) fn interrupted(&self) -> bool {
+		let (status, cause) = (self.cp0[STATUS], self.cp0[CAUSE]);
+		status & (IE | EXL) == IE && cause & status & IP & IM != 0 && self.handler()
+	}
+
+	// whether the program has an exception handler
+	fn handler(&self) -> bool {
+		self.text.iter().any(|range| range.contains(&HANDLER))
+	}
+
+	// transfers a fault to the exception handler, unless there is none or it is already handling one
+	pub(super) fn raise(&mut self, fault: &Fault) -> bool {
+		let code = match fault {
+			Fault::AddressError { store: false, .. } | Fault::KernelAddress { store: false, .. } => ADDRESS_LOAD,
+			Fault::AddressError { store: true, .. } | Fault::KernelAddress { store: true, .. } => ADDRESS_STORE,
+			Fault::Syscall(_) => SYSCALL,
+			Fault::Break(_) => BREAKPOINT,
+			Fault::ReservedInstruction(_) => RESERVED_INSTRUCTION,
+			Fault::Overflow => OVERFLOW,
+			Fault::Trap => TRAP,
+			Fault::Limit(_) => return false
+		};
+
+		if !self.handler() || self.cp0[STATUS] & EXL != 0 {
+			return false;
+		}
+
+		if let Fault::AddressError { address, .. } | Fault::KernelAddress { address, .. } = fault {
+			self.cp0[BAD_VADDR] = *address;
+		}
+		self.exception(code);
+		true
+	}
+
+	pub(super) fn interrupt(&mut self) {
+		self.exception(INTERRUPT);
+	}
+
+	// the faulting instruction is returned to, or the branch before it when in its delay slot
+	fn exception(&mut self, code: u32) {
+		let delay_slot = self.in_delay_slot;
+		let cause = self.cp0[CAUSE] & !(BD | EXC_CODE);
+
+		self.cp0[CAUSE] = cause | code << 2 | if delay_slot { BD } else { 0 };
+		self.cp0[EPC] = if delay_slot { self.pc.wrapping_sub(4) } else { self.pc };
+		self.cp0[STATUS] |= EXL;
+		self.jump(HANDLER);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runtime::{Config, Exit};
+	use crate::source::SourceMap;
+
+	// faults on an unaligned load in the delay slot of the branch, the handler keeping EPC and Cause
+	// and returning past the delay slot
+	fn fault_in_delay_slot(branch: &str) -> Machine {
+		let text = format!(".text
+			main: li $t0, 1
+			branch: {} $t0, $zero, done
+			lw $t1, 1($zero)
+			done: li $v0, 17
+			li $a0, 7
+			syscall
+			.ktext 0x80000180
+			mfc0 $s0, $14
+			mfc0 $s1, $13
+			addiu $k0, $s0, 8
+			mtc0 $k0, $14
+			eret", branch);
+		let mut sources = SourceMap::new();
+		sources.add_text("delay.asm", &text);
+		let mut machine = Machine::new(&crate::assemble(&mut sources).unwrap(), Config { delay_slots: true, ..Config::default() });
+		assert_eq!(machine.run_until(|_| false).unwrap(), Some(Exit::Code(7)));
+		machine
+	}
+
+	#[test]
+	fn returns_to_the_branch_of_a_delay_slot_whether_taken_or_not() {
+		for branch in ["beq", "bne"] {
+			let machine = fault_in_delay_slot(branch);
+			let address = machine.label("branch").unwrap().address;
+			assert_eq!(machine.registers[16], address, "EPC after {}", branch);
+			assert_eq!(machine.registers[17] & BD, BD, "Cause.BD after {}", branch);
+			assert_eq!(machine.registers[17] & EXC_CODE, ADDRESS_LOAD << 2);
+			assert_eq!(machine.cp0[BAD_VADDR], 1);
+		}
+	}
+
+	#[test]
+	fn returns_to_the_instruction_itself_outside_of_a_delay_slot() {
+		let text = "main: lw $t1, 1($zero)
+			li $v0, 10
+			syscall
+			.ktext 0x80000180
+			mfc0 $s0, $14
+			mfc0 $s1, $13
+			addiu $k0, $s0, 4
+			mtc0 $k0, $14
+			eret";
+		for delay_slots in [false, true] {
+			let mut sources = SourceMap::new();
+			sources.add_text("fault.asm", text);
+			let mut machine = Machine::new(&crate::assemble(&mut sources).unwrap(), Config { delay_slots, ..Config::default() });
+			machine.run_until(|_| false).unwrap();
+			assert_eq!(machine.registers[16], machine.label("main").unwrap().address);
+			assert_eq!(machine.registers[17] & BD, 0);
+		}
+	}
+}
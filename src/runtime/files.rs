@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// the flags of open, as in MARS
+const READ: u32 = 0;
+const WRITE: u32 = 1;
+const APPEND: u32 = 9;
+
+// the first descriptor given out, after stdin, stdout and stderr
+const FIRST: u32 = 3;
+
+// what the file syscalls reach: a directory kept in memory, or one on the host when the program is allowed it
+pub enum Disk {
+	Virtual,
+	Host(PathBuf)
+}
+
+pub(super) enum Handle {
+	Virtual { path: String, position: usize, write: bool },
+	Host { file: fs::File, write: bool }
+}
+
+pub struct Files {
+	pub disk: Disk,
+	pub contents: BTreeMap<String, Vec<u8>>,	// the virtual files, by their path from the root
+	pub written: BTreeSet<String>,				// the virtual files the program has opened for writing
+	pub(super) open: HashMap<u32, Handle>
+}
+
+impl Files {
+	pub fn new() -> Files {
+		Files { disk: Disk::Virtual, contents: BTreeMap::new(), written: BTreeSet::new(), open: HashMap::new() }
+	}
+
+	// files on the host within a directory, which no path may lead out of
+	pub fn host(directory: PathBuf) -> Files {
+		Files { disk: Disk::Host(directory), ..Files::new() }
+	}
+
+	// the bytes held by the virtual files, which count towards the memory of the program
+	pub fn size(&self) -> u64 {
+		self.contents.values().map(|bytes| bytes.len() as u64).sum()
+	}
+
+	pub fn add(&mut self, path: &str, bytes: Vec<u8>) -> Result<(), String> {
+		let path = normalize(path).ok_or(format!("\"{}\" leads out of the filesystem", path))?;
+		self.contents.insert(path, bytes);
+		Ok(())
+	}
+
+	pub fn get(&self, path: &str) -> Option<&[u8]> {
+		self.contents.get(&normalize(path)?).map(|bytes| bytes.as_slice())
+	}
+
+	// copies the files of a directory on the host, or of a tar archive, into the virtual filesystem
+	pub fn preload(&mut self, path: &Path) -> Result<(), String> {
+		if path.is_dir() {
+			return self.copy(path, "");
+		}
+
+		let bytes = fs::read(path).map_err(|why| why.to_string())?;
+		for (name, contents) in untar(&bytes)? {
+			self.add(&name, contents)?;
+		}
+		Ok(())
+	}
+
+	fn copy(&mut self, directory: &Path, prefix: &str) -> Result<(), String> {
+		let entries = fs::read_dir(directory).map_err(|why| format!("{}: {}", directory.display(), why))?;
+		for entry in entries {
+			let entry = entry.map_err(|why| why.to_string())?;
+			let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+			let path = entry.path();
+			match path.is_dir() {
+				true => self.copy(&path, &format!("{}/", name))?,
+				false => self.add(&name, fs::read(&path).map_err(|why| format!("{}: {}", path.display(), why))?)?
+			}
+		}
+		Ok(())
+	}
+
+	// writes the virtual files the program wrote into a directory on the host
+	pub fn save(&self, directory: &Path) -> Result<(), String> {
+		for path in &self.written {
+			let target = directory.join(path);
+			if let Some(parent) = target.parent() {
+				fs::create_dir_all(parent).map_err(|why| format!("{}: {}", parent.display(), why))?;
+			}
+			fs::write(&target, self.contents.get(path).map_or(&[][..], |bytes| bytes)).map_err(|why| format!("{}: {}", target.display(), why))?;
+		}
+		Ok(())
+	}
+
+	// a descriptor for the file, or -1 when it cannot be opened with those flags
+	pub fn open(&mut self, name: &str, flags: u32) -> i32 {
+		let Some(path) = normalize(name) else { return -1 };
+		let write = match flags {
+			READ => false,
+			WRITE | APPEND => true,
+			_ => return -1
+		};
+
+		let handle = match &self.disk {
+			Disk::Virtual => {
+				if !write && !self.contents.contains_key(&path) {
+					return -1;
+				}
+				let contents = self.contents.entry(path.clone()).or_default();
+				if flags == WRITE {
+					contents.clear();
+				}
+				if write {
+					self.written.insert(path.clone());
+				}
+				Handle::Virtual { position: if flags == APPEND { contents.len() } else { 0 }, path, write }
+			},
+			Disk::Host(directory) => {
+				let mut options = fs::OpenOptions::new();
+				match flags {
+					READ => options.read(true),
+					WRITE => options.write(true).create(true).truncate(true),
+					_ => options.append(true).create(true)
+				};
+				let Some(target) = within(directory, &path) else { return -1 };
+				match options.open(target) {
+					Ok(file) => Handle::Host { file, write },
+					Err(_) => return -1
+				}
+			}
+		};
+
+		let descriptor = (FIRST..).find(|descriptor| !self.open.contains_key(descriptor)).unwrap_or(FIRST);
+		self.open.insert(descriptor, handle);
+		descriptor as i32
+	}
+
+	// at most the given bytes from the file, fewer at its end, or none when it is not open for reading
+	pub fn read(&mut self, descriptor: u32, length: usize) -> Option<Vec<u8>> {
+		match self.open.get_mut(&descriptor)? {
+			Handle::Virtual { write: true, .. } | Handle::Host { write: true, .. } => None,
+			Handle::Virtual { path, position, .. } => {
+				let contents = self.contents.get(path.as_str())?;
+				let start = (*position).min(contents.len());
+				let bytes = contents[start..(start + length).min(contents.len())].to_vec();
+				*position = start + bytes.len();
+				Some(bytes)
+			},
+			Handle::Host { file, .. } => {
+				let mut bytes = Vec::new();
+				Read::by_ref(file).take(length as u64).read_to_end(&mut bytes).ok()?;
+				Some(bytes)
+			}
+		}
+	}
+
+	// whether the bytes were written, the file being open for writing
+	pub fn write(&mut self, descriptor: u32, bytes: &[u8]) -> bool {
+		match self.open.get_mut(&descriptor) {
+			Some(Handle::Virtual { path, position, write: true }) => {
+				let contents = self.contents.entry(path.clone()).or_default();
+				let end = *position + bytes.len();
+				if contents.len() < end {
+					contents.resize(end, 0);
+				}
+				contents[*position..end].copy_from_slice(bytes);
+				*position = end;
+				true
+			},
+			Some(Handle::Host { file, write: true }) => file.write_all(bytes).is_ok(),
+			_ => false
+		}
+	}
+
+	pub fn close(&mut self, descriptor: u32) {
+		self.open.remove(&descriptor);
+	}
+}
+
+impl Default for Files {
+	fn default() -> Files {
+		Files::new()
+	}
+}
+
+// a path from the root of the filesystem, or none when it climbs out of it
+fn normalize(name: &str) -> Option<String> {
+	let mut parts: Vec<&str> = Vec::new();
+	for part in name.split(['/', '\\']) {
+		match part {
+			"" | "." => {},
+			".." => {
+				parts.pop()?;
+			},
+			part => parts.push(part)
+		}
+	}
+	match parts.is_empty() {
+		true => None,
+		false => Some(parts.join("/"))
+	}
+}
+
+// where a path leads on the host within the directory, or none when a link along it leads out
+// a file which does not exist yet is placed by the directory it would be made in, unless it is a link to nowhere
+fn within(directory: &Path, path: &str) -> Option<PathBuf> {
+	let root = directory.canonicalize().ok()?;
+	let joined = root.join(path);
+	let resolved = match joined.canonicalize() {
+		Ok(resolved) => resolved,
+		Err(_) if fs::symlink_metadata(&joined).is_ok() => return None,
+		Err(_) => joined.parent()?.canonicalize().ok()?.join(joined.file_name()?)
+	};
+	resolved.starts_with(&root).then_some(resolved)
+}
+
+// the regular files of a tar archive, by name
+fn untar(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+	let field = |header: &[u8], start: usize, end: usize| -> String {
+		let field = &header[start..end];
+		let length = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+		String::from_utf8_lossy(&field[..length]).into_owned()
+	};
+
+	if !bytes.len().is_multiple_of(512) {
+		return Err("not a directory or a tar archive".to_string());
+	}
+
+	let mut files = Vec::new();
+	let mut at = 0;
+	while at + 512 <= bytes.len() {
+		let header = &bytes[at..at + 512];
+		if header.iter().all(|byte| *byte == 0) {
+			break;
+		}
+		if &header[257..262] != b"ustar" && !header[100..108].iter().all(|byte| byte.is_ascii_digit() || *byte == b' ' || *byte == 0) {
+			return Err("not a directory or a tar archive".to_string());
+		}
+
+		let size = field(header, 124, 136);
+		let size = usize::from_str_radix(size.trim(), 8).map_err(|_| format!("the size \"{}\" in the archive is not valid", size.trim()))?;
+		let (prefix, name) = (field(header, 345, 500), field(header, 0, 100));
+		let name = match prefix.is_empty() {
+			true => name,
+			false => format!("{}/{}", prefix, name)
+		};
+
+		let start = at + 512;
+		let contents = bytes.get(start..start + size).ok_or(format!("\"{}\" runs past the end of the archive", name))?;
+		if matches!(header[156], b'0' | 0) {
+			files.push((name, contents.to_vec()));
+		}
+		at = start + size.div_ceil(512) * 512;
+	}
+	Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// a ustar archive of regular files
+	fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		for (name, contents) in files {
+			let mut header = [0; 512];
+			header[..name.len()].copy_from_slice(name.as_bytes());
+			header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+			header[156] = b'0';
+			header[257..262].copy_from_slice(b"ustar");
+			bytes.extend_from_slice(&header);
+			bytes.extend_from_slice(contents);
+			bytes.resize(bytes.len().next_multiple_of(512), 0);
+		}
+		bytes.resize(bytes.len() + 1024, 0);
+		bytes
+	}
+
+	// a directory of its own under the temporary one, empty
+	#[cfg(unix)]
+	fn directory(name: &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("rustic-mips-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&directory);
+		fs::create_dir_all(&directory).unwrap();
+		directory
+	}
+
+	#[test]
+	fn normalizes_paths_within_the_root() {
+		assert_eq!(normalize("a/./b//c").as_deref(), Some("a/b/c"));
+		assert_eq!(normalize("/a/b/../c").as_deref(), Some("a/c"));
+		assert_eq!(normalize("a\\b").as_deref(), Some("a/b"));
+		assert_eq!(normalize("../x"), None);
+		assert_eq!(normalize("a/../../x"), None);
+		assert_eq!(normalize("a/.."), None);
+		assert_eq!(normalize(""), None);
+	}
+
+	#[test]
+	fn reads_the_files_of_an_archive() {
+		let archive = tar(&[("a.txt", b"hello"), ("dir/b.txt", &[7; 600])]);
+		let files = untar(&archive).unwrap();
+		assert_eq!(files, [("a.txt".to_string(), b"hello".to_vec()), ("dir/b.txt".to_string(), vec![7; 600])]);
+	}
+
+	#[test]
+	fn refuses_archives_which_are_cut_short() {
+		let archive = tar(&[("a.txt", &[1; 1000])]);
+		assert!(untar(&archive[..1024]).unwrap_err().contains("runs past the end"));
+		assert!(untar(&archive[..700]).is_err());
+		assert!(untar(&[b'x'; 512]).is_err());
+	}
+
+	#[test]
+	fn opens_reads_and_writes_virtual_files() {
+		let mut files = Files::new();
+		files.add("in.txt", b"abcdef".to_vec()).unwrap();
+		assert!(files.add("../out.txt", Vec::new()).is_err());
+		assert_eq!(files.open("missing.txt", READ), -1);
+		assert_eq!(files.open("../in.txt", READ), -1);
+
+		let input = files.open("./in.txt", READ);
+		assert_eq!(input, FIRST as i32);
+		assert_eq!(files.read(input as u32, 4).as_deref(), Some(&b"abcd"[..]));
+		assert_eq!(files.read(input as u32, 4).as_deref(), Some(&b"ef"[..]));
+		assert!(!files.write(input as u32, b"no"));
+
+		let output = files.open("out/log.txt", WRITE);
+		assert!(files.write(output as u32, b"one"));
+		files.close(output as u32);
+		let output = files.open("out/log.txt", APPEND);
+		assert!(files.write(output as u32, b" two"));
+		assert_eq!(files.read(output as u32, 1), None);
+		assert_eq!(files.get("out/log.txt"), Some(&b"one two"[..]));
+		assert!(files.written.contains("out/log.txt"));
+		assert_eq!(files.size(), 13);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn keeps_host_files_within_their_directory() {
+		use std::os::unix::fs::symlink;
+
+		let outside = directory("outside");
+		fs::write(outside.join("secret.txt"), "secret").unwrap();
+		let root = directory("jail");
+		fs::write(root.join("inside.txt"), "inside").unwrap();
+		fs::create_dir(root.join("sub")).unwrap();
+		symlink(outside.join("secret.txt"), root.join("link.txt")).unwrap();
+		symlink(&outside, root.join("out")).unwrap();
+		symlink(root.join("inside.txt"), root.join("sub/back.txt")).unwrap();
+		symlink(outside.join("missing.txt"), root.join("dangling.txt")).unwrap();
+
+		let mut files = Files::host(root.clone());
+		for path in ["link.txt", "out/secret.txt", "out/new.txt", "dangling.txt", "../outside/secret.txt"] {
+			assert_eq!(files.open(path, READ), -1, "{}", path);
+			assert_eq!(files.open(path, WRITE), -1, "{}", path);
+		}
+		assert!(!outside.join("new.txt").exists() && !outside.join("missing.txt").exists());
+
+		let descriptor = files.open("sub/back.txt", READ);
+		assert_eq!(files.read(descriptor as u32, 16).as_deref(), Some(&b"inside"[..]));
+		let descriptor = files.open("sub/new.txt", WRITE);
+		assert!(files.write(descriptor as u32, b"made"));
+		files.close(descriptor as u32);
+		assert_eq!(fs::read(root.join("sub/new.txt")).unwrap(), b"made");
+
+		let _ = fs::remove_dir_all(outside);
+		let _ = fs::remove_dir_all(root);
+	}
+}
@@ -0,0 +1,231 @@
+use super::{Machine, Fault};
+
+use crate::encoding::Machine as Word;
+use crate::parse::instructions::Instruction;
+
+// the result of converting a value which does not fit in a word, or is not a number
+const INVALID_WORD: u32 = 0x7fffffff;
+
+// the control register of coprocessor 1 which is the FCSR, the others reading as 0
+const FCSR: usize = 31;
+
+// coprocessor 1, whose registers each hold a single or together with the register following an even one a double
+impl Machine {
+	pub fn single(&self, register: usize) -> f32 {
+		f32::from_bits(self.floats[register])
+	}
+
+	pub fn set_single(&mut self, register: usize, value: f32) {
+		self.floats[register] = value.to_bits();
+	}
+
+	// the even register holds the low half
+	pub fn double(&self, register: usize) -> f64 {
+		let register = register & !1;
+		f64::from_bits((self.floats[register + 1] as u64) << 32 | self.floats[register] as u64)
+	}
+
+	pub fn set_double(&mut self, register: usize, value: f64) {
+		self.set_double_bits(register, value.to_bits());
+	}
+
+	fn set_double_bits(&mut self, register: usize, bits: u64) {
+		let register = register & !1;
+		self.floats[register] = bits as u32;
+		self.floats[register + 1] = (bits >> 32) as u32;
+	}
+
+	// flag 0 is bit 23 of the FCSR, flags 1 to 7 are bits 25 to 31
+	pub fn condition(&self, cc: u32) -> bool {
+		self.fcsr >> condition_bit(cc) & 1 == 1
+	}
+
+	fn set_condition(&mut self, cc: u32, value: bool) {
+		let bit = condition_bit(cc);
+		self.fcsr = (self.fcsr & !(1 << bit)) | (value as u32) << bit;
+	}
+
+	// executes an instruction of coprocessor 1, returning where it branches to if it does
+	pub(super) fn fpu(&mut self, word: &Word) -> Result<Option<u32>, Fault> {
+		use Instruction::*;
+
+		let (ft, fs, fd) = (word.rt as usize, word.rd as usize, word.shamt as usize);
+		let address = self.registers[word.rs as usize].wrapping_add(word.signed() as u32);
+		let cc = word.rt >> 2;
+		let branch = self.pc.wrapping_add(4).wrapping_add((word.signed() as u32) << 2);
+
+		match word.instruction {
+			AddSingle => self.set_single(fd, self.single(fs) + self.single(ft)),
+			AddDouble => self.set_double(fd, self.double(fs) + self.double(ft)),
+			SubtractSingle => self.set_single(fd, self.single(fs) - self.single(ft)),
+			SubtractDouble => self.set_double(fd, self.double(fs) - self.double(ft)),
+			MultiplySingle => self.set_single(fd, self.single(fs) * self.single(ft)),
+			MultiplyDouble => self.set_double(fd, self.double(fs) * self.double(ft)),
+			DivideSingle => self.set_single(fd, self.single(fs) / self.single(ft)),
+			DivideDouble => self.set_double(fd, self.double(fs) / self.double(ft)),
+			SquareRootSingle => self.set_single(fd, self.single(fs).sqrt()),
+			SquareRootDouble => self.set_double(fd, self.double(fs).sqrt()),
+
+			// these only change the sign bit, whatever the value
+			AbsoluteSingle => self.floats[fd] = self.floats[fs] & !(1 << 31),
+			AbsoluteDouble => self.set_double_bits(fd, self.double(fs).to_bits() & !(1 << 63)),
+			NegateSingle => self.floats[fd] = self.floats[fs] ^ 1 << 31,
+			NegateDouble => self.set_double_bits(fd, self.double(fs).to_bits() ^ 1 << 63),
+			MoveSingle => self.floats[fd] = self.floats[fs],
+			MoveDouble => self.set_double_bits(fd, self.double(fs).to_bits()),
+
+			ConvertSingleDouble => self.set_single(fd, self.double(fs) as f32),
+			ConvertSingleWord => self.set_single(fd, self.floats[fs] as i32 as f32),
+			ConvertDoubleSingle => self.set_double(fd, self.single(fs) as f64),
+			ConvertDoubleWord => self.set_double(fd, self.floats[fs] as i32 as f64),
+			// cvt.w rounds as the FCSR says, round.w always to nearest with ties to even
+			ConvertWordSingle => self.floats[fd] = word_of(self.single(fs) as f64, self.rounding()),
+			ConvertWordDouble => self.floats[fd] = word_of(self.double(fs), self.rounding()),
+			RoundWordSingle => self.floats[fd] = word_of(self.single(fs) as f64, f64::round_ties_even),
+			RoundWordDouble => self.floats[fd] = word_of(self.double(fs), f64::round_ties_even),
+			TruncateWordSingle => self.floats[fd] = word_of(self.single(fs) as f64, f64::trunc),
+			TruncateWordDouble => self.floats[fd] = word_of(self.double(fs), f64::trunc),
+			FloorWordSingle => self.floats[fd] = word_of(self.single(fs) as f64, f64::floor),
+			FloorWordDouble => self.floats[fd] = word_of(self.double(fs), f64::floor),
+			CeilWordSingle => self.floats[fd] = word_of(self.single(fs) as f64, f64::ceil),
+			CeilWordDouble => self.floats[fd] = word_of(self.double(fs), f64::ceil),
+
+			// comparisons with a NaN are false
+			CompareEqualSingle => self.set_condition(fd as u32 >> 2, self.single(fs) == self.single(ft)),
+			CompareEqualDouble => self.set_condition(fd as u32 >> 2, self.double(fs) == self.double(ft)),
+			CompareLessThanSingle => self.set_condition(fd as u32 >> 2, self.single(fs) < self.single(ft)),
+			CompareLessThanDouble => self.set_condition(fd as u32 >> 2, self.double(fs) < self.double(ft)),
+			CompareLessEqualSingle => self.set_condition(fd as u32 >> 2, self.single(fs) <= self.single(ft)),
+			CompareLessEqualDouble => self.set_condition(fd as u32 >> 2, self.double(fs) <= self.double(ft)),
+
+			BranchFloatTrue => return Ok(self.condition(cc).then_some(branch)),
+			BranchFloatFalse => return Ok((!self.condition(cc)).then_some(branch)),
+
+			MoveOnFalse => if !self.condition(cc) { self.registers[word.rd as usize] = self.registers[word.rs as usize] },
+			MoveOnTrue => if self.condition(cc) { self.registers[word.rd as usize] = self.registers[word.rs as usize] },
+			MoveOnFalseSingle => if !self.condition(cc) { self.floats[fd] = self.floats[fs] },
+			MoveOnTrueSingle => if self.condition(cc) { self.floats[fd] = self.floats[fs] },
+			MoveOnFalseDouble => if !self.condition(cc) { self.set_double_bits(fd, self.double(fs).to_bits()) },
+			MoveOnTrueDouble => if self.condition(cc) { self.set_double_bits(fd, self.double(fs).to_bits()) },
+
+			MoveToFloat => self.floats[fs] = self.registers[word.rt as usize],
+			MoveFromFloat => self.registers[word.rt as usize] = self.floats[fs],
+			MoveControlToFloat => if fs == FCSR { self.fcsr = self.registers[word.rt as usize] },
+			MoveControlFromFloat => self.registers[word.rt as usize] = if fs == FCSR { self.fcsr } else { 0 },
+
+			LoadWordFloat => self.floats[ft] = self.memory.read_u32(self.access(address, 4, false)?),
+			StoreWordFloat => self.memory.write_u32(self.access(address, 4, true)?, self.floats[ft]),
+			LoadDoubleFloat => {
+				let bits = self.memory.read_u64(self.access(address, 8, false)?);
+				self.set_double_bits(ft, bits);
+			},
+			StoreDoubleFloat => self.memory.write_u64(self.access(address, 8, true)?, self.double(ft).to_bits()),
+
+			_ => unreachable!("{} is not a floating point instruction", word.instruction.mnemonic())
+		}
+
+		Ok(None)
+	}
+
+	// the rounding mode in the low two bits of the FCSR: to nearest, towards zero, up or down
+	fn rounding(&self) -> fn(f64) -> f64 {
+		match self.fcsr & 3 {
+			0 => f64::round_ties_even,
+			1 => f64::trunc,
+			2 => f64::ceil,
+			_ => f64::floor
+		}
+	}
+}
+
+fn condition_bit(cc: u32) -> u32 {
+	match cc {
+		0 => 23,
+		_ => 24 + (cc & 7)
+	}
+}
+
+// a value rounded to an integer, as a word
+fn word_of(value: f64, round: fn(f64) -> f64) -> u32 {
+	let rounded = round(value);
+	match (i32::MIN as f64..=i32::MAX as f64).contains(&rounded) {
+		true => rounded as i32 as u32,
+		false => INVALID_WORD
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runtime::Config;
+	use crate::source::SourceMap;
+
+	// sets the rounding mode from $a0, then converts $f0 as a single and $f10 as a double into $t0 to $t5
+	const CONVERT: &str = "ctc1 $a0, $31
+		cvt.w.s $f2, $f0
+		mfc1 $t0, $f2
+		round.w.s $f2, $f0
+		mfc1 $t1, $f2
+		trunc.w.s $f2, $f0
+		mfc1 $t2, $f2
+		cvt.w.d $f2, $f10
+		mfc1 $t3, $f2
+		round.w.d $f2, $f10
+		mfc1 $t4, $f2
+		trunc.w.d $f2, $f10
+		mfc1 $t5, $f2
+		cfc1 $t6, $31
+		li $v0, 10
+		syscall";
+
+	// cvt.w, round.w and trunc.w of the value under the mode, which are the same for a single and a double
+	fn convert(value: f64, mode: u32) -> [i32; 3] {
+		let mut sources = SourceMap::new();
+		sources.add_text("convert.asm", CONVERT);
+		let mut machine = Machine::new(&crate::assemble(&mut sources).unwrap(), Config::default());
+		machine.registers[4] = mode;
+		machine.set_single(0, value as f32);
+		machine.set_double(10, value);
+		machine.run_until(|_| false).unwrap();
+
+		assert_eq!(machine.registers[14], mode);
+		let words = [8, 9, 10, 11, 12, 13].map(|register| machine.registers[register] as i32);
+		assert_eq!(words[..3], words[3..], "a double converts as a single does");
+		[words[0], words[1], words[2]]
+	}
+
+	#[test]
+	fn converts_by_the_rounding_mode() {
+		// to nearest with ties to even, towards zero, up and down
+		let expected = [
+			(2.5, [2, 2, 3, 2]),
+			(3.5, [4, 3, 4, 3]),
+			(-2.5, [-2, -2, -2, -3]),
+			(-2.7, [-3, -2, -2, -3]),
+			(1.2, [1, 1, 2, 1])
+		];
+		for (value, by_mode) in expected {
+			for (mode, cvt) in by_mode.into_iter().enumerate() {
+				assert_eq!(convert(value, mode as u32)[0], cvt, "cvt.w of {} in mode {}", value, mode);
+			}
+		}
+	}
+
+	#[test]
+	fn rounds_and_truncates_whatever_the_mode() {
+		for mode in 0..4 {
+			assert_eq!(convert(2.5, mode)[1..3], [2, 2]);
+			assert_eq!(convert(3.5, mode)[1..3], [4, 3]);
+			assert_eq!(convert(-2.7, mode)[1..3], [-3, -2]);
+		}
+	}
+
+	#[test]
+	fn converts_what_does_not_fit_to_the_invalid_word() {
+		for value in [f64::NAN, f64::INFINITY, 3e9, -3e9] {
+			for mode in 0..4 {
+				assert_eq!(convert(value, mode).map(|word| word as u32), [INVALID_WORD; 3]);
+			}
+		}
+	}
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use crate::mips::Endian;
+
+pub(super) const PAGE_SIZE: u32 = 4096;
+
+// the 4GB address space, allocated a page at a time as it is written
+// memory which has never been written reads as zero
+pub struct Memory {
+	pub endian: Endian,
+	pub(super) pages: HashMap<u32, Box<[u8; PAGE_SIZE as usize]>>
+}
+
+impl Memory {
+	pub fn new(endian: Endian) -> Memory {
+		Memory {
+			endian,
+			pages: HashMap::new()
+		}
+	}
+
+	// the bytes of the pages which have been written
+	pub fn size(&self) -> u64 {
+		self.pages.len() as u64 * PAGE_SIZE as u64
+	}
+
+	pub fn read_u8(&self, address: u32) -> u8 {
+		match self.pages.get(&(address / PAGE_SIZE)) {
+			Some(page) => page[(address % PAGE_SIZE) as usize],
+			None => 0
+		}
+	}
+
+	pub fn write_u8(&mut self, address: u32, value: u8) {
+		let page = self.pages.entry(address / PAGE_SIZE).or_insert_with(|| Box::new([0; PAGE_SIZE as usize]));
+		page[(address % PAGE_SIZE) as usize] = value;
+	}
+
+	pub fn read(&self, address: u32, len: usize) -> Vec<u8> {
+		(0..len as u32).map(|offset| self.read_u8(address.wrapping_add(offset))).collect()
+	}
+
+	pub fn write(&mut self, address: u32, bytes: &[u8]) {
+		for (offset, byte) in bytes.iter().enumerate() {
+			self.write_u8(address.wrapping_add(offset as u32), *byte);
+		}
+	}
+
+	// alignment is checked by the caller
+	pub fn read_u16(&self, address: u32) -> u16 {
+		self.endian.read_u16(&self.read(address, 2))
+	}
+
+	pub fn read_u32(&self, address: u32) -> u32 {
+		self.endian.read_u32(&self.read(address, 4))
+	}
+
+	pub fn read_u64(&self, address: u32) -> u64 {
+		self.endian.read_u64(&self.read(address, 8))
+	}
+
+	pub fn write_u16(&mut self, address: u32, value: u16) {
+		let mut bytes = [0; 2];
+		self.endian.write_u16(&mut bytes, value);
+		self.write(address, &bytes);
+	}
+
+	pub fn write_u32(&mut self, address: u32, value: u32) {
+		let mut bytes = [0; 4];
+		self.endian.write_u32(&mut bytes, value);
+		self.write(address, &bytes);
+	}
+
+	pub fn write_u64(&mut self, address: u32, value: u64) {
+		let mut bytes = [0; 8];
+		self.endian.write_u64(&mut bytes, value);
+		self.write(address, &bytes);
+	}
+
+	// a null terminated string, cut short at the given length
+	pub fn read_string(&self, address: u32, max: usize) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		while bytes.len() < max {
+			match self.read_u8(address.wrapping_add(bytes.len() as u32)) {
+				0 => break,
+				byte => bytes.push(byte)
+			}
+		}
+		bytes
+	}
+}
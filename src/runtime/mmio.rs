@@ -0,0 +1,167 @@
+use std::ops::Range;
+
+use super::Machine;
+use crate::encoding::Machine as Word;
+
+// the keyboard and display of MARS, at the top of memory
+pub const RECEIVER_CONTROL: u32 = 0xffff0000;
+pub const RECEIVER_DATA: u32 = 0xffff0004;
+pub const TRANSMITTER_CONTROL: u32 = 0xffff0008;
+pub const TRANSMITTER_DATA: u32 = 0xffff000c;
+
+// instructions taken to show a character before the display is ready for the next, by default
+pub const DELAY: u32 = 5;
+
+// bits of the control registers
+const READY: u32 = 1 << 0;
+const INTERRUPT_ENABLE: u32 = 1 << 1;
+
+// the interrupts raised in the cause register, as MARS raises them
+pub const KEYBOARD_INTERRUPT: u32 = 1 << 8;
+pub const DISPLAY_INTERRUPT: u32 = 1 << 9;
+
+// the registers of the device, the only kernel addresses user mode may access
+const REGISTERS: Range<u32> = RECEIVER_CONTROL..TRANSMITTER_DATA + 4;
+
+pub fn contains(address: u32) -> bool {
+	REGISTERS.contains(&address)
+}
+
+// the device registers are kept in memory, and updated as the program accesses them
+pub struct Mmio {
+	pub delay: u32,							// instructions taken to show a character
+	pub(super) active: bool,				// whether the program has used the device, the keyboard being read only from then
+	pub(super) received: Option<u8>,		// the character in the receiver data register, until it is read
+	pub(super) receiver_interrupts: bool,
+	pub(super) sending: u32,				// instructions until the display is ready again
+	pub(super) transmitter_interrupts: bool
+}
+
+impl Mmio {
+	pub fn new() -> Mmio {
+		Mmio {
+			delay: DELAY,
+			active: false,
+			received: None,
+			receiver_interrupts: false,
+			sending: 0,
+			transmitter_interrupts: false
+		}
+	}
+
+	// whether the program has used the device, which is left alone until then
+	pub fn active(&self) -> bool {
+		self.active
+	}
+
+	// the interrupts the device is raising, while it is ready and they are enabled
+	pub fn interrupts(&self) -> u32 {
+		let keyboard = self.receiver_interrupts && self.received.is_some();
+		let display = self.transmitter_interrupts && self.sending == 0;
+		(keyboard as u32 * KEYBOARD_INTERRUPT) | (display as u32 * DISPLAY_INTERRUPT)
+	}
+}
+
+impl Default for Mmio {
+	fn default() -> Mmio {
+		Mmio::new()
+	}
+}
+
+impl Machine {
+	// advances the device by one instruction, taking the next character typed once the last has been read
+	pub(super) fn poll(&mut self) {
+		if self.mmio.sending > 0 {
+			self.mmio.sending -= 1;
+		}
+		if self.mmio.active && self.mmio.received.is_none() {
+			self.mmio.received = self.io.key();
+		}
+		self.mirror();
+	}
+
+	// the device responding to a load or store of its registers by the instruction
+	pub(super) fn device(&mut self, address: u32, store: bool, word: &Word) {
+		self.mmio.active = true;
+
+		let interrupts = |word: u32| word & INTERRUPT_ENABLE != 0;
+		match (address & !3, store) {
+			(RECEIVER_CONTROL, true) => self.mmio.receiver_interrupts = interrupts(self.memory.read_u32(RECEIVER_CONTROL)),
+			(RECEIVER_DATA, false) => self.mmio.received = None,
+			(TRANSMITTER_CONTROL, true) => self.mmio.transmitter_interrupts = interrupts(self.memory.read_u32(TRANSMITTER_CONTROL)),
+			// characters written while the display is busy are lost
+			// the character is the low byte of the register stored, whichever byte of the data register it was stored to
+			(TRANSMITTER_DATA, true) if self.mmio.sending == 0 => {
+				let stored = match word.instruction.is_float() {
+					true => self.floats[word.rt as usize],
+					false => self.registers[word.rt as usize]
+				};
+				// going over the limit on output ends the run at the next step
+				let _ = self.print(&[stored as u8]);
+				self.mmio.sending = self.mmio.delay;
+			},
+			_ => {}
+		}
+		self.mirror();
+	}
+
+	// writes the state of the device into its registers, of which only the interrupt enable bits may be written
+	pub(super) fn mirror(&mut self) {
+		let control = |ready: bool, interrupts: bool| (ready as u32 * READY) | (interrupts as u32 * INTERRUPT_ENABLE);
+
+		let receiver = control(self.mmio.received.is_some(), self.mmio.receiver_interrupts);
+		let transmitter = control(self.mmio.sending == 0, self.mmio.transmitter_interrupts);
+		let data = self.mmio.received.unwrap_or_else(|| self.memory.read_u32(RECEIVER_DATA) as u8);
+
+		self.memory.write_u32(RECEIVER_CONTROL, receiver);
+		self.memory.write_u32(RECEIVER_DATA, data as u32);
+		self.memory.write_u32(TRANSMITTER_CONTROL, transmitter);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::link;
+	use crate::mips::Endian;
+	use crate::runtime::{Config, Fault};
+	use crate::source::SourceMap;
+
+	fn machine(text: &str, endian: Endian) -> Machine {
+		let mut sources = SourceMap::new();
+		let file = sources.add_text("mmio.asm", text);
+		let object = crate::assemble_file(&mut sources, file, endian).unwrap().1;
+		let program = link::link(&[object], &link::Config::default()).unwrap();
+		Machine::new(&program, Config { mmio_delay: 0, ..Config::default() })
+	}
+
+	#[test]
+	fn shows_the_low_byte_of_what_is_stored_whatever_the_endian() {
+		let text = "lui $t1, 0xffff
+			li $t0, 0x41
+			sb $t0, 12($t1)
+			li $t0, 0x142
+			sw $t0, 12($t1)
+			li $t0, 0x4443
+			sh $t0, 14($t1)
+			li $v0, 10
+			syscall";
+		for endian in [Endian::Little, Endian::Big] {
+			let mut machine = machine(text, endian);
+			machine.run_until(|_| false).unwrap();
+			assert_eq!(machine.io.written(), Some(&b"ABC"[..]), "{:?} endian", endian);
+		}
+	}
+
+	#[test]
+	fn leaves_the_rest_of_the_kernel_segment_to_the_kernel() {
+		for (offset, allowed) in [(0, true), (12, true), (16, false), (0x100, false)] {
+			let mut machine = machine(&format!("lui $t1, 0xffff\nlw $t0, {}($t1)\nli $v0, 10\nsyscall", offset), Endian::Little);
+			match machine.run_until(|_| false) {
+				Ok(_) => assert!(allowed, "{:#x} is allowed", offset),
+				Err(Fault::KernelAddress { address, store: false }) => assert_eq!((address, allowed), (0xffff0000 + offset, false)),
+				Err(fault) => panic!("{}", fault)
+			}
+		}
+	}
+}
@@ -0,0 +1,317 @@
+use std::io::{self, Write};
+
+use crate::link::Label;
+use crate::mips::Endian;
+use crate::object::{self, invalid, write_u32, Binding, Reader};
+
+use super::files::{Files, Handle};
+use super::memory::{Memory, PAGE_SIZE};
+use super::{Exit, Limits, Machine};
+
+const MAGIC: &[u8; 4] = b"\x7fRMS";
+const VERSION: u16 = 1;
+
+const FLAG_BIG_ENDIAN: u16 = 1 << 0;
+const FLAG_DELAY_SLOTS: u16 = 1 << 1;
+const FLAG_IN_DELAY_SLOT: u16 = 1 << 2;
+
+// the device's flags
+const ACTIVE: u8 = 1 << 0;
+const RECEIVED: u8 = 1 << 1;
+const RECEIVER_INTERRUPTS: u8 = 1 << 2;
+const TRANSMITTER_INTERRUPTS: u8 = 1 << 3;
+
+impl Machine {
+	// everything the program can see of the machine, so that it can carry on from here in another run
+	// files on the host are left out, the host being given again when the run resumes, as are the source lines of the program
+	pub fn save(&self, out: &mut impl Write) -> io::Result<()> {
+		let mut flags = 0;
+		if self.memory.endian == Endian::Big {
+			flags |= FLAG_BIG_ENDIAN;
+		}
+		if self.delay_slots {
+			flags |= FLAG_DELAY_SLOTS;
+		}
+		if self.in_delay_slot {
+			flags |= FLAG_IN_DELAY_SLOT;
+		}
+
+		out.write_all(MAGIC)?;
+		out.write_all(&VERSION.to_le_bytes())?;
+		out.write_all(&flags.to_le_bytes())?;
+
+		for word in self.registers.iter().chain([&self.hi, &self.lo]).chain(&self.floats).chain([&self.fcsr]).chain(&self.cp0) {
+			write_u32(out, *word)?;
+		}
+		for word in [self.pc, self.next_pc, self.heap] {
+			write_u32(out, word)?;
+		}
+		write_u64(out, self.steps)?;
+		write_u64(out, self.printed)?;
+		match self.exit {
+			None => out.write_all(&[0, 0, 0, 0, 0])?,
+			Some(Exit::Code(code)) => {
+				out.write_all(&[1])?;
+				write_u32(out, code as u32)?;
+			},
+			Some(Exit::FellOff) => out.write_all(&[2, 0, 0, 0, 0])?
+		}
+
+		let mmio = &self.mmio;
+		let device = [(mmio.active, ACTIVE), (mmio.received.is_some(), RECEIVED), (mmio.receiver_interrupts, RECEIVER_INTERRUPTS), (mmio.transmitter_interrupts, TRANSMITTER_INTERRUPTS)]
+			.iter().filter(|(set, _)| *set).fold(0, |flags, (_, flag)| flags | flag);
+		out.write_all(&[device, mmio.received.unwrap_or_default()])?;
+		write_u32(out, mmio.delay)?;
+		write_u32(out, mmio.sending)?;
+
+		write_u32(out, self.text.len() as u32)?;
+		for range in &self.text {
+			write_u32(out, range.start)?;
+			write_u32(out, range.end)?;
+		}
+
+		// pages in order, so that the same state always makes the same file
+		let mut pages: Vec<_> = self.memory.pages.iter().collect();
+		pages.sort_by_key(|(number, _)| **number);
+		write_u32(out, pages.len() as u32)?;
+		for (number, page) in pages {
+			write_u32(out, *number)?;
+			out.write_all(&page[..])?;
+		}
+
+		write_u32(out, self.labels.len() as u32)?;
+		for label in &self.labels {
+			write_string(out, &label.name)?;
+			write_u32(out, label.address)?;
+			out.write_all(&[object::section_id(Some(label.section)), label.binding as u8])?;
+		}
+
+		let files = &self.files;
+		write_u32(out, files.contents.len() as u32)?;
+		for (path, contents) in &files.contents {
+			write_string(out, path)?;
+			write_u32(out, contents.len() as u32)?;
+			out.write_all(contents)?;
+		}
+		write_u32(out, files.written.len() as u32)?;
+		for path in &files.written {
+			write_string(out, path)?;
+		}
+		let mut open: Vec<_> = files.open.iter().filter_map(|(descriptor, handle)| match handle {
+			Handle::Virtual { path, position, write } => Some((*descriptor, path, *position, *write)),
+			Handle::Host { .. } => None
+		}).collect();
+		open.sort_by_key(|(descriptor, ..)| *descriptor);
+		write_u32(out, open.len() as u32)?;
+		for (descriptor, path, position, write) in open {
+			write_u32(out, descriptor)?;
+			write_string(out, path)?;
+			write_u32(out, position as u32)?;
+			out.write_all(&[write as u8])?;
+		}
+
+		Ok(())
+	}
+
+	// a machine as it was saved, under limits of its own, which count the instructions from the start of the first run
+	pub fn restore(bytes: &[u8], limits: Limits) -> io::Result<Machine> {
+		let mut reader = Reader { bytes, idx: 0 };
+
+		if reader.take(4)? != MAGIC {
+			return Err(invalid("not a snapshot"));
+		}
+
+		let version = reader.u16()?;
+		if version != VERSION {
+			return Err(invalid(&format!("unsupported snapshot version {}", version)));
+		}
+
+		let flags = reader.u16()?;
+		let endian = if flags & FLAG_BIG_ENDIAN != 0 { Endian::Big } else { Endian::Little };
+		let mut machine = Machine::empty(endian, super::Config { delay_slots: flags & FLAG_DELAY_SLOTS != 0, limits, ..Default::default() });
+
+		for register in machine.registers.iter_mut() {
+			*register = reader.u32()?;
+		}
+		machine.hi = reader.u32()?;
+		machine.lo = reader.u32()?;
+		for register in machine.floats.iter_mut() {
+			*register = reader.u32()?;
+		}
+		machine.fcsr = reader.u32()?;
+		for register in machine.cp0.iter_mut() {
+			*register = reader.u32()?;
+		}
+		machine.pc = reader.u32()?;
+		machine.next_pc = reader.u32()?;
+		machine.in_delay_slot = flags & FLAG_IN_DELAY_SLOT != 0;
+		machine.heap = reader.u32()?;
+		machine.steps = reader.u64()?;
+		machine.printed = reader.u64()?;
+		machine.exit = match (reader.u8()?, reader.u32()?) {
+			(0, _) => None,
+			(1, code) => Some(Exit::Code(code as i32)),
+			(2, _) => Some(Exit::FellOff),
+			(other, _) => return Err(invalid(&format!("unknown exit {}", other)))
+		};
+
+		let (device, received) = (reader.u8()?, reader.u8()?);
+		machine.mmio.active = device & ACTIVE != 0;
+		machine.mmio.received = (device & RECEIVED != 0).then_some(received);
+		machine.mmio.receiver_interrupts = device & RECEIVER_INTERRUPTS != 0;
+		machine.mmio.transmitter_interrupts = device & TRANSMITTER_INTERRUPTS != 0;
+		machine.mmio.delay = reader.u32()?;
+		machine.mmio.sending = reader.u32()?;
+
+		for _ in 0..reader.u32()? {
+			machine.text.push(reader.u32()?..reader.u32()?);
+		}
+
+		machine.memory = Memory::new(endian);
+		for _ in 0..reader.u32()? {
+			let number = reader.u32()?;
+			let mut page = Box::new([0; PAGE_SIZE as usize]);
+			page.copy_from_slice(reader.take(PAGE_SIZE as usize)?);
+			machine.memory.pages.insert(number, page);
+		}
+
+		for _ in 0..reader.u32()? {
+			let name = reader.string()?;
+			let address = reader.u32()?;
+			let section = object::section_from_id(reader.u8()?)?.ok_or(invalid("a label outside of any section"))?;
+			let binding = match reader.u8()? {
+				0 => Binding::Local,
+				1 => Binding::Global,
+				other => return Err(invalid(&format!("unknown symbol binding {}", other)))
+			};
+			machine.labels.push(Label { name, address, section, binding, segment: None });
+		}
+
+		let mut files = Files::new();
+		for _ in 0..reader.u32()? {
+			let path = reader.string()?;
+			let len = reader.u32()? as usize;
+			files.contents.insert(path, reader.take(len)?.to_vec());
+		}
+		for _ in 0..reader.u32()? {
+			files.written.insert(reader.string()?);
+		}
+		for _ in 0..reader.u32()? {
+			let descriptor = reader.u32()?;
+			let path = reader.string()?;
+			let position = reader.u32()? as usize;
+			let write = reader.u8()? != 0;
+			files.open.insert(descriptor, Handle::Virtual { path, position, write });
+		}
+		machine.files = files;
+
+		Ok(machine)
+	}
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+	out.write_all(&value.to_le_bytes())
+}
+
+fn write_string(out: &mut impl Write, text: &str) -> io::Result<()> {
+	write_u32(out, text.len() as u32)?;
+	out.write_all(text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runtime::Config;
+	use crate::source::SourceMap;
+
+	// takes from the heap and opens a file before stop, then writes to both after it
+	const PROGRAM: &str = ".data
+		name: .asciiz \"log.txt\"
+		text: .ascii \"abc\"
+		.text
+		main: li $v0, 9
+		li $a0, 64
+		syscall
+		move $s0, $v0
+		li $t0, 7
+		sw $t0, 0($s0)
+		li $v0, 13
+		la $a0, name
+		li $a1, 1
+		syscall
+		move $s1, $v0
+		li $v0, 15
+		move $a0, $s1
+		la $a1, text
+		li $a2, 3
+		syscall
+		mtc1 $t0, $f4
+		li $v0, 1
+		li $a0, 5
+		syscall
+		stop: li $v0, 15
+		move $a0, $s1
+		la $a1, text
+		li $a2, 2
+		syscall
+		lw $a0, 0($s0)
+		li $v0, 1
+		syscall
+		li $v0, 17
+		li $a0, 3
+		syscall";
+
+	// the machine stopped at stop, run with delay slots so that their flag is saved too
+	fn stopped() -> Machine {
+		let mut sources = SourceMap::new();
+		sources.add_text("program.asm", PROGRAM);
+		let program = crate::assemble(&mut sources).unwrap();
+		let mut machine = Machine::new(&program, Config { delay_slots: true, ..Config::default() });
+		let stop = machine.label("stop").unwrap().address;
+		assert_eq!(machine.run_until(|machine| machine.pc == stop).unwrap(), None);
+		machine
+	}
+
+	fn save(machine: &Machine) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		machine.save(&mut bytes).unwrap();
+		bytes
+	}
+
+	#[test]
+	fn saves_a_restored_machine_as_it_was() {
+		let machine = stopped();
+		let bytes = save(&machine);
+		let restored = Machine::restore(&bytes, Limits::default()).unwrap();
+		assert_eq!(save(&restored), bytes);
+
+		assert_eq!((restored.pc, restored.steps, restored.heap, restored.delay_slots), (machine.pc, machine.steps, machine.heap, true));
+		assert_eq!(restored.floats[4], 7);
+		assert_eq!(restored.files.get("log.txt"), Some(&b"abc"[..]));
+		assert_eq!(restored.labels.len(), machine.labels.len());
+	}
+
+	#[test]
+	fn carries_on_where_it_stopped() {
+		let mut machine = stopped();
+		let mut restored = Machine::restore(&save(&machine), Limits::default()).unwrap();
+		for machine in [&mut machine, &mut restored] {
+			assert_eq!(machine.run_until(|_| false).unwrap(), Some(Exit::Code(3)));
+			assert_eq!(machine.files.get("log.txt"), Some(&b"abcab"[..]));
+		}
+		assert_eq!(machine.io.written(), Some(&b"57"[..]));
+		assert_eq!(restored.io.written(), Some(&b"7"[..]));
+		assert_eq!((restored.steps, restored.pc), (machine.steps, machine.pc));
+	}
+
+	#[test]
+	fn refuses_what_is_not_a_whole_snapshot() {
+		let bytes = save(&stopped());
+		assert!(Machine::restore(b"\x7fELF", Limits::default()).is_err());
+		assert!(Machine::restore(&bytes[..bytes.len() - 1], Limits::default()).is_err());
+
+		let mut newer = bytes.clone();
+		newer[4] = VERSION as u8 + 1;
+		assert!(Machine::restore(&newer, Limits::default()).is_err());
+	}
+}
@@ -0,0 +1,235 @@
+use std::io;
+use std::time::Duration;
+
+use super::{Machine, Fault, Exit, Limit, HEAP_BASE};
+use super::clock;
+
+const V0: usize = 2;
+const A0: usize = 4;
+const A1: usize = 5;
+const A2: usize = 6;
+const F0: usize = 0;
+const F12: usize = 12;
+
+// the longest string printed at once, so that a missing terminator does not print the whole address space
+// and the longest line read, the rest of a longer one being skipped so that input cannot grow memory without bound
+const MAX_STRING: usize = 1 << 20;
+
+// the longest line read for a number
+const MAX_NUMBER: usize = 1 << 10;
+
+// services numbered as in MARS, taken from $v0
+impl Machine {
+	pub(super) fn syscall(&mut self) -> Result<(), Fault> {
+		let a0 = self.registers[A0];
+		let a1 = self.registers[A1];
+		let a2 = self.registers[A2];
+
+		// the clock is otherwise only read every so often, and a syscall which waits may be the last the program makes
+		// reading input waits no longer than the time left
+		if matches!(self.registers[V0], 5..=8 | 12 | 32) || (self.registers[V0] == 14 && a0 == 0) {
+			let left = self.time_left().map_err(Fault::Limit)?;
+			self.io.wait(left);
+		}
+
+		match self.registers[V0] {
+			1 => self.print((a0 as i32).to_string().as_bytes())?,
+			2 => self.print(java(self.single(F12) as f64, &format!("{:e}", self.single(F12))).as_bytes())?,
+			3 => self.print(java(self.double(F12), &format!("{:e}", self.double(F12))).as_bytes())?,
+			4 => self.print(String::from_utf8_lossy(&self.memory.read_string(a0, MAX_STRING)).as_bytes())?,
+			5 => {
+				let line = String::from_utf8_lossy(&self.read_line(MAX_NUMBER)?).into_owned();
+				match line.trim().parse::<i32>() {
+					Ok(value) => self.registers[V0] = value as u32,
+					Err(_) => return Err(Fault::Syscall(format!("\"{}\" is not an integer.", line.trim())))
+				}
+			},
+			6 => {
+				let line = String::from_utf8_lossy(&self.read_line(MAX_NUMBER)?).into_owned();
+				match line.trim().parse::<f32>() {
+					Ok(value) => self.set_single(F0, value),
+					Err(_) => return Err(Fault::Syscall(format!("\"{}\" is not a number.", line.trim())))
+				}
+			},
+			7 => {
+				let line = String::from_utf8_lossy(&self.read_line(MAX_NUMBER)?).into_owned();
+				match line.trim().parse::<f64>() {
+					Ok(value) => self.set_double(F0, value),
+					Err(_) => return Err(Fault::Syscall(format!("\"{}\" is not a number.", line.trim())))
+				}
+			},
+			// reads at most $a1 - 1 characters into the buffer at $a0, stopping after a newline
+			8 => {
+				if a1 == 0 {
+					return Ok(());
+				}
+
+				let mut line = self.read_line((a1 as usize - 1).min(MAX_STRING))?;
+				line.push(0);
+				self.memory.write(a0, &line);
+			},
+			9 => {
+				let heap = self.heap.wrapping_add(a0).wrapping_add(3) & !3;
+				if let Some(memory) = self.limits.memory.filter(|memory| heap.wrapping_sub(HEAP_BASE) as u64 > *memory) {
+					return Err(Fault::Limit(Limit::Memory(memory)));
+				}
+				self.registers[V0] = self.heap;
+				self.heap = heap;
+			},
+			10 => self.exit = Some(Exit::Code(0)),
+			11 => self.print(String::from_utf8_lossy(&[a0 as u8]).as_bytes())?,
+			12 => {
+				let mut byte = [0];
+				let read = self.io.read(&mut byte).map_err(|why| self.input_error(why))?;
+				self.registers[V0] = if read == 0 { 0 } else { byte[0] as u32 };
+			},
+			// files are opened with 0 to read, 1 to write and 9 to append, failing with -1
+			13 => {
+				let name = String::from_utf8_lossy(&self.memory.read_string(a0, MAX_STRING)).into_owned();
+				self.registers[V0] = self.files.open(&name, a1) as u32;
+			},
+			// reads at most $a2 bytes into the buffer at $a1, giving the number read
+			14 => {
+				let length = (a2 as usize).min(MAX_STRING);
+				let bytes = match a0 {
+					0 => {
+						let mut bytes = vec![0; length];
+						match self.io.read(&mut bytes) {
+							Ok(read) => Some(bytes[..read].to_vec()),
+							Err(why) if why.kind() == io::ErrorKind::TimedOut => return Err(self.input_error(why)),
+							Err(_) => None
+						}
+					},
+					descriptor => self.files.read(descriptor, length)
+				};
+				self.registers[V0] = match bytes {
+					Some(bytes) => {
+						self.memory.write(a1, &bytes);
+						bytes.len() as u32
+					},
+					None => -1i32 as u32
+				};
+			},
+			// writes $a2 bytes from the buffer at $a1, giving the number written
+			15 => {
+				let bytes = self.memory.read(a1, (a2 as usize).min(MAX_STRING));
+				let written = match a0 {
+					1 => {
+						self.print(&bytes)?;
+						true
+					},
+					2 => {
+						self.print_error(&bytes)?;
+						true
+					},
+					descriptor => self.files.write(descriptor, &bytes)
+				};
+				self.registers[V0] = if written { bytes.len() as u32 } else { -1i32 as u32 };
+			},
+			16 => self.files.close(a0),
+			17 => self.exit = Some(Exit::Code(a0 as i32)),
+			30 => {
+				let millis = clock::millis();
+				self.registers[A0] = millis as u32;
+				self.registers[A1] = (millis >> 32) as u32;
+			},
+			// sleeping past the time limit only sleeps until it
+			32 => {
+				let duration = Duration::from_millis(a0 as u64);
+				match (self.time_left().map_err(Fault::Limit)?, self.limits.time) {
+					(Some(left), Some(time)) if left <= duration => {
+						clock::sleep(left);
+						return Err(Fault::Limit(Limit::Time(time)));
+					},
+					_ => clock::sleep(duration)
+				}
+			},
+			34 => self.print(format!("{:#010x}", a0).as_bytes())?,
+			35 => self.print(format!("{:032b}", a0).as_bytes())?,
+			36 => self.print(a0.to_string().as_bytes())?,
+			code => return Err(Fault::Syscall(format!("Unknown syscall {}.", code)))
+		}
+
+		Ok(())
+	}
+}
+
+impl Machine {
+	pub(super) fn print(&mut self, bytes: &[u8]) -> Result<(), Fault> {
+		self.output(bytes, false)
+	}
+
+	fn print_error(&mut self, bytes: &[u8]) -> Result<(), Fault> {
+		self.output(bytes, true)
+	}
+
+	// writes what the program prints to stdout or stderr, which share the limit on output, as far as it and failing once it is passed
+	fn output(&mut self, bytes: &[u8], error: bool) -> Result<(), Fault> {
+		let allowed = match self.limits.output {
+			Some(limit) => &bytes[..(limit.saturating_sub(self.printed).min(bytes.len() as u64)) as usize],
+			None => bytes
+		};
+		self.printed += bytes.len() as u64;
+
+		match error {
+			true => self.io.write_error(allowed),
+			false => self.io.write(allowed)
+		}
+
+		match self.limits.output {
+			Some(limit) if self.printed > limit => Err(Fault::Limit(Limit::Output(limit))),
+			_ => Ok(())
+		}
+	}
+
+	// a line of input, keeping its newline, cut short at the limit
+	fn read_line(&mut self, limit: usize) -> Result<Vec<u8>, Fault> {
+		let mut line = Vec::new();
+		match self.io.read_line(&mut line, limit) {
+			Ok(_) => Ok(line),
+			Err(why) => Err(self.input_error(why))
+		}
+	}
+
+	// the time limit when waiting for input ran out of it, otherwise the failure to read
+	fn input_error(&self, why: io::Error) -> Fault {
+		match (why.kind(), self.limits.time) {
+			(io::ErrorKind::TimedOut, Some(time)) => Fault::Limit(Limit::Time(time)),
+			_ => Fault::Syscall(format!("Failed to read input: {}.", why))
+		}
+	}
+}
+
+// a float as Java prints it, which is how MARS shows them, from its shortest digits in scientific notation
+fn java(value: f64, scientific: &str) -> String {
+	if value.is_nan() {
+		return "NaN".to_string();
+	}
+
+	let sign = if value.is_sign_negative() { "-" } else { "" };
+	if value.is_infinite() {
+		return format!("{}Infinity", sign);
+	}
+	if value == 0.0 {
+		return format!("{}0.0", sign);
+	}
+
+	let (mantissa, exponent) = scientific.trim_start_matches('-').split_once('e').unwrap_or(("0", "0"));
+	let digits = mantissa.replace('.', "");
+	let exponent: i32 = exponent.parse().unwrap_or(0);
+
+	// values from 10^-3 up to 10^7 are written out in full
+	if !(1e-3..1e7).contains(&value.abs()) {
+		let (first, rest) = digits.split_at(1);
+		return format!("{}{}.{}E{}", sign, first, if rest.is_empty() { "0" } else { rest }, exponent);
+	}
+
+	if exponent < 0 {
+		return format!("{}0.{}{}", sign, "0".repeat((-exponent - 1) as usize), digits);
+	}
+
+	let point = exponent as usize + 1;
+	let padded = format!("{:0<width$}", digits, width = point);
+	let (whole, fraction) = padded.split_at(point);
+	format!("{}{}.{}", sign, whole, if fraction.is_empty() { "0" } else { fraction })
+}
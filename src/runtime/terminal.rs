@@ -0,0 +1,179 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::console::{self, Io};
+
+// the terminal the emulator runs in, of which there is none in the browser
+#[derive(Default)]
+pub struct Console {
+	keyboard: Option<Receiver<u8>>,		// characters typed, read from stdin once the program first uses the device or a read may time out
+	timeout: Option<Duration>
+}
+
+impl Console {
+	pub fn new() -> Console {
+		Console::default()
+	}
+}
+
+impl Io for Console {
+	// stdin is read directly until it must be read on another thread, and from then on only there
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		let deadline = self.deadline();
+		match (&self.keyboard, deadline) {
+			(None, None) => io::stdin().read(buffer),
+			_ => receive(self.keyboard.get_or_insert_with(keyboard), buffer, deadline)
+		}
+	}
+
+	fn read_line(&mut self, line: &mut Vec<u8>, limit: usize) -> io::Result<usize> {
+		let deadline = self.deadline();
+		match (&self.keyboard, deadline) {
+			(None, None) => console::read_line(&mut io::stdin().lock(), line, limit),
+			_ => receive_line(self.keyboard.get_or_insert_with(keyboard), line, limit, deadline)
+		}
+	}
+
+	fn key(&mut self) -> Option<u8> {
+		self.keyboard.get_or_insert_with(keyboard).try_recv().ok()
+	}
+
+	fn wait(&mut self, timeout: Option<Duration>) {
+		self.timeout = timeout;
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		let mut stdout = io::stdout();
+		let _ = stdout.write_all(bytes);
+		let _ = stdout.flush();
+	}
+
+	fn write_error(&mut self, bytes: &[u8]) {
+		let mut stderr = io::stderr();
+		let _ = stderr.write_all(bytes);
+		let _ = stderr.flush();
+	}
+}
+
+impl Console {
+	fn deadline(&self) -> Option<Instant> {
+		self.timeout.map(|timeout| Instant::now() + timeout)
+	}
+}
+
+// reads stdin on another thread, so the program keeps running while no key is typed
+fn keyboard() -> Receiver<u8> {
+	let (sender, receiver) = mpsc::channel();
+	thread::spawn(move || {
+		let mut byte = [0];
+		while let Ok(1) = io::stdin().read(&mut byte) {
+			if sender.send(byte[0]).is_err() {
+				break;
+			}
+		}
+	});
+	receiver
+}
+
+// the next byte of input, none at its end, waiting no later than the deadline
+fn next(receiver: &Receiver<u8>, deadline: Option<Instant>) -> io::Result<Option<u8>> {
+	let received = match deadline {
+		Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+		None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+	};
+	match received {
+		Ok(byte) => Ok(Some(byte)),
+		Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+		Err(RecvTimeoutError::Disconnected) => Ok(None)
+	}
+}
+
+// waits for the first byte, then takes as many more as have arrived
+fn receive(receiver: &Receiver<u8>, buffer: &mut [u8], deadline: Option<Instant>) -> io::Result<usize> {
+	if buffer.is_empty() {
+		return Ok(0);
+	}
+	let Some(first) = next(receiver, deadline)? else { return Ok(0) };
+	buffer[0] = first;
+
+	let mut read = 1;
+	while let Some(byte) = buffer.get_mut(read) {
+		match receiver.try_recv() {
+			Ok(received) => *byte = received,
+			Err(_) => break
+		}
+		read += 1;
+	}
+	Ok(read)
+}
+
+// a line as console::read_line reads it, a byte at a time
+fn receive_line(receiver: &Receiver<u8>, line: &mut Vec<u8>, limit: usize, deadline: Option<Instant>) -> io::Result<usize> {
+	let mut kept = 0;
+	while let Some(byte) = next(receiver, deadline)? {
+		if kept < limit {
+			line.push(byte);
+			kept += 1;
+		}
+		if byte == b'\n' {
+			break;
+		}
+	}
+	Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::mpsc::Sender;
+
+	fn typed(bytes: &[u8]) -> (Sender<u8>, Receiver<u8>) {
+		let (sender, receiver) = mpsc::channel();
+		for byte in bytes {
+			sender.send(*byte).unwrap();
+		}
+		(sender, receiver)
+	}
+
+	#[test]
+	fn receives_what_has_been_typed() {
+		let (sender, receiver) = typed(b"abc");
+		let mut buffer = [0; 2];
+		assert_eq!(receive(&receiver, &mut buffer, None).unwrap(), 2);
+		assert_eq!(&buffer, b"ab");
+		assert_eq!(receive(&receiver, &mut buffer, None).unwrap(), 1);
+		assert_eq!(buffer[0], b'c');
+
+		drop(sender);
+		assert_eq!(receive(&receiver, &mut buffer, None).unwrap(), 0);
+	}
+
+	#[test]
+	fn receives_lines_cut_short_at_the_limit() {
+		let (sender, receiver) = typed(b"hello\nworld");
+		let mut line = Vec::new();
+		assert_eq!(receive_line(&receiver, &mut line, 3, None).unwrap(), 3);
+		assert_eq!(line, b"hel");
+
+		drop(sender);
+		let mut line = Vec::new();
+		assert_eq!(receive_line(&receiver, &mut line, 100, None).unwrap(), 5);
+		assert_eq!(line, b"world");
+	}
+
+	#[test]
+	fn times_out_waiting_at_the_deadline() {
+		let (_sender, receiver) = typed(b"no newline");
+		let deadline = Instant::now() + Duration::from_millis(50);
+		let mut line = Vec::new();
+		let why = receive_line(&receiver, &mut line, 100, Some(deadline)).unwrap_err();
+		assert_eq!(why.kind(), io::ErrorKind::TimedOut);
+		assert!(Instant::now() >= deadline);
+
+		let mut buffer = [0; 4];
+		let deadline = Instant::now() + Duration::from_millis(50);
+		assert_eq!(receive(&receiver, &mut buffer, Some(deadline)).unwrap_err().kind(), io::ErrorKind::TimedOut);
+	}
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::lexer;
+use crate::lexer::tokens::{Token, CodeSegment};
+use crate::parse;
+use crate::errors;
+
+use colored::Colorize;
+
+pub struct SourceFile {
+	pub path: PathBuf,
+	pub lines: Vec<String>
+}
+
+// every file read while assembling, indexed by the file id of a code segment
+pub struct SourceMap {
+	files: Vec<SourceFile>
+}
+
+impl SourceMap {
+	pub fn new() -> SourceMap {
+		SourceMap {
+			files: Vec::new()
+		}
+	}
+
+	// reads a file into the map, returning its id
+	// a file which has already been read keeps its id
+	pub fn load(&mut self, path: &Path) -> io::Result<usize> {
+		let path = fs::canonicalize(path)?;
+		if let Some(id) = self.files.iter().position(|file| file.path == path) {
+			return Ok(id);
+		}
+
+		let lines = fs::read_to_string(&path)?.lines().map(|line| line.to_string()).collect();
+		Ok(self.add(path, lines))
+	}
+
+	pub fn add(&mut self, path: PathBuf, lines: Vec<String>) -> usize {
+		self.files.push(SourceFile { path, lines });
+		self.files.len() - 1
+	}
+
+	// source which was never read from a file, under the name its errors are shown with
+	pub fn add_text(&mut self, name: &str, text: &str) -> usize {
+		self.add(PathBuf::from(name), text.lines().map(|line| line.to_string()).collect())
+	}
+
+	pub fn ids(&self) -> std::ops::Range<usize> {
+		0..self.files.len()
+	}
+
+	pub fn file(&self, id: usize) -> &SourceFile {
+		&self.files[id]
+	}
+
+	// the name of a file as it should be shown to the user
+	pub fn name(&self, id: usize) -> String {
+		let path = &self.files[id].path;
+		match std::env::current_dir().ok().and_then(|dir| path.strip_prefix(dir).ok().map(|p| p.to_path_buf())) {
+			Some(relative) => relative.display().to_string(),
+			None => path.display().to_string()
+		}
+	}
+
+	pub fn line(&self, segment: &CodeSegment) -> &str {
+		&self.files[segment.file].lines[segment.line]
+	}
+
+	// "file:line:col" of a segment
+	pub fn location(&self, segment: &CodeSegment) -> String {
+		format!("{}:{}:{}", self.name(segment.file), segment.line + 1, segment.idx + 1)
+	}
+}
+
+impl Default for SourceMap {
+	fn default() -> SourceMap {
+		SourceMap::new()
+	}
+}
+
+// tokenizes a file, replacing every ".include" directive with the tokens of the file it names
+pub fn tokenize(sources: &mut SourceMap, file: usize) -> Result<Vec<Token>, errors::Err> {
+	tokenize_included(sources, file, &mut vec![file])
+}
+
+fn tokenize_included(sources: &mut SourceMap, file: usize, stack: &mut Vec<usize>) -> Result<Vec<Token>, errors::Err> {
+	let tokens = lexer::tokenize(&sources.file(file).lines, file)?;
+
+	let mut output = Vec::new();
+	let mut idx = 0;
+	while idx < tokens.len() {
+		match &tokens[idx] {
+			Token::Directive(id, segment) if id == "include" => {
+				idx += 1;
+				let (name, name_segment) = match tokens.get(idx) {
+					Some(Token::StringLiteral(name, name_segment)) => (name, name_segment),
+					Some(token) => {
+						return err(&parse::extract_segment(token), errors::Msg::Many(vec![
+							format!("Unexpected token {}.", token.to_string().red()),
+							"Expected file name as string literal.".to_string()
+						]));
+					},
+					None => return err(segment, errors::Msg::One("Expected file name as string literal.".to_string()))
+				};
+
+				// included files are found relative to the file including them
+				let path = match sources.file(file).path.parent() {
+					Some(dir) => dir.join(name),
+					None => PathBuf::from(name)
+				};
+
+				let included = match sources.load(&path) {
+					Ok(included) => included,
+					Err(why) => {
+						return err(name_segment, errors::Msg::One(format!("Failed to open \"{}\": {}", name.bright_black(), why)));
+					}
+				};
+
+				if stack.contains(&included) {
+					return err(name_segment, errors::Msg::One(format!("\"{}\" includes itself.", name.bright_black())));
+				}
+
+				stack.push(included);
+				output.append(&mut tokenize_included(sources, included, stack)?);
+				stack.pop();
+			},
+
+			token => output.push(token.clone())
+		}
+
+		idx += 1;
+	}
+
+	Ok(output)
+}
+
+fn err<T>(segment: &CodeSegment, msg: errors::Msg) -> Result<T, errors::Err> {
+	Err(errors::Err {
+		segment: segment.clone(),
+		errtype: errors::ErrType::Include,
+		msg
+	})
+}
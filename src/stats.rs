@@ -0,0 +1,246 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+use crate::encoding::{self, Encoding};
+use crate::parse::instructions::Instruction;
+use crate::runtime::Machine;
+use crate::source::SourceMap;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// A report to read.
+	Text,
+	/// Rows of group, name, count and cycles.
+	Csv,
+	/// An object of counts by group.
+	Json
+}
+
+// the classes of instruction, which the cycles per instruction are given for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+	Alu,
+	Load,
+	Store,
+	Branch,
+	Jump,
+	Syscall,
+	Other		// break, traps and coprocessor 0
+}
+
+impl Category {
+	pub const ALL: [Category; 7] = [Category::Alu, Category::Load, Category::Store, Category::Branch, Category::Jump, Category::Syscall, Category::Other];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Category::Alu => "alu",
+			Category::Load => "load",
+			Category::Store => "store",
+			Category::Branch => "branch",
+			Category::Jump => "jump",
+			Category::Syscall => "syscall",
+			Category::Other => "other"
+		}
+	}
+
+	fn of(instruction: Instruction) -> Category {
+		use Instruction::*;
+		match instruction.encoding() {
+			_ if instruction.is_branch() => Category::Branch,
+			_ if instruction.is_jump() => Category::Jump,
+			Some(Encoding::Immediate(0x20..=0x26 | 0x30 | 0x31 | 0x35)) => Category::Load,
+			Some(Encoding::Immediate(0x28..=0x2e | 0x38 | 0x39 | 0x3d)) => Category::Store,
+			_ => match instruction {
+				SystemCall => Category::Syscall,
+				Break | MoveFromCoprocessor0 | MoveToCoprocessor0 | ExceptionReturn |
+				TrapEqual | TrapNotEqual | TrapGreaterEqual | TrapGreaterEqualUnsigned | TrapLessThan | TrapLessThanUnsigned |
+				TrapEqualImmediate | TrapNotEqualImmediate | TrapGreaterEqualImmediate | TrapGreaterEqualImmediateUnsigned |
+				TrapLessThanImmediate | TrapLessThanImmediateUnsigned => Category::Other,
+				_ => Category::Alu
+			}
+		}
+	}
+}
+
+// the cycles taken by each category, written as `load=5,branch=3`, every other taking one
+#[derive(Debug, Clone, Copy)]
+pub struct Cycles([u32; 7]);
+
+impl Cycles {
+	pub fn parse(arg: &str) -> Result<Cycles, String> {
+		let mut cycles = [1; 7];
+		for option in arg.split(',').filter(|option| !option.is_empty()) {
+			let (name, value) = option.split_once('=').ok_or(format!("\"{}\" is not a class and its cycles such as load=5", option))?;
+			let idx = Category::ALL.iter().position(|category| category.name() == name)
+				.ok_or(format!("\"{}\" is not one of alu, load, store, branch, jump, syscall or other", name))?;
+			cycles[idx] = value.parse().map_err(|_| format!("\"{}\" is not a number of cycles", value))?;
+		}
+		Ok(Cycles(cycles))
+	}
+}
+
+impl Default for Cycles {
+	fn default() -> Cycles {
+		Cycles([1; 7])
+	}
+}
+
+// the R, I and J formats, by how the instruction is encoded
+fn format(instruction: Instruction) -> usize {
+	match instruction.encoding() {
+		Some(Encoding::Jump(_)) => 2,
+		Some(Encoding::Immediate(_) | Encoding::RegImm(_) | Encoding::Cop1Branch(_)) => 1,
+		_ => 0
+	}
+}
+
+const FORMATS: [&str; 3] = ["R", "I", "J"];
+
+// counts of the instructions the machine executes, by their address
+#[derive(Default)]
+pub struct Stats {
+	total: u64,
+	addresses: HashMap<u32, u64>
+}
+
+// a group of counts, each with the cycles they took
+type Group = Vec<(String, u64, u64)>;
+
+impl Stats {
+	pub fn new() -> Stats {
+		Stats::default()
+	}
+
+	pub fn retire(&mut self, machine: &Machine) {
+		let Some(retired) = machine.retired else { return };
+		self.total += 1;
+		*self.addresses.entry(retired.pc).or_default() += 1;
+	}
+
+	// every group of counts, from the most common, except for lines which are in the order they were written
+	fn groups(&self, machine: &Machine, sources: &SourceMap, cycles: &Cycles) -> Vec<(&'static str, Group)> {
+		let sorted = |group: HashMap<String, (u64, u64)>| -> Group {
+			let mut group: Group = group.into_iter().map(|(name, (count, cycles))| (name, count, cycles)).collect();
+			group.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+			group
+		};
+
+		let mut mnemonics: HashMap<String, (u64, u64)> = HashMap::new();
+		let mut formats = [(0, 0); 3];
+		let mut categories = [(0, 0); 7];
+		let mut lines: BTreeMap<(usize, usize), (u64, u64)> = BTreeMap::new();
+		let mut labels: HashMap<String, (u64, u64)> = HashMap::new();
+		for (&address, &count) in &self.addresses {
+			let instruction = encoding::decode(machine.memory.read_u32(address)).map(|word| word.instruction);
+			let category = Category::ALL.iter().position(|category| Some(*category) == instruction.map(Category::of)).unwrap_or(Category::ALL.len() - 1);
+			let weight = count * cycles.0[category] as u64;
+			let add = |entry: &mut (u64, u64)| {
+				entry.0 += count;
+				entry.1 += weight;
+			};
+
+			if let Some(instruction) = instruction {
+				add(mnemonics.entry(instruction.mnemonic().to_string()).or_default());
+				add(&mut formats[format(instruction)]);
+			}
+			add(&mut categories[category]);
+			// instructions without a source are kept after those with one, by their address
+			let line = machine.line(address).map_or((usize::MAX, address as usize), |segment| (segment.file, segment.line));
+			add(lines.entry(line).or_default());
+			let label = machine.label_before(address).map_or("(none)".to_string(), |(label, _)| label.name.to_string());
+			add(labels.entry(label).or_default());
+		}
+
+		let named = |names: &[&str], counts: &[(u64, u64)]| -> Group {
+			names.iter().zip(counts).map(|(name, (count, cycles))| (name.to_string(), *count, *cycles)).collect()
+		};
+		let formats = named(&FORMATS, &formats);
+		let lines = lines.into_iter().map(|((file, line), (count, cycles))| match file {
+			usize::MAX => (format!("{:#010x}", line), count, cycles),
+			_ => (format!("{}:{}", sources.name(file), line + 1), count, cycles)
+		}).collect();
+		let categories = named(&Category::ALL.map(|category| category.name()), &categories);
+
+		vec![
+			("instruction", sorted(mnemonics)),
+			("format", formats),
+			("category", categories),
+			("line", lines),
+			("label", sorted(labels))
+		]
+	}
+
+	pub fn report(&self, machine: &Machine, sources: &SourceMap, cycles: &Cycles, format: Format) -> String {
+		let groups = self.groups(machine, sources, cycles);
+		let total_cycles: u64 = groups.iter().find(|(name, _)| *name == "category").map_or(0, |(_, group)| group.iter().map(|row| row.2).sum());
+		let cpi = total_cycles as f64 / self.total.max(1) as f64;
+		let mut out = String::new();
+
+		match format {
+			Format::Text => {
+				let _ = writeln!(out, "{} instructions, {} cycles, {:.2} cycles per instruction", self.total, total_cycles, cpi);
+				for (name, group) in &groups {
+					let width = group.iter().map(|row| row.0.len()).max().unwrap_or(0).max(name.len());
+					let _ = writeln!(out, "\n{:width$}  {:>10}  {:>7}  {:>10}", name, "count", "share", "cycles");
+					for (key, count, cycles) in group {
+						let _ = writeln!(out, "{:width$}  {:>10}  {:>6.2}%  {:>10}", key, count, 100.0 * *count as f64 / self.total.max(1) as f64, cycles);
+					}
+				}
+			},
+			Format::Csv => {
+				out.push_str("group,name,count,cycles\n");
+				let _ = writeln!(out, "total,instructions,{},{}", self.total, total_cycles);
+				for (name, group) in &groups {
+					for (key, count, cycles) in group {
+						let _ = writeln!(out, "{},{},{},{}", name, csv(key), count, cycles);
+					}
+				}
+			},
+			Format::Json => {
+				let report = Json { instructions: self.total, cycles: total_cycles, cpi: (cpi * 1e4).round() / 1e4, groups: &groups };
+				out = serde_json::to_string(&report).unwrap_or_else(|_| unreachable!("the report is made of strings and numbers"));
+				out.push('\n');
+			}
+		}
+		out
+	}
+}
+
+fn csv(field: &str) -> String {
+	match field.contains([',', '"', '\n']) {
+		true => format!("\"{}\"", field.replace('"', "\"\"")),
+		false => field.to_string()
+	}
+}
+
+// the report in json, an object with an array for each group, kept in the order of the text
+struct Json<'a> {
+	instructions: u64,
+	cycles: u64,
+	cpi: f64,
+	groups: &'a [(&'static str, Group)]
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+	name: &'a str,
+	count: u64,
+	cycles: u64
+}
+
+impl Serialize for Json<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(None)?;
+		map.serialize_entry("instructions", &self.instructions)?;
+		map.serialize_entry("cycles", &self.cycles)?;
+		map.serialize_entry("cpi", &self.cpi)?;
+		for (name, group) in self.groups {
+			let rows: Vec<Row> = group.iter().map(|(name, count, cycles)| Row { name, count: *count, cycles: *cycles }).collect();
+			map.serialize_entry(name, &rows)?;
+		}
+		map.end()
+	}
+}
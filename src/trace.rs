@@ -0,0 +1,386 @@
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+
+use crate::disasm;
+use crate::parse::instructions::{Instruction, Register};
+use crate::runtime::Machine;
+use crate::source::SourceMap;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// A line for each instruction, in columns.
+	Text,
+	/// A JSON object for each instruction, one to a line.
+	Json
+}
+
+// the instructions traced, by a range of addresses written as `0x400000..0x400040` or a label, which takes in everything up to the next
+#[derive(Debug, Clone)]
+pub enum Filter {
+	Range(u32, u32),
+	Label(String)
+}
+
+impl Filter {
+	pub fn parse(arg: &str) -> Result<Filter, String> {
+		let number = |text: &str| -> Result<u32, String> {
+			let parsed = match text.strip_prefix("0x") {
+				Some(hex) => u32::from_str_radix(hex, 16),
+				None => text.parse()
+			};
+			parsed.map_err(|_| format!("\"{}\" is not an address", text))
+		};
+
+		match arg.split_once("..") {
+			Some((start, end)) => {
+				let (start, end) = (number(start)?, number(end)?);
+				match start < end {
+					true => Ok(Filter::Range(start, end)),
+					false => Err(format!("the range \"{}\" is empty", arg))
+				}
+			},
+			None => Ok(Filter::Label(arg.to_string()))
+		}
+	}
+}
+
+// the state an instruction may change, besides the program counter and memory
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct State {
+	registers: [u32; 32],
+	hi: u32,
+	lo: u32,
+	floats: [u32; 32],
+	fcsr: u32
+}
+
+impl State {
+	fn of(machine: &Machine) -> State {
+		State { registers: machine.registers, hi: machine.hi, lo: machine.lo, floats: machine.floats, fcsr: machine.fcsr }
+	}
+
+	// the registers which differ in another state, by name, with their values there
+	fn changes(&self, after: &State) -> Vec<(String, u32)> {
+		let mut changes = Vec::new();
+		for (idx, (before, after)) in self.registers.iter().zip(after.registers).enumerate() {
+			if *before != after {
+				changes.push((format!("${}", Register::NAMES[idx]), after));
+			}
+		}
+		for (name, before, after) in [("hi", self.hi, after.hi), ("lo", self.lo, after.lo)] {
+			if before != after {
+				changes.push((name.to_string(), after));
+			}
+		}
+		for (idx, (before, after)) in self.floats.iter().zip(after.floats).enumerate() {
+			if *before != after {
+				changes.push((format!("$f{}", idx), after));
+			}
+		}
+		if self.fcsr != after.fcsr {
+			changes.push(("fcsr".to_string(), after.fcsr));
+		}
+		changes
+	}
+}
+
+// an executed instruction and what it changed
+// in json the changes are objects, from names or addresses to the values after
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+	#[serde(deserialize_with = "word")]
+	pub pc: u32,
+	#[serde(default, deserialize_with = "word")]
+	pub word: u32,
+	#[serde(default)]
+	pub text: String,
+	#[serde(default)]
+	pub source: Option<String>,
+	#[serde(default, with = "changes")]
+	pub registers: Vec<(String, u32)>,
+	#[serde(default, with = "changes")]
+	pub memory: Vec<(u32, u32)>		// the words stored to, by their aligned address, with their values after
+}
+
+impl Entry {
+	pub fn to_text(&self) -> String {
+		let mut line = format!("{:#010x}  {:#010x}  {:28}  {:16}", self.pc, self.word, self.text, self.source.as_deref().unwrap_or("-"));
+		for (name, value) in &self.registers {
+			let _ = write!(line, "  {}={:#010x}", name, value);
+		}
+		for (address, value) in &self.memory {
+			let _ = write!(line, "  [{:#010x}]={:#010x}", address, value);
+		}
+		line.trim_end().to_string()
+	}
+
+	// reads a line of a trace as written in either format, values in json being numbers or strings of hexadecimal
+	pub fn parse(line: &str) -> Result<Entry, String> {
+		let line = line.trim();
+		match line.starts_with('{') {
+			true => serde_json::from_str(line).map_err(|why| why.to_string()),
+			false => Entry::from_text(line)
+		}
+	}
+
+	// the address and word lead the line and the changes end it, none of which hold spaces
+	// of what is left between them, the source is the last column, the disassembly taking any spaces it has
+	fn from_text(line: &str) -> Result<Entry, String> {
+		let (pc, rest) = line.split_once(char::is_whitespace).ok_or("there are too few columns".to_string())?;
+		let (word, mut rest) = rest.trim_start().split_once(char::is_whitespace).ok_or("there are too few columns".to_string())?;
+
+		let mut changes = Vec::new();
+		while let Some((before, last)) = rest.trim_end().rsplit_once(char::is_whitespace) {
+			if !last.contains("=0x") {
+				break;
+			}
+			changes.push(last);
+			rest = before;
+		}
+		changes.reverse();
+		let (text, source) = rest.trim().rsplit_once("  ").ok_or("there are too few columns".to_string())?;
+
+		let mut entry = Entry {
+			pc: hex(pc)?,
+			word: hex(word)?,
+			text: text.trim().to_string(),
+			source: Some(source.trim().to_string()).filter(|source| source != "-"),
+			registers: Vec::new(),
+			memory: Vec::new()
+		};
+		for change in changes {
+			let (name, value) = change.split_once('=').ok_or(format!("\"{}\" is not a change such as $t0=0x00000001", change))?;
+			match name.strip_prefix('[').and_then(|name| name.strip_suffix(']')) {
+				Some(address) => entry.memory.push((hex(address)?, hex(value)?)),
+				None => entry.registers.push((name.to_string(), hex(value)?))
+			}
+		}
+		Ok(entry)
+	}
+}
+
+fn hex(text: &str) -> Result<u32, String> {
+	let digits = text.strip_prefix("0x").ok_or(format!("\"{}\" is not a hexadecimal number", text))?;
+	u32::from_str_radix(digits, 16).map_err(|_| format!("\"{}\" is not a hexadecimal number", text))
+}
+
+// a value in json, written as a number and read as one or as a string of hexadecimal
+struct Word(u32);
+
+impl<'de> Deserialize<'de> for Word {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Word, D::Error> {
+		struct Value;
+
+		impl de::Visitor<'_> for Value {
+			type Value = Word;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				write!(f, "a word, as a number or a string such as \"0x0000002a\"")
+			}
+
+			fn visit_u64<E: de::Error>(self, value: u64) -> Result<Word, E> {
+				u32::try_from(value).map(Word).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+			}
+
+			fn visit_str<E: de::Error>(self, value: &str) -> Result<Word, E> {
+				hex(value).map(Word).map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+			}
+		}
+
+		deserializer.deserialize_any(Value)
+	}
+}
+
+fn word<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+	Ok(Word::deserialize(deserializer)?.0)
+}
+
+// the changes of an entry as an object, keeping the order they were made in
+mod changes {
+	use std::fmt;
+	use std::marker::PhantomData;
+
+	use serde::de::{self, MapAccess, Visitor};
+	use serde::{Deserializer, Serializer};
+
+	use super::Word;
+
+	// what a change is keyed by, a register by its name or memory by its address, in decimal or hexadecimal
+	pub trait Key: fmt::Display + Sized {
+		fn parse(key: &str) -> Result<Self, String>;
+	}
+
+	impl Key for String {
+		fn parse(key: &str) -> Result<String, String> {
+			Ok(key.to_string())
+		}
+	}
+
+	impl Key for u32 {
+		fn parse(key: &str) -> Result<u32, String> {
+			key.parse().or_else(|_| super::hex(key))
+		}
+	}
+
+	pub fn serialize<K: Key, S: Serializer>(changes: &[(K, u32)], serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_map(changes.iter().map(|(key, value)| (key.to_string(), value)))
+	}
+
+	pub fn deserialize<'de, K: Key, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(K, u32)>, D::Error> {
+		struct Changes<K>(PhantomData<K>);
+
+		impl<'de, K: Key> Visitor<'de> for Changes<K> {
+			type Value = Vec<(K, u32)>;
+
+			fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				write!(f, "an object of changes")
+			}
+
+			fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+				Ok(Vec::new())
+			}
+
+			fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+				let mut changes = Vec::new();
+				while let Some((key, value)) = map.next_entry::<String, Word>()? {
+					changes.push((K::parse(&key).map_err(de::Error::custom)?, value.0));
+				}
+				Ok(changes)
+			}
+		}
+
+		deserializer.deserialize_any(Changes(PhantomData))
+	}
+}
+
+// the words a store wrote to, from the address it accessed
+// memory written by system calls, such as read string, is not seen
+fn stored(machine: &Machine, instruction: Instruction, address: u32) -> Vec<(u32, u32)> {
+	let last = match instruction {
+		Instruction::StoreDoubleFloat => address + 4,
+		_ => address
+	};
+	let (first, last) = (address & !3, last & !3);
+	(first..=last).step_by(4).map(|address| (address, machine.memory.read_u32(address))).collect()
+}
+
+// writes an entry for each instruction the machine executes, within the filters if there are any
+pub struct Tracer {
+	format: Format,
+	ranges: Vec<(u32, u32)>,
+	state: State,
+	out: Box<dyn Write>
+}
+
+impl Tracer {
+	pub fn new(machine: &Machine, format: Format, filters: &[Filter], out: Box<dyn Write>) -> Result<Tracer, String> {
+		let mut ranges = Vec::new();
+		for filter in filters {
+			ranges.push(match filter {
+				Filter::Range(start, end) => (*start, *end),
+				Filter::Label(name) => {
+					let label = machine.labels.iter().find(|label| label.name.as_str() == name && label.section.executable())
+						.ok_or(format!("there is no label \"{}\" in the text to trace", name))?;
+					let end = machine.labels.iter()
+						.filter(|other| other.section.executable() && other.address > label.address)
+						.map(|other| other.address)
+						.min()
+						.unwrap_or(u32::MAX);
+					(label.address, end)
+				}
+			});
+		}
+		Ok(Tracer { format, ranges, state: State::of(machine), out })
+	}
+
+	// the entry for the last step of the machine, if it executed an instruction, taking in what it changed
+	pub fn entry(&mut self, machine: &Machine, sources: &SourceMap) -> Option<Entry> {
+		let before = std::mem::replace(&mut self.state, State::of(machine));
+		let retired = machine.retired?;
+		let word = machine.memory.read_u32(retired.pc);
+		let memory = match retired.access {
+			Some((address, true)) => stored(machine, retired.word.instruction, address),
+			_ => Vec::new()
+		};
+
+		Some(Entry {
+			pc: retired.pc,
+			word,
+			text: disasm::disassemble(word, retired.pc, &machine.labels, true),
+			source: machine.line(retired.pc).map(|segment| format!("{}:{}", sources.name(segment.file), segment.line + 1)),
+			registers: before.changes(&self.state),
+			memory
+		})
+	}
+
+	pub fn retire(&mut self, machine: &Machine, sources: &SourceMap) -> io::Result<()> {
+		let traced = machine.retired.is_some_and(|retired| self.ranges.is_empty() || self.ranges.iter().any(|(start, end)| (*start..*end).contains(&retired.pc)));
+		let entry = self.entry(machine, sources);
+
+		match entry.filter(|_| traced) {
+			Some(entry) => {
+				match self.format {
+					Format::Text => self.out.write_all(entry.to_text().as_bytes())?,
+					Format::Json => serde_json::to_writer(&mut self.out, &entry)?
+				}
+				writeln!(self.out)
+			},
+			None => Ok(())
+		}
+	}
+
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.out.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(text: &str, source: Option<&str>, registers: &[(&str, u32)], memory: &[(u32, u32)]) -> Entry {
+		Entry {
+			pc: 0x00400010,
+			word: 0xafa8fffc,
+			text: text.to_string(),
+			source: source.map(str::to_string),
+			registers: registers.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+			memory: memory.to_vec()
+		}
+	}
+
+	#[test]
+	fn reads_back_the_entries_it_writes() {
+		let entries = [
+			entry("addiu $t0, $t0, 1", Some("main.asm:3"), &[("$t0", 1)], &[]),
+			entry("lw $t0, a_label_longer_than_the_column+4($gp)", Some("main.asm:12"), &[("$t0", 0xdeadbeef)], &[]),
+			entry("syscall", None, &[], &[]),
+			entry("sw $t0, -4($sp)", Some("-"), &[], &[(0x7fffeffc, 42)]),
+			entry("jal a_function_with_a_very_long_name", Some("lib.asm:1"), &[("$ra", 0x00400018), ("hi", 2)], &[(0x10010000, 1), (0x10010004, 2)])
+		];
+		for entry in entries {
+			let text = entry.to_text();
+			let expected = Entry { source: entry.source.clone().filter(|source| source != "-"), ..entry.clone() };
+			assert_eq!(Entry::parse(&text), Ok(expected), "reading {}", text);
+
+			let json = serde_json::to_string(&entry).unwrap();
+			assert_eq!(Entry::parse(&json), Ok(entry));
+		}
+	}
+
+	#[test]
+	fn reads_changes_in_json_as_numbers_or_hexadecimal() {
+		let json = r#"{"pc": "0x00400010", "registers": {"$t0": "0x0000002a", "lo": 7}, "memory": {"0x10010000": 1, "268500996": "0x00000002"}}"#;
+		assert_eq!(Entry::parse(json), Ok(Entry {
+			pc: 0x00400010,
+			word: 0,
+			text: String::new(),
+			source: None,
+			registers: vec![("$t0".to_string(), 42), ("lo".to_string(), 7)],
+			memory: vec![(0x10010000, 1), (0x10010004, 2)]
+		}));
+	}
+}
@@ -0,0 +1,130 @@
+use wasm_bindgen::prelude::*;
+
+use crate::runtime::console::Shared;
+use crate::runtime::{Config, Exit, Machine};
+use crate::source::SourceMap;
+
+// a program assembled from source and the machine running it, for the page to step and inspect
+#[wasm_bindgen]
+pub struct Emulator {
+	machine: Machine,
+	sources: SourceMap,
+	terminal: Shared
+}
+
+#[wasm_bindgen]
+impl Emulator {
+	// assembles the source, failing with its errors as the command line shows them, without colour
+	#[wasm_bindgen(constructor)]
+	pub fn new(source: &str) -> Result<Emulator, JsError> {
+		colored::control::set_override(false);
+
+		let mut sources = SourceMap::new();
+		sources.add_text("main.asm", source);
+		let program = crate::assemble(&mut sources).map_err(|err| JsError::new(&err.report(&sources)))?;
+
+		let terminal = Shared::default();
+		let mut machine = Machine::new(&program, Config::default());
+		machine.io = Box::new(terminal.clone());
+		Ok(Emulator { machine, sources, terminal })
+	}
+
+	#[wasm_bindgen(setter = delaySlots)]
+	pub fn set_delay_slots(&mut self, delay_slots: bool) {
+		self.machine.delay_slots = delay_slots;
+	}
+
+	#[wasm_bindgen(setter = maxSteps)]
+	pub fn set_max_steps(&mut self, steps: Option<u32>) {
+		self.machine.limits.steps = steps.map(|steps| steps as u64);
+	}
+
+	// executes one instruction, giving whether the program is still running
+	pub fn step(&mut self) -> Result<bool, JsError> {
+		self.run(1)
+	}
+
+	// executes at most the given instructions, so that the page can be drawn between runs
+	pub fn run(&mut self, steps: u32) -> Result<bool, JsError> {
+		if steps == 0 {
+			return Ok(self.machine.exit.is_none());
+		}
+		let mut left = steps;
+		let ran = self.machine.run_until(|_| {
+			left = left.saturating_sub(1);
+			left == 0
+		});
+		match ran {
+			Ok(exit) => Ok(exit.is_none()),
+			Err(fault) => Err(JsError::new(&self.machine.report(&fault, &self.sources)))
+		}
+	}
+
+	// text for the program to read, after any given before
+	pub fn input(&mut self, text: &str) {
+		self.terminal.0.borrow_mut().give(text.as_bytes());
+	}
+
+	// what the program has printed since this was last called
+	pub fn output(&mut self) -> String {
+		let bytes = std::mem::take(&mut self.terminal.0.borrow_mut().output);
+		String::from_utf8_lossy(&bytes).into_owned()
+	}
+
+	// what the program has written to stderr since this was last called
+	#[wasm_bindgen(js_name = errorOutput)]
+	pub fn error_output(&mut self) -> String {
+		let bytes = std::mem::take(&mut self.terminal.0.borrow_mut().error);
+		String::from_utf8_lossy(&bytes).into_owned()
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn pc(&self) -> u32 {
+		self.machine.pc
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn steps(&self) -> f64 {
+		self.machine.steps as f64
+	}
+
+	// the code the program exited with, running off the end of the text counting as 0
+	#[wasm_bindgen(getter = exitCode)]
+	pub fn exit_code(&self) -> Option<i32> {
+		self.machine.exit.map(|exit| match exit {
+			Exit::Code(code) => code,
+			Exit::FellOff => 0
+		})
+	}
+
+	// the line of source, from 1, of the instruction about to be executed
+	#[wasm_bindgen(getter)]
+	pub fn line(&self) -> Option<u32> {
+		self.machine.line(self.machine.pc).map(|segment| segment.line as u32 + 1)
+	}
+
+	// a register by its name, such as `$t0`, `v0`, `hi` or `$f12`
+	pub fn register(&self, name: &str) -> Option<u32> {
+		self.machine.named_register(name)
+	}
+
+	// the general purpose registers, by number
+	pub fn registers(&self) -> Vec<u32> {
+		self.machine.registers.to_vec()
+	}
+
+	#[wasm_bindgen(js_name = readWord)]
+	pub fn read_word(&self, address: u32) -> u32 {
+		self.machine.read_word(address)
+	}
+
+	#[wasm_bindgen(js_name = readMemory)]
+	pub fn read_memory(&self, address: u32, length: usize) -> Vec<u8> {
+		self.machine.read_bytes(address, length)
+	}
+
+	// the address of a label
+	pub fn label(&self, name: &str) -> Option<u32> {
+		self.machine.label(name).map(|label| label.address)
+	}
+}
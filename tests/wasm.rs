@@ -0,0 +1,76 @@
+// run under node with `cargo test --target wasm32-unknown-unknown --test wasm`, wasm-bindgen-test-runner being the runner
+#![cfg(target_arch = "wasm32")]
+
+use rustic_mips::wasm::Emulator;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const ECHO: &str = "
+	.data
+result:	.word 0
+	.text
+main:	li $v0, 5
+	syscall
+	sll $t0, $v0, 1
+	sw $t0, result
+	move $a0, $t0
+	li $v0, 1
+	syscall
+	li $v0, 17
+	li $a0, 3
+	syscall
+";
+
+#[wasm_bindgen_test]
+fn runs_with_input() {
+	let mut emulator = Emulator::new(ECHO).unwrap_or_else(|_| panic!("the program assembles"));
+	emulator.input("21\n");
+	assert_eq!(emulator.run(1000).ok(), Some(false));
+	assert_eq!(emulator.output(), "42");
+	assert_eq!(emulator.exit_code(), Some(3));
+	assert_eq!(emulator.read_word(emulator.label("result").unwrap_or_default()), 42);
+}
+
+#[wasm_bindgen_test]
+fn steps() {
+	let mut emulator = Emulator::new(ECHO).unwrap_or_else(|_| panic!("the program assembles"));
+	assert_eq!(emulator.line(), Some(5));
+	assert_eq!(emulator.step().ok(), Some(true));
+	assert_eq!(emulator.register("$v0"), Some(5));
+	assert_eq!(emulator.pc(), 0x00400004);
+	assert_eq!(emulator.steps(), 1.0);
+
+	// running no instructions runs none
+	assert_eq!(emulator.run(0).ok(), Some(true));
+	assert_eq!(emulator.pc(), 0x00400004);
+	assert_eq!(emulator.steps(), 1.0);
+}
+
+#[wasm_bindgen_test]
+fn reports_errors() {
+	assert!(Emulator::new("main: addi $t0, $t0").is_err());
+
+	// reading an integer with no input given faults
+	let mut emulator = Emulator::new(ECHO).unwrap_or_else(|_| panic!("the program assembles"));
+	assert!(emulator.run(1000).is_err());
+}
+
+#[wasm_bindgen_test]
+fn keeps_stderr_apart() {
+	let source = "
+	.data
+message:	.ascii \"oops\"
+	.text
+main:	li $v0, 15
+	li $a0, 2
+	la $a1, message
+	li $a2, 4
+	syscall
+	li $v0, 1
+	li $a0, 7
+	syscall
+";
+	let mut emulator = Emulator::new(source).unwrap_or_else(|_| panic!("the program assembles"));
+	assert_eq!(emulator.run(1000).ok(), Some(false));
+	assert_eq!(emulator.output(), "7");
+	assert_eq!(emulator.error_output(), "oops");
+}